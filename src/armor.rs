@@ -0,0 +1,183 @@
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+use stamp_core::util::{base64_decode, base64_encode};
+
+/// Label for sealed messages (including sealed stamps).
+pub const MESSAGE: &str = "STAMP MESSAGE";
+/// Label for signatures.
+pub const SIGNATURE: &str = "STAMP SIGNATURE";
+/// Label for files encrypted with a `secret` key.
+pub const ENCRYPTED: &str = "STAMP ENCRYPTED FILE";
+/// Label for transactions (usually staged ones being passed between signers).
+pub const TRANSACTION: &str = "STAMP TRANSACTION";
+
+/// Clearsigned documents have their own format, see `commands::sign`.
+const CLEARSIGN_LABEL: &str = "STAMP SIGNED MESSAGE";
+
+/// An armored block that's been read back into its parts.
+pub struct Armored {
+    pub label: String,
+    pub headers: Vec<(String, String)>,
+    pub data: Vec<u8>,
+}
+
+/// Three bytes of the SHA256 of the data, which catches copy/paste damage
+/// before we try to deserialize anything.
+fn checksum(data: &[u8]) -> String {
+    let hash = Sha256::digest(data);
+    base64_encode(&hash[0..3])
+}
+
+/// Wrap binary data in an ASCII armor block, ex:
+///
+/// ```text
+/// -----BEGIN STAMP MESSAGE-----
+/// Version: stamp-cli 0.1.4
+/// Recipient: <short identity id>
+///
+/// <base64, 64 characters per line>
+/// =<checksum>
+/// -----END STAMP MESSAGE-----
+/// ```
+pub fn armor(label: &str, headers: &[(&str, String)], data: &[u8]) -> String {
+    let mut out = vec![
+        format!("-----BEGIN {}-----", label),
+        format!("Version: stamp-cli {}", env!("CARGO_PKG_VERSION")),
+    ];
+    for (key, val) in headers {
+        out.push(format!("{}: {}", key, val));
+    }
+    out.push(String::from(""));
+    let encoded = base64_encode(data);
+    for line in encoded.as_bytes().chunks(64) {
+        out.push(String::from_utf8_lossy(line).to_string());
+    }
+    out.push(format!("={}", checksum(data)));
+    out.push(format!("-----END {}-----", label));
+    out.push(String::from(""));
+    out.join("\n")
+}
+
+/// Find and read the first armored Stamp block in the input, checking its
+/// checksum. Returns None if there's no armored block, or if the input is a
+/// clearsigned document (its signature block is armored, but the document has
+/// to be read as a whole).
+pub fn dearmor(bytes: &[u8]) -> Result<Option<Armored>> {
+    let text = match std::str::from_utf8(bytes) {
+        Ok(x) => x.replace("\r\n", "\n"),
+        Err(_) => return Ok(None),
+    };
+    let mut lines = text.lines().map(|x| x.trim());
+    let label = loop {
+        match lines.next() {
+            Some(line) => {
+                let label = line.strip_prefix("-----BEGIN ").and_then(|x| x.strip_suffix("-----"));
+                match label {
+                    Some(CLEARSIGN_LABEL) => return Ok(None),
+                    Some(label) if label.starts_with("STAMP ") => break String::from(label),
+                    _ => {}
+                }
+            }
+            None => return Ok(None),
+        }
+    };
+    let end = format!("-----END {}-----", label);
+    let mut headers = Vec::new();
+    let mut body = Vec::new();
+    let mut in_headers = true;
+    let mut terminated = false;
+    for line in lines {
+        if line == end {
+            terminated = true;
+            break;
+        }
+        if in_headers {
+            if line.is_empty() {
+                in_headers = false;
+                continue;
+            }
+            if let Some((key, val)) = line.split_once(": ") {
+                headers.push((String::from(key), String::from(val)));
+                continue;
+            }
+            // no headers, straight into the data
+            in_headers = false;
+        }
+        if !line.is_empty() {
+            body.push(line);
+        }
+    }
+    if !terminated {
+        Err(anyhow!("Armored {} is missing its end line ({})", label.to_lowercase(), end))?;
+    }
+    let has_check = body
+        .last()
+        .map(|last| last.len() == 5 && last.starts_with('=') && !last.ends_with('='))
+        .unwrap_or(false);
+    let check = if has_check { body.pop().map(|x| &x[1..]) } else { None };
+    let data = base64_decode(body.concat().as_bytes()).map_err(|e| anyhow!("Armored {} is damaged: {}", label.to_lowercase(), e))?;
+    if let Some(check) = check {
+        if check != checksum(data.as_slice()) {
+            Err(anyhow!(
+                "Armored {} failed its checksum. It was probably damaged while being copied.",
+                label.to_lowercase()
+            ))?;
+        }
+    }
+    Ok(Some(Armored { label, headers, data }))
+}
+
+/// If the input is armored, return the data inside, otherwise return the input
+/// as-is.
+pub fn strip(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    match dearmor(bytes.as_slice())? {
+        Some(armored) => Ok(armored.data),
+        None => Ok(bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn armor_roundtrip() {
+        let data = (0..200u8).collect::<Vec<_>>();
+        let armored = armor(MESSAGE, &[("Recipient", String::from("zef7Qo5S"))], data.as_slice());
+        // pasted into the middle of an email, with CRLF line endings
+        let pasted = format!("hi, here it is:\r\n\r\n{}\r\nthanks!", armored.replace('\n', "\r\n"));
+        let read = dearmor(pasted.as_bytes()).unwrap().unwrap();
+        assert_eq!(read.label, MESSAGE);
+        assert!(read.headers.contains(&(String::from("Recipient"), String::from("zef7Qo5S"))));
+        assert_eq!(read.data, data);
+    }
+
+    #[test]
+    fn armor_checksum_catches_damage() {
+        let data = (0..200u8).collect::<Vec<_>>();
+        let armored = armor(MESSAGE, &[], data.as_slice());
+        let mut lines = armored.lines().map(String::from).collect::<Vec<_>>();
+        let body = lines.iter().position(|x| x.is_empty()).unwrap() + 1;
+        let replacement = if lines[body].starts_with('A') { "B" } else { "A" };
+        lines[body].replace_range(0..1, replacement);
+        let err = dearmor(lines.join("\n").as_bytes()).err().unwrap();
+        assert!(format!("{}", err).contains("checksum"));
+    }
+
+    #[test]
+    fn armor_needs_end_line() {
+        let armored = armor(SIGNATURE, &[], b"hello");
+        let truncated = armored.replace(&format!("-----END {}-----", SIGNATURE), "");
+        assert!(dearmor(truncated.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn strip_passes_through_unarmored() {
+        let binary = vec![0u8, 159, 146, 150, 1, 2, 3];
+        assert_eq!(strip(binary.clone()).unwrap(), binary);
+        let clearsigned =
+            b"-----BEGIN STAMP SIGNED MESSAGE-----\nhello\n-----BEGIN STAMP SIGNATURE-----\nAAAA\n-----END STAMP SIGNATURE-----\n";
+        assert!(dearmor(clearsigned).unwrap().is_none());
+        assert_eq!(strip(clearsigned.to_vec()).unwrap(), clearsigned.to_vec());
+    }
+}
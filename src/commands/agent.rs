@@ -6,6 +6,42 @@ use stamp_core::crypto::base::SecretKey;
 use tokio::{sync::mpsc as channel, task};
 use tracing::warn;
 
+// TODO: chat bridge mode. Once the agent runs again, add an optional task here
+// that joins a Matrix room (or Slack app) and answers requests like "verify this
+// signature" or "show identity X" using `commands::sign::verify` and the local
+// identity store, posting replies signed by a configured org identity. This needs
+// a Matrix/Slack client dependency and an unlocked signing key, so it should hang
+// off the same unlock flow as `UIMessage::UnlockIdentity`.
+//
+// TODO: blind relay operator mode. When running with a blind sync token on an
+// untrusted box, the relay should enforce per-channel storage quotas, a TTL on
+// stored messages, and an optional channel allow-list so it can't be used as free
+// encrypted storage. Storage for the sync listener is inside
+// `stamp_aux::sync::listen`, so the limits need to be passed through there.
+//
+// TODO: LAN discovery. Devices sharing a sync channel should find each other on
+// the local network with mDNS/DNS-SD instead of going through a public relay. The
+// advertised service name should be derived from the channel (not the identity
+// ID, which would leak who's on the network), and discovered peers get dialed and
+// added next to `sync_join`. libp2p ships an mDNS behaviour, so this is mostly a
+// matter of enabling it in stamp-net's swarm.
+//
+// TODO: guest tokens. Let the agent mint scoped, expiring tokens for local apps
+// (ex "verify signatures" or "decrypt messages to key X" for 24 hours) that the
+// local API checks before handling a request, plus `stamp agent tokens
+// list|revoke`. Tokens should be stored hashed, and revoking must take effect
+// without restarting the agent.
+//
+// TODO: staged transaction webhooks. `stage::notify_if_ready` shows a desktop
+// notification when a staged transaction we receive has all its signatures. The
+// agent should do the same for transactions arriving over sync while it runs,
+// and optionally POST to a configured webhook so a group identity's initiator
+// hears about it without a desktop session.
+//
+// TODO: Windows. The local API should listen on a named pipe
+// (\\.\pipe\stamp-agent-<user>) instead of a TCP port on Windows, with the pipe's
+// security descriptor limited to the current user, since localhost ports are
+// reachable by every user on the machine.
 /*
 pub fn run(bind: Multiaddr, sync_token: Option<SyncToken>, sync_join: Vec<Multiaddr>, agent_port: u32, agent_lock_after: u64, net: bool, net_join: Vec<Multiaddr>) -> Result<()> {
     tokio::runtime::Builder::new_current_thread()
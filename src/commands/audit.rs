@@ -0,0 +1,205 @@
+use crate::{commands::id, db, util};
+use anyhow::Result;
+use prettytable::Table;
+use stamp_core::{
+    crypto::private::MaybePrivate,
+    identity::{claim::ClaimSpec, Identity, IdentityID},
+};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+/// A claim value that more than one identity in the local store says belongs to
+/// them.
+pub struct Conflict {
+    pub ty: &'static str,
+    pub value: String,
+    pub identities: Vec<String>,
+}
+
+/// Pull out the public claim values that should only ever belong to one
+/// identity, normalized so trivial differences (case, trailing slashes) don't
+/// hide a conflict.
+fn unique_claims(identity: &Identity) -> Vec<(&'static str, String)> {
+    identity
+        .claims()
+        .iter()
+        .filter_map(|claim| match claim.spec() {
+            ClaimSpec::Email(MaybePrivate::Public(val)) => Some(("email", val.trim().to_lowercase())),
+            ClaimSpec::Domain(MaybePrivate::Public(val)) => Some(("domain", val.trim().trim_end_matches('.').to_lowercase())),
+            ClaimSpec::Url(MaybePrivate::Public(val)) => Some(("url", String::from(val.clone()).trim_end_matches('/').to_string())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Find claims in `identity` that are also claimed by one of `others`.
+pub fn conflicts_with(identity: &Identity, others: &Vec<Identity>) -> Vec<Conflict> {
+    let ours = unique_claims(identity);
+    let mut conflicts = Vec::new();
+    for (ty, value) in ours {
+        let identities = others
+            .iter()
+            .filter(|other| other.id() != identity.id())
+            .filter(|other| unique_claims(other).iter().any(|(t, v)| *t == ty && *v == value))
+            .filter_map(|other| id_str!(other.id()).ok())
+            .collect::<Vec<_>>();
+        if identities.len() > 0 {
+            conflicts.push(Conflict { ty, value, identities });
+        }
+    }
+    conflicts
+}
+
+/// Find all claim values claimed by more than one identity.
+pub fn find_conflicts(identities: &Vec<Identity>) -> Vec<Conflict> {
+    let mut index: BTreeMap<(&'static str, String), Vec<String>> = BTreeMap::new();
+    for identity in identities {
+        let id_str = match id_str!(identity.id()) {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+        for key in unique_claims(identity) {
+            let entry = index.entry(key).or_insert_with(|| Vec::new());
+            if !entry.contains(&id_str) {
+                entry.push(id_str.clone());
+            }
+        }
+    }
+    index
+        .into_iter()
+        .filter(|(_, ids)| ids.len() > 1)
+        .map(|((ty, value), identities)| Conflict { ty, value, identities })
+        .collect()
+}
+
+fn load_all() -> Result<Vec<Identity>> {
+    db::list_local_identities(None)?
+        .iter()
+        .map(|x| util::build_identity(x))
+        .collect::<Result<Vec<_>>>()
+}
+
+fn print_conflicts_table(conflicts: &Vec<Conflict>, verbose: bool) {
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(row!["Type", "Value", "Claimed by"]);
+    for conflict in conflicts {
+        let ids = conflict
+            .identities
+            .iter()
+            .map(|x| if verbose { x.clone() } else { IdentityID::short(x) })
+            .collect::<Vec<_>>()
+            .join(", ");
+        table.add_row(row![conflict.ty, conflict.value, ids]);
+    }
+    table.printstd();
+}
+
+/// Warn if an identity we're about to import claims an email/domain/url that a
+/// different identity in our store already claims. Returns true if any
+/// conflicts were found.
+pub fn warn_import_conflicts(identity: &Identity) -> Result<bool> {
+    let existing = load_all()?;
+    let conflicts = conflicts_with(identity, &existing);
+    if conflicts.len() == 0 {
+        return Ok(false);
+    }
+    let yellow = dialoguer::console::Style::new().yellow();
+    eprintln!(
+        "{} {}",
+        yellow.apply_to("Warning:"),
+        util::text_wrap("This identity makes claims that another identity in your store also makes. One of them may be an impersonation attempt.")
+    );
+    print_conflicts_table(&conflicts, false);
+    let mut involved = vec![identity.clone()];
+    for other in existing {
+        let other_id = id_str!(other.id())?;
+        if conflicts.iter().any(|c| c.identities.contains(&other_id)) {
+            involved.push(other);
+        }
+    }
+    println!("");
+    id::print_identities_table(&involved, false);
+    println!("");
+    Ok(true)
+}
+
+/// Fold characters that are easy to confuse visually into a single form, so
+/// IDs like `0l1O...` and `Ol10...` compare equal.
+fn homoglyph_fold(val: &str) -> String {
+    val.chars()
+        .map(|c| match c {
+            'O' | 'o' | 'Q' | 'D' => '0',
+            'l' | 'I' | 'i' | 'j' | '|' => '1',
+            'Z' | 'z' => '2',
+            'S' | 's' => '5',
+            'B' => '8',
+            'G' => '6',
+            'q' | 'g' => '9',
+            'u' | 'U' => 'v',
+            '_' => '-',
+            'm' => 'n',
+            c => c.to_ascii_lowercase(),
+        })
+        .collect()
+}
+
+/// Look for signs that `identity` is trying to pass itself off as one of
+/// `others`: the same display name under a different ID, or an ID that looks
+/// like a known one at a glance.
+pub fn impersonation_warnings(identity: &Identity, others: &Vec<Identity>) -> Result<Vec<String>> {
+    let id_str = id_str!(identity.id())?;
+    let id_short = IdentityID::short(&id_str);
+    let names = identity.names().iter().map(|x| x.trim().to_lowercase()).collect::<Vec<_>>();
+    let mut warnings = Vec::new();
+    for other in others {
+        if other.id() == identity.id() {
+            continue;
+        }
+        let other_str = id_str!(other.id())?;
+        let other_short = IdentityID::short(&other_str);
+        let shared_name = other
+            .names()
+            .iter()
+            .find(|x| names.contains(&x.trim().to_lowercase()))
+            .map(|x| x.clone());
+        if let Some(name) = shared_name {
+            warnings.push(format!("The name \"{}\" is already used by a different identity ({})", name, other_short));
+        }
+        if id_short == other_short {
+            warnings.push(format!("The ID starts the same as a different identity ({}) but differs after that", other_short));
+        } else if homoglyph_fold(&id_short) == homoglyph_fold(&other_short) {
+            warnings.push(format!(
+                "The ID {} looks very similar to a different identity's ID ({})",
+                id_short, other_short
+            ));
+        }
+    }
+    Ok(warnings)
+}
+
+/// Warn if an identity we're about to import looks like it's impersonating one
+/// we already have. Returns true if anything suspicious was found.
+pub fn warn_impersonation(identity: &Identity) -> Result<bool> {
+    let warnings = impersonation_warnings(identity, &load_all()?)?;
+    if warnings.len() == 0 {
+        return Ok(false);
+    }
+    let yellow = dialoguer::console::Style::new().yellow();
+    for warning in warnings {
+        eprintln!("{} {}", yellow.apply_to("Warning:"), util::text_wrap(&warning));
+    }
+    Ok(true)
+}
+
+/// List all claims in the local store that are claimed by more than one
+/// identity.
+pub fn conflicts(verbose: bool) -> Result<()> {
+    let conflicts = find_conflicts(&load_all()?);
+    if conflicts.len() == 0 {
+        println!("No conflicting claims found.");
+        return Ok(());
+    }
+    print_conflicts_table(&conflicts, verbose);
+    Ok(())
+}
@@ -1,13 +1,13 @@
 use crate::{
-    commands::{dag, id, stamp},
-    config, db, util,
+    commands::{dag, id, keychain, stage, stamp},
+    config, db, dns, photo, util,
 };
 use anyhow::{anyhow, Result};
 use prettytable::Table;
-use stamp_aux;
+use stamp_aux::{self, db::find_staged_transactions};
 use stamp_core::{
     crypto::{
-        base::{rng, SecretKey},
+        base::{rng, Hash, SecretKey},
         private::MaybePrivate,
     },
     dag::{TransactionID, Transactions},
@@ -17,12 +17,58 @@ use stamp_core::{
         Identity, IdentityID,
     },
     rasn::{Decode, Encode},
-    util::{BinaryVec, Date, Public, SerText, Timestamp, Url},
+    util::{base64_encode, BinaryVec, Date, Public, SerText, Timestamp, Url},
 };
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::io::IsTerminal;
 use std::ops::Deref;
 use std::str::FromStr;
 
+/// Calling codes for the regions we know how to normalize national phone numbers
+/// for. Not exhaustive, but numbers entered with a leading `+` or `00` work for
+/// any region.
+const PHONE_REGIONS: &[(&str, &str)] = &[
+    ("US", "1"),
+    ("CA", "1"),
+    ("GB", "44"),
+    ("IE", "353"),
+    ("DE", "49"),
+    ("FR", "33"),
+    ("ES", "34"),
+    ("IT", "39"),
+    ("NL", "31"),
+    ("BE", "32"),
+    ("CH", "41"),
+    ("AT", "43"),
+    ("SE", "46"),
+    ("NO", "47"),
+    ("DK", "45"),
+    ("FI", "358"),
+    ("PL", "48"),
+    ("PT", "351"),
+    ("AU", "61"),
+    ("NZ", "64"),
+    ("JP", "81"),
+    ("KR", "82"),
+    ("CN", "86"),
+    ("IN", "91"),
+    ("BR", "55"),
+    ("MX", "52"),
+    ("AR", "54"),
+    ("ZA", "27"),
+];
+
+/// The raw (as-entered) form of a phone claim, saved locally alongside the
+/// normalized value stored in the claim itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhoneRecord {
+    pub raw: String,
+    pub normalized: String,
+    pub region: Option<String>,
+}
+
 fn prompt_claim_value(prompt: &str) -> Result<String> {
     let value: String = dialoguer::Input::new()
         .with_prompt(prompt)
@@ -44,12 +90,728 @@ pub(crate) fn claim_pre_noval(id: &str) -> Result<(SecretKey, Transactions)> {
     Ok((master_key, transactions))
 }
 
-pub(crate) fn claim_pre(id: &str, prompt: &str) -> Result<(SecretKey, Transactions, String)> {
+/// Unlock an identity and get the value for a new claim, prompting for the value
+/// unless one was given on the command line.
+pub(crate) fn claim_pre(id: &str, prompt: &str, value: Option<String>) -> Result<(SecretKey, Transactions, String)> {
     let (master_key, transactions) = claim_pre_noval(id)?;
-    let value = prompt_claim_value(prompt)?;
+    let value = match value {
+        Some(value) => value,
+        None => prompt_claim_value(prompt)?,
+    };
     Ok((master_key, transactions, value))
 }
 
+/// Normalize a phone number to E.164 (ex +15551234567). Numbers without a country
+/// code are assumed to be in `region`.
+pub(crate) fn normalize_phone(raw: &str, region: Option<&str>) -> Result<String> {
+    let trimmed = raw.trim();
+    let digits = trimmed.chars().filter(|c| c.is_ascii_digit()).collect::<String>();
+    let international = if trimmed.starts_with('+') {
+        digits
+    } else if digits.starts_with("00") {
+        String::from(&digits[2..])
+    } else {
+        let region = region.ok_or(anyhow!(
+            "The phone number {} has no country code. Either enter it as +<country code><number> or set a default region with `stamp config set-phone-region`",
+            raw
+        ))?;
+        let code = PHONE_REGIONS
+            .iter()
+            .find(|(r, _)| r.eq_ignore_ascii_case(region))
+            .map(|(_, code)| *code)
+            .ok_or(anyhow!("Unknown phone region: {}", region))?;
+        let national = if code == "1" {
+            digits.strip_prefix('1').filter(|x| x.len() == 10).unwrap_or(&digits)
+        } else {
+            digits.trim_start_matches('0')
+        };
+        format!("{}{}", code, national)
+    };
+    if international.len() < 8 || international.len() > 15 {
+        Err(anyhow!("Invalid phone number: {}", raw))?;
+    }
+    Ok(format!("+{}", international))
+}
+
+/// Returns true if any phone claim in the identity matches the given
+/// (normalized) number. Private claims are matched against the normalized form
+/// saved locally when they were made (see [PhoneRecord]), so nothing needs to be
+/// decrypted.
+pub(crate) fn has_phone(identity: &Identity, normalized: &str, region: Option<&str>) -> bool {
+    identity.claims().iter().any(|claim| match claim.spec() {
+        ClaimSpec::PhoneNumber(MaybePrivate::Public(number)) => {
+            normalize_phone(number, region).map(|x| x == normalized).unwrap_or(false)
+        }
+        ClaimSpec::PhoneNumber(_) => id_str!(claim.id())
+            .ok()
+            .and_then(|claim_id| db::load_record::<PhoneRecord>("phone", &claim_id).ok().flatten())
+            .map(|record| record.normalized == normalized)
+            .unwrap_or(false),
+        _ => false,
+    })
+}
+
+/// Create a new phone claim, normalizing the number to E.164 and refusing to
+/// create a duplicate of an existing phone claim.
+pub fn new_phone(id: &str, value: Option<String>, private: bool, name: Option<&str>, stage: bool, sign_with: Option<&str>) -> Result<()> {
+    let hash_with = config::hash_algo(Some(&id));
+    let region = config::load_cli()?.phone_region;
+    let (master_key, transactions, raw) = claim_pre(id, "Enter your phone number", value)?;
+    let normalized = normalize_phone(&raw, region.as_ref().map(|x| x.as_str()))?;
+    let identity = util::build_identity(&transactions)?;
+    if has_phone(&identity, &normalized, region.as_ref().map(|x| x.as_str())) {
+        Err(anyhow!("This identity already has a claim for the phone number {}", normalized))?;
+    }
+    if normalized != raw.trim() {
+        println!("Normalized phone number {} to {}", raw.trim(), normalized);
+    }
+    let trans = stamp_aux::claim::new_phone(&master_key, &transactions, &hash_with, normalized.clone(), private, name)
+        .map_err(|e| anyhow!("Problem adding claim: {}", e))?;
+    let signed = util::sign_helper(&identity, trans, &master_key, stage, sign_with)?;
+    let claim_id = id_str!(signed.id())?;
+    dag::save_or_stage(transactions, signed, stage)?;
+    let record = PhoneRecord {
+        raw: String::from(raw.trim()),
+        normalized,
+        region,
+    };
+    db::save_record("phone", &claim_id, &record)?;
+    Ok(())
+}
+
+/// A structured postal address. Addresses are stored in the claim as canonical
+/// `field: value` lines in a fixed order, which keeps the serialized claim stable
+/// and lets individual fields be pulled out (and eventually disclosed) on their own.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Address {
+    pub street: String,
+    pub city: String,
+    pub region: String,
+    pub postal_code: String,
+    pub country: String,
+}
+
+impl Address {
+    const FIELDS: [&'static str; 5] = ["street", "city", "region", "postal-code", "country"];
+
+    fn values(&self) -> [&String; 5] {
+        [&self.street, &self.city, &self.region, &self.postal_code, &self.country]
+    }
+
+    /// Prompt for each field of an address.
+    pub fn prompt() -> Result<Self> {
+        let field = |prompt: &str| -> Result<String> {
+            let value: String = dialoguer::Input::new()
+                .with_prompt(prompt)
+                .allow_empty(true)
+                .interact_text()
+                .map_err(|e| anyhow!("Error grabbing address value: {:?}", e))?;
+            Ok(String::from(value.trim()))
+        };
+        let address = Self {
+            street: field("Street address")?,
+            city: field("City")?,
+            region: field("State/province/region")?,
+            postal_code: field("Postal code")?,
+            country: field("Country")?,
+        };
+        if address.values().iter().all(|x| x.is_empty()) {
+            Err(anyhow!("Address cannot be empty"))?;
+        }
+        Ok(address)
+    }
+
+    /// Serialize the address into its canonical form.
+    pub fn to_canonical(&self) -> String {
+        Self::FIELDS
+            .iter()
+            .zip(self.values().iter())
+            .filter(|(_, val)| !val.is_empty())
+            .map(|(field, val)| format!("{}: {}", field, val.replace('\n', " ")))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Parse a canonical address. Returns `None` for free-text addresses (ie, those
+    /// created before addresses were structured).
+    pub fn from_canonical(canonical: &str) -> Option<Self> {
+        let mut address = Self::default();
+        for line in canonical.lines() {
+            let (field, val) = line.split_once(": ")?;
+            let val = String::from(val);
+            match field {
+                "street" => address.street = val,
+                "city" => address.city = val,
+                "region" => address.region = val,
+                "postal-code" => address.postal_code = val,
+                "country" => address.country = val,
+                _ => return None,
+            }
+        }
+        Some(address)
+    }
+
+    /// Render the address on a single line.
+    pub fn to_line(&self) -> String {
+        let region_postal = [self.region.as_str(), self.postal_code.as_str()]
+            .iter()
+            .filter(|x| !x.is_empty())
+            .map(|x| *x)
+            .collect::<Vec<_>>()
+            .join(" ");
+        [self.street.as_str(), self.city.as_str(), region_postal.as_str(), self.country.as_str()]
+            .iter()
+            .filter(|x| !x.is_empty())
+            .map(|x| *x)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Render an address claim value for display, falling back to the raw value for
+/// free-text addresses.
+pub(crate) fn address_to_line(value: &str) -> String {
+    Address::from_canonical(value).map(|x| x.to_line()).unwrap_or_else(|| String::from(value))
+}
+
+/// Create a new address claim using structured entry.
+pub fn new_address(id: &str, value: Option<String>, private: bool, name: Option<&str>, stage: bool, sign_with: Option<&str>) -> Result<()> {
+    let hash_with = config::hash_algo(Some(&id));
+    let (master_key, transactions) = claim_pre_noval(id)?;
+    let address = match value {
+        Some(value) => Address::from_canonical(&value).ok_or(anyhow!(
+            "Addresses given with --value must be `field: value` lines with the fields: {}",
+            Address::FIELDS.join(", ")
+        ))?,
+        None => Address::prompt()?,
+    };
+    let trans = stamp_aux::claim::new_address(&master_key, &transactions, &hash_with, address.to_canonical(), private, name)
+        .map_err(|e| anyhow!("Problem adding claim: {}", e))?;
+    let identity = util::build_identity(&transactions)?;
+    let signed = util::sign_helper(&identity, trans, &master_key, stage, sign_with)?;
+    dag::save_or_stage(transactions, signed, stage)?;
+    Ok(())
+}
+
+/// The social platforms we know how to build profile URLs for and fetch proofs
+/// from.
+pub(crate) const SOCIAL_PLATFORMS: &[&str] = &["github", "mastodon", "reddit"];
+
+/// Build the canonical profile URL for a well-known service, returning the URL
+/// and a default claim name.
+pub(crate) fn service_url(service: &str, username: &str) -> Result<(String, String)> {
+    let username = username.trim().trim_start_matches('@');
+    if username.len() == 0 {
+        Err(anyhow!("Username cannot be empty"))?;
+    }
+    match service {
+        "github" => Ok((format!("https://github.com/{}", username), String::from("github"))),
+        "mastodon" => {
+            let (user, instance) = username
+                .split_once('@')
+                .ok_or(anyhow!("Mastodon usernames must include the instance, ex: user@mastodon.social"))?;
+            Ok((format!("https://{}/@{}", instance, user), String::from("mastodon")))
+        }
+        "reddit" => Ok((format!("https://www.reddit.com/user/{}", username.trim_start_matches("u/")), String::from("reddit"))),
+        _ => Err(anyhow!("Unknown platform {} (expected {})", service, SOCIAL_PLATFORMS.join(", "))),
+    }
+}
+
+/// Work out which platform (and username on that platform) a profile URL
+/// belongs to. Mastodon is federated, so any `https://<instance>/@<user>` URL is
+/// treated as a Mastodon profile.
+pub(crate) fn platform_for_url(url: &str) -> Option<(&'static str, String)> {
+    let parsed = url::Url::parse(url).ok()?;
+    let host = parsed.host_str()?.trim_start_matches("www.");
+    let segments = parsed.path_segments()?.filter(|x| x.len() > 0).collect::<Vec<_>>();
+    match (host, segments.as_slice()) {
+        ("github.com", [user]) => Some(("github", String::from(*user))),
+        ("reddit.com", ["user", user]) | ("reddit.com", ["u", user]) => Some(("reddit", String::from(*user))),
+        (instance, [user]) if user.starts_with('@') && user.len() > 1 => {
+            Some(("mastodon", format!("{}@{}", user.trim_start_matches('@'), instance)))
+        }
+        _ => None,
+    }
+}
+
+/// The exact text to publish on a platform for a claim to be checked, and where
+/// to put it.
+fn social_proof(platform: &str, username: &str, id_str: &str, value: &str) -> (String, String) {
+    let content = format!("Verifying my Stamp identity {}\n\n{}\n", IdentityID::short(id_str), value);
+    let instructions = match platform {
+        "github" => format!(
+            "Create a public gist at https://gist.github.com/ containing a file named {} with the content below (or add it to the README.md of your {}/{} profile repository):",
+            GITHUB_PROOF_FILE, username, username
+        ),
+        "mastodon" => String::from("Publish a public post with the content below, or paste the last line into your bio or one of your profile metadata fields:"),
+        "reddit" => String::from("Make a post on your profile with the content below, or paste the last line into your profile's \"About\" description:"),
+        _ => String::from("Publish the content below on your profile:"),
+    };
+    (instructions, content)
+}
+
+/// The file name `claim check` looks for in a GitHub user's public gists.
+const GITHUB_PROOF_FILE: &str = "stamp-proof.md";
+
+fn http_get(url: &str) -> Result<String> {
+    stamp_aux::util::http_get(url).map_err(|e| anyhow!("Problem fetching {}: {}", url, e))
+}
+
+fn http_get_json(url: &str) -> Result<serde_json::Value> {
+    let body = http_get(url)?;
+    serde_json::from_str(&body).map_err(|e| anyhow!("Problem parsing response from {}: {}", url, e))
+}
+
+/// Fetch every place a platform lets a user publish a proof, returning the
+/// location and its contents. Locations that can't be fetched are skipped, but
+/// if none can be fetched the last error is returned.
+fn fetch_social_proofs(platform: &str, username: &str) -> Result<Vec<(String, String)>> {
+    let mut found = Vec::new();
+    let mut last_err = None;
+    let mut push = |location: String, res: Result<String>| match res {
+        Ok(content) => found.push((location, content)),
+        Err(e) => last_err = Some(e),
+    };
+    match platform {
+        "github" => {
+            let gists_url = format!("https://api.github.com/users/{}/gists", username);
+            match http_get_json(&gists_url) {
+                Ok(gists) => {
+                    for gist in gists.as_array().map(|x| x.as_slice()).unwrap_or(&[]) {
+                        let raw_url = gist["files"][GITHUB_PROOF_FILE]["raw_url"].as_str();
+                        if let Some(raw_url) = raw_url {
+                            let location = gist["html_url"].as_str().unwrap_or(raw_url).to_string();
+                            push(location, http_get(raw_url));
+                        }
+                    }
+                }
+                Err(e) => push(gists_url, Err(e)),
+            }
+            let readme_url = format!("https://raw.githubusercontent.com/{}/{}/HEAD/README.md", username, username);
+            push(format!("https://github.com/{}/{}", username, username), http_get(&readme_url));
+        }
+        "mastodon" => {
+            let (user, instance) = username.split_once('@').ok_or(anyhow!("Invalid Mastodon username: {}", username))?;
+            let lookup_url = format!("https://{}/api/v1/accounts/lookup?acct={}", instance, user);
+            let account = http_get_json(&lookup_url)?;
+            let mut profile = vec![account["note"].as_str().unwrap_or("").to_string()];
+            for field in account["fields"].as_array().map(|x| x.as_slice()).unwrap_or(&[]) {
+                profile.push(field["value"].as_str().unwrap_or("").to_string());
+            }
+            push(format!("https://{}/@{}", instance, user), Ok(profile.join("\n")));
+            if let Some(account_id) = account["id"].as_str() {
+                let statuses_url = format!("https://{}/api/v1/accounts/{}/statuses?limit=40&exclude_reblogs=true", instance, account_id);
+                match http_get_json(&statuses_url) {
+                    Ok(statuses) => {
+                        for status in statuses.as_array().map(|x| x.as_slice()).unwrap_or(&[]) {
+                            let location = status["url"].as_str().unwrap_or(&statuses_url).to_string();
+                            push(location, Ok(status["content"].as_str().unwrap_or("").to_string()));
+                        }
+                    }
+                    Err(e) => push(statuses_url, Err(e)),
+                }
+            }
+        }
+        "reddit" => {
+            let about_url = format!("https://www.reddit.com/user/{}/about.json", username);
+            match http_get_json(&about_url) {
+                Ok(about) => {
+                    let desc = about["data"]["subreddit"]["public_description"].as_str().unwrap_or("").to_string();
+                    push(format!("https://www.reddit.com/user/{}", username), Ok(desc));
+                }
+                Err(e) => push(about_url, Err(e)),
+            }
+            let posts_url = format!("https://www.reddit.com/user/{}/submitted.json?limit=50", username);
+            match http_get_json(&posts_url) {
+                Ok(posts) => {
+                    for post in posts["data"]["children"].as_array().map(|x| x.as_slice()).unwrap_or(&[]) {
+                        let data = &post["data"];
+                        let location = data["permalink"]
+                            .as_str()
+                            .map(|x| format!("https://www.reddit.com{}", x))
+                            .unwrap_or_else(|| posts_url.clone());
+                        let content = format!("{}\n{}", data["title"].as_str().unwrap_or(""), data["selftext"].as_str().unwrap_or(""));
+                        push(location, Ok(content));
+                    }
+                }
+                Err(e) => push(posts_url, Err(e)),
+            }
+        }
+        _ => Err(anyhow!("Unknown platform {}", platform))?,
+    }
+    if found.len() == 0 {
+        Err(last_err.unwrap_or_else(|| anyhow!("No proof locations found on {}", platform)))?;
+    }
+    Ok(found)
+}
+
+/// Check a social profile claim by fetching the platform's proof locations
+/// directly (gists, posts, bios) rather than scraping the profile page. Returns
+/// the location the proof was found at.
+fn check_social(identity: &Identity, claim: &Claim, platform: &str, username: &str) -> Result<String> {
+    let allowed = claim
+        .instant_verify_allowed_values(identity.id())
+        .map_err(|e| anyhow!("Problem grabbing allowed claim values: {}", e))?;
+    let proofs = fetch_social_proofs(platform, username)?;
+    proofs
+        .into_iter()
+        .find(|(_, content)| allowed.iter().any(|val| content.contains(val.as_str())))
+        .map(|(location, _)| location)
+        .ok_or_else(|| anyhow!("No proof for this claim was found on {} for {}", platform, username))
+}
+
+pub fn new_social(
+    id: &str,
+    platform: &str,
+    username: &str,
+    private: bool,
+    name: Option<&str>,
+    stage: bool,
+    sign_with: Option<&str>,
+) -> Result<()> {
+    let (url, default_name) = service_url(platform, username)?;
+    let hash_with = config::hash_algo(Some(&id));
+    let (master_key, transactions) = claim_pre_noval(id)?;
+    let name = name.unwrap_or(&default_name);
+    let trans = stamp_aux::claim::new_url(&master_key, &transactions, &hash_with, url.clone(), private, Some(name))
+        .map_err(|e| anyhow!("Problem adding claim: {}", e))?;
+    let identity = util::build_identity(&transactions)?;
+    let signed = util::sign_helper(&identity, trans, &master_key, stage, sign_with)?;
+    let claim_id: ClaimID = signed.id().clone().into();
+    let (transactions, _) = dag::save_or_stage(transactions, signed, stage)?;
+    if stage || private {
+        return Ok(());
+    }
+    let identity = util::build_identity(&transactions)?;
+    let claim = identity
+        .claims()
+        .iter()
+        .find(|c| c.id() == &claim_id)
+        .ok_or_else(|| anyhow!("Unable to find created claim"))?;
+    let allowed = claim
+        .instant_verify_allowed_values(identity.id())
+        .map_err(|e| anyhow!("Problem grabbing allowed claim values: {}", e))?;
+    let id_str = id_str!(identity.id())?;
+    let (_, username) = platform_for_url(&url).ok_or_else(|| anyhow!("Unable to parse profile URL {}", url))?;
+    let (instructions, content) = social_proof(platform, &username, &id_str, &allowed[0]);
+    println!("\n{}\n", util::text_wrap(&instructions));
+    println!("{}", content);
+    println!(
+        "{}",
+        util::text_wrap(&format!(
+            "Once published, anyone can check it with:\n  stamp claim check {}",
+            ClaimID::short(&id_str!(&claim_id)?)
+        ))
+    );
+    Ok(())
+}
+
+/// Render the key/value of a custom (extension) claim. Values that aren't text
+/// are shown by size.
+pub(crate) fn extension_to_string(key: &BinaryVec, value: &BinaryVec) -> String {
+    let key = String::from_utf8_lossy(key.as_slice());
+    match std::str::from_utf8(value.as_slice()) {
+        Ok(text) => format!("{}: {}", key, text),
+        Err(_) => format!("{}: <{} bytes>", key, value.len()),
+    }
+}
+
+/// Create a custom claim: an application-defined `kind` with arbitrary data.
+/// Useful for things Stamp doesn't have a claim type for (a Matrix handle, a
+/// payment address, etc).
+pub fn new_custom(
+    id: &str,
+    kind: &str,
+    value: Option<Vec<u8>>,
+    private: bool,
+    name: Option<&str>,
+    stage: bool,
+    sign_with: Option<&str>,
+) -> Result<()> {
+    if kind.trim().len() == 0 {
+        Err(anyhow!("A custom claim needs a kind (ex \"matrix\")"))?;
+    }
+    let hash_with = config::hash_algo(Some(&id));
+    let (master_key, transactions) = claim_pre_noval(id)?;
+    let value = match value {
+        Some(value) => value,
+        None => Vec::from(prompt_claim_value(&format!("Enter the value for your {} claim", kind))?.as_bytes()),
+    };
+    let value = if private {
+        let mut rng = rng::chacha20();
+        MaybePrivate::new_private(&mut rng, &master_key, BinaryVec::from(value))
+            .map_err(|e| anyhow!("Problem encrypting claim value: {}", e))?
+    } else {
+        MaybePrivate::new_public(BinaryVec::from(value))
+    };
+    let spec = ClaimSpec::Extension {
+        key: BinaryVec::from(Vec::from(kind.trim().as_bytes())),
+        value,
+    };
+    let trans = transactions
+        .make_claim(&hash_with, Timestamp::now(), spec, name)
+        .map_err(|e| anyhow!("Problem adding claim: {}", e))?;
+    let identity = util::build_identity(&transactions)?;
+    let signed = util::sign_helper(&identity, trans, &master_key, stage, sign_with)?;
+    dag::save_or_stage(transactions, signed, stage)?;
+    Ok(())
+}
+
+/// When a claim expires. Claims themselves have no expiration, so this is kept
+/// as local metadata for the claims we make, keyed by claim ID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ClaimExpiry {
+    pub identity: String,
+    pub expires: Timestamp,
+}
+
+/// Expiration dates for all claims we know about, by claim ID.
+pub(crate) fn claim_expiries() -> Result<HashMap<String, Timestamp>> {
+    Ok(db::list_records::<ClaimExpiry>("claim-expiry")?
+        .into_iter()
+        .map(|(claim_id, expiry)| (claim_id, expiry.expires))
+        .collect())
+}
+
+fn set_expiry(identity_id: &str, claim_id: &str, expires: &Timestamp) -> Result<()> {
+    db::save_record(
+        "claim-expiry",
+        claim_id,
+        &ClaimExpiry {
+            identity: identity_id.into(),
+            expires: expires.clone(),
+        },
+    )
+}
+
+/// IDs of an identity's claims, plus its staged transactions (a staged claim's ID
+/// is its transaction ID). Used to spot the claim a command just created.
+pub(crate) fn known_claim_ids(id: &str) -> Result<Vec<String>> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let mut ids = identity.claims().iter().map(|c| id_str!(c.id())).collect::<Result<Vec<_>>>()?;
+    for staged in find_staged_transactions(identity.id()).map_err(|e| anyhow!("Error loading staged transactions: {:?}", e))? {
+        ids.push(format!("{}", staged.id()));
+    }
+    Ok(ids)
+}
+
+/// Set an expiration on whatever claims were created since `known` was taken.
+pub(crate) fn expire_new_claims(id: &str, known: &Vec<String>, expires: &Timestamp) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity_id = transactions.identity_id().ok_or_else(|| anyhow!("Identity {} not found", id))?;
+    for claim_id in known_claim_ids(id)?.into_iter().filter(|x| !known.contains(x)) {
+        set_expiry(&id_str!(&identity_id)?, &claim_id, expires)?;
+        println!("Claim {} expires {}", ClaimID::short(&claim_id), expires.local().format("%b %d, %Y"));
+    }
+    Ok(())
+}
+
+/// Re-issue a claim (same value and name) with a new expiration, then request
+/// stamps on the new claim from everyone who stamped the old one. Requests to
+/// identities we own go straight to their inbox, the rest are written to
+/// `output` (a directory) to be sent along.
+pub fn renew(
+    id: &str,
+    claim_search: &str,
+    expires: Option<Timestamp>,
+    key_from_search: Option<&str>,
+    output: &str,
+    stage: bool,
+    sign_with: Option<&str>,
+) -> Result<()> {
+    let hash_with = config::hash_algo(Some(&id));
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let claim = identity
+        .claims()
+        .iter()
+        .find(|x| {
+            id_str!(x.id()).map(|x| x.starts_with(claim_search)).unwrap_or(false)
+                || x.name().as_ref().map(|x| x == claim_search).unwrap_or(false)
+        })
+        .ok_or_else(|| anyhow!("Cannot find claim {}", claim_search))?
+        .clone();
+    let old_claim_id = id_str!(claim.id())?;
+    let master_key =
+        util::passphrase_prompt(&format!("Your master passphrase for identity {}", IdentityID::short(&id_str)), identity.created())?;
+    transactions
+        .test_master_key(&master_key)
+        .map_err(|e| anyhow!("Incorrect passphrase: {:?}", e))?;
+    let trans = transactions
+        .make_claim(&hash_with, Timestamp::now(), claim.spec().clone(), claim.name().as_deref())
+        .map_err(|e| anyhow!("Problem re-issuing claim: {}", e))?;
+    let signed = util::sign_helper(&identity, trans, &master_key, stage, sign_with)?;
+    let new_claim_id: ClaimID = signed.id().clone().into();
+    let new_claim_id_str = id_str!(&new_claim_id)?;
+    let (transactions, _) = dag::save_or_stage(transactions, signed, stage)?;
+    if let Some(expires) = expires.as_ref() {
+        set_expiry(&id_str, &new_claim_id_str, expires)?;
+    }
+    println!(
+        "{}",
+        util::text_wrap(&format!(
+            "Claim {} re-issued as {}. Once it has been re-stamped you can remove the old claim with:\n  stamp claim delete {}",
+            ClaimID::short(&old_claim_id),
+            ClaimID::short(&new_claim_id_str),
+            ClaimID::short(&old_claim_id)
+        ))
+    );
+    if stage {
+        println!("{}", util::text_wrap("The new claim is staged, so no stamp requests were made. Use `stamp claim renew` again once it's published, or `stamp stamp req`."));
+        return Ok(());
+    }
+
+    let mut stampers: Vec<IdentityID> = Vec::new();
+    for stamp in claim.stamps() {
+        if stamp.revocation().is_none() && !stampers.contains(stamp.entry().stamper()) {
+            stampers.push(stamp.entry().stamper().clone());
+        }
+    }
+    if stampers.len() == 0 {
+        println!("The old claim had no stamps, so there's nobody to ask for a new one.");
+        return Ok(());
+    }
+    let identity = util::build_identity(&transactions)?;
+    let new_claim = identity
+        .claims()
+        .iter()
+        .find(|c| c.id() == &new_claim_id)
+        .ok_or_else(|| anyhow!("Unable to find re-issued claim"))?;
+    let key_from = keychain::find_keys_by_search_or_prompt(&identity, key_from_search, "crypto", |sub| sub.key().as_cryptokey())?;
+    let mut artifacts = Vec::new();
+    for stamper_id in stampers {
+        let stamper_str = id_str!(&stamper_id)?;
+        let stamper = match db::load_identity(&stamper_id)? {
+            Some(stamper_transactions) => util::build_identity(&stamper_transactions)?,
+            None => {
+                println!("Skipping stamper {}: their identity isn't imported.", IdentityID::short(&stamper_str));
+                continue;
+            }
+        };
+        let key_to = match keychain::find_keys_by_search_or_prompt(&stamper, None, "crypto", |sub| sub.key().as_cryptokey()) {
+            Ok(key) => key,
+            Err(e) => {
+                println!("Skipping stamper {}: {}", IdentityID::short(&stamper_str), e);
+                continue;
+            }
+        };
+        let req = stamp::request_message(&master_key, &identity, &key_from, &key_to, new_claim)?;
+        artifacts.push(util::Artifact::new(
+            format!("stamp-request-{}.b64", IdentityID::short(&stamper_str)),
+            "stamp-request",
+            Vec::from(base64_encode(req.as_slice()).as_bytes()),
+        ));
+    }
+    if artifacts.len() > 0 {
+        let meta = serde_json::json!({
+            "identity": id_str,
+            "claim": new_claim_id_str,
+            "renews": old_claim_id,
+        });
+        util::write_artifacts(output, &artifacts, meta)?;
+        println!(
+            "{}",
+            util::text_wrap("Send each request to its stamper. They can open it with `stamp stamp open-req`.")
+        );
+    }
+    Ok(())
+}
+
+/// The custom claim kind used for file hash claims.
+pub(crate) const FILE_CLAIM_KIND: &str = "file";
+
+/// What a file claim stores: enough to recognize the file later without
+/// keeping the file itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct FileClaim {
+    pub filename: String,
+    pub size: u64,
+    pub blake3: String,
+}
+
+/// Claim a file by its hash, for publishing ownership or authorship of a
+/// document. The claim is named after the file unless a name is given.
+pub fn new_file(id: &str, path: &str, private: bool, name: Option<&str>, stage: bool, sign_with: Option<&str>) -> Result<()> {
+    let bytes = util::load_file(path)?;
+    let hash = Hash::new_blake3(bytes.as_slice()).map_err(|e| anyhow!("Problem hashing file: {}", e))?;
+    let filename = std::path::Path::new(path)
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .ok_or_else(|| anyhow!("Invalid file path: {}", path))?;
+    let file_claim = FileClaim {
+        filename: filename.clone(),
+        size: bytes.len() as u64,
+        blake3: format!("{}", hash),
+    };
+    let value = serde_json::to_vec(&file_claim)?;
+    new_custom(id, FILE_CLAIM_KIND, Some(value), private, Some(name.unwrap_or(&filename)), stage, sign_with)
+}
+
+/// Check that a file matches a file claim.
+pub fn check_file(claim_id: &str, path: &str) -> Result<()> {
+    let transactions =
+        db::find_identity_by_prefix("claim", claim_id)?.ok_or(anyhow!("Identity with claim id {} was not found", claim_id))?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let claim = identity
+        .claims()
+        .iter()
+        .find(|x| id_str!(x.id()).map(|x| x.starts_with(claim_id)).ok() == Some(true))
+        .ok_or(anyhow!("Couldn't find the claim {} in identity {}", claim_id, IdentityID::short(&id_str)))?;
+    let claim_id_str = id_str!(claim.id())?;
+    let value = match claim.spec() {
+        ClaimSpec::Extension { key, value } if key.as_slice() == FILE_CLAIM_KIND.as_bytes() => value,
+        _ => Err(anyhow!("The claim {} is not a file claim", ClaimID::short(&claim_id_str)))?,
+    };
+    if value.has_private() && !identity.is_owned() {
+        Err(anyhow!("You cannot check private claims on an identity you don't own."))?;
+    }
+    let value = unwrap_maybe(value, || {
+        let master_key =
+            util::passphrase_prompt(format!("Your master passphrase for identity {}", IdentityID::short(&id_str)), identity.created())?;
+        identity
+            .test_master_key(&master_key)
+            .map_err(|e| anyhow!("Incorrect passphrase: {:?}", e))?;
+        Ok(master_key)
+    })?;
+    let file_claim: FileClaim = serde_json::from_slice(value.as_slice())
+        .map_err(|e| anyhow!("Problem reading file claim {}: {}", ClaimID::short(&claim_id_str), e))?;
+    let created = claims_with_created(&transactions, std::iter::once(claim))?
+        .pop()
+        .map(|(_, ts)| ts.local().format("%b %d, %Y").to_string())
+        .unwrap_or_default();
+    let bytes = util::load_file(path)?;
+    let hash = Hash::new_blake3(bytes.as_slice()).map_err(|e| anyhow!("Problem hashing file: {}", e))?;
+    if format!("{}", hash) == file_claim.blake3 && bytes.len() as u64 == file_claim.size {
+        let green = dialoguer::console::Style::new().green();
+        println!("\nThe file {} the claim {}!\n", green.apply_to("matches"), ClaimID::short(&claim_id_str));
+        println!(
+            "{}",
+            util::text_wrap(&format!(
+                "The identity {} claimed this file ({}, {} bytes) on {}.",
+                IdentityID::short(&id_str),
+                file_claim.filename,
+                file_claim.size,
+                created
+            ))
+        );
+        Ok(())
+    } else {
+        let red = dialoguer::console::Style::new().red();
+        println!("\nThe file {} the claim {}\n", red.apply_to("does not match"), ClaimID::short(&claim_id_str));
+        Err(anyhow!(
+            "The claim is for {} ({} bytes, {}), the given file is {} bytes with hash {}",
+            file_claim.filename,
+            file_claim.size,
+            file_claim.blake3,
+            bytes.len(),
+            hash
+        ))
+    }
+}
+
 fn unwrap_maybe<T, F>(maybe: &MaybePrivate<T>, masterkey_fn: F) -> Result<T>
 where
     T: Encode + Decode + Clone,
@@ -65,7 +827,27 @@ where
     }
 }
 
-pub fn check(claim_id: &str) -> Result<()> {
+/// Check a domain claim's TXT records with our own resolver, so a specific
+/// resolver, DoH, or DNSSEC can be used. Returns the domain.
+fn check_domain(identity: &Identity, claim: &Claim, domain: &str, settings: &dns::DnsSettings) -> Result<String> {
+    let allowed = claim
+        .instant_verify_allowed_values(identity.id())
+        .map_err(|e| anyhow!("Problem grabbing allowed claim values: {}", e))?;
+    let answer = dns::lookup_txt(settings, domain)?;
+    println!("Resolver: {}", settings.describe());
+    println!("DNSSEC:   {}", if answer.dnssec_validated { "validated" } else { "not checked" });
+    if answer
+        .records
+        .iter()
+        .any(|rec| allowed.iter().any(|val| rec.contains(val.as_str())))
+    {
+        Ok(String::from(domain))
+    } else {
+        Err(anyhow!("No TXT record on {} matches this claim", domain))
+    }
+}
+
+pub fn check(claim_id: &str, dns_settings: &dns::DnsSettings) -> Result<()> {
     let transactions =
         db::find_identity_by_prefix("claim", claim_id)?.ok_or(anyhow!("Identity with claim id {} was not found", claim_id))?;
     let identity = util::build_identity(&transactions)?;
@@ -76,7 +858,28 @@ pub fn check(claim_id: &str) -> Result<()> {
         .find(|x| id_str!(x.id()).map(|x| x.starts_with(claim_id)).ok() == Some(true))
         .ok_or(anyhow!("Couldn't find the claim {} in identity {}", claim_id, IdentityID::short(&id_str)))?;
     let claim_id_str = id_str!(claim.id())?;
-    match stamp_aux::claim::check_claim(&transactions, claim) {
+    let social = match claim.spec() {
+        ClaimSpec::Url(MaybePrivate::Public(url)) => platform_for_url(&format!("{}", url)),
+        _ => None,
+    };
+    let domain = match claim.spec() {
+        ClaimSpec::Domain(MaybePrivate::Public(domain)) if dns_settings.is_custom() => Some(format!("{}", domain)),
+        _ => None,
+    };
+    let checked = match (social, domain) {
+        (_, Some(domain)) => check_domain(&identity, claim, &domain, dns_settings),
+        // fall back to checking the profile page itself, but report why the
+        // platform check failed if that doesn't work either
+        (Some((platform, username)), _) => check_social(&identity, claim, platform, &username).or_else(|social_err| {
+            stamp_aux::claim::check_claim(&transactions, claim)
+                .map(|url| format!("{}", url))
+                .map_err(|_| social_err)
+        }),
+        (None, None) => stamp_aux::claim::check_claim(&transactions, claim)
+            .map(|url| format!("{}", url))
+            .map_err(|e| anyhow!("{}", e)),
+    };
+    match checked {
         Ok(url) => {
             let green = dialoguer::console::Style::new().green();
             println!("\nThe claim {} has been {}!\n", ClaimID::short(&claim_id_str), green.apply_to("verified"));
@@ -98,7 +901,10 @@ pub fn check(claim_id: &str) -> Result<()> {
     }
 }
 
-pub fn view(id: &str, claim_id: &str, output: &str) -> Result<()> {
+/// View a claim's value. Photos written to a terminal are drawn inline if the
+/// terminal supports an image protocol (`preview` is "auto", "none", or a
+/// specific protocol), otherwise they're saved to a file in the temp dir.
+pub fn view(id: &str, claim_id: &str, output: &str, preview: &str) -> Result<()> {
     let transactions = id::try_load_single_identity(id)?;
     let identity = util::build_identity(&transactions)?;
     let mut found: Option<Claim> = None;
@@ -161,8 +967,42 @@ pub fn view(id: &str, claim_id: &str, output: &str) -> Result<()> {
             let val = unwrap_maybe(maybe, masterkey_fn)?;
             Vec::from(val.as_bytes())
         }
+        ClaimSpec::Extension { value, .. } => {
+            let val = unwrap_maybe(value, masterkey_fn)?;
+            Vec::from(val.as_slice())
+        }
         _ => Err(anyhow!("Viewing is not implemented for this claim type"))?,
     };
+    if matches!(claim.spec(), ClaimSpec::Photo(..)) && output == "-" && std::io::stdout().is_terminal() && preview != "none" {
+        let protocol = match preview {
+            "auto" => photo::detect_protocol(),
+            _ => Some(preview.parse()?),
+        };
+        match protocol {
+            Some(protocol) => {
+                let rendered = photo::render(output_bytes.as_slice(), protocol)?;
+                util::write_file("-", rendered.as_slice())?;
+            }
+            None => {
+                // save to the temp dir rather than dropping files wherever the
+                // user happens to be. use -o to put it somewhere specific.
+                let filename = std::env::temp_dir()
+                    .join(format!(
+                        "stamp-photo-{}.{}",
+                        ClaimID::short(&id_str!(claim.id())?),
+                        photo::extension(output_bytes.as_slice())
+                    ))
+                    .to_string_lossy()
+                    .to_string();
+                util::write_file(&filename, output_bytes.as_slice())?;
+                println!(
+                    "{}",
+                    util::text_wrap(&format!("Your terminal can't display images inline, so the photo was saved to {}", filename))
+                );
+            }
+        }
+        return Ok(());
+    }
     util::write_file(output, output_bytes.as_slice())?;
     Ok(())
 }
@@ -181,10 +1021,18 @@ pub fn list(id: &str, private: bool, verbose: bool) -> Result<()> {
     } else {
         None
     };
+    let claim_list = claims_with_created(&transactions, identity.claims().iter())?;
+    print_claims_table(&claim_list, master_key_maybe, verbose);
+    Ok(())
+}
+
+/// Pair claims with the time they were made, for display.
+fn claims_with_created<'a, I>(transactions: &Transactions, claims: I) -> Result<Vec<(Claim, Timestamp)>>
+where
+    I: Iterator<Item = &'a Claim>,
+{
     let ts_fake = Timestamp::from_str("0000-01-01T00:00:00.000Z").map_err(|e| anyhow!("Error creating fake timestamp: {:?}", e))?;
-    let claim_list = identity
-        .claims()
-        .iter()
+    let claim_list = claims
         .map(|claim| {
             let claim_id: TransactionID = claim.id().deref().clone();
             let ts = transactions
@@ -195,7 +1043,104 @@ pub fn list(id: &str, private: bool, verbose: bool) -> Result<()> {
             (claim.clone(), ts)
         })
         .collect::<Vec<_>>();
-    print_claims_table(&claim_list, master_key_maybe, verbose);
+    Ok(claim_list)
+}
+
+/// Which claims `stamp claim delete --bulk` should remove. All given filters
+/// must match.
+pub struct DeleteFilter<'a> {
+    pub ty: Option<&'a str>,
+    /// A glob (`*` and `?`) matched against the claim's name.
+    pub name: Option<&'a str>,
+    pub unstamped_private: bool,
+}
+
+impl<'a> DeleteFilter<'a> {
+    fn is_empty(&self) -> bool {
+        self.ty.is_none() && self.name.is_none() && !self.unstamped_private
+    }
+
+    fn matches(&self, claim: &Claim, name_re: Option<&regex::Regex>) -> bool {
+        if let Some(ty) = self.ty {
+            if claim_type(claim.spec()) != ty {
+                return false;
+            }
+        }
+        if let Some(name_re) = name_re {
+            match claim.name() {
+                Some(name) if name_re.is_match(name) => {}
+                _ => return false,
+            }
+        }
+        if self.unstamped_private && (!claim.spec().has_private() || claim.stamps().len() > 0) {
+            return false;
+        }
+        true
+    }
+}
+
+fn glob_to_regex(glob: &str) -> Result<regex::Regex> {
+    let pattern = regex::escape(glob).replace("\\*", ".*").replace("\\?", ".");
+    regex::Regex::new(&format!("^{}$", pattern)).map_err(|e| anyhow!("Invalid name pattern {}: {}", glob, e))
+}
+
+/// Delete every claim matching a filter, asking for confirmation and the master
+/// passphrase once. When staging, each deletion is staged as its own transaction.
+pub fn delete_bulk(id: &str, filter: DeleteFilter, stage: bool, sign_with: Option<&str>) -> Result<()> {
+    if filter.is_empty() {
+        Err(anyhow!("Bulk delete needs at least one filter (--type, --name, or --unstamped-private)"))?;
+    }
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let hash_with = config::hash_algo(Some(&id_str));
+    let name_re = filter.name.map(glob_to_regex).transpose()?;
+    let matched = identity
+        .claims()
+        .iter()
+        .filter(|claim| filter.matches(claim, name_re.as_ref()))
+        .collect::<Vec<_>>();
+    if matched.len() == 0 {
+        println!("No claims match those filters.");
+        return Ok(());
+    }
+    let claim_list = claims_with_created(&transactions, matched.iter().map(|x| *x))?;
+    print_claims_table(&claim_list, None, false);
+    if !util::yesno_prompt(&format!("Really delete these {} claims and all of their stamps? [y/N]", matched.len()), "n")? {
+        return Ok(());
+    }
+    let master_key =
+        util::passphrase_prompt(&format!("Your master passphrase for identity {}", IdentityID::short(&id_str)), identity.created())?;
+    transactions
+        .test_master_key(&master_key)
+        .map_err(|e| anyhow!("Incorrect passphrase: {:?}", e))?;
+
+    let loaded = transactions.clone();
+    let mut updated = transactions;
+    let mut staged = Vec::new();
+    for claim in matched.iter() {
+        let claim_id = id_str!(claim.id())?;
+        let trans = stamp_aux::claim::delete(&updated, &hash_with, &claim_id).map_err(|e| anyhow!("Problem deleting claim: {}", e))?;
+        let current = util::build_identity(&updated)?;
+        let signed = util::sign_helper(&current, trans, &master_key, stage, sign_with)?;
+        if stage {
+            staged.push(stage::stage(identity.id(), signed)?);
+        } else {
+            updated = updated
+                .push_transaction(signed)
+                .map_err(|e| anyhow!("Error deleting claim {}: {:?}", claim_id, e))?;
+        }
+    }
+    if stage {
+        println!("Staged {} claim deletions. To view:", staged.len());
+        for txid in staged {
+            println!("  stamp stage view {}", txid);
+        }
+    } else {
+        dag::save_checked(&loaded, updated, true)?;
+        let green = dialoguer::console::Style::new().green();
+        println!("{} {} claims", green.apply_to("Deleted"), matched.len());
+    }
     Ok(())
 }
 
@@ -265,11 +1210,54 @@ pub fn stamp_delete(id: &str, stamp_id: &str, stage: bool, sign_with: Option<&st
     Ok(())
 }
 
+/// The short name for a claim's type, as used on the command line (ex
+/// `stamp claim new email`).
+pub(crate) fn claim_type(spec: &ClaimSpec) -> &'static str {
+    match spec {
+        ClaimSpec::Identity(..) => "identity",
+        ClaimSpec::Name(..) => "name",
+        ClaimSpec::Birthday(..) => "birthday",
+        ClaimSpec::Email(..) => "email",
+        ClaimSpec::Photo(..) => "photo",
+        ClaimSpec::Pgp(..) => "pgp",
+        ClaimSpec::Domain(..) => "domain",
+        ClaimSpec::Url(..) => "url",
+        ClaimSpec::Address(..) => "address",
+        ClaimSpec::PhoneNumber(..) => "phone",
+        ClaimSpec::Relation(..) => "relation",
+        ClaimSpec::Extension { .. } => "custom",
+        _ => "<unknown>",
+    }
+}
+
+/// A claim's value as text, if it's public and something that reads as text
+/// (so not photos, files, etc).
+pub(crate) fn public_claim_value(spec: &ClaimSpec) -> Option<String> {
+    match spec {
+        ClaimSpec::Identity(MaybePrivate::Public(id)) => id_str!(id).ok(),
+        ClaimSpec::Name(MaybePrivate::Public(val)) => Some(val.clone()),
+        ClaimSpec::Birthday(MaybePrivate::Public(val)) => Some(val.to_string()),
+        ClaimSpec::Email(MaybePrivate::Public(val)) => Some(val.clone()),
+        ClaimSpec::Pgp(MaybePrivate::Public(val)) => Some(val.clone()),
+        ClaimSpec::Domain(MaybePrivate::Public(val)) => Some(val.clone()),
+        ClaimSpec::Url(MaybePrivate::Public(val)) => Some(String::from(val.clone())),
+        ClaimSpec::Address(MaybePrivate::Public(val)) => Some(address_to_line(val)),
+        ClaimSpec::PhoneNumber(MaybePrivate::Public(val)) => Some(val.clone()),
+        ClaimSpec::Extension {
+            key,
+            value: MaybePrivate::Public(val),
+        } => Some(extension_to_string(key, val)),
+        _ => None,
+    }
+}
+
 pub fn print_claims_table(claims: &Vec<(Claim, Timestamp)>, master_key_maybe: Option<SecretKey>, verbose: bool) {
     let mut table = Table::new();
     table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
     let id_field = if verbose { "ID" } else { "ID (short)" };
-    table.set_titles(row![id_field, "Name", "Type", "Value", "Created", "# stamps"]);
+    table.set_titles(row![id_field, "Name", "Type", "Value", "Created", "Expires", "# stamps"]);
+    let expiries = claim_expiries().unwrap_or_default();
+    let now = Timestamp::now();
     for (claim, created_ts) in claims {
         let (id_full, id_short) = id_str_split!(claim.id());
         macro_rules! extract_str {
@@ -321,7 +1309,7 @@ pub fn print_claims_table(claims: &Vec<(Claim, Timestamp)>, master_key_maybe: Op
             ClaimSpec::Pgp(pgp) => ("pgp", extract_str!(pgp)),
             ClaimSpec::Domain(domain) => ("domain", extract_str!(domain)),
             ClaimSpec::Url(url) => ("url", extract_str!(url, |x: Url| String::from(x))),
-            ClaimSpec::Address(address) => ("address", extract_str!(address)),
+            ClaimSpec::Address(address) => ("address", extract_str!(address, |x: String| address_to_line(&x))),
             ClaimSpec::PhoneNumber(number) => ("phone #", extract_str!(number)),
             ClaimSpec::Relation(relation) => {
                 let rel_str = match relation {
@@ -338,15 +1326,27 @@ pub fn print_claims_table(claims: &Vec<(Claim, Timestamp)>, master_key_maybe: Op
                 };
                 ("relation", rel_str)
             }
+            ClaimSpec::Extension { key, value } => ("custom", extract_str!(value, |x: BinaryVec| extension_to_string(key, &x))),
             _ => ("<unknown>", String::from("<unknown>")),
         };
         let created = created_ts.local().format("%b %d, %Y").to_string();
+        let (expires, expired) = match expiries.get(&id_full) {
+            Some(ts) => (ts.local().format("%b %d, %Y").to_string(), ts.local() <= now.local()),
+            None => (String::from("-"), false),
+        };
+        let red = dialoguer::console::Style::new().red();
+        let expires = if expired {
+            format!("{}", red.apply_to(format!("expired {}", expires)))
+        } else {
+            expires
+        };
         table.add_row(row![
             if verbose { &id_full } else { &id_short },
             name,
             ty,
             val,
             created,
+            expires,
             format!("{}", claim.stamps().len()),
         ]);
     }
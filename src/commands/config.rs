@@ -1,4 +1,4 @@
-use crate::{commands, config, db, util};
+use crate::{commands, config, db, dns, util};
 use anyhow::{anyhow, Result};
 use stamp_aux::config::NetConfig;
 use stamp_net::Multiaddr;
@@ -31,3 +31,152 @@ pub fn set_stampnet_servers(servers: Vec<Multiaddr>) -> Result<()> {
     conf.net = Some(NetConfig::new(servers));
     config::save(&conf)
 }
+
+pub fn set_phone_region(region: &str) -> Result<()> {
+    let region = region.to_uppercase();
+    // make sure we can actually normalize numbers with this region
+    commands::claim::normalize_phone("555 123 4567", Some(&region))?;
+    let mut conf = config::load_cli()?;
+    println!("Setting default phone region to {}", region);
+    conf.phone_region = Some(region);
+    config::save_cli(&conf)
+}
+
+pub fn set_export_cooldown(minutes: u64) -> Result<()> {
+    let mut conf = config::load_cli()?;
+    if minutes == 0 {
+        println!("Disabling the secret export cooldown");
+        conf.secret_export_cooldown_mins = None;
+    } else {
+        println!("Allowing one secret export per identity every {} minutes", minutes);
+        conf.secret_export_cooldown_mins = Some(minutes);
+    }
+    config::save_cli(&conf)
+}
+
+pub fn set_backup_reminder(days: u64) -> Result<()> {
+    let mut conf = config::load_cli()?;
+    if days == 0 {
+        println!("Disabling backup reminders");
+        conf.backup_reminder_days = None;
+    } else {
+        println!("Reminding about backups older than {} days", days);
+        conf.backup_reminder_days = Some(days);
+    }
+    config::save_cli(&conf)
+}
+
+fn configured_joinlist() -> Result<Vec<Multiaddr>> {
+    Ok(config::load()?.net.map(|net| net.join_list.clone()).unwrap_or_default())
+}
+
+pub fn net_add_join(server: Multiaddr) -> Result<()> {
+    let mut servers = configured_joinlist()?;
+    if servers.contains(&server) {
+        Err(anyhow!("{} is already in the join list", server))?;
+    }
+    println!("Adding {} to the StampNet join list", server);
+    servers.push(server);
+    let mut conf = config::load()?;
+    conf.net = Some(NetConfig::new(servers));
+    config::save(&conf)
+}
+
+pub fn net_remove_join(server: Multiaddr) -> Result<()> {
+    let servers = configured_joinlist()?;
+    if !servers.contains(&server) {
+        Err(anyhow!("{} is not in the join list", server))?;
+    }
+    println!("Removing {} from the StampNet join list", server);
+    let servers = servers.into_iter().filter(|x| x != &server).collect::<Vec<_>>();
+    let mut conf = config::load()?;
+    conf.net = if servers.len() > 0 { Some(NetConfig::new(servers)) } else { None };
+    config::save(&conf)
+}
+
+pub fn net_list_join() -> Result<()> {
+    let servers = configured_joinlist()?;
+    let no_default = config::load_cli()?.no_default_bootstrap;
+    if servers.len() > 0 {
+        for server in servers {
+            println!("{}", server);
+        }
+    } else if no_default {
+        println!("No StampNet servers configured (default bootstrap servers are disabled).");
+    } else {
+        println!("No StampNet servers configured, using the defaults:");
+        for server in commands::net::default_joinlist()? {
+            println!("{}", server);
+        }
+    }
+    Ok(())
+}
+
+pub fn net_set_default_bootstrap(enabled: bool) -> Result<()> {
+    let mut conf = config::load_cli()?;
+    if enabled {
+        println!("Using the public StampNet bootstrap servers when no join list is configured");
+    } else {
+        println!("Disabling the public StampNet bootstrap servers");
+    }
+    conf.no_default_bootstrap = !enabled;
+    config::save_cli(&conf)
+}
+
+/// Update the DNS settings used for domain claim checks, then print them.
+pub fn dns(resolver: Option<&str>, doh: Option<&str>, dnssec: Option<bool>, system: bool) -> Result<()> {
+    let mut conf = config::load_cli()?;
+    let changed = resolver.is_some() || doh.is_some() || dnssec.is_some() || system;
+    if system {
+        conf.dns_resolver = None;
+        conf.dns_doh = None;
+    }
+    if let Some(resolver) = resolver {
+        dns::parse_resolver(resolver)?;
+        conf.dns_resolver = Some(resolver.into());
+        conf.dns_doh = None;
+    }
+    if let Some(doh) = doh {
+        dns::doh_config(doh)?;
+        conf.dns_doh = Some(doh.into());
+        conf.dns_resolver = None;
+    }
+    if let Some(dnssec) = dnssec {
+        conf.dns_dnssec = dnssec;
+    }
+    if changed {
+        config::save_cli(&conf)?;
+    }
+    let settings = dns::DnsSettings::from_config(&conf, None, None, false);
+    println!("Resolver: {}", settings.describe());
+    println!("DNSSEC:   {}", if settings.dnssec { "required" } else { "not required" });
+    Ok(())
+}
+
+/// Turn caching of master keys in the OS keyring on or off. Turning it off (or
+/// `forget`) removes any master keys already cached for local identities.
+pub fn keyring(ttl_mins: Option<u64>, forget: bool) -> Result<()> {
+    let mut conf = config::load_cli()?;
+    if let Some(ttl_mins) = ttl_mins {
+        if ttl_mins == 0 {
+            println!("Disabling the OS keyring cache");
+            conf.keyring_ttl_mins = None;
+        } else {
+            println!("Caching master keys in the OS keyring for {} minutes after unlocking", ttl_mins);
+            conf.keyring_ttl_mins = Some(ttl_mins);
+        }
+        config::save_cli(&conf)?;
+    }
+    if forget || conf.keyring_ttl_mins.is_none() {
+        for transactions in db::list_local_identities(None)? {
+            let identity = util::build_identity(&transactions)?;
+            util::forget_master_key(identity.created())?;
+        }
+        if forget {
+            println!("Removed cached master keys from the OS keyring");
+        }
+    } else if ttl_mins.is_none() {
+        println!("Master keys are cached in the OS keyring for {} minutes", conf.keyring_ttl_mins.unwrap_or(0));
+    }
+    Ok(())
+}
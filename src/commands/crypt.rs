@@ -0,0 +1,103 @@
+use crate::{
+    armor,
+    commands::{id, keychain},
+    util,
+};
+use anyhow::{anyhow, Result};
+use serde_derive::{Deserialize, Serialize};
+use stamp_core::{
+    crypto::base::{rng, Sealed},
+    identity::IdentityID,
+};
+
+/// A file encrypted with one of our `secret` subkeys. The identity and key ID
+/// are kept so decrypting doesn't need to ask which key to use.
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedFile {
+    identity: String,
+    key_id: String,
+    sealed: Sealed,
+}
+
+/// Encrypt a file to ourselves with a `secret` subkey.
+pub fn encrypt(id: &str, key_search: Option<&str>, input: &str, output: &str, armored: bool) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let subkey = keychain::find_keys_by_search_or_prompt(&identity, key_search, "secret", |sub| sub.key().as_secretkey())?;
+    if subkey.revocation().is_some() {
+        Err(anyhow!("The key {} is revoked and can't be used to encrypt", subkey.name()))?;
+    }
+    let plaintext = util::read_file(input)?;
+    let master_key = util::passphrase_prompt(
+        &format!("Your current master passphrase for identity {}", IdentityID::short(&id_str)),
+        identity.created(),
+    )?;
+    transactions
+        .test_master_key(&master_key)
+        .map_err(|e| anyhow!("Incorrect passphrase: {:?}", e))?;
+    let secret_key = subkey
+        .key()
+        .as_secretkey()
+        .ok_or(anyhow!("The key {} is not a `secret` key", subkey.name()))?
+        .open(&master_key)
+        .map_err(|e| anyhow!("Problem opening secret key: {:?}", e))?;
+    let mut rng = rng::chacha20();
+    let sealed = secret_key
+        .seal(&mut rng, plaintext.as_slice())
+        .map_err(|e| anyhow!("Problem encrypting: {:?}", e))?;
+    let encrypted = EncryptedFile {
+        identity: id_str,
+        key_id: subkey.key_id().as_string(),
+        sealed,
+    };
+    let serialized = util::serialize_cbor(&encrypted)?;
+    if armored {
+        // no headers: the key name would be readable by anyone holding the file
+        let armored = armor::armor(armor::ENCRYPTED, &[], serialized.as_slice());
+        util::write_file(output, armored.as_bytes())?;
+    } else {
+        util::write_file(output, serialized.as_slice())?;
+    }
+    Ok(())
+}
+
+/// Decrypt a file made with [encrypt]. The key is picked from the file itself,
+/// and revoked keys can still decrypt.
+pub fn decrypt(id: &str, input: &str, output: &str) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let encrypted_bytes = armor::strip(util::read_file(input)?)?;
+    let encrypted = util::deserialize_cbor::<EncryptedFile>(encrypted_bytes.as_slice())
+        .map_err(|e| anyhow!("This doesn't look like a file from `stamp crypt encrypt`: {}", e))?;
+    if encrypted.identity != id_str {
+        Err(anyhow!(
+            "This file was encrypted by identity {}, not {}. Use `--id` to pick the right identity.",
+            IdentityID::short(&encrypted.identity),
+            IdentityID::short(&id_str)
+        ))?;
+    }
+    let subkey = identity
+        .keychain()
+        .subkey_by_keyid_str(&encrypted.key_id)
+        .ok_or(anyhow!("The key {} used to encrypt this file is not in your keychain", encrypted.key_id))?;
+    let master_key = util::passphrase_prompt(
+        &format!("Your current master passphrase for identity {}", IdentityID::short(&id_str)),
+        identity.created(),
+    )?;
+    transactions
+        .test_master_key(&master_key)
+        .map_err(|e| anyhow!("Incorrect passphrase: {:?}", e))?;
+    let secret_key = subkey
+        .key()
+        .as_secretkey()
+        .ok_or(anyhow!("The key {} is not a `secret` key", subkey.name()))?
+        .open(&master_key)
+        .map_err(|e| anyhow!("Problem opening secret key: {:?}", e))?;
+    let plaintext = secret_key
+        .open(&encrypted.sealed)
+        .map_err(|e| anyhow!("Problem decrypting: {:?}", e))?;
+    util::write_file(output, plaintext.as_slice())?;
+    Ok(())
+}
@@ -1,23 +1,737 @@
-use crate::{commands::id, db, util};
+use crate::{
+    commands::{claim, id, net, stage, status, sync},
+    config, db, util,
+};
 use anyhow::{anyhow, Result};
 use prettytable::Table;
-use stamp_aux::db::stage_transaction;
+use serde_derive::{Deserialize, Serialize};
 use stamp_core::{
     crypto::{base::KeyID, private::MaybePrivate},
-    dag::{Transaction, TransactionBody, Transactions},
-    identity::{claim::ClaimSpec, keychain::Key, IdentityID},
-    util::{base64_encode, SerdeBinary},
+    dag::{Transaction, TransactionBody, TransactionID, Transactions},
+    identity::{
+        claim::{ClaimID, ClaimSpec},
+        keychain::Key,
+        Identity, IdentityID,
+    },
+    policy::MultisigPolicySignature,
+    util::{base64_encode, Public, SerText, SerdeBinary, Timestamp},
 };
 use std::convert::{From, TryFrom};
+use std::io::IsTerminal;
 use std::ops::Deref;
 
-pub fn list(id: &str) -> Result<()> {
+/// How [list] orders transactions.
+pub enum ListSort {
+    /// The order they're stored in the DAG.
+    Dag,
+    Created,
+    Type,
+}
+
+pub fn list(
+    id: &str,
+    types: &[&str],
+    since: Option<Timestamp>,
+    until: Option<Timestamp>,
+    contains: Option<&str>,
+    sort: ListSort,
+    reverse: bool,
+) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let history = transactions.transactions();
+    let contains = contains.map(|x| x.to_lowercase());
+    let mut filtered = history
+        .iter()
+        .filter(|t| types.len() == 0 || types.iter().any(|ty| ty.eq_ignore_ascii_case(transaction_to_string(t))))
+        .filter(|t| since.as_ref().map(|x| t.entry().created().local() >= x.local()).unwrap_or(true))
+        .filter(|t| until.as_ref().map(|x| t.entry().created().local() <= x.local()).unwrap_or(true))
+        .filter(|t| match contains.as_ref() {
+            Some(text) => {
+                let id = id_str!(t.id()).unwrap_or_default();
+                let desc = transaction_description(t, history.as_slice());
+                id.to_lowercase().contains(text) || desc.to_lowercase().contains(text)
+            }
+            None => true,
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    match sort {
+        ListSort::Dag => {}
+        ListSort::Created => filtered.sort_by(|a, b| a.entry().created().local().cmp(&b.entry().created().local())),
+        ListSort::Type => filtered.sort_by(|a, b| transaction_to_string(a).cmp(transaction_to_string(b))),
+    }
+    if reverse {
+        filtered.reverse();
+    }
+    if filtered.len() == 0 {
+        println!("No transactions match.");
+        return Ok(());
+    }
+    print_transactions_table_with_history(&filtered, history.as_slice());
+    if filtered.len() < history.len() {
+        println!("Showing {} of {} transactions.", filtered.len(), history.len());
+    }
+    Ok(())
+}
+
+/// Show everything about a single transaction in an identity's DAG: its body,
+/// what it builds on, who signed it, and whether it still verifies.
+pub fn view(id: &str, txid: &str) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let history = transactions.transactions();
+    let matches = history
+        .iter()
+        .filter(|x| id_str!(x.id()).map(|id| id.starts_with(txid)).unwrap_or(false))
+        .collect::<Vec<_>>();
+    let trans = match matches.as_slice() {
+        [] => Err(anyhow!("Transaction {} not found for identity {}", txid, IdentityID::short(&id_str)))?,
+        [trans] => *trans,
+        _ => {
+            println!("Transactions starting with {}:", txid);
+            print_transactions_table_with_history(&matches.into_iter().cloned().collect::<Vec<_>>(), history.as_slice());
+            Err(anyhow!("Multiple transactions matched {}, use more of the ID", txid))?
+        }
+    };
+    let green = dialoguer::console::Style::new().green();
+    let red = dialoguer::console::Style::new().red();
+
+    println!("Transaction: {}", trans.id());
+    println!("Type:        {}", transaction_to_string(trans));
+    println!("Description: {}", transaction_description(trans, history.as_slice()));
+    println!("Created:     {}", trans.entry().created().local().format("%b %e, %Y  %H:%M:%S"));
+    match trans.verify(Some(&identity)) {
+        Ok(_) => println!("Verifies:    {}", green.apply_to("yes, against the current identity")),
+        Err(e) => println!("Verifies:    {} ({})", red.apply_to("no"), e),
+    }
+    let previous = trans.entry().previous_transactions();
+    if previous.len() == 0 {
+        println!("Previous:    none");
+    } else {
+        println!("Previous:");
+        for prev in previous.iter() {
+            let desc = history
+                .iter()
+                .find(|t| t.id() == prev)
+                .map(|t| transaction_description(t, history.as_slice()))
+                .unwrap_or_else(|| String::from("<not in this identity>"));
+            println!("  {}  {}", prev, desc);
+        }
+    }
+    println!("Signatures:");
+    for sig in trans.signatures().iter() {
+        match sig {
+            MultisigPolicySignature::Key { key, .. } => {
+                let key_id = KeyID::from(key.key_id());
+                let name = key_name(history.as_slice(), &key_id).unwrap_or_else(|| String::from("<unknown key>"));
+                println!("  {}  {}", key_id, name);
+            }
+        }
+    }
+    let serialized = trans
+        .serialize_text()
+        .map_err(|e| anyhow!("Problem serializing transaction: {:?}", e))?;
+    println!("\n{}", serialized);
+    Ok(())
+}
+
+/// Check an identity's DAG from the genesis transaction forward: that each
+/// transaction's links point at transactions that came before it, and that its
+/// hash, signatures, and policy all verify against the identity as it stood
+/// then. Stops at the first failure.
+pub fn verify(id: &str) -> Result<()> {
     let transactions = id::try_load_single_identity(id)?;
-    print_transactions_table(transactions.transactions());
+    let history = transactions.transactions();
+    let total = history.len();
+    let green = dialoguer::console::Style::new().green();
+    let red = dialoguer::console::Style::new().red();
+
+    let mut rebuilt = Transactions::new();
+    let mut identity: Option<Identity> = None;
+    let mut seen: Vec<&TransactionID> = Vec::new();
+    for (i, trans) in history.iter().enumerate() {
+        let previous = trans.entry().previous_transactions();
+        let link_problem = if i == 0 {
+            match trans.entry().body() {
+                TransactionBody::CreateIdentityV1 { .. } if previous.len() == 0 => None,
+                TransactionBody::CreateIdentityV1 { .. } => Some(String::from("the genesis transaction links to previous transactions")),
+                _ => Some(format!("the first transaction is {}, not CreateIdentityV1", transaction_to_string(trans))),
+            }
+        } else if previous.len() == 0 {
+            Some(String::from("no previous transactions, only the genesis transaction can have none"))
+        } else {
+            previous
+                .iter()
+                .find(|prev| !seen.contains(prev))
+                .map(|prev| format!("links to {}, which isn't earlier in the DAG", prev))
+        };
+        let result = match link_problem {
+            Some(problem) => Err(problem),
+            // hash, signatures, and policy
+            None => trans.verify(identity.as_ref()).map_err(|e| format!("{}", e)),
+        }
+        .and_then(|_| {
+            rebuilt
+                .clone()
+                .push_transaction(trans.clone())
+                .map_err(|e| format!("doesn't apply: {:?}", e))
+        })
+        .and_then(|next| util::build_identity(&next).map(|built| (next, built)).map_err(|e| format!("{}", e)));
+        match result {
+            Ok((next, built)) => {
+                rebuilt = next;
+                identity = Some(built);
+                seen.push(trans.id());
+            }
+            Err(cause) => {
+                println!(
+                    "{} at transaction {} of {}: {} {} ({})",
+                    red.apply_to("Verification failed"),
+                    i + 1,
+                    total,
+                    trans.id(),
+                    transaction_to_string(trans),
+                    trans.entry().created().local().format("%b %e, %Y  %H:%M:%S")
+                );
+                println!("  {}", cause);
+                Err(anyhow!("Identity DAG failed verification"))?;
+            }
+        }
+    }
+    println!("{} all {} transaction(s) verified.", green.apply_to("OK:"), total);
+    Ok(())
+}
+
+/// Read a set of transactions from a file: a bundle from [export_all] or an
+/// identity in any of the formats we export them in.
+fn load_transactions_file(filename: &str) -> Result<Transactions> {
+    let contents = util::read_file(filename)?;
+    if let Ok(bundle) = util::deserialize_cbor::<DagBundle>(contents.as_slice()) {
+        let mut transactions = Transactions::new();
+        for trans in bundle.transactions {
+            transactions = transactions
+                .push_transaction(trans)
+                .map_err(|e| anyhow!("Problem reading bundle {}: {:?}", filename, e))?;
+        }
+        return Ok(transactions);
+    }
+    Transactions::deserialize_binary(contents.as_slice())
+        .or_else(|_| Transactions::deserialize_binary(&stamp_core::util::base64_decode(contents.as_slice())?))
+        .or_else(|_| util::deserialize_cbor::<Transactions>(contents.as_slice()))
+        .map_err(|e| anyhow!("Unable to read an identity from {}: {}", filename, e))
+}
+
+/// Compare two sets of transactions for (usually) the same identity, ex our
+/// local copy and a published one. `other` is a file if one exists at that
+/// path, otherwise an identity we have locally.
+pub fn diff(id: &str, other: &str) -> Result<()> {
+    let ours = id::try_load_single_identity(id)?;
+    let theirs = if std::path::Path::new(other).exists() {
+        load_transactions_file(other)?
+    } else {
+        id::try_load_single_identity(other)?
+    };
+    let identity_ours = util::build_identity(&ours)?;
+    let identity_theirs = util::build_identity(&theirs)?;
+    let (id_ours, id_theirs) = (id_str!(identity_ours.id())?, id_str!(identity_theirs.id())?);
+    let yellow = dialoguer::console::Style::new().yellow();
+    println!("Left:  {} ({} transactions)", IdentityID::short(&id_ours), ours.transactions().len());
+    println!("Right: {} ({} transactions)", IdentityID::short(&id_theirs), theirs.transactions().len());
+    if id_ours != id_theirs {
+        println!("{} these are two different identities.", yellow.apply_to("Note:"));
+    }
+
+    let only_in = |a: &Transactions, b: &Transactions| -> Vec<Transaction> {
+        a.transactions()
+            .iter()
+            .filter(|t| !b.transactions().iter().any(|x| x.id() == t.id()))
+            .cloned()
+            .collect()
+    };
+    let only_ours = only_in(&ours, &theirs);
+    let only_theirs = only_in(&theirs, &ours);
+    if only_ours.len() == 0 && only_theirs.len() == 0 {
+        println!("\nBoth sides have the same transactions.");
+        return Ok(());
+    }
+    if only_ours.len() > 0 {
+        println!("\nOnly on the left:");
+        print_transactions_table_with_history(&only_ours, ours.transactions().as_slice());
+    }
+    if only_theirs.len() > 0 {
+        println!("\nOnly on the right:");
+        print_transactions_table_with_history(&only_theirs, theirs.transactions().as_slice());
+    }
+
+    // what going from the left to the right would change
+    let mut changes = net::identity_changes(&identity_ours, &identity_theirs);
+    let stamps_made = |identity: &Identity| identity.stamps().iter().map(|x| format!("{}", x.id())).collect::<Vec<_>>();
+    let stamps_received = |identity: &Identity| {
+        identity
+            .claims()
+            .iter()
+            .flat_map(|c| c.stamps().iter().map(|x| format!("{}", x.id())))
+            .collect::<Vec<_>>()
+    };
+    for (label, left, right) in vec![
+        ("Stamp made", stamps_made(&identity_ours), stamps_made(&identity_theirs)),
+        ("Stamp received", stamps_received(&identity_ours), stamps_received(&identity_theirs)),
+    ] {
+        for stamp_id in right.iter().filter(|x| !left.contains(x)) {
+            changes.push(format!("{} added: {}", label, stamp_id));
+        }
+        for stamp_id in left.iter().filter(|x| !right.contains(x)) {
+            changes.push(format!("{} removed: {}", label, stamp_id));
+        }
+    }
+    if changes.len() > 0 {
+        println!("\nGoing from left to right:");
+        for change in changes {
+            println!("  {}", change);
+        }
+    }
     Ok(())
 }
 
-pub fn reset(id: &str, txid: &str) -> Result<()> {
+/// Show a byte count in a human-friendly way.
+fn size_str(bytes: usize) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    } else if bytes >= 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+/// How many of an identity's largest transactions [stats] lists.
+const STATS_LARGEST: usize = 5;
+
+/// A quick overview of an identity's DAG: what it's made of, how big it is, and
+/// where the size comes from.
+pub fn stats(id: &str) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let history = transactions.transactions();
+    let total_size = transactions
+        .serialize_binary()
+        .map_err(|e| anyhow!("Problem serializing identity: {:?}", e))?
+        .len();
+    let mut sizes = history
+        .iter()
+        .map(|t| {
+            let size = t
+                .serialize_binary()
+                .map_err(|e| anyhow!("Problem serializing transaction: {:?}", e))?
+                .len();
+            Ok((t, size))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let signatures = history.iter().map(|t| t.signatures().len()).sum::<usize>();
+    let first = history.iter().map(|t| t.entry().created().local()).min();
+    let last = history.iter().map(|t| t.entry().created().local()).max();
+
+    println!("Identity:     {}", IdentityID::short(&id_str));
+    println!("Transactions: {}", history.len());
+    println!("Signatures:   {}", signatures);
+    println!("Size:         {}", size_str(total_size));
+    if let (Some(first), Some(last)) = (first, last) {
+        println!("First:        {}", first.format("%b %e, %Y  %H:%M:%S"));
+        println!("Last:         {}", last.format("%b %e, %Y  %H:%M:%S"));
+    }
+
+    let mut by_type: Vec<(&'static str, usize, usize)> = Vec::new();
+    for (trans, size) in sizes.iter() {
+        let ty = transaction_to_string(trans);
+        match by_type.iter_mut().find(|(x, ..)| *x == ty) {
+            Some((_, count, total)) => {
+                *count += 1;
+                *total += size;
+            }
+            None => by_type.push((ty, 1, *size)),
+        }
+    }
+    by_type.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    println!("\nBy type:");
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(row!["Type", "Count", "Size"]);
+    for (ty, count, total) in by_type {
+        table.add_row(row![ty, count, size_str(total)]);
+    }
+    table.printstd();
+
+    sizes.sort_by(|a, b| b.1.cmp(&a.1));
+    println!("\nLargest transactions:");
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(row!["ID", "Description", "Size", "Created"]);
+    for (trans, size) in sizes.iter().take(STATS_LARGEST) {
+        let id = id_str!(trans.id()).unwrap_or_else(|e| format!("<bad id {:?} -- {:?}>", trans.id(), e));
+        let desc = transaction_description(trans, history.as_slice());
+        let created = trans.entry().created().local().format("%b %e, %Y  %H:%M:%S");
+        table.add_row(row![id, desc, size_str(*size), created]);
+    }
+    table.printstd();
+    Ok(())
+}
+
+/// Every transaction `head` builds on, directly or not, including itself.
+fn ancestors<'a>(history: &'a [Transaction], head: &'a Transaction) -> Vec<&'a TransactionID> {
+    let mut found = vec![head.id()];
+    let mut idx = 0;
+    while idx < found.len() {
+        if let Some(trans) = history.iter().find(|t| t.id() == found[idx]) {
+            for prev in trans.entry().previous_transactions().iter() {
+                if !found.contains(&prev) {
+                    found.push(prev);
+                }
+            }
+        }
+        idx += 1;
+    }
+    found
+}
+
+/// Resolve an identity whose DAG has split into several heads (ex edits made on
+/// two devices, or conflicting imports). `other` is an optional copy of the
+/// identity to merge in first. Each branch is shown, then either all of them
+/// are kept (the next transaction joins them back together) or just one, with
+/// the others' transactions dropped. Before saving, every branch is written to
+/// a recovery bundle so nothing dropped is lost.
+pub fn merge(id: &str, other: Option<&str>, keep: Option<&str>, skip_confirm: bool) -> Result<()> {
+    let ours = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&ours)?;
+    let id_str = id_str!(identity.id())?;
+    let (merged, added) = match other {
+        Some(other) => {
+            let theirs = if std::path::Path::new(other).exists() {
+                load_transactions_file(other)?
+            } else {
+                id::try_load_single_identity(other)?
+            };
+            sync::merge_transactions(ours, &theirs)?
+        }
+        None => (ours, 0),
+    };
+    if added > 0 {
+        println!("Merged in {} transaction(s) from {}", added, other.unwrap_or_default());
+    }
+    let history = merged.transactions();
+    let heads = history
+        .iter()
+        .filter(|t| {
+            !history
+                .iter()
+                .any(|x| x.entry().previous_transactions().iter().any(|p| p == t.id()))
+        })
+        .collect::<Vec<_>>();
+    if heads.len() < 2 {
+        if added > 0 {
+            db::save_identity(merged)?;
+            println!("Identity {} has a single head, nothing to resolve.", IdentityID::short(&id_str));
+        } else {
+            println!("Identity {} has a single head, nothing to merge.", IdentityID::short(&id_str));
+        }
+        return Ok(());
+    }
+
+    let branches = heads.iter().map(|head| ancestors(history.as_slice(), head)).collect::<Vec<_>>();
+    println!("Identity {} has {} divergent heads.", IdentityID::short(&id_str), heads.len());
+    for (i, (head, branch)) in heads.iter().zip(branches.iter()).enumerate() {
+        // only show what's particular to this branch
+        let exclusive = history
+            .iter()
+            .filter(|t| branch.contains(&t.id()))
+            .filter(|t| !branches.iter().all(|other| other.contains(&t.id())))
+            .cloned()
+            .collect::<Vec<_>>();
+        println!(
+            "\nBranch {}, head {} ({} transaction(s) not shared by every branch):",
+            i + 1,
+            head.id(),
+            exclusive.len()
+        );
+        print_transactions_table_with_history(&exclusive, history.as_slice());
+    }
+
+    let choice = match keep {
+        Some(keep) => String::from(keep),
+        None => {
+            println!("\nEnter \"all\" to keep every branch (the next transaction you make joins them), or a branch number to keep only that branch.");
+            util::value_prompt("Keep which branch? [all]")?
+        }
+    };
+    let choice = choice.trim();
+    let keep_idx = if choice == "" || choice.eq_ignore_ascii_case("all") {
+        None
+    } else {
+        let idx = match choice.parse::<usize>() {
+            Ok(num) if num >= 1 && num <= heads.len() => num - 1,
+            _ => heads
+                .iter()
+                .position(|h| id_str!(h.id()).map(|x| x.starts_with(choice)).unwrap_or(false))
+                .ok_or(anyhow!("No branch matches {}", choice))?,
+        };
+        Some(idx)
+    };
+
+    let result = match keep_idx {
+        None => merged.clone(),
+        Some(idx) => {
+            let mut kept = Transactions::new();
+            for trans in history.iter().filter(|t| branches[idx].contains(&t.id())) {
+                kept = kept
+                    .push_transaction(trans.clone())
+                    .map_err(|e| anyhow!("Problem rebuilding branch {}: {:?}", idx + 1, e))?;
+            }
+            kept
+        }
+    };
+    let identity_result = util::build_identity(&result)?;
+    let changes = net::identity_changes(&identity, &identity_result);
+    let dropped = merged.transactions().len() - result.transactions().len();
+    if dropped == 0 && added == 0 {
+        println!("Keeping all branches, nothing to save. The next transaction you make will join them.");
+        return Ok(());
+    }
+    println!("\nThe merged identity:");
+    println!("  {} transaction(s) merged in, {} dropped", added, dropped);
+    for change in changes.iter() {
+        println!("  {}", change);
+    }
+    if !skip_confirm && !util::yesno_prompt("Save the merged identity? [y/N]", "n")? {
+        return Ok(());
+    }
+    // every branch, including any we're about to drop, goes in the bundle
+    let backup_str = save_recovery_bundle(&merged, &id_str, "merge-backups", None, false)?;
+    println!("Saved every branch of the identity to {}", backup_str);
+    db::save_identity(result)?;
+    println!("Identity {} saved.", IdentityID::short(&id_str));
+    println!("To get the dropped branches back, run:\n  stamp dag import-bundle {}", backup_str);
+    Ok(())
+}
+
+/// The graph formats [graph] can write.
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+/// Write out an identity's DAG as a Graphviz or Mermaid graph. Edges point from
+/// a transaction to the ones built on it, so forks show up as a node with
+/// several children and merges as one with several parents.
+pub fn graph(id: &str, format: GraphFormat, output: &str) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let history = transactions.transactions();
+    let ids = history.iter().map(|t| id_str!(t.id())).collect::<Result<Vec<_>>>()?;
+    let node = |idx: usize| format!("t{}", idx);
+    let short = |id: &str| id.chars().take(8).collect::<String>();
+
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+    let mut missing = Vec::new();
+    for (idx, trans) in history.iter().enumerate() {
+        let parents = trans.entry().previous_transactions();
+        let children = history
+            .iter()
+            .filter(|t| t.entry().previous_transactions().iter().any(|p| p == trans.id()))
+            .count();
+        let label = format!(
+            "{}\\n{}\\n{}",
+            transaction_to_string(trans),
+            trans.entry().created().local().format("%Y-%m-%d %H:%M"),
+            short(&ids[idx])
+        );
+        // forks and merges are what the graph is for, so make them stand out
+        let kind = if parents.len() > 1 {
+            "merge"
+        } else if children > 1 {
+            "fork"
+        } else if children == 0 {
+            "head"
+        } else {
+            "normal"
+        };
+        nodes.push((node(idx), label, kind));
+        for prev in parents.iter() {
+            match history.iter().position(|t| t.id() == prev) {
+                Some(prev_idx) => edges.push((node(prev_idx), node(idx), false)),
+                None => {
+                    // a parent that isn't in the DAG (ex removed by a reset)
+                    let prev_str = format!("{}", prev);
+                    let missing_node = format!("m{}", missing.len());
+                    missing.push((missing_node.clone(), format!("missing\\n{}", short(&prev_str))));
+                    edges.push((missing_node, node(idx), true));
+                }
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    match format {
+        GraphFormat::Dot => {
+            out.push(String::from("digraph stamp {"));
+            out.push(String::from("    rankdir=TB;"));
+            out.push(String::from("    node [shape=box, fontname=\"monospace\"];"));
+            for (name, label, kind) in nodes.iter() {
+                let style = match *kind {
+                    "merge" => ", style=filled, fillcolor=\"#cfe2ff\"",
+                    "fork" => ", style=filled, fillcolor=\"#fff3cd\"",
+                    "head" => ", peripheries=2",
+                    _ => "",
+                };
+                out.push(format!("    {} [label=\"{}\"{}];", name, label, style));
+            }
+            for (name, label) in missing.iter() {
+                out.push(format!("    {} [label=\"{}\", style=dashed];", name, label));
+            }
+            for (from, to, dashed) in edges.iter() {
+                let style = if *dashed { " [style=dashed]" } else { "" };
+                out.push(format!("    {} -> {}{};", from, to, style));
+            }
+            out.push(String::from("}"));
+        }
+        GraphFormat::Mermaid => {
+            out.push(String::from("graph TD"));
+            for (name, label, kind) in nodes.iter() {
+                let label = label.replace("\\n", "<br/>");
+                match *kind {
+                    "merge" | "fork" => out.push(format!("    {}{{\"{}\"}}", name, label)),
+                    "head" => out.push(format!("    {}([\"{}\"])", name, label)),
+                    _ => out.push(format!("    {}[\"{}\"]", name, label)),
+                }
+            }
+            for (name, label) in missing.iter() {
+                out.push(format!("    {}[\"{}\"]", name, label.replace("\\n", "<br/>")));
+                out.push(format!("    style {} stroke-dasharray: 5 5", name));
+            }
+            for (from, to, dashed) in edges.iter() {
+                let arrow = if *dashed { "-.->" } else { "-->" };
+                out.push(format!("    {} {} {}", from, arrow, to));
+            }
+        }
+    }
+    out.push(String::from(""));
+    util::write_file(output, out.join("\n").as_bytes())?;
+    Ok(())
+}
+
+/// Find the name a key was created with, by looking for the transaction that
+/// added it.
+pub(crate) fn key_name(history: &[Transaction], key_id: &KeyID) -> Option<String> {
+    history.iter().find_map(|t| match t.entry().body() {
+        TransactionBody::AddSubkeyV1 { key, name, .. } if &key.key_id() == key_id => Some(name.clone()),
+        TransactionBody::AddAdminKeyV1 { admin_key } if &admin_key.key().key_id() == key_id => Some(admin_key.name().clone()),
+        _ => None,
+    })
+}
+
+/// Find the type of a claim, by looking for the transaction that made it.
+fn claim_desc(history: &[Transaction], claim_id: &TransactionID) -> String {
+    let claim_id_str = format!("{}", claim_id);
+    let ty = history.iter().find_map(|t| match t.entry().body() {
+        TransactionBody::MakeClaimV1 { spec, .. } if t.id() == claim_id => Some(claim::claim_type(spec)),
+        _ => None,
+    });
+    match ty {
+        Some(ty) => format!("{} claim {}", ty, ClaimID::short(&claim_id_str)),
+        None => format!("claim {}", ClaimID::short(&claim_id_str)),
+    }
+}
+
+/// A short, human-readable description of what a transaction does, ex "Added
+/// crypto key 'email'". `history` is the rest of the identity's transactions,
+/// used to look up things like key names that the transaction only references.
+pub fn transaction_description(trans: &Transaction, history: &[Transaction]) -> String {
+    let named_key = |key_id: KeyID| match key_name(history, &key_id) {
+        Some(name) => format!("key '{}'", name),
+        None => format!("key {}", key_id),
+    };
+    match trans.entry().body() {
+        TransactionBody::CreateIdentityV1 { .. } => String::from("Created identity"),
+        TransactionBody::ResetIdentityV1 { .. } => String::from("Reset admin keys and policies"),
+        TransactionBody::AddAdminKeyV1 { admin_key } => format!("Added admin key '{}'", admin_key.name()),
+        TransactionBody::EditAdminKeyV1 { id, .. } => format!("Edited admin {}", named_key(KeyID::from(id.clone()))),
+        TransactionBody::RevokeAdminKeyV1 { id, .. } => format!("Revoked admin {}", named_key(KeyID::from(id.clone()))),
+        TransactionBody::AddPolicyV1 { .. } => String::from("Added policy"),
+        TransactionBody::DeletePolicyV1 { .. } => String::from("Deleted policy"),
+        TransactionBody::MakeClaimV1 { spec, name, .. } => {
+            let name = name.as_ref().map(|x| format!(" '{}'", x)).unwrap_or_default();
+            let private = if spec.has_private() { "private " } else { "" };
+            format!("Made {}{} claim{}", private, claim::claim_type(spec), name)
+        }
+        TransactionBody::EditClaimV1 { claim_id, name } => match name {
+            Some(name) => format!("Renamed {} to '{}'", claim_desc(history, claim_id.deref()), name),
+            None => format!("Removed name from {}", claim_desc(history, claim_id.deref())),
+        },
+        TransactionBody::DeleteClaimV1 { claim_id } => format!("Deleted {}", claim_desc(history, claim_id.deref())),
+        TransactionBody::MakeStampV1 { stamp } => format!(
+            "Stamped claim {} of identity {}",
+            ClaimID::short(&format!("{}", stamp.claim_id().deref())),
+            IdentityID::short(&format!("{}", stamp.stampee()))
+        ),
+        TransactionBody::RevokeStampV1 { stamp_id, .. } => format!("Revoked stamp {}", stamp_id),
+        TransactionBody::AcceptStampV1 { stamp_transaction } => match stamp_transaction.entry().body() {
+            TransactionBody::MakeStampV1 { stamp } => format!(
+                "Accepted stamp from {} on {}",
+                IdentityID::short(&format!("{}", stamp.stamper())),
+                claim_desc(history, stamp.claim_id().deref())
+            ),
+            _ => format!("Accepted stamp {}", stamp_transaction.id()),
+        },
+        TransactionBody::DeleteStampV1 { stamp_id } => format!("Deleted stamp {}", stamp_id.deref()),
+        TransactionBody::AddSubkeyV1 { key, name, .. } => {
+            let ty = match key {
+                Key::Sign(..) => "sign",
+                Key::Crypto(..) => "crypto",
+                Key::Secret(..) => "secret",
+            };
+            format!("Added {} key '{}'", ty, name)
+        }
+        TransactionBody::EditSubkeyV1 { id, .. } => format!("Edited {}", named_key(id.clone())),
+        TransactionBody::RevokeSubkeyV1 { id, .. } => format!("Revoked {}", named_key(id.clone())),
+        TransactionBody::DeleteSubkeyV1 { id, .. } => format!("Deleted {}", named_key(id.clone())),
+        TransactionBody::PublishV1 { .. } => String::from("Published identity"),
+        TransactionBody::SignV1 { body_hash, .. } => format!("Signed document with hash {}", body_hash),
+        TransactionBody::ExtV1 { .. } => String::from("Extension transaction"),
+    }
+}
+
+/// Save an identity to a recovery bundle that [import_bundle] can restore,
+/// either at `path` or in a timestamped file under `folder` in Stamp's data
+/// directory. Returns where it was saved.
+fn save_recovery_bundle(transactions: &Transactions, id_str: &str, folder: &str, path: Option<&str>, force: bool) -> Result<String> {
+    let backup_path = match path {
+        Some("-") if std::io::stdout().is_terminal() => Err(anyhow!(
+            "Refusing to write a binary recovery bundle to the terminal. Pipe the output somewhere or give a file."
+        ))?,
+        Some(path) => {
+            let path = std::path::PathBuf::from(path);
+            if path != std::path::Path::new("-") && path.exists() && !force {
+                Err(anyhow!("The backup file {} already exists (use --force to overwrite it)", path.to_string_lossy()))?;
+            }
+            path
+        }
+        None => {
+            let mut dir = config::data_dir()?;
+            dir.push(folder);
+            std::fs::create_dir_all(&dir).map_err(|e| anyhow!("Problem creating backup directory: {:?}: {}", dir, e))?;
+            dir.push(format!("{}-{}.bundle", IdentityID::short(id_str), Timestamp::now().local().format("%Y%m%d-%H%M%S")));
+            dir
+        }
+    };
+    let backup_str = backup_path.to_string_lossy().to_string();
+    util::write_file(&backup_str, util::serialize_cbor(&make_bundle(transactions, false))?.as_slice())?;
+    Ok(backup_str)
+}
+
+/// Roll an identity back to `txid`. Before anything is removed, the identity as
+/// it was is written to a recovery bundle (see [import_bundle]) so the reset can
+/// be undone. With `dry_run`, only show what would be removed.
+pub fn reset(id: &str, txid: &str, dry_run: bool, backup: Option<&str>, force: bool) -> Result<()> {
     let transactions = id::try_load_single_identity(id)?;
     let identity = util::build_identity(&transactions)?;
     let id_str = id_str!(identity.id())?;
@@ -30,13 +744,48 @@ pub fn reset(id: &str, txid: &str) -> Result<()> {
         .clone()
         .reset(trans.id())
         .map_err(|e| anyhow!("Problem resetting transactions: {}", e))?;
-    let removed = transactions.transactions().len() - transactions_reset.transactions().len();
-    println!("Removed {} transactions from identity {}", removed, IdentityID::short(&id_str));
+    let removed = transactions
+        .transactions()
+        .iter()
+        .filter(|t| !transactions_reset.transactions().iter().any(|x| x.id() == t.id()))
+        .cloned()
+        .collect::<Vec<_>>();
+    if removed.len() == 0 {
+        println!("Nothing to reset, {} is the latest transaction.", trans.id());
+        return Ok(());
+    }
+    if dry_run {
+        println!("Resetting to {} would remove {} transaction(s):", trans.id(), removed.len());
+        print_transactions_table_with_history(&removed, transactions.transactions().as_slice());
+        let changes = net::identity_changes(&identity, &util::build_identity(&transactions_reset)?);
+        if changes.len() > 0 {
+            println!("\nThe identity would change like so:");
+            for change in changes {
+                println!("  {}", change);
+            }
+        }
+        println!("\nNothing was changed.");
+        return Ok(());
+    }
+
+    let backup_str = save_recovery_bundle(&transactions, &id_str, "reset-backups", backup, force)?;
+    // if the bundle went to stdout, keep our chatter out of it
+    let say = |msg: String| {
+        if backup_str == "-" {
+            eprintln!("{}", msg);
+        } else {
+            println!("{}", msg);
+        }
+    };
+    say(format!("Saved the identity as it was to {}", backup_str));
+
+    say(format!("Removed {} transactions from identity {}", removed.len(), IdentityID::short(&id_str)));
     db::save_identity(transactions_reset)?;
+    say(format!("To undo this reset, run:\n  stamp dag import-bundle {}", backup_str));
     Ok(())
 }
 
-pub fn export(id: &str, txid: &str, output: &str, base64: bool) -> Result<()> {
+pub fn export(id: &str, txid: &str, output: &str, base64: bool, cbor: bool) -> Result<()> {
     let transactions = id::try_load_single_identity(id)?;
     let identity = util::build_identity(&transactions)?;
     let id_str = id_str!(identity.id())?;
@@ -45,9 +794,13 @@ pub fn export(id: &str, txid: &str, output: &str, base64: bool) -> Result<()> {
         .iter()
         .find(|x| id_str!(x.id()).map(|id| id.starts_with(txid)).unwrap_or(false))
         .ok_or(anyhow!("Transaction {} not found for identity {}", txid, IdentityID::short(&id_str)))?;
-    let serialized = trans
-        .serialize_binary()
-        .map_err(|e| anyhow!("Problem serializing transaction: {:?}", e))?;
+    let serialized = if cbor {
+        util::serialize_cbor(trans)?
+    } else {
+        trans
+            .serialize_binary()
+            .map_err(|e| anyhow!("Problem serializing transaction: {:?}", e))?
+    };
     if base64 {
         let serialized_str = base64_encode(serialized.as_slice());
         util::write_file(output, serialized_str.as_bytes())?;
@@ -57,6 +810,119 @@ pub fn export(id: &str, txid: &str, output: &str, base64: bool) -> Result<()> {
     Ok(())
 }
 
+// TODO: `stamp dag compact`. Compacting needs a checkpoint transaction in
+// stamp-core that commits to the identity's state (and the hash of the history
+// before it) so identities can be built and verified from the checkpoint on,
+// with older transactions moved to an archive (ex a bundle from `export_all`)
+// that's only read back for a full `stamp dag verify`. Until core can build an
+// identity from anything but the genesis transaction, there's nothing for the
+// CLI to compact.
+
+/// An identity's entire DAG, exactly as stored, for moving between machines or
+/// keeping in cold storage.
+#[derive(Debug, Serialize, Deserialize)]
+struct DagBundle {
+    identity: String,
+    exported: Timestamp,
+    /// Whether private data was stripped before export.
+    public: bool,
+    transactions: Vec<Transaction>,
+}
+
+fn make_bundle(transactions: &Transactions, public: bool) -> DagBundle {
+    DagBundle {
+        identity: transactions.identity_id().and_then(|x| id_str!(&x).ok()).unwrap_or_default(),
+        exported: Timestamp::now(),
+        public,
+        transactions: transactions
+            .transactions()
+            .iter()
+            .map(|t| if public { t.clone().strip_private() } else { t.clone() })
+            .collect(),
+    }
+}
+
+/// Export every transaction in an identity as a bundle. Unless `public` is set
+/// the bundle keeps (encrypted) private data, so it's treated like any other
+/// secret export.
+pub fn export_all(id: &str, output: &str, public: bool) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let has_private = transactions.transactions().iter().any(|t| t.has_private());
+    if !public && has_private {
+        util::confirm_secret_export(&id_str)?;
+    }
+    let bundle = make_bundle(&transactions, public);
+    let serialized = util::serialize_cbor(&bundle)?;
+    util::write_file(output, serialized.as_slice())?;
+    if !public && has_private {
+        status::record(&id_str, status::BackupEvent::Backup)?;
+    }
+    eprintln!("Exported {} transaction(s) from identity {}", bundle.transactions.len(), IdentityID::short(&id_str));
+    Ok(())
+}
+
+/// Import a bundle from [export_all]. Every transaction is verified by
+/// rebuilding the identity from scratch, then the result is merged into the
+/// local copy (if any) so nothing we have already is lost.
+pub fn import_bundle(input: &str, skip_confirm: bool) -> Result<()> {
+    let contents = util::read_file(input)?;
+    let bundle = util::deserialize_cbor::<DagBundle>(contents.as_slice())
+        .map_err(|e| anyhow!("This doesn't look like a bundle from `stamp dag export-all`: {}", e))?;
+    let mut theirs = Transactions::new();
+    for (i, trans) in bundle.transactions.iter().enumerate() {
+        theirs = theirs.push_transaction(trans.clone()).map_err(|e| {
+            anyhow!(
+                "Bundle failed verification at transaction {} of {} ({}): {:?}",
+                i + 1,
+                bundle.transactions.len(),
+                trans.id(),
+                e
+            )
+        })?;
+    }
+    let identity = util::build_identity(&theirs)?;
+    let id_str = id_str!(identity.id())?;
+    if id_str != bundle.identity {
+        Err(anyhow!(
+            "Bundle claims to be identity {} but its transactions build identity {}",
+            IdentityID::short(&bundle.identity),
+            IdentityID::short(&id_str)
+        ))?;
+    }
+    let public = if bundle.public { " (public data only)" } else { "" };
+    println!(
+        "Bundle for identity {}{}, exported {}, {} transaction(s) verified.",
+        IdentityID::short(&id_str),
+        public,
+        bundle.exported.local().format("%b %e, %Y  %H:%M:%S"),
+        bundle.transactions.len()
+    );
+    match db::load_identity(identity.id())? {
+        Some(ours) => {
+            let (merged, added) = sync::merge_transactions(ours, &theirs)?;
+            if added == 0 {
+                println!("Identity {} is already up to date.", IdentityID::short(&id_str));
+                return Ok(());
+            }
+            if !skip_confirm && !util::yesno_prompt(&format!("Merge {} new transaction(s) into the local identity? [Y/n]", added), "y")? {
+                return Ok(());
+            }
+            db::save_identity(merged)?;
+            println!("Merged {} transaction(s) into identity {}", added, IdentityID::short(&id_str));
+        }
+        None => {
+            if !skip_confirm && !util::yesno_prompt("This identity doesn't exist locally. Import it? [Y/n]", "y")? {
+                return Ok(());
+            }
+            db::save_identity(theirs)?;
+            println!("Imported identity {}", IdentityID::short(&id_str));
+        }
+    }
+    Ok(())
+}
+
 pub fn post_save(transactions: &Transactions, transaction: &Transaction, stage: bool) -> Result<Option<String>> {
     let identity = util::build_identity(transactions)?;
     let view_staged = || format!("View the staged transaction with:\n  stamp stage view {}", transaction.id());
@@ -211,7 +1077,13 @@ pub fn post_save(transactions: &Transactions, transaction: &Transaction, stage:
         }
         TransactionBody::SignV1 { creator, body_hash } => {
             if stage {
-                format!("Policy signature with hash {} created. {}", body_hash, view_staged())
+                format!(
+                    "Policy signature with hash {} created. Other admins can add their signatures with `stamp stage sign {}`, and once it's valid, write it out with `stamp sign finish {}`.\n{}",
+                    body_hash,
+                    transaction.id(),
+                    transaction.id(),
+                    view_staged()
+                )
             } else {
                 // weird, should never get here.
                 return Ok(None);
@@ -222,23 +1094,60 @@ pub fn post_save(transactions: &Transactions, transaction: &Transaction, stage:
     Ok(Some(msg))
 }
 
-pub fn save_or_stage(transactions: Transactions, transaction: Transaction, stage: bool) -> Result<Transactions> {
+/// Save an updated identity, first making sure the stored copy hasn't changed
+/// since `loaded` was read (for instance by the agent syncing while a command
+/// waited on a passphrase prompt). If it has, either offer to rebase the update
+/// onto the stored copy or refuse to save, so changes are never silently lost.
+pub(crate) fn save_checked(loaded: &Transactions, updated: Transactions, allow_rebase: bool) -> Result<Transactions> {
+    let identity_id = loaded.identity_id().ok_or(anyhow!("Unable to generate identity id"))?;
+    let current = match db::load_identity(&identity_id)? {
+        Some(x) => x,
+        None => return db::save_identity(updated),
+    };
+    let loaded_ids = loaded.transactions().iter().map(|t| t.id()).collect::<Vec<_>>();
+    let moved = current.transactions().iter().filter(|t| !loaded_ids.contains(&t.id())).count();
+    if moved == 0 {
+        return db::save_identity(updated);
+    }
+    let yellow = dialoguer::console::Style::new().yellow();
+    eprintln!(
+        "{} {}",
+        yellow.apply_to("Warning:"),
+        util::text_wrap(&format!(
+            "Identity {} was changed by something else ({} new transaction(s)) while this command was running.",
+            identity_id, moved
+        ))
+    );
+    if !allow_rebase {
+        Err(anyhow!("Refusing to overwrite the newer identity. Run the command again."))?;
+    }
+    if !util::yesno_prompt("Apply your change on top of the newer version? [Y/n]", "y")? {
+        Err(anyhow!("Aborted, nothing was saved"))?;
+    }
+    let (rebased, _) = sync::merge_transactions(current, &updated)?;
+    db::save_identity(rebased)
+}
+
+/// Save a transaction to its identity, or stage it. Returns the identity and,
+/// if the transaction was staged, its staged ID.
+pub fn save_or_stage(transactions: Transactions, transaction: Transaction, stage: bool) -> Result<(Transactions, Option<String>)> {
     let identity_id = transactions.identity_id().ok_or(anyhow!("Unable to generate identity id"))?;
     let trans_clone = transaction.clone();
-    let transactions = if stage {
-        stage_transaction(&identity_id, transaction).map_err(|e| anyhow!("Error staging transaction: {:?}", e))?;
-        transactions
+    let (transactions, staged) = if stage {
+        let txid = stage::stage(&identity_id, transaction)?;
+        (transactions, Some(txid))
     } else {
+        let loaded = transactions.clone();
         let transactions_mod = transactions
             .push_transaction(transaction)
             .map_err(|e| anyhow!("Error saving transaction: {:?}", e))?;
-        db::save_identity(transactions_mod)?
+        (save_checked(&loaded, transactions_mod, true)?, None)
     };
     let msg = post_save(&transactions, &trans_clone, stage)?;
     if let Some(msg) = msg {
         println!("{}", msg);
     }
-    Ok(transactions)
+    Ok((transactions, staged))
 }
 
 pub fn transaction_to_string(trans: &Transaction) -> &'static str {
@@ -270,15 +1179,22 @@ pub fn transaction_to_string(trans: &Transaction) -> &'static str {
 }
 
 pub fn print_transactions_table(transactions: &Vec<Transaction>) {
+    print_transactions_table_with_history(transactions, transactions.as_slice());
+}
+
+/// Print a table of some of an identity's transactions, using the rest of its
+/// `history` to describe them (ex to look up key names).
+pub fn print_transactions_table_with_history(transactions: &Vec<Transaction>, history: &[Transaction]) {
     let mut table = Table::new();
     table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
-    table.set_titles(row!["ID", "Type", "Signatures", "Created"]);
+    table.set_titles(row!["ID", "Type", "Description", "Signatures", "Created"]);
     for trans in transactions {
         let ty = transaction_to_string(trans);
+        let desc = transaction_description(trans, history);
         let id = id_str!(trans.id()).unwrap_or_else(|e| format!("<bad id {:?} -- {:?}>", trans.id(), e));
         let created = trans.entry().created().local().format("%b %e, %Y  %H:%M:%S");
         let num_sig = trans.signatures().len();
-        table.add_row(row![id, ty, num_sig, created,]);
+        table.add_row(row![id, ty, desc, num_sig, created,]);
     }
     table.printstd();
 }
@@ -0,0 +1,194 @@
+use crate::{
+    config,
+    db::{self, RecordRef},
+    util,
+};
+use anyhow::{anyhow, Result};
+use stamp_aux::db::load_staged_transaction;
+use stamp_core::{dag::TransactionID, identity::IdentityID, util::SerdeBinary};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+
+/// Check the local db: every identity builds, every record can be read, and
+/// nothing points at an identity or staged transaction that's gone. With
+/// `repair`, broken identities and records are moved into the `quarantine`
+/// folder of the data directory (and orphaned records with them) so other
+/// commands stop tripping over them.
+pub fn fsck(repair: bool) -> Result<()> {
+    let green = dialoguer::console::Style::new().green();
+    let red = dialoguer::console::Style::new().red();
+    let yellow = dialoguer::console::Style::new().yellow();
+    let mut problems = 0;
+    let mut repaired = 0;
+
+    // identities. each one maps to None if it builds, or why it doesn't
+    let identities = db::list_local_identities(None).map_err(|e| {
+        anyhow!(
+            "The identity database can't be read, so nothing else can be checked: {}\nRestore it from a backup, or re-import your identities.",
+            e
+        )
+    })?;
+    let mut identity_errors: HashMap<String, Option<String>> = HashMap::new();
+    let mut claim_ids = HashSet::new();
+    for transactions in identities.iter() {
+        let err = match util::build_identity(transactions) {
+            Ok(identity) => {
+                claim_ids.extend(identity.claims().iter().filter_map(|c| id_str!(c.id()).ok()));
+                None
+            }
+            Err(e) => Some(format!("{}", e)),
+        };
+        let id_str = match transactions.identity_id().and_then(|x| id_str!(&x).ok()) {
+            Some(id_str) => id_str,
+            None => {
+                // without an ID the database can't be asked to remove it, so
+                // save a copy and try the genesis transaction's ID, which the
+                // identity ID is normally made from
+                problems += 1;
+                let genesis = transactions.transactions().get(0).and_then(|t| id_str!(t.id()).ok());
+                println!(
+                    "{} an identity with no ID (first transaction {}) can't be read: {}",
+                    red.apply_to("Broken:"),
+                    genesis.as_ref().map(|x| x.as_str()).unwrap_or("<none>"),
+                    err.unwrap_or_else(|| String::from("no identity ID"))
+                );
+                if repair {
+                    let name = format!("unknown-{}", genesis.as_ref().map(|x| x.as_str()).unwrap_or("empty"));
+                    let dest = quarantine_identity(transactions, &name)?;
+                    println!("  saved a copy to {}", dest);
+                    match genesis.as_ref().map(|id| db::delete_identity(id)) {
+                        Some(Ok(_)) => {
+                            repaired += 1;
+                            println!("  removed it from the identity database");
+                        }
+                        _ => println!("  it couldn't be removed from the identity database. Restore the database from a backup."),
+                    }
+                }
+                continue;
+            }
+        };
+        identity_errors.insert(id_str.clone(), err.clone());
+        if let Some(err) = err {
+            problems += 1;
+            println!("{} identity {} doesn't build: {}", red.apply_to("Broken:"), IdentityID::short(&id_str), err);
+            if repair {
+                let dest = quarantine_identity(transactions, &id_str)?;
+                db::delete_identity(&id_str)?;
+                repaired += 1;
+                println!("  quarantined to {}", dest);
+            }
+        }
+    }
+    println!("Checked {} identities.", identities.len());
+
+    // records
+    let kinds = db::RECORD_KINDS.iter().map(|(kind, _)| *kind).collect::<Vec<_>>();
+    for kind in kinds.iter() {
+        for (key, err) in db::check_records(kind)? {
+            problems += 1;
+            println!("{} {} record {}: {}", red.apply_to("Broken:"), kind, key, err);
+            if repair {
+                let dest = db::quarantine_record(kind, &key)?;
+                repaired += 1;
+                println!("  quarantined to {}", dest.to_string_lossy());
+            }
+        }
+    }
+    for kind in db::list_record_kinds()? {
+        if !kinds.contains(&kind.as_str()) {
+            println!(
+                "{} the {} folder in Stamp's data directory isn't a kind of record Stamp knows about, so it wasn't checked.",
+                yellow.apply_to("Unknown:"),
+                kind
+            );
+        }
+    }
+    println!("Checked {} kinds of records.", kinds.len());
+
+    // orphans. records that couldn't be read were reported above, so skip them
+    let readable = |kind: &str| -> Result<Vec<(String, toml::Value)>> {
+        Ok(db::list_record_keys(kind)?
+            .into_iter()
+            .filter_map(|key| match db::load_record::<toml::Value>(kind, &key) {
+                Ok(Some(value)) => Some((key, value)),
+                _ => None,
+            })
+            .collect())
+    };
+    // why a record pointing at this identity is orphaned, if it is. records of
+    // identities that don't build get the build error, since they go to
+    // quarantine along with the identity.
+    let identity_problem = |id: &str| match identity_errors.get(id) {
+        Some(None) => None,
+        Some(Some(err)) => Some(format!("identity {} doesn't build: {}", IdentityID::short(id), err)),
+        None => Some(format!("identity {} isn't stored locally", IdentityID::short(id))),
+    };
+    let staged_exists = |key: &str| -> Result<bool> {
+        match TransactionID::try_from(key) {
+            Ok(txid) => Ok(load_staged_transaction(&txid)
+                .map_err(|e| anyhow!("Error loading staged transaction: {:?}", e))?
+                .is_some()),
+            Err(_) => Ok(false),
+        }
+    };
+    let mut orphans: Vec<(&str, String, String)> = Vec::new();
+    for (kind, refs) in db::RECORD_KINDS {
+        for (key, value) in readable(kind)? {
+            let mut why = None;
+            for rec_ref in refs.iter() {
+                why = match rec_ref {
+                    RecordRef::IdentityKey => identity_problem(&key),
+                    RecordRef::IdentityField(field) => value.get(*field).and_then(|x| x.as_str()).and_then(|id| identity_problem(id)),
+                    RecordRef::StagedKey if !staged_exists(&key)? => Some(String::from("the staged transaction is gone")),
+                    RecordRef::ClaimKey if !claim_ids.contains(&key) && !staged_exists(&key)? => {
+                        Some(String::from("the claim isn't on any local identity"))
+                    }
+                    _ => None,
+                };
+                if why.is_some() {
+                    break;
+                }
+            }
+            if let Some(why) = why {
+                orphans.push((*kind, key, why));
+            }
+        }
+    }
+    // TODO: stamp_aux can only look up staged transactions by identity, so
+    // staged transactions for identities that no longer exist can't be found
+    // from here.
+    for (kind, key, why) in orphans.iter() {
+        problems += 1;
+        println!("{} {} record {}: {}", yellow.apply_to("Orphaned:"), kind, key, why);
+        if repair {
+            let dest = db::quarantine_record(kind, key)?;
+            repaired += 1;
+            println!("  quarantined to {}", dest.to_string_lossy());
+        }
+    }
+
+    if problems == 0 {
+        println!("{} no problems found.", green.apply_to("OK:"));
+    } else if repair {
+        println!("Found {} problem(s), quarantined {}.", problems, repaired);
+    } else {
+        Err(anyhow!("Found {} problem(s). Run `stamp db fsck --repair` to quarantine them.", problems))?;
+    }
+    Ok(())
+}
+
+/// Save a copy of a broken identity in the `quarantine` folder of the data
+/// directory. Returns where it went.
+fn quarantine_identity(transactions: &stamp_core::dag::Transactions, name: &str) -> Result<String> {
+    let mut dest = config::data_dir()?;
+    dest.push("quarantine");
+    dest.push("identities");
+    std::fs::create_dir_all(&dest).map_err(|e| anyhow!("Problem creating quarantine directory: {:?}: {}", dest, e))?;
+    dest.push(format!("{}.stamp", name));
+    let serialized = transactions
+        .serialize_binary()
+        .map_err(|e| anyhow!("Problem serializing identity {}: {:?}", name, e))?;
+    let dest = dest.to_string_lossy().to_string();
+    util::write_file(&dest, serialized.as_slice())?;
+    Ok(dest)
+}
@@ -1,10 +1,16 @@
-use crate::{commands::id, db, util};
+use crate::{
+    commands::{dag, id, net},
+    db, util,
+};
 use anyhow::{anyhow, Result};
 #[cfg(feature = "yaml-export")]
+use stamp_core::util::{text_export, text_import};
 use stamp_core::{
     dag::Transactions,
-    util::{text_export, text_import},
+    identity::Identity,
+    util::{base64_decode, SerdeBinary},
 };
+use std::convert::TryFrom;
 
 pub fn resave(id: &str) -> Result<()> {
     let identity = id::try_load_single_identity(id)?;
@@ -18,28 +24,165 @@ pub fn export(id: &str) -> Result<()> {
     unimplemented!("Please enable yaml-export feature.");
 }
 
-#[cfg(not(feature = "yaml-export"))]
-pub fn import(id: &str) -> Result<()> {
-    unimplemented!("Please enable yaml-export feature.");
-}
-
 #[cfg(feature = "yaml-export")]
 pub fn export(id: &str) -> Result<()> {
     let identity = id::try_load_single_identity(id)?;
+    let identity_id = identity.identity_id().expect("should have identity id");
+    util::confirm_secret_export(&id_str!(&identity_id)?)?;
     let export = text_export(&identity)?;
     println!("{}", export);
     Ok(())
 }
 
-#[cfg(feature = "yaml-export")]
-pub fn import(export_file: &str) -> Result<()> {
-    let yaml = util::read_file(export_file)?;
-    let yaml_string = String::from_utf8(yaml).map_err(|e| anyhow!("Error reading YAML file: {}", e))?;
-    let identity: Transactions = text_import(&yaml_string)?;
-    let identity_id = identity.identity_id()
-        // panics are fine and kewl if you are building debug commands...
-        .expect("should have identity id");
+/// Read an identity from a debug (YAML) export, or from any of the formats
+/// identities are otherwise exported in (binary, base64, CBOR).
+fn parse_import(contents: &[u8]) -> Result<Transactions> {
+    #[cfg(feature = "yaml-export")]
+    if let Ok(yaml_string) = std::str::from_utf8(contents) {
+        if let Ok(identity) = text_import::<Transactions>(yaml_string) {
+            return Ok(identity);
+        }
+    }
+    Transactions::deserialize_binary(contents)
+        .or_else(|_| Transactions::deserialize_binary(&base64_decode(contents)?))
+        .or_else(|_| util::deserialize_cbor::<Transactions>(contents))
+        .map_err(|e| anyhow!("Unable to read an identity from the import: {}", e))
+}
+
+/// Check an identity before importing it: that every transaction applies and the
+/// identity builds, and what importing it would do to the local copy. Returns
+/// whether the identity is valid.
+fn preflight(identity: &Transactions) -> Result<bool> {
+    let green = dialoguer::console::Style::new().green();
+    let red = dialoguer::console::Style::new().red();
+    let yellow = dialoguer::console::Style::new().yellow();
+    let identity_id = identity.identity_id().ok_or(anyhow!("The import doesn't contain an identity"))?;
+    let total = identity.transactions().len();
+    println!("Identity:     {}", identity_id);
+    println!("Transactions: {}", total);
+
+    let mut replayed = Transactions::new();
+    let mut failed = None;
+    for (i, trans) in identity.transactions().iter().enumerate() {
+        match replayed.clone().push_transaction(trans.clone()) {
+            Ok(next) => replayed = next,
+            Err(e) => {
+                failed = Some(format!("transaction {} of {} ({}) failed: {:?}", i + 1, total, trans.id(), e));
+                break;
+            }
+        }
+    }
+    let valid = match (failed, util::build_identity(identity)) {
+        (Some(err), _) => {
+            println!("Valid:        {} {}", red.apply_to("no,"), err);
+            false
+        }
+        (None, Err(e)) => {
+            println!("Valid:        {} {}", red.apply_to("no,"), e);
+            false
+        }
+        (None, Ok(built)) => {
+            let owned = if built.is_owned() {
+                "owned, includes private keys"
+            } else {
+                "not owned"
+            };
+            println!("Valid:        {} ({})", green.apply_to("yes"), owned);
+            true
+        }
+    };
+
+    match db::load_identity(&identity_id)? {
+        None => println!("Local copy:   none, the identity would be created"),
+        Some(local) => {
+            let import_ids = identity.transactions().iter().map(|t| t.id()).collect::<Vec<_>>();
+            let local_ids = local.transactions().iter().map(|t| t.id()).collect::<Vec<_>>();
+            let lost = local_ids.iter().filter(|id| !import_ids.contains(id)).count();
+            let added = import_ids.iter().filter(|id| !local_ids.contains(id)).count();
+            if lost == 0 && added == 0 {
+                println!("Local copy:   identical");
+            } else if lost > 0 {
+                println!(
+                    "Local copy:   {} {} local transaction(s) not in the import would be lost, {} would be added",
+                    yellow.apply_to("would be overwritten,"),
+                    lost,
+                    added
+                );
+            } else {
+                println!("Local copy:   would be overwritten, {} transaction(s) would be added", added);
+            }
+        }
+    }
+    Ok(valid)
+}
+
+/// Import an identity exported with `stamp debug export`, overwriting any local
+/// copy. With `verify_only`, only report what the import contains.
+pub fn import(export_file: &str, verify_only: bool) -> Result<()> {
+    let contents = util::read_file(export_file)?;
+    let identity = parse_import(contents.as_slice())?;
+    let valid = preflight(&identity)?;
+    if verify_only {
+        if !valid {
+            Err(anyhow!("The import is not a valid identity"))?;
+        }
+        return Ok(());
+    }
+    if !valid {
+        Err(anyhow!("Refusing to import an identity that doesn't build"))?;
+    }
+    let identity_id = identity.identity_id().ok_or(anyhow!("The import doesn't contain an identity"))?;
     db::save_identity(identity)?;
     println!("Identity {} imported.", identity_id);
     Ok(())
 }
+
+/// Rebuild an identity one transaction at a time, printing what each one changed
+/// and stopping at the first transaction that fails to verify or apply.
+pub fn replay(id: &str) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let green = dialoguer::console::Style::new().green();
+    let red = dialoguer::console::Style::new().red();
+    let total = transactions.transactions().len();
+    let mut replayed = Transactions::new();
+    let mut previous: Option<Identity> = None;
+    for (i, trans) in transactions.transactions().iter().enumerate() {
+        println!(
+            "[{}/{}] {} {} ({})",
+            i + 1,
+            total,
+            id_str!(trans.id())?,
+            dag::transaction_to_string(trans),
+            trans.entry().created().local().format("%b %d, %Y %H:%M:%S")
+        );
+        if let Err(e) = trans.verify(previous.as_ref()) {
+            println!("  {} {}", red.apply_to("verify failed:"), e);
+            return Err(anyhow!("Replay stopped at transaction {} of {}", i + 1, total));
+        }
+        replayed = match replayed.push_transaction(trans.clone()) {
+            Ok(x) => x,
+            Err(e) => {
+                println!("  {} {:?}", red.apply_to("apply failed:"), e);
+                return Err(anyhow!("Replay stopped at transaction {} of {}", i + 1, total));
+            }
+        };
+        let identity = match util::build_identity(&replayed) {
+            Ok(x) => x,
+            Err(e) => {
+                println!("  {} {}", red.apply_to("build failed:"), e);
+                return Err(anyhow!("Replay stopped at transaction {} of {}", i + 1, total));
+            }
+        };
+        let changes = match previous.as_ref() {
+            Some(previous) => net::identity_changes(previous, &identity),
+            None => vec![format!("Identity created: {}", id_str!(identity.id())?)],
+        };
+        println!("  {}", green.apply_to("ok"));
+        for change in changes {
+            println!("    {}", change);
+        }
+        previous = Some(identity);
+    }
+    println!("\nReplayed {} transactions without errors.", total);
+    Ok(())
+}
@@ -1,8 +1,10 @@
-use crate::{config, db, util};
+use crate::{
+    commands::{audit, claim, stage, status, sync},
+    config, db, util,
+};
 use anyhow::{anyhow, Result};
 use indicatif::{ProgressBar, ProgressStyle};
 use prettytable::Table;
-use stamp_aux::db::stage_transaction;
 use stamp_core::{
     crypto::base::SecretKey,
     dag::Transactions,
@@ -88,11 +90,43 @@ pub(crate) fn create_vanity(
 
 pub fn import(location: &str, join: Vec<Multiaddr>) -> Result<()> {
     let contents = util::load_file_extended(location, join)?;
+    // identities exported as CBOR get converted back to the native format
+    let contents = match util::deserialize_cbor::<Transactions>(contents.as_slice()) {
+        Ok(transactions) => transactions
+            .serialize_binary()
+            .map_err(|e| anyhow!("Error importing identity: {:?}", e))?,
+        Err(_) => contents,
+    };
     let (transactions, existing) =
         stamp_aux::id::import_pre(contents.as_slice()).map_err(|e| anyhow!("Error importing identity: {}", e))?;
     let identity = util::build_identity(&transactions)?;
     if existing.is_some() {
-        if !util::yesno_prompt("The identity you're importing already exists locally. Overwrite? [y/N]", "n")? {
+        // merge rather than overwrite so we don't clobber local changes
+        let ours = db::load_identity(identity.id())?.ok_or(anyhow!("Unable to load existing identity"))?;
+        let (merged, added) = sync::merge_transactions(ours, &transactions)?;
+        let id_str = id_str!(identity.id())?;
+        if added == 0 {
+            println!("Identity {} already exists locally and is up to date.", IdentityID::short(&id_str));
+            return Ok(());
+        }
+        if !util::yesno_prompt(
+            &format!(
+                "The identity you're importing already exists locally. Merge {} new transaction(s) into it? [Y/n]",
+                added
+            ),
+            "y",
+        )? {
+            return Ok(());
+        }
+        db::save_identity(merged)?;
+        let green = dialoguer::console::Style::new().green();
+        println!("{} {}", green.apply_to("Merged identity"), id_str);
+        return Ok(());
+    }
+    let conflicts = audit::warn_import_conflicts(&identity)?;
+    let impersonation = audit::warn_impersonation(&identity)?;
+    if conflicts || impersonation {
+        if !util::yesno_prompt("This identity may be impersonating one you already have. Import it anyway? [y/N]", "n")? {
             return Ok(());
         }
     }
@@ -103,6 +137,33 @@ pub fn import(location: &str, join: Vec<Multiaddr>) -> Result<()> {
     Ok(())
 }
 
+/// Search local identities by ID, name, and email. If the search looks like a
+/// phone number, identities with a matching (normalized) phone claim are included
+/// as well.
+pub fn search_identities(search: Option<&str>) -> Result<Vec<Identity>> {
+    let mut identities = db::list_local_identities(search)?
+        .iter()
+        .map(|x| util::build_identity(x))
+        .collect::<Result<Vec<_>>>()?;
+    let region = config::load_cli()?.phone_region;
+    let region = region.as_ref().map(|x| x.as_str());
+    let normalized = search
+        .filter(|x| x.chars().any(|c| c.is_ascii_digit()) && x.chars().all(|c| "+0123456789 ()-.".contains(c)))
+        .and_then(|x| claim::normalize_phone(x, region).ok());
+    if let Some(normalized) = normalized {
+        for transactions in db::list_local_identities(None)? {
+            let identity = util::build_identity(&transactions)?;
+            if identities.iter().any(|x| x.id() == identity.id()) {
+                continue;
+            }
+            if claim::has_phone(&identity, &normalized, region) {
+                identities.push(identity);
+            }
+        }
+    }
+    Ok(identities)
+}
+
 pub fn publish(id: &str, stage: bool, sign_with: Option<&str>) -> Result<String> {
     let hash_with = config::hash_algo(Some(&id));
     let transactions = try_load_single_identity(id)?;
@@ -117,8 +178,7 @@ pub fn publish(id: &str, stage: bool, sign_with: Option<&str>) -> Result<String>
 
     let signed = util::sign_helper(&identity, transaction, &master_key, stage, sign_with)?;
     if stage {
-        let transaction = stage_transaction(identity.id(), signed).map_err(|e| anyhow!("Error staging transaction: {:?}", e))?;
-        id_str!(transaction.id())
+        stage::stage(identity.id(), signed)
     } else {
         signed
             .serialize_text()
@@ -126,11 +186,82 @@ pub fn publish(id: &str, stage: bool, sign_with: Option<&str>) -> Result<String>
     }
 }
 
-pub fn export_private(id: &str) -> Result<Vec<u8>> {
+pub enum ListSort {
+    Created,
+    Name,
+    Updated,
+    Stamps,
+}
+
+/// Filters and sorting for `stamp id list`.
+pub struct ListOptions {
+    pub sort: Option<ListSort>,
+    pub owned: bool,
+    pub imported: bool,
+    pub has_claim_type: Option<String>,
+    pub created_after: Option<chrono::NaiveDate>,
+}
+
+fn stamp_count(identity: &Identity) -> usize {
+    identity.claims().iter().map(|c| c.stamps().len()).sum()
+}
+
+/// When the identity last changed, meaning the creation time of its newest
+/// transaction.
+fn last_updated(identity: &Identity) -> Result<i64> {
+    let transactions = db::load_identity(identity.id())?;
+    Ok(transactions
+        .as_ref()
+        .and_then(|t| t.transactions().iter().map(|x| x.entry().created().local().timestamp()).max())
+        .unwrap_or_else(|| identity.created().local().timestamp()))
+}
+
+pub fn filter_and_sort(identities: Vec<Identity>, options: &ListOptions) -> Result<Vec<Identity>> {
+    let mut identities = identities
+        .into_iter()
+        .filter(|identity| !options.owned || identity.is_owned())
+        .filter(|identity| !options.imported || !identity.is_owned())
+        .filter(|identity| match options.has_claim_type.as_ref() {
+            Some(ty) => identity.claims().iter().any(|c| claim::claim_type(c.spec()) == ty),
+            None => true,
+        })
+        .filter(|identity| match options.created_after.as_ref() {
+            Some(date) => identity.created().local().date_naive() > *date,
+            None => true,
+        })
+        .collect::<Vec<_>>();
+    match options.sort {
+        Some(ListSort::Created) => identities.sort_by_key(|x| x.created().local().timestamp()),
+        Some(ListSort::Name) => {
+            identities.sort_by_key(|x| x.names().get(0).map(|n| n.to_lowercase()).unwrap_or_default());
+        }
+        Some(ListSort::Updated) => {
+            let mut keyed = identities
+                .into_iter()
+                .map(|x| Ok((last_updated(&x)?, x)))
+                .collect::<Result<Vec<_>>>()?;
+            // most recently updated first
+            keyed.sort_by(|a, b| b.0.cmp(&a.0));
+            identities = keyed.into_iter().map(|(_, x)| x).collect();
+        }
+        Some(ListSort::Stamps) => identities.sort_by(|a, b| stamp_count(b).cmp(&stamp_count(a))),
+        None => {}
+    }
+    Ok(identities)
+}
+
+pub fn export_private(id: &str, cbor: bool) -> Result<Vec<u8>> {
     let identity = try_load_single_identity(id)?;
-    let serialized = identity
-        .serialize_binary()
-        .map_err(|e| anyhow!("There was a problem serializing the identity: {:?}", e))?;
+    let identity_id = identity.identity_id().ok_or_else(|| anyhow!("Identity {} not found", id))?;
+    util::confirm_secret_export(&id_str!(&identity_id)?)?;
+    let serialized = if cbor {
+        util::serialize_cbor(&identity)?
+    } else {
+        identity
+            .serialize_binary()
+            .map_err(|e| anyhow!("There was a problem serializing the identity: {:?}", e))?
+    };
+    status::record(&id_str!(&identity_id)?, status::BackupEvent::Backup)?;
     Ok(serialized)
 }
 
@@ -179,7 +310,11 @@ pub fn view(search: &str) -> Result<String> {
 pub fn fingerprint(id: &str, format: FingerprintFormat) -> Result<String> {
     let transactions = try_load_single_identity(id)?;
     let identity_id = transactions.identity_id().ok_or_else(|| anyhow!("Identity {} not found", id))?;
-    let fingerprint = stamp_aux::id::fingerprint(&identity_id).map_err(|e| anyhow!("Problem generating fingerprint: {:?}", e))?;
+    fingerprint_for(&identity_id, format)
+}
+
+fn fingerprint_for(identity_id: &IdentityID, format: FingerprintFormat) -> Result<String> {
+    let fingerprint = stamp_aux::id::fingerprint(identity_id).map_err(|e| anyhow!("Problem generating fingerprint: {:?}", e))?;
     match format {
         FingerprintFormat::Svg => Ok(stamp_aux::id::fingerprint_to_svg(&fingerprint)),
         FingerprintFormat::Term => {
@@ -198,6 +333,40 @@ pub fn fingerprint(id: &str, format: FingerprintFormat) -> Result<String> {
     }
 }
 
+/// Show who we're about to deal with: the identity's fingerprint alongside a
+/// short summary of its claims. Used in confirmation steps so the other side is
+/// checked at the moment of action, not just by ID prefix. Goes to STDERR so it
+/// doesn't mix with output written to STDOUT.
+pub(crate) fn print_counterparty(identity: &Identity, role: &str) -> Result<()> {
+    let bold = dialoguer::console::Style::new().bold();
+    let id_str = id_str!(identity.id())?;
+    let fingerprint = fingerprint_for(identity.id(), FingerprintFormat::Term)?;
+    let mut claim_types: Vec<&str> = Vec::new();
+    for claim in identity.claims() {
+        let ty = claim::claim_type(claim.spec());
+        if !claim_types.contains(&ty) {
+            claim_types.push(ty);
+        }
+    }
+    let mut summary = vec![
+        format!("{}", bold.apply_to(role)),
+        format!("ID:      {}", id_str),
+        format!("Name:    {}", identity.names().get(0).map(|x| x.as_str()).unwrap_or("-")),
+        format!("Email:   {}", identity.emails().get(0).map(|x| x.as_str()).unwrap_or("-")),
+        format!("Claims:  {} ({})", identity.claims().len(), claim_types.join(", ")),
+        format!("Created: {}", identity.created().local().format("%b %d, %Y")),
+    ];
+    if identity.is_owned() {
+        summary.push(String::from("(this is one of your identities)"));
+    }
+    eprintln!("");
+    for (i, row) in fingerprint.lines().enumerate() {
+        eprintln!("{}  {}", row, summary.get(i).map(|x| x.as_str()).unwrap_or(""));
+    }
+    eprintln!("");
+    Ok(())
+}
+
 /// Output a table of identities.
 pub(crate) fn print_identities_table(identities: &Vec<Identity>, verbose: bool) {
     let mut table = Table::new();
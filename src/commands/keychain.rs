@@ -1,6 +1,6 @@
 use crate::{
-    commands::{claim::claim_pre_noval, dag, id},
-    config, db, util,
+    commands::{claim::claim_pre_noval, dag, id, net, status},
+    config, db, pdf, ssh, util,
 };
 use anyhow::{anyhow, Result};
 use prettytable::Table;
@@ -16,7 +16,9 @@ use stamp_core::{
     },
     util::{base64_decode, base64_encode, Public, Timestamp},
 };
+use stamp_net::Multiaddr;
 use std::convert::{TryFrom, TryInto};
+use std::ops::Deref;
 
 pub struct PrintableKey {
     key_id: KeyID,
@@ -58,6 +60,29 @@ impl From<&Subkey> for PrintableKey {
     }
 }
 
+fn generate_subkey(master_key: &SecretKey, ty: &str) -> Result<Key> {
+    let mut rng = rng::chacha20();
+    let rng = &mut rng;
+    let key = match ty {
+        "sign" => {
+            let new_key = crypto::base::SignKeypair::new_ed25519(rng, master_key).map_err(|e| anyhow!("Error generating key: {:?}", e))?;
+            Key::new_sign(new_key)
+        }
+        "crypto" => {
+            let new_key = crypto::base::CryptoKeypair::new_curve25519xchacha20poly1305(rng, master_key)
+                .map_err(|e| anyhow!("Error generating key: {:?}", e))?;
+            Key::new_crypto(new_key)
+        }
+        "secret" => {
+            let rand_key = crypto::base::SecretKey::new_xchacha20poly1305(rng).map_err(|e| anyhow!("Unable to generate key: {}", e))?;
+            let new_key = PrivateWithHmac::seal(rng, master_key, rand_key).map_err(|e| anyhow!("Error generating key: {:?}", e))?;
+            Key::new_secret(new_key)
+        }
+        _ => Err(anyhow!("Invalid key type: {}", ty))?,
+    };
+    Ok(key)
+}
+
 pub fn new(id: &str, ty: &str, name: &str, desc: Option<&str>, stage: bool, sign_with: Option<&str>) -> Result<()> {
     let mut rng = rng::chacha20();
     let transactions = id::try_load_single_identity(id)?;
@@ -80,26 +105,7 @@ pub fn new(id: &str, ty: &str, name: &str, desc: Option<&str>, stage: bool, sign
                 .map_err(|e| anyhow!("Problem adding key to identity: {:?}", e))?
         }
         "sign" | "crypto" | "secret" => {
-            let key = match ty {
-                "sign" => {
-                    let new_key = crypto::base::SignKeypair::new_ed25519(&mut rng, &master_key)
-                        .map_err(|e| anyhow!("Error generating key: {:?}", e))?;
-                    Key::new_sign(new_key)
-                }
-                "crypto" => {
-                    let new_key = crypto::base::CryptoKeypair::new_curve25519xchacha20poly1305(&mut rng, &master_key)
-                        .map_err(|e| anyhow!("Error generating key: {:?}", e))?;
-                    Key::new_crypto(new_key)
-                }
-                "secret" => {
-                    let rand_key =
-                        crypto::base::SecretKey::new_xchacha20poly1305(&mut rng).map_err(|e| anyhow!("Unable to generate key: {}", e))?;
-                    let new_key =
-                        PrivateWithHmac::seal(&mut rng, &master_key, rand_key).map_err(|e| anyhow!("Error generating key: {:?}", e))?;
-                    Key::new_secret(new_key)
-                }
-                _ => Err(anyhow!("Invalid key type: {}", ty))?,
-            };
+            let key = generate_subkey(&master_key, ty)?;
             transactions
                 .add_subkey(&hash_with, Timestamp::now(), key, name, desc)
                 .map_err(|e| anyhow!("Problem adding key to identity: {:?}", e))?
@@ -111,6 +117,132 @@ pub fn new(id: &str, ty: &str, name: &str, desc: Option<&str>, stage: bool, sign
     Ok(())
 }
 
+/// Wrap an existing private key (from another system) as a `sign` or `crypto`
+/// subkey. The key is sealed with the master key like any generated subkey.
+pub fn import(id: &str, ty: &str, keyfile: &str, name: &str, desc: Option<&str>, stage: bool, sign_with: Option<&str>) -> Result<()> {
+    let mut rng = rng::chacha20();
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let hash_with = config::hash_algo(Some(&id_str));
+    let key_bytes = util::read_file(keyfile)?;
+    let imported = ssh::parse_private_key(key_bytes.as_slice())?;
+    match (ty, imported.algo) {
+        ("sign", Some(ssh::KeyAlgo::X25519)) => Err(anyhow!("This is an x25519 key, which can only be imported as a `crypto` key"))?,
+        ("crypto", Some(ssh::KeyAlgo::Ed25519)) => Err(anyhow!("This is an ed25519 key, which can only be imported as a `sign` key"))?,
+        ("sign", _) | ("crypto", _) => {}
+        _ => Err(anyhow!("Invalid key type: {} (expected sign or crypto)", ty))?,
+    }
+    println!("Importing {} key from {} ({})", ty, keyfile, imported.format);
+    let master_key = util::passphrase_prompt(
+        &format!("Your current master passphrase for identity {}", IdentityID::short(&id_str)),
+        identity.created(),
+    )?;
+    identity
+        .test_master_key(&master_key)
+        .map_err(|e| anyhow!("Incorrect passphrase: {:?}", e))?;
+    let key = match ty {
+        "sign" => {
+            let keypair = crypto::base::SignKeypair::new_ed25519_from_bytes(&mut rng, &master_key, imported.secret)
+                .map_err(|e| anyhow!("Error importing key: {:?}", e))?;
+            Key::new_sign(keypair)
+        }
+        _ => {
+            let keypair = crypto::base::CryptoKeypair::new_curve25519xchacha20poly1305_from_bytes(&mut rng, &master_key, imported.secret)
+                .map_err(|e| anyhow!("Error importing key: {:?}", e))?;
+            Key::new_crypto(keypair)
+        }
+    };
+    if identity.keychain().subkeys().iter().any(|k| k.key_id() == key.key_id()) {
+        Err(anyhow!("This key is already in the keychain"))?;
+    }
+    let transaction = transactions
+        .add_subkey(&hash_with, Timestamp::now(), key, name, desc)
+        .map_err(|e| anyhow!("Problem adding key to identity: {:?}", e))?;
+    let signed = util::sign_helper(&identity, transaction, &master_key, stage, sign_with)?;
+    dag::save_or_stage(transactions, signed, stage)?;
+    Ok(())
+}
+
+/// The raw public key of an ed25519 `sign` keypair.
+pub(crate) fn ed25519_public(keypair: &crypto::base::SignKeypair) -> Result<[u8; 32]> {
+    match keypair {
+        crypto::base::SignKeypair::Ed25519 { public, .. } => Ok(*public.deref()),
+    }
+}
+
+/// The raw secret (seed) of an ed25519 `sign` keypair, opened with the master key.
+pub(crate) fn ed25519_secret(master_key: &SecretKey, keypair: &crypto::base::SignKeypair) -> Result<[u8; 32]> {
+    match keypair {
+        crypto::base::SignKeypair::Ed25519 { secret: Some(secret), .. } => {
+            let opened = secret
+                .open(master_key)
+                .map_err(|e| anyhow!("Problem opening secret key: {:?}", e))?;
+            Ok(*opened.deref())
+        }
+        crypto::base::SignKeypair::Ed25519 { secret: None, .. } => Err(anyhow!("This key has no secret part")),
+    }
+}
+
+/// Export a `sign` subkey as an OpenSSH keypair (`<output>` and `<output>.pub`),
+/// or just the public key line when `public_only` is set.
+pub fn export_ssh(id: &str, search: &str, output: &str, public_only: bool) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let subkey = identity
+        .keychain()
+        .subkeys()
+        .iter()
+        .find(|k| k.key_id().as_string().starts_with(search) || k.name() == search)
+        .ok_or_else(|| anyhow!("Cannot find key {}", search))?;
+    let keypair = subkey
+        .key()
+        .as_signkey()
+        .ok_or_else(|| anyhow!("Key {} is not a `sign` key", search))?;
+    if subkey.revocation().is_some() {
+        Err(anyhow!("Key {} has been revoked", search))?;
+    }
+    let public = ed25519_public(keypair)?;
+    let comment = format!("{}@stamp:{}", subkey.name(), IdentityID::short(&id_str));
+    let public_line = ssh::ed25519_public_line(&public, &comment);
+    if public_only {
+        util::write_file(output, format!("{}\n", public_line).as_bytes())?;
+        return Ok(());
+    }
+    if output == "-" {
+        Err(anyhow!("Exporting a private key requires an output file (ex: -o ~/.ssh/id_ed25519)"))?;
+    }
+    let public_file = format!("{}.pub", output);
+    for path in &[output, public_file.as_str()] {
+        if std::path::Path::new(path).exists() {
+            Err(anyhow!("{} already exists, refusing to overwrite it", path))?;
+        }
+    }
+    util::confirm_secret_export(&id_str)?;
+    let master_key = util::passphrase_prompt(
+        &format!("Your current master passphrase for identity {}", IdentityID::short(&id_str)),
+        identity.created(),
+    )?;
+    identity
+        .test_master_key(&master_key)
+        .map_err(|e| anyhow!("Incorrect passphrase: {:?}", e))?;
+    let seed = ed25519_secret(&master_key, keypair)?;
+    // the checkint only detects a bad decryption, so it doesn't need to be secret
+    let checkint = Timestamp::now().local().timestamp_subsec_nanos();
+    let private = ssh::ed25519_private_file(&seed, &public, &comment, checkint);
+    std::fs::write(output, private.as_bytes()).map_err(|e| anyhow!("Problem writing {}: {}", output, e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(output, std::fs::Permissions::from_mode(0o600))
+            .map_err(|e| anyhow!("Problem setting permissions on {}: {}", output, e))?;
+    }
+    std::fs::write(&public_file, format!("{}\n", public_line).as_bytes()).map_err(|e| anyhow!("Problem writing {}: {}", public_file, e))?;
+    println!("Wrote {} and {}", output, public_file);
+    Ok(())
+}
+
 pub fn list(id: &str, ty: Option<&str>, revoked: bool, search: Option<&str>) -> Result<()> {
     let transactions = id::try_load_single_identity(id)?;
     let identity = util::build_identity(&transactions)?;
@@ -352,6 +484,7 @@ pub fn passwd(id: &str, keyfile: Option<&str>, keyparts: Vec<&str>) -> Result<()
     };
     let (_, new_master_key) =
         util::with_new_passphrase("Your new master passphrase", |_master_key, _now| Ok(()), Some(identity.created().clone()))?;
+    let loaded = transactions.clone();
     let transactions_reencrypted = transactions
         .reencrypt(&mut rng, &master_key, &new_master_key)
         .map_err(|e| anyhow!("Password change failed: {}", e))?;
@@ -359,11 +492,113 @@ pub fn passwd(id: &str, keyfile: Option<&str>, keyparts: Vec<&str>) -> Result<()
     transactions_reencrypted
         .test_master_key(&new_master_key)
         .map_err(|e| anyhow!("Password change failed: {}", e))?;
-    db::save_identity(transactions_reencrypted)?;
+    // a re-encrypted identity can't be rebased, since anything added meanwhile is
+    // still encrypted with the old passphrase
+    dag::save_checked(&loaded, transactions_reencrypted, false)?;
+    status::record(&id_str, status::BackupEvent::Passwd)?;
+    util::forget_master_key(identity.created())?;
     println!("Identity re-encrypted with new passphrase!");
     Ok(())
 }
 
+/// Respond to a (suspected) compromised master passphrase in one go: every
+/// active subkey is replaced by a freshly generated one with the same name, the
+/// old keys are revoked as compromised, the identity is re-encrypted with a new
+/// passphrase, and it's republished if asked to (or if auto-publishing is set up).
+pub fn rekey_all(id: &str, publish: bool, join: Vec<Multiaddr>) -> Result<()> {
+    let mut rng = rng::chacha20();
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let hash_with = config::hash_algo(Some(&id_str));
+    let subkeys = identity
+        .keychain()
+        .subkeys()
+        .iter()
+        .filter(|k| k.revocation().is_none())
+        .map(|k| k.clone())
+        .collect::<Vec<_>>();
+
+    let red = dialoguer::console::Style::new().red();
+    util::print_wrapped(&format!(
+        "This will replace all {} active subkey(s) in identity {}, revoke the old ones as {}, and change your master passphrase.\n\n",
+        subkeys.len(),
+        IdentityID::short(&id_str),
+        red.apply_to("compromised"),
+    ));
+    util::print_wrapped("Anyone you've shared your old keys with will need your updated identity before they can send you messages or verify your new signatures.\n\n");
+    if !util::yesno_prompt("Continue? [y/N]", "n")? {
+        return Ok(());
+    }
+    let master_key = util::passphrase_prompt(
+        &format!("Your current master passphrase for identity {}", IdentityID::short(&id_str)),
+        identity.created(),
+    )?;
+    transactions
+        .test_master_key(&master_key)
+        .map_err(|e| anyhow!("Incorrect passphrase: {:?}", e))?;
+
+    let loaded = transactions.clone();
+    let mut updated = transactions;
+    let revoked_date = Timestamp::now().local().format("%Y-%m-%d").to_string();
+    for subkey in subkeys.iter() {
+        let ty = match subkey.key() {
+            Key::Sign(..) => "sign",
+            Key::Crypto(..) => "crypto",
+            Key::Secret(..) => "secret",
+        };
+        // move the old key out of the way so the replacement can take its name
+        let revoked_name = format!("{}/revoked-{}", subkey.name(), revoked_date);
+        let identity = util::build_identity(&updated)?;
+        let transaction = updated
+            .revoke_subkey(&hash_with, Timestamp::now(), subkey.key_id(), RevocationReason::Compromised, Some(revoked_name))
+            .map_err(|e| anyhow!("Error revoking subkey {}: {:?}", subkey.name(), e))?;
+        let signed = util::sign_helper(&identity, transaction, &master_key, false, None)?;
+        updated = updated
+            .push_transaction(signed)
+            .map_err(|e| anyhow!("Error revoking subkey {}: {:?}", subkey.name(), e))?;
+
+        let key = generate_subkey(&master_key, ty)?;
+        let identity = util::build_identity(&updated)?;
+        let transaction = updated
+            .add_subkey(&hash_with, Timestamp::now(), key, subkey.name().as_str(), subkey.description().as_deref())
+            .map_err(|e| anyhow!("Error replacing subkey {}: {:?}", subkey.name(), e))?;
+        let signed = util::sign_helper(&identity, transaction, &master_key, false, None)?;
+        updated = updated
+            .push_transaction(signed)
+            .map_err(|e| anyhow!("Error replacing subkey {}: {:?}", subkey.name(), e))?;
+        println!("Replaced {} key {}", ty, subkey.name());
+    }
+
+    let (_, new_master_key) =
+        util::with_new_passphrase("Your new master passphrase", |_master_key, _now| Ok(()), Some(identity.created().clone()))?;
+    let updated = updated
+        .reencrypt(&mut rng, &master_key, &new_master_key)
+        .map_err(|e| anyhow!("Password change failed: {}", e))?;
+    updated
+        .test_master_key(&new_master_key)
+        .map_err(|e| anyhow!("Password change failed: {}", e))?;
+    dag::save_checked(&loaded, updated, false)?;
+    status::record(&id_str, status::BackupEvent::Passwd)?;
+    util::forget_master_key(identity.created())?;
+    let green = dialoguer::console::Style::new().green();
+    println!("{} {} subkey(s) and changed your master passphrase.", green.apply_to("Replaced"), subkeys.len());
+
+    let auto = db::load_record::<net::AutoPublish>("autopublish", &id_str)?;
+    if publish || auto.is_some() {
+        net::publish(&id_str, None, join, auto.map(|x| x.interval_days))?;
+    } else {
+        println!("Run `stamp net publish` to let others know about your new keys.");
+    }
+    let yellow = dialoguer::console::Style::new().yellow();
+    println!(
+        "{} {}",
+        yellow.apply_to("Note:"),
+        util::text_wrap("Admin keys are not replaced. If you think they were exposed as well, add new ones with `stamp keychain new admin` and revoke the old ones. Any keyfiles, sync tokens, or exported backups made with your old passphrase should be regenerated.")
+    );
+    Ok(())
+}
+
 /// Generate a sync token or display the currently saved one.
 pub(crate) fn sync_token(id: &str, blind: bool, stage: bool, sign_with: Option<&str>) -> Result<()> {
     /*
@@ -400,7 +635,87 @@ pub(crate) fn sync_token(id: &str, blind: bool, stage: bool, sign_with: Option<&
     Ok(())
 }
 
-pub fn keyfile(id: &str, shamir: &str, output: &str) -> Result<()> {
+/// Lay out one keyfile share as a printable page: a QR code of the share, the
+/// identity's fingerprint, the share as text, and how to recover with it.
+fn keyfile_page(identity: &Identity, share: &str, index: usize, num_shares: u8, min_shares: u8) -> Result<pdf::Page> {
+    let id_str = id_str!(identity.id())?;
+    let mut page = pdf::Page::new();
+    let margin = 54.0;
+    page.text(margin, 64.0, pdf::Font::HelveticaBold, 20.0, "Stamp master key backup");
+    page.text(margin, 88.0, pdf::Font::Helvetica, 10.0, &format!("Identity: {}", id_str));
+    if let Some(name) = identity.names().get(0) {
+        page.text(margin, 102.0, pdf::Font::Helvetica, 10.0, &format!("Name: {}", name));
+    }
+    let share_label = if num_shares > 1 {
+        format!("Part {} of {} (any {} parts recover the key)", index + 1, num_shares, min_shares)
+    } else {
+        String::from("Complete keyfile")
+    };
+    page.text(margin, 116.0, pdf::Font::Helvetica, 10.0, &share_label);
+    page.text(
+        margin,
+        130.0,
+        pdf::Font::Helvetica,
+        10.0,
+        &format!("Backed up: {}", Timestamp::now().local().format("%b %d, %Y")),
+    );
+
+    let qr = qrcode::QrCode::new(share.as_bytes()).map_err(|e| anyhow!("Problem generating QR code: {}", e))?;
+    let modules = qr.width();
+    let module_size = 216.0 / (modules + 8) as f32;
+    let qr_top = 156.0;
+    let colors = qr.to_colors();
+    for y in 0..modules {
+        for x in 0..modules {
+            if colors[y * modules + x] == qrcode::Color::Dark {
+                page.rect(
+                    margin + (x + 4) as f32 * module_size,
+                    qr_top + (y + 4) as f32 * module_size,
+                    module_size,
+                    module_size,
+                    [0, 0, 0],
+                );
+            }
+        }
+    }
+
+    let fingerprint = stamp_aux::id::fingerprint(identity.id()).map_err(|e| anyhow!("Problem generating fingerprint: {:?}", e))?;
+    let fp_left = pdf::PAGE_WIDTH - margin - 192.0;
+    page.rect(fp_left, qr_top + 12.0, 192.0, 192.0, [0, 0, 0]);
+    for (x, y, rgb) in fingerprint {
+        page.rect(fp_left + x as f32 * 12.0, qr_top + 12.0 + y as f32 * 12.0, 12.0, 12.0, rgb);
+    }
+    page.text(fp_left, qr_top + 222.0, pdf::Font::Helvetica, 8.0, "Identity fingerprint (`stamp id fingerprint`)");
+
+    let mut y = 410.0;
+    page.text(margin, y, pdf::Font::HelveticaBold, 11.0, "Key part (text)");
+    for line in share.as_bytes().chunks(64) {
+        y += 14.0;
+        page.text(margin, y, pdf::Font::Courier, 10.0, &String::from_utf8_lossy(line));
+    }
+
+    y += 36.0;
+    page.text(margin, y, pdf::Font::HelveticaBold, 11.0, "Recovering your identity");
+    let steps = [
+        String::from("This page lets you set a new master passphrase if you forget yours. Keep it somewhere safe and private:"),
+        String::from("anyone who gets enough of these pages can take over your identity."),
+        String::new(),
+        String::from("1. Scan the QR code or type the key part above into a text file, one key part per line."),
+        format!(
+            "2. Gather at least {} key part(s) and run: stamp keychain passwd --id {} --keyfile <file>",
+            min_shares,
+            IdentityID::short(&id_str)
+        ),
+        String::from("3. Check the fingerprint above matches `stamp id fingerprint` before trusting a recovered identity."),
+    ];
+    for step in steps.iter() {
+        y += 14.0;
+        page.text(margin, y, pdf::Font::Helvetica, 9.0, step);
+    }
+    Ok(page)
+}
+
+pub fn keyfile(id: &str, shamir: &str, output: &str, pdf: bool) -> Result<()> {
     let mut shamir_parts = shamir.split("/");
     let min_shares: u8 = shamir_parts
         .next()
@@ -418,6 +733,7 @@ pub fn keyfile(id: &str, shamir: &str, output: &str) -> Result<()> {
     let transactions = id::try_load_single_identity(id)?;
     let identity = util::build_identity(&transactions)?;
     let id_str = id_str!(identity.id())?;
+    util::confirm_secret_export(&id_str)?;
     let master_key = util::passphrase_prompt(
         &format!("Your current master passphrase for identity {}", IdentityID::short(&id_str)),
         identity.created(),
@@ -431,7 +747,44 @@ pub fn keyfile(id: &str, shamir: &str, output: &str) -> Result<()> {
         .take(num_shares as usize)
         .map(|x| base64_encode(Vec::from(&x).as_slice()))
         .collect::<Vec<_>>();
-    util::write_file(output, shares.join("\n").as_bytes())
+    if util::is_output_dir(output) {
+        let short = IdentityID::short(&id_str);
+        let artifacts = shares
+            .iter()
+            .enumerate()
+            .map(|(i, share)| {
+                if pdf {
+                    let page = keyfile_page(&identity, share, i, num_shares, min_shares)?;
+                    Ok(util::Artifact::new(
+                        format!("{}-keyfile-{}-of-{}.pdf", short, i + 1, num_shares),
+                        "keyfile-share-pdf",
+                        pdf::render(&[page]),
+                    ))
+                } else {
+                    Ok(util::Artifact::new(
+                        format!("{}-keyfile-{}-of-{}.txt", short, i + 1, num_shares),
+                        "keyfile-share",
+                        share.as_bytes().to_vec(),
+                    ))
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let meta = serde_json::json!({
+            "identity": id_str,
+            "shamir": { "min_shares": min_shares, "num_shares": num_shares },
+        });
+        util::write_artifacts(output, &artifacts, meta)?;
+    } else if pdf {
+        let pages = shares
+            .iter()
+            .enumerate()
+            .map(|(i, share)| keyfile_page(&identity, share, i, num_shares, min_shares))
+            .collect::<Result<Vec<_>>>()?;
+        util::write_file(output, pdf::render(&pages).as_slice())?;
+    } else {
+        util::write_file(output, shares.join("\n").as_bytes())?;
+    }
+    status::record(&id_str, status::BackupEvent::Keyfile)
 }
 
 pub fn print_keys_table(keys: &Vec<PrintableKey>, choice: bool, show_revoked: bool) {
@@ -1,26 +1,536 @@
 use crate::{
-    commands::{id, keychain},
+    armor,
+    commands::{dag, id, keychain, stage},
     db, util,
 };
 use anyhow::{anyhow, Result};
+use prettytable::Table;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use stamp_aux::db::{load_staged_transaction, stage_transaction};
 use stamp_core::{
     crypto::{
         base::rng,
         message::{self, Message},
+        sign,
     },
-    identity::IdentityID,
-    util::{base64_decode, base64_encode, SerdeBinary},
+    identity::{Identity, IdentityID},
+    util::{base64_decode, base64_encode, SerdeBinary, Timestamp},
 };
 use std::convert::TryFrom;
 
+/// A sealed message we sent or opened, kept so conversations can be reviewed
+/// and archived later. Only the sealed form is stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageRecord {
+    pub our_identity: String,
+    /// The other side of the conversation (None for anonymous messages).
+    pub peer_identity: Option<String>,
+    /// "in" or "out"
+    pub direction: String,
+    pub timestamp: Timestamp,
+    /// base64 of the sealed message
+    pub sealed: String,
+    /// For messages we opened, whether they were signed by the sender and the
+    /// signature checked out (false means anonymous). None for sent messages.
+    #[serde(default)]
+    pub verified: Option<bool>,
+    /// For messages we sent, base64 of a copy sealed to our own `crypto` key so
+    /// it can be read back later.
+    #[serde(default)]
+    pub copy: Option<String>,
+    /// The message's ID and the ID of the message it replies to, taken from its
+    /// envelope.
+    #[serde(default)]
+    pub message_id: Option<String>,
+    #[serde(default)]
+    pub in_reply_to: Option<String>,
+    /// If this message is a read receipt, the ID of the message it acknowledges.
+    #[serde(default)]
+    pub receipt_for: Option<String>,
+    /// For messages we sent, when the recipient's read receipt says they read it.
+    #[serde(default)]
+    pub read_at: Option<String>,
+}
+
+/// Marks plaintext that's an [Envelope] rather than a bare message.
+const ENVELOPE_MAGIC: &[u8] = b"stamp-envelope/1\n";
+
+/// Lets a message carry files along with their names and types, plus the IDs
+/// that tie a conversation together. Messages that are only a body are sent as
+/// bare bytes, as before (see [Envelope::is_plain]).
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Envelope {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    in_reply_to: Option<String>,
+    #[serde(default)]
+    receipt: Option<Receipt>,
+    /// base64 of the message body, if there is one
+    #[serde(default)]
+    body: Option<String>,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    #[serde(default)]
+    staged: Option<StagedTransaction>,
+}
+
+impl Envelope {
+    /// Whether there's nothing here but a body, in which case the message is
+    /// sent without an envelope so older versions can still read it.
+    fn is_plain(&self) -> bool {
+        self.in_reply_to.is_none() && self.receipt.is_none() && self.attachments.len() == 0 && self.staged.is_none()
+    }
+}
+
+/// Acknowledges that a message was read. Receipts are sent as messages, so
+/// they're signed by the reader like any other.
+#[derive(Debug, Serialize, Deserialize)]
+struct Receipt {
+    message_id: String,
+    /// RFC3339
+    read: String,
+}
+
+/// A staged transaction sent to a co-signer, which gets put into their staging
+/// area when the message is opened.
+#[derive(Debug, Serialize, Deserialize)]
+struct StagedTransaction {
+    /// The identity the transaction is staged for
+    identity: String,
+    /// base64 of the serialized transaction
+    transaction: String,
+    /// The sender's non-local comments on the transaction.
+    #[serde(default)]
+    comments: Vec<stage::StageComment>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Attachment {
+    filename: String,
+    mime: String,
+    /// base64 of the file contents
+    data: String,
+}
+
+fn guess_mime(filename: &str) -> &'static str {
+    let ext = std::path::Path::new(filename)
+        .extension()
+        .and_then(|x| x.to_str())
+        .map(|x| x.to_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "txt" => "text/plain",
+        "md" => "text/markdown",
+        "html" | "htm" => "text/html",
+        "csv" => "text/csv",
+        "json" => "application/json",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Generate an ID for a message we're about to send. It only has to be unique
+/// for the sender, so a hash of who's sending, when, and what is enough.
+fn new_message_id(from: &str, plaintext: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(from.as_bytes());
+    hasher.update(
+        Timestamp::now()
+            .local()
+            .to_rfc3339_opts(chrono::SecondsFormat::Nanos, true)
+            .as_bytes(),
+    );
+    hasher.update(plaintext);
+    base64_encode(&hasher.finalize()[0..16])
+}
+
+/// Read the body and attachments for a message we're sending. With no
+/// attachments the body is required, and read from STDIN if not given.
+fn build_envelope(input: Option<&str>, attachments: &[&str]) -> Result<Envelope> {
+    let body = match (input, attachments.len()) {
+        (Some(input), _) => Some(base64_encode(util::read_file(input)?.as_slice())),
+        (None, 0) => Some(base64_encode(util::read_file("-")?.as_slice())),
+        (None, _) => None,
+    };
+    let attachments = attachments
+        .iter()
+        .map(|path| {
+            let filename = std::path::Path::new(path)
+                .file_name()
+                .and_then(|x| x.to_str())
+                .ok_or_else(|| anyhow!("Invalid attachment path: {}", path))?;
+            Ok(Attachment {
+                filename: String::from(filename),
+                mime: String::from(guess_mime(filename)),
+                data: base64_encode(util::load_file(path)?.as_slice()),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Envelope {
+        body,
+        attachments,
+        ..Default::default()
+    })
+}
+
+fn envelope_bytes(envelope: &Envelope) -> Result<Vec<u8>> {
+    let mut plaintext = Vec::from(ENVELOPE_MAGIC);
+    plaintext.extend(serde_json::to_vec(envelope)?);
+    Ok(plaintext)
+}
+
+/// What actually gets sealed: the bare body for plain messages, otherwise the
+/// whole envelope.
+fn message_bytes(envelope: &Envelope) -> Result<Vec<u8>> {
+    if !envelope.is_plain() {
+        return envelope_bytes(envelope);
+    }
+    match envelope.body.as_ref() {
+        Some(body) => base64_decode(body.as_bytes()).map_err(|e| anyhow!("Problem decoding message body: {}", e)),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Read the [Envelope] out of an opened message, if it has one.
+fn read_envelope(opened: &[u8]) -> Result<Option<Envelope>> {
+    match opened.strip_prefix(ENVELOPE_MAGIC) {
+        Some(json) => Ok(Some(
+            serde_json::from_slice::<Envelope>(json).map_err(|e| anyhow!("Problem reading message envelope: {}", e))?,
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Write out an opened message. If it's an [Envelope], the body goes to
+/// `output` and attachments are saved under their original names in `save_dir`
+/// (or just listed if there's no `save_dir`).
+fn write_plaintext(opened: &[u8], output: &str, save_dir: Option<&str>) -> Result<()> {
+    let envelope = match read_envelope(opened)? {
+        Some(envelope) => envelope,
+        None => return util::write_file(output, opened),
+    };
+    if let Some(receipt) = envelope.receipt.as_ref() {
+        eprintln!("Read receipt: message {} was read {}", receipt.message_id, receipt.read);
+    }
+    if let Some(body) = envelope.body.as_ref() {
+        let body = base64_decode(body.as_bytes()).map_err(|e| anyhow!("Problem decoding message body: {}", e))?;
+        util::write_file(output, body.as_slice())?;
+    }
+    if let Some(dir) = save_dir {
+        std::fs::create_dir_all(dir).map_err(|e| anyhow!("Error creating directory: {}: {:?}", dir, e))?;
+    }
+    for (i, attachment) in envelope.attachments.iter().enumerate() {
+        let data =
+            base64_decode(attachment.data.as_bytes()).map_err(|e| anyhow!("Problem decoding attachment {}: {}", attachment.filename, e))?;
+        match save_dir {
+            Some(dir) => {
+                // never let the sender pick where the file goes, only its name
+                let filename = std::path::Path::new(&attachment.filename)
+                    .file_name()
+                    .and_then(|x| x.to_str())
+                    .map(|x| String::from(x))
+                    .unwrap_or_else(|| format!("attachment-{}", i + 1));
+                let path = std::path::Path::new(dir).join(&filename);
+                if path.exists() {
+                    Err(anyhow!("Refusing to overwrite existing file: {}", path.display()))?;
+                }
+                util::write_file(&path.to_string_lossy(), data.as_slice())?;
+                eprintln!("Saved attachment {} ({}, {} bytes)", path.display(), attachment.mime, data.len());
+            }
+            None => eprintln!("Attachment: {} ({}, {} bytes)", attachment.filename, attachment.mime, data.len()),
+        }
+    }
+    if save_dir.is_none() && envelope.attachments.len() > 0 {
+        eprintln!("Use --save-attachments <DIR> to save attachments.");
+    }
+    Ok(())
+}
+
+/// Put a staged transaction from an opened message into our staging area, as
+/// long as we have the identity it's for, the transaction builds on it, and the
+/// sender is the identity itself or holds one of its admin keys. Unless
+/// `skip_confirm` is set, we ask first. A copy we already have with more
+/// signatures is kept.
+fn import_staged(envelope: &Envelope, sender: &str, skip_confirm: bool) -> Result<()> {
+    let staged = match envelope.staged.as_ref() {
+        Some(x) => x,
+        None => return Ok(()),
+    };
+    let trans_bytes = base64_decode(staged.transaction.as_bytes()).map_err(|e| anyhow!("Problem decoding staged transaction: {}", e))?;
+    let transaction = util::deserialize_transaction(trans_bytes.as_slice())?;
+    let identity_id =
+        IdentityID::try_from(staged.identity.as_str()).map_err(|e| anyhow!("Invalid identity in staged transaction: {:?}", e))?;
+    let yellow = dialoguer::console::Style::new().yellow();
+    let transactions = match db::load_identity(&identity_id)? {
+        Some(x) => x,
+        None => {
+            eprintln!(
+                "{}: this message carries staged transaction {} for identity {}, which isn't imported. Import it and then use `stamp stage import`.",
+                yellow.apply_to("Note"),
+                transaction.id(),
+                IdentityID::short(&staged.identity)
+            );
+            return Ok(());
+        }
+    };
+    let identity = util::build_identity(&transactions)?;
+    if !stage::builds_on(&transactions, &transaction) {
+        eprintln!(
+            "{}: staged transaction {} in this message doesn't build on identity {}, so it wasn't staged.",
+            yellow.apply_to("Warning"),
+            transaction.id(),
+            IdentityID::short(&staged.identity)
+        );
+        return Ok(());
+    }
+    let shared_keys = if sender == staged.identity {
+        Vec::new()
+    } else {
+        let sender_id = IdentityID::try_from(sender).map_err(|e| anyhow!("Invalid sender identity: {:?}", e))?;
+        match db::load_identity(&sender_id)? {
+            Some(sender_transactions) => stage::shared_admin_keys(&identity, &util::build_identity(&sender_transactions)?),
+            None => Vec::new(),
+        }
+    };
+    if sender != staged.identity && shared_keys.len() == 0 {
+        eprintln!(
+            "{}: staged transaction {} came from {}, which doesn't hold any admin keys of identity {}, so it wasn't staged. If you trust it anyway, use `stamp stage import`.",
+            yellow.apply_to("Warning"),
+            transaction.id(),
+            IdentityID::short(sender),
+            IdentityID::short(&staged.identity)
+        );
+        return Ok(());
+    }
+    let existing = load_staged_transaction(transaction.id()).map_err(|e| anyhow!("Error loading staged transaction: {:?}", e))?;
+    if let Some((_, existing)) = existing {
+        if existing.signatures().len() > transaction.signatures().len() {
+            eprintln!(
+                "{}: staged transaction {} is already staged with more signatures, keeping ours",
+                yellow.apply_to("Note"),
+                transaction.id()
+            );
+            return Ok(());
+        }
+    }
+    let txid = transaction.id().clone();
+    if !skip_confirm {
+        eprintln!(
+            "This message carries a staged transaction for identity {}:\n  {}  {}",
+            IdentityID::short(&staged.identity),
+            txid,
+            dag::transaction_to_string(&transaction)
+        );
+        if shared_keys.len() > 0 {
+            eprintln!("The sender holds its admin key(s): {}", shared_keys.join(", "));
+        }
+        if !util::yesno_prompt("Stage it? [y/N]", "n")? {
+            eprintln!("Not staged.");
+            return Ok(());
+        }
+    }
+    stage_transaction(&identity_id, transaction.clone()).map_err(|e| anyhow!("Error staging transaction: {:?}", e))?;
+    stage::merge_comments(&format!("{}", txid), &staged.comments)?;
+    eprintln!(
+        "Staged transaction {} for identity {} was imported. Review and sign it with:",
+        txid,
+        IdentityID::short(&staged.identity)
+    );
+    eprintln!("  stamp stage view {}\n  stamp stage sign {}", txid, txid);
+    stage::notify_if_ready(&identity_id, &transaction)?;
+    Ok(())
+}
+
+fn record_message(
+    our_identity: &str,
+    peer_identity: Option<&str>,
+    direction: &str,
+    sealed: &[u8],
+    verified: Option<bool>,
+    copy: Option<&[u8]>,
+    envelope: Option<&Envelope>,
+) -> Result<String> {
+    // opening the same message again shouldn't add it twice
+    let sealed_str = base64_encode(sealed);
+    let existing = history(our_identity, peer_identity)?
+        .into_iter()
+        .find(|(_, rec)| rec.direction == direction && rec.sealed == sealed_str);
+    if let Some((key, _)) = existing {
+        return Ok(key);
+    }
+    let now = Timestamp::now();
+    let record = MessageRecord {
+        our_identity: our_identity.into(),
+        peer_identity: peer_identity.map(|x| x.into()),
+        direction: direction.into(),
+        timestamp: now.clone(),
+        sealed: sealed_str,
+        verified,
+        copy: copy.map(|x| base64_encode(x)),
+        message_id: envelope.and_then(|x| x.id.clone()),
+        in_reply_to: envelope.and_then(|x| x.in_reply_to.clone()),
+        receipt_for: envelope.and_then(|x| x.receipt.as_ref()).map(|x| x.message_id.clone()),
+        read_at: None,
+    };
+    let key = format!("{}-{}", now.format("%Y%m%d%H%M%S%3f"), direction);
+    db::save_record("message", &key, &record)?;
+    // a receipt from the other side marks the message we sent them as read
+    if let (Some(receipt), Some(peer)) = (envelope.and_then(|x| x.receipt.as_ref()), peer_identity) {
+        let sent = history(our_identity, Some(peer))?
+            .into_iter()
+            .find(|(_, rec)| rec.direction == "out" && rec.message_id.as_ref() == Some(&receipt.message_id));
+        if let Some((sent_key, mut sent)) = sent {
+            sent.read_at = Some(receipt.read.clone());
+            db::save_record("message", &sent_key, &sent)?;
+        }
+    }
+    Ok(key)
+}
+
+pub(crate) fn history(our_identity: &str, peer_identity: Option<&str>) -> Result<Vec<(String, MessageRecord)>> {
+    Ok(db::list_records::<MessageRecord>("message")?
+        .into_iter()
+        .filter(|(_, rec)| rec.our_identity == our_identity)
+        .filter(|(_, rec)| peer_identity.is_none() || rec.peer_identity.as_ref().map(|x| x.as_str()) == peer_identity)
+        .collect())
+}
+
+fn find_single_identity(search: &str) -> Result<Identity> {
+    let identities = db::list_local_identities(Some(search))?;
+    if identities.len() > 1 {
+        let identities_vec = identities.iter().map(|x| util::build_identity(x)).collect::<Result<Vec<_>>>()?;
+        id::print_identities_table(&identities_vec, false);
+        Err(anyhow!("Multiple identities matched that search"))?;
+    } else if identities.len() == 0 {
+        Err(anyhow!("No identities match that search"))?;
+    }
+    util::build_identity(&identities[0])
+}
+
+// TODO: forward-secret sessions (`stamp message session start/send/recv`). Each
+// message here is sealed straight to the recipient's long-term `crypto` key, so
+// losing that key exposes every past message. A ratchet needs ephemeral key
+// agreement and a KDF chain, which stamp-core's message module doesn't expose,
+// and the session state would have to be sealed with the master key like any
+// other private material.
 pub fn send(
     id_from: &str,
     key_search_from: Option<&str>,
     key_search_to: Option<&str>,
-    input: &str,
+    input: Option<&str>,
+    attachments: &[&str],
     output: &str,
     search_to: &str,
     base64: bool,
+    armored: bool,
+) -> Result<()> {
+    let envelope = build_envelope(input, attachments)?;
+    send_envelope(id_from, key_search_from, key_search_to, search_to, envelope, output, base64, armored)
+}
+
+/// Reply to a message in our history. The reply carries the original's message
+/// ID so the conversation can be threaded on both sides.
+pub fn reply(
+    id_from: &str,
+    key_search_from: Option<&str>,
+    key_search_to: Option<&str>,
+    message_id: &str,
+    input: Option<&str>,
+    attachments: &[&str],
+    output: &str,
+    base64: bool,
+    armored: bool,
+) -> Result<()> {
+    let transactions = id::try_load_single_identity(id_from)?;
+    let id_str = id_str!(util::build_identity(&transactions)?.id())?;
+    let (_, record) = find_record(&id_str, message_id)?;
+    let peer = record
+        .peer_identity
+        .ok_or_else(|| anyhow!("Message {} is anonymous, so there's nobody to reply to", message_id))?;
+    let mut envelope = build_envelope(input, attachments)?;
+    envelope.in_reply_to = record.message_id;
+    send_envelope(id_from, key_search_from, key_search_to, &peer, envelope, output, base64, armored)
+}
+
+/// Send a signed read receipt for a message we received.
+pub fn receipt(
+    id_from: &str,
+    key_search_from: Option<&str>,
+    key_search_to: Option<&str>,
+    message_id: &str,
+    output: &str,
+    base64: bool,
+    armored: bool,
+) -> Result<()> {
+    let transactions = id::try_load_single_identity(id_from)?;
+    let id_str = id_str!(util::build_identity(&transactions)?.id())?;
+    let (_, record) = find_record(&id_str, message_id)?;
+    if record.direction != "in" {
+        Err(anyhow!("Message {} is one we sent, receipts are for messages we received", message_id))?;
+    }
+    let peer = record
+        .peer_identity
+        .ok_or_else(|| anyhow!("Message {} is anonymous, so there's nobody to send a receipt to", message_id))?;
+    let acked = record.message_id.ok_or_else(|| {
+        anyhow!(
+            "Message {} doesn't have a message ID to acknowledge (plain text messages don't carry one)",
+            message_id
+        )
+    })?;
+    let envelope = Envelope {
+        in_reply_to: Some(acked.clone()),
+        receipt: Some(Receipt {
+            message_id: acked,
+            read: Timestamp::now().local().to_rfc3339(),
+        }),
+        ..Default::default()
+    };
+    send_envelope(id_from, key_search_from, key_search_to, &peer, envelope, output, base64, armored)
+}
+
+/// Send a staged transaction to a co-signer as a message. It lands in their
+/// staging area when they open it.
+pub fn send_staged(
+    id_from: &str,
+    key_search_from: Option<&str>,
+    key_search_to: Option<&str>,
+    search_to: &str,
+    identity_id: &str,
+    transaction: &[u8],
+    comments: Vec<stage::StageComment>,
+    note: Option<&str>,
+    output: &str,
+    armored: bool,
+) -> Result<()> {
+    let envelope = Envelope {
+        body: note.map(|x| base64_encode(x.as_bytes())),
+        staged: Some(StagedTransaction {
+            identity: String::from(identity_id),
+            transaction: base64_encode(transaction),
+            comments,
+        }),
+        ..Default::default()
+    };
+    send_envelope(id_from, key_search_from, key_search_to, search_to, envelope, output, false, armored)
+}
+
+fn send_envelope(
+    id_from: &str,
+    key_search_from: Option<&str>,
+    key_search_to: Option<&str>,
+    search_to: &str,
+    mut envelope: Envelope,
+    output: &str,
+    base64: bool,
+    armored: bool,
 ) -> Result<()> {
     let mut rng = rng::chacha20();
     let transactions_from = id::try_load_single_identity(id_from)?;
@@ -35,11 +545,17 @@ pub fn send(
     }
     let transactions_to = identities[0].clone();
     let identity_to = util::build_identity(&transactions_to)?;
+    let id_str_to = id_str!(identity_to.id())?;
     let key_from = keychain::find_keys_by_search_or_prompt(&identity_from, key_search_from, "crypto", |sub| sub.key().as_cryptokey())?;
     let key_to = keychain::find_keys_by_search_or_prompt(&identity_to, key_search_to, "crypto", |sub| sub.key().as_cryptokey())?;
 
-    let msg_bytes = util::read_file(input)?;
+    id::print_counterparty(&identity_to, "Sending a message to")?;
     let id_str = id_str!(identity_from.id())?;
+    // plain messages don't get an ID, since it would need an envelope to ride in
+    if !envelope.is_plain() {
+        envelope.id = Some(new_message_id(&id_str, envelope_bytes(&envelope)?.as_slice()));
+    }
+    let msg_bytes = message_bytes(&envelope)?;
     let master_key_from = util::passphrase_prompt(
         &format!("Your current master passphrase for identity {}", IdentityID::short(&id_str)),
         identity_from.created(),
@@ -52,7 +568,25 @@ pub fn send(
     let serialized = sealed
         .serialize_binary()
         .map_err(|e| anyhow!("Problem serializing the sealed message: {}", e))?;
-    if base64 {
+    // the message is sealed to the recipient, so keep a copy we can open ourselves
+    let copy = message::send(&mut rng, &master_key_from, identity_from.id(), &key_from, &key_from, msg_bytes.as_slice())
+        .map_err(|e| anyhow!("Problem sealing the message: {}", e))?
+        .serialize_binary()
+        .map_err(|e| anyhow!("Problem serializing the sealed message: {}", e))?;
+    let key = record_message(
+        &id_str,
+        Some(&id_str_to),
+        "out",
+        serialized.as_slice(),
+        None,
+        Some(copy.as_slice()),
+        Some(&envelope),
+    )?;
+    eprintln!("Message saved as {} (see `stamp message list`)", key);
+    if armored {
+        let armored = armor::armor(armor::MESSAGE, &[("Recipient", IdentityID::short(&id_str_to))], serialized.as_slice());
+        util::write_file(output, armored.as_bytes())?;
+    } else if base64 {
         let base64 = base64_encode(serialized.as_slice());
         util::write_file(output, base64.as_bytes())?;
     } else {
@@ -61,7 +595,15 @@ pub fn send(
     Ok(())
 }
 
-pub fn send_anonymous(key_search_to: Option<&str>, input: &str, output: &str, search_to: &str, base64: bool) -> Result<()> {
+pub fn send_anonymous(
+    key_search_to: Option<&str>,
+    input: Option<&str>,
+    attachments: &[&str],
+    output: &str,
+    search_to: &str,
+    base64: bool,
+    armored: bool,
+) -> Result<()> {
     let mut rng = rng::chacha20();
     let identities = db::list_local_identities(Some(search_to))?;
     if identities.len() > 1 {
@@ -74,14 +616,19 @@ pub fn send_anonymous(key_search_to: Option<&str>, input: &str, output: &str, se
     let transactions_to = identities[0].clone();
     let identity_to = util::build_identity(&transactions_to)?;
     let key_to = keychain::find_keys_by_search_or_prompt(&identity_to, key_search_to, "crypto", |sub| sub.key().as_cryptokey())?;
+    id::print_counterparty(&identity_to, "Sending an anonymous message to")?;
 
-    let msg_bytes = util::read_file(input)?;
+    let msg_bytes = message_bytes(&build_envelope(input, attachments)?)?;
     let sealed =
         message::send_anonymous(&mut rng, &key_to, msg_bytes.as_slice()).map_err(|e| anyhow!("Problem sealing the message: {}", e))?;
     let serialized = sealed
         .serialize_binary()
         .map_err(|e| anyhow!("Problem serializing the sealed message: {}", e))?;
-    if base64 {
+    if armored {
+        // no recipient hint: anyone seeing the message in transit would learn who
+        // it's for
+        util::write_file(output, armor::armor(armor::MESSAGE, &[], serialized.as_slice()).as_bytes())?;
+    } else if base64 {
         let base64 = base64_encode(serialized.as_slice());
         util::write_file(output, base64.as_bytes())?;
     } else {
@@ -90,10 +637,34 @@ pub fn send_anonymous(key_search_to: Option<&str>, input: &str, output: &str, se
     Ok(())
 }
 
-pub fn open(id_to: &str, key_search_open: Option<&str>, input: &str, output: &str) -> Result<()> {
+pub fn open(
+    id_to: &str,
+    key_search_open: Option<&str>,
+    input: &str,
+    output: &str,
+    save_dir: Option<&str>,
+    skip_confirm: bool,
+) -> Result<()> {
     let transactions_to = id::try_load_single_identity(id_to)?;
     let identity_to = util::build_identity(&transactions_to)?;
-    let sealed_bytes = util::read_file(input)?;
+    let input_bytes = util::read_file(input)?;
+    let sealed_bytes = match armor::dearmor(input_bytes.as_slice())? {
+        Some(armored) => {
+            if armored.label != armor::MESSAGE {
+                Err(anyhow!("Expected a {}, but found a {}", armor::MESSAGE, armored.label))?;
+            }
+            let recipient = armored.headers.iter().find(|(key, _)| key == "Recipient").map(|(_, val)| val);
+            if let Some(recipient) = recipient {
+                let our_short = IdentityID::short(&id_str!(identity_to.id())?);
+                if recipient != &our_short {
+                    let yellow = dialoguer::console::Style::new().yellow();
+                    eprintln!("{}: this message says it's for {}, not {}", yellow.apply_to("Warning"), recipient, our_short);
+                }
+            }
+            armored.data
+        }
+        None => input_bytes,
+    };
     let sealed_message = Message::deserialize_binary(sealed_bytes.as_slice())
         .or_else(|_| Message::deserialize_binary(&base64_decode(sealed_bytes.as_slice())?))
         .map_err(|e| anyhow!("Error reading sealed message: {}", e))?;
@@ -127,12 +698,347 @@ pub fn open(id_to: &str, key_search_open: Option<&str>, input: &str, output: &st
                 .keychain()
                 .subkey_by_keyid(&signed_msg.signed_by_key())
                 .ok_or(anyhow!("The identity that send this message is missing the key used to sign the message"))?;
+            id::print_counterparty(&identity_from, "Message from")?;
             dry! {
                 { master_key_to, key_to, bytes }
                 message::open(&master_key_to, &key_to, &key_from, &sealed_message)
             }
         }
     };
-    util::write_file(output, opened.as_slice())?;
+    let peer = match &sealed_message {
+        Message::Signed(signed_msg) => Some(id_str!(signed_msg.signed_by_identity())?),
+        Message::Anonymous(_) => None,
+    };
+    let serialized = sealed_message
+        .serialize_binary()
+        .map_err(|e| anyhow!("Problem serializing the sealed message: {}", e))?;
+    let envelope = read_envelope(opened.as_slice())?;
+    let key = record_message(
+        &id_str!(identity_to.id())?,
+        peer.as_ref().map(|x| x.as_str()),
+        "in",
+        serialized.as_slice(),
+        Some(peer.is_some()),
+        None,
+        envelope.as_ref(),
+    )?;
+    write_plaintext(opened.as_slice(), output, save_dir)?;
+    eprintln!("Message saved as {} (see `stamp message list`)", key);
+    if let (Some(envelope), Some(peer)) = (envelope.as_ref(), peer.as_ref()) {
+        import_staged(envelope, peer, skip_confirm)?;
+    }
+    Ok(())
+}
+
+fn peer_name(peer_identity: &str) -> String {
+    IdentityID::try_from(peer_identity)
+        .ok()
+        .and_then(|id| db::load_identity(&id).ok().flatten())
+        .and_then(|t| util::build_identity(&t).ok())
+        .and_then(|i| i.names().get(0).cloned())
+        .unwrap_or_else(|| String::from(""))
+}
+
+/// List the messages we've sent and opened, optionally only those with one
+/// other identity or in one direction ("in" or "out").
+pub fn list(id: &str, search_peer: Option<&str>, direction: Option<&str>, verbose: bool) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let peer_str = match search_peer {
+        Some(search) => Some(id_str!(find_single_identity(search)?.id())?),
+        None => None,
+    };
+    let all = history(&id_str, peer_str.as_ref().map(|x| x.as_str()))?;
+    // replies point at message IDs, show them as the local ID of the original
+    let local_id = |message_id: &str| {
+        all.iter()
+            .find(|(_, rec)| rec.message_id.as_ref().map(|x| x.as_str()) == Some(message_id))
+            .map(|(key, _)| key.clone())
+            .unwrap_or_else(|| String::from("(not found)"))
+    };
+    let records = all
+        .iter()
+        .filter(|(_, rec)| direction.is_none() || Some(rec.direction.as_str()) == direction)
+        .collect::<Vec<_>>();
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(row!["ID", "Direction", "With", "Name", "Date", "Status", "Reply To"]);
+    for (key, rec) in records {
+        let (with, name) = match rec.peer_identity.as_ref() {
+            Some(peer) if verbose => (peer.clone(), peer_name(peer)),
+            Some(peer) => (IdentityID::short(peer), peer_name(peer)),
+            None => (String::from("-"), String::from("")),
+        };
+        let date = rec.timestamp.local().format("%b %d, %Y  %H:%M:%S").to_string();
+        let status = match (rec.direction.as_str(), rec.verified) {
+            _ if rec.receipt_for.is_some() => String::from("receipt"),
+            ("in", Some(true)) => String::from("verified"),
+            ("in", Some(false)) => String::from("anonymous"),
+            ("out", _) if rec.read_at.is_some() => String::from("read"),
+            ("out", _) if rec.copy.is_some() => String::from("sent"),
+            ("out", _) => String::from("sent (sealed)"),
+            _ => String::from("-"),
+        };
+        let reply_to = rec.in_reply_to.as_ref().map(|x| local_id(x)).unwrap_or_default();
+        table.add_row(row![key, rec.direction, with, name, date, status, reply_to]);
+    }
+    table.printstd();
+    Ok(())
+}
+
+fn find_record(our_identity: &str, message_id: &str) -> Result<(String, MessageRecord)> {
+    history(our_identity, None)?
+        .into_iter()
+        .find(|(key, _)| key == message_id)
+        .ok_or_else(|| anyhow!("Message {} not found", message_id))
+}
+
+/// Open a stored message again. Received messages are re-verified against the
+/// sender's current identity, sent messages are opened from our own copy.
+pub fn read(id: &str, key_search_open: Option<&str>, message_id: &str, output: &str, save_dir: Option<&str>) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let (_, record) = find_record(&id_str, message_id)?;
+    let sealed = match (record.direction.as_str(), record.copy.as_ref()) {
+        ("out", Some(copy)) => copy,
+        ("out", None) => Err(anyhow!(
+            "Message {} was sent before copies were kept and is sealed to the recipient, so it can't be read back",
+            message_id
+        ))?,
+        _ => &record.sealed,
+    };
+    let sealed = base64_decode(sealed.as_bytes()).map_err(|e| anyhow!("Problem decoding message {}: {}", message_id, e))?;
+    let sealed_message = Message::deserialize_binary(sealed.as_slice())
+        .or_else(|_| Message::deserialize_binary(&base64_decode(sealed.as_slice())?))
+        .map_err(|e| anyhow!("Error reading sealed message: {}", e))?;
+    let master_key = util::passphrase_prompt(
+        &format!("Your current master passphrase for identity {}", IdentityID::short(&id_str)),
+        identity.created(),
+    )?;
+    transactions
+        .test_master_key(&master_key)
+        .map_err(|e| anyhow!("Incorrect passphrase: {}", e))?;
+    let opened = match &sealed_message {
+        Message::Signed(signed_msg) => {
+            let key_from = if record.direction == "out" {
+                identity.keychain().subkey_by_keyid(&signed_msg.signed_by_key()).cloned()
+            } else {
+                let transactions_from = db::load_identity(signed_msg.signed_by_identity())?
+                    .ok_or(anyhow!("The identity that sent this message is no longer imported"))?;
+                util::build_identity(&transactions_from)?
+                    .keychain()
+                    .subkey_by_keyid(&signed_msg.signed_by_key())
+                    .cloned()
+            };
+            let key_from = key_from.ok_or(anyhow!("The key used to sign this message is missing"))?;
+            let key_open = if record.direction == "out" {
+                key_from.clone()
+            } else {
+                keychain::find_keys_by_search_or_prompt(&identity, key_search_open, "crypto", |sub| sub.key().as_cryptokey())?
+            };
+            message::open(&master_key, &key_open, &key_from, &sealed_message)
+        }
+        Message::Anonymous(_) => {
+            let key_open = keychain::find_keys_by_search_or_prompt(&identity, key_search_open, "crypto", |sub| sub.key().as_cryptokey())?;
+            message::open_anonymous(&master_key, &key_open, &sealed_message)
+        }
+    }
+    .map_err(|e| anyhow!("Problem opening message: {}", e))?;
+    match (record.direction.as_str(), record.peer_identity.as_ref()) {
+        ("out", Some(peer)) => eprintln!("To: {} {}", peer, peer_name(peer)),
+        (_, Some(peer)) => eprintln!("From: {} {}", peer, peer_name(peer)),
+        (_, None) => eprintln!("From: (anonymous)"),
+    }
+    eprintln!("Date: {}", record.timestamp.local().format("%b %d, %Y  %H:%M:%S"));
+    if let Some(message_id) = record.message_id.as_ref() {
+        eprintln!("Message-ID: {}", message_id);
+    }
+    if let Some(in_reply_to) = record.in_reply_to.as_ref() {
+        eprintln!("In-Reply-To: {}", in_reply_to);
+    }
+    if let Some(read_at) = record.read_at.as_ref() {
+        eprintln!("Read: {}", read_at);
+    }
+    eprintln!("");
+    write_plaintext(opened.as_slice(), output, save_dir)?;
+    Ok(())
+}
+
+/// Remove a message from local history.
+pub fn delete(id: &str, message_id: &str, skip_confirm: bool) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let (key, _) = find_record(&id_str, message_id)?;
+    if !skip_confirm && !util::yesno_prompt(&format!("Really delete message {}? [y/N]", key), "n")? {
+        return Ok(());
+    }
+    db::delete_record("message", &key)?;
+    println!("Message {} deleted", key);
+    Ok(())
+}
+
+/// Package the history of a conversation with another identity into a signed
+/// archive. Messages we received are opened and verified; messages we sent are
+/// sealed to the recipient so they're included as-is. The archive is signed with
+/// one of our `sign` keys and can optionally be sealed to ourselves.
+pub fn export_session(
+    id: &str,
+    search_peer: &str,
+    key_search_open: Option<&str>,
+    key_search_sign: Option<&str>,
+    encrypt: bool,
+    output: &str,
+) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let peer = find_single_identity(search_peer)?;
+    let peer_str = id_str!(peer.id())?;
+    let records = history(&id_str, Some(&peer_str))?;
+    if records.len() == 0 {
+        Err(anyhow!("No messages with {} found", IdentityID::short(&peer_str)))?;
+    }
+    let key_open = keychain::find_keys_by_search_or_prompt(&identity, key_search_open, "crypto", |sub| sub.key().as_cryptokey())?;
+    let key_sign = keychain::find_keys_by_search_or_prompt(&identity, key_search_sign, "sign", |sub| sub.key().as_signkey())?;
+    let master_key = util::passphrase_prompt(
+        &format!("Your current master passphrase for identity {}", IdentityID::short(&id_str)),
+        identity.created(),
+    )?;
+    transactions
+        .test_master_key(&master_key)
+        .map_err(|e| anyhow!("Incorrect passphrase: {}", e))?;
+
+    let mut messages = Vec::with_capacity(records.len());
+    for (_, record) in records {
+        let mut entry = serde_json::json!({
+            "direction": record.direction,
+            "timestamp": record.timestamp.local().to_rfc3339(),
+            "message_id": record.message_id,
+            "in_reply_to": record.in_reply_to,
+            "sealed": record.sealed,
+        });
+        if record.direction == "in" {
+            let sealed = base64_decode(record.sealed.as_bytes())?;
+            let opened = Message::deserialize_binary(sealed.as_slice())
+                .map_err(|e| anyhow!("{}", e))
+                .and_then(|msg| match &msg {
+                    Message::Signed(signed_msg) => {
+                        let key_from = peer
+                            .keychain()
+                            .subkey_by_keyid(&signed_msg.signed_by_key())
+                            .ok_or(anyhow!("sender key {} not found", signed_msg.signed_by_key()))?;
+                        message::open(&master_key, &key_open, &key_from, &msg).map_err(|e| anyhow!("{}", e))
+                    }
+                    Message::Anonymous(_) => Err(anyhow!("anonymous message")),
+                });
+            match opened {
+                Ok(opened) => {
+                    entry["verified"] = serde_json::json!(true);
+                    let body = match read_envelope(opened.as_slice())? {
+                        Some(envelope) => {
+                            entry["attachments"] = serde_json::json!(envelope
+                                .attachments
+                                .iter()
+                                .map(|x| serde_json::json!({ "filename": x.filename, "mime": x.mime }))
+                                .collect::<Vec<_>>());
+                            match envelope.body {
+                                Some(body) => base64_decode(body.as_bytes())?,
+                                None => Vec::new(),
+                            }
+                        }
+                        None => opened,
+                    };
+                    entry["body"] = match String::from_utf8(body.clone()) {
+                        Ok(text) => serde_json::json!(text),
+                        Err(_) => serde_json::json!({ "base64": base64_encode(body.as_slice()) }),
+                    };
+                }
+                Err(e) => {
+                    entry["verified"] = serde_json::json!(false);
+                    entry["error"] = serde_json::json!(format!("{}", e));
+                }
+            }
+        }
+        messages.push(entry);
+    }
+    let session = serde_json::json!({
+        "identity": id_str,
+        "peer": peer_str,
+        "exported": Timestamp::now().local().to_rfc3339(),
+        "messages": messages,
+    });
+    let session_bytes = serde_json::to_vec_pretty(&session)?;
+    let signature = sign::sign(&master_key, identity.id(), &key_sign, session_bytes.as_slice())
+        .map_err(|e| anyhow!("Problem signing archive: {}", e))?;
+    let signature = signature
+        .serialize_binary()
+        .map_err(|e| anyhow!("Problem serializing the signature: {}", e))?;
+    let archive = serde_json::json!({
+        "session": String::from_utf8(session_bytes)?,
+        "signature": base64_encode(signature.as_slice()),
+    });
+    let archive_bytes = serde_json::to_vec_pretty(&archive)?;
+    if encrypt {
+        let mut rng = rng::chacha20();
+        let sealed = message::send(&mut rng, &master_key, identity.id(), &key_open, &key_open, archive_bytes.as_slice())
+            .map_err(|e| anyhow!("Problem sealing the archive: {}", e))?;
+        let serialized = sealed
+            .serialize_binary()
+            .map_err(|e| anyhow!("Problem serializing the sealed archive: {}", e))?;
+        util::write_file(output, base64_encode(serialized.as_slice()).as_bytes())?;
+    } else {
+        util::write_file(output, archive_bytes.as_slice())?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_messages_are_sent_bare() {
+        let envelope = Envelope {
+            body: Some(base64_encode(b"hi there")),
+            ..Default::default()
+        };
+        let bytes = message_bytes(&envelope).unwrap();
+        assert_eq!(bytes, b"hi there");
+        assert!(read_envelope(bytes.as_slice()).unwrap().is_none());
+    }
+
+    #[test]
+    fn envelope_roundtrip() {
+        let envelope = Envelope {
+            id: Some(String::from("msg1")),
+            in_reply_to: Some(String::from("msg0")),
+            body: Some(base64_encode(b"see attached")),
+            attachments: vec![Attachment {
+                filename: String::from("notes.txt"),
+                mime: String::from(guess_mime("notes.txt")),
+                data: base64_encode(b"1, 2, 3"),
+            }],
+            ..Default::default()
+        };
+        let bytes = message_bytes(&envelope).unwrap();
+        assert!(bytes.starts_with(ENVELOPE_MAGIC));
+        let read = read_envelope(bytes.as_slice()).unwrap().unwrap();
+        assert_eq!(read.id.as_deref(), Some("msg1"));
+        assert_eq!(read.in_reply_to.as_deref(), Some("msg0"));
+        assert_eq!(read.body, envelope.body);
+        assert_eq!(read.attachments.len(), 1);
+        assert_eq!(read.attachments[0].filename, "notes.txt");
+        assert_eq!(read.attachments[0].mime, "text/plain");
+        assert_eq!(base64_decode(read.attachments[0].data.as_bytes()).unwrap(), b"1, 2, 3");
+    }
+
+    #[test]
+    fn damaged_envelope_is_an_error() {
+        let mut bytes = Vec::from(ENVELOPE_MAGIC);
+        bytes.extend_from_slice(b"{\"body\": ");
+        assert!(read_envelope(bytes.as_slice()).is_err());
+    }
+}
@@ -1,12 +1,20 @@
 pub mod agent;
+pub mod audit;
 pub mod claim;
 pub mod config;
+pub mod crypt;
 pub mod dag;
+pub mod db;
 pub mod debug;
 pub mod id;
 pub mod keychain;
 pub mod message;
 pub mod net;
+pub mod release;
+pub mod search;
 pub mod sign;
 pub mod stage;
 pub mod stamp;
+pub mod status;
+pub mod sync;
+pub mod trust;
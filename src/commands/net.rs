@@ -2,28 +2,90 @@ use crate::{commands::id::try_load_single_identity, config, db, util};
 use anyhow::{anyhow, Result};
 use chrono::{Days, Local};
 use indicatif::{ProgressBar, ProgressStyle};
+use prettytable::Table;
+use serde_derive::{Deserialize, Serialize};
 use stamp_aux::id::sign_with_optimal_key;
 use stamp_core::{
     dag::{Transaction, Transactions},
     identity::{Identity, IdentityID},
-    util::{base64_decode, SerText, SerdeBinary, Timestamp},
+    util::{base64_decode, base64_encode, SerText, SerdeBinary, Timestamp},
 };
 use stamp_net::{
     agent::{self, random_peer_key, Agent, DHTMode, Event, Quorum, RelayMode},
     Multiaddr,
 };
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
+use std::io::IsTerminal;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
     sync::{mpsc, oneshot, RwLock},
     task,
 };
 use tracing::log::{trace, warn};
 
-async fn event_sink(mut events: mpsc::Receiver<Event>, tx_ident: mpsc::Sender<()>, min_idents: usize) -> stamp_net::error::Result<()> {
+/// Counters exposed by `stamp net node --metrics`.
+///
+/// TODO: stored records, connected peers, and publish/lookup rates. `Agent`
+/// doesn't expose its record store or the DHT requests it serves yet.
+#[derive(Debug)]
+pub struct NodeMetrics {
+    started: Timestamp,
+    events: RwLock<BTreeMap<&'static str, u64>>,
+}
+
+impl NodeMetrics {
+    fn new() -> Self {
+        Self {
+            started: Timestamp::now(),
+            events: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    async fn record(&self, event: &Event) {
+        let kind = match event {
+            Event::IdentifyRecv => "IdentifyRecv",
+            Event::Quit => "Quit",
+            _ => "Other",
+        };
+        *self.events.write().await.entry(kind).or_insert(0) += 1;
+    }
+
+    async fn render(&self) -> String {
+        let uptime = Timestamp::now().local().timestamp() - self.started.local().timestamp();
+        let events = self.events.read().await;
+        let mut out = String::new();
+        out.push_str("# HELP stamp_node_uptime_seconds Seconds since the node started.\n");
+        out.push_str("# TYPE stamp_node_uptime_seconds gauge\n");
+        out.push_str(&format!("stamp_node_uptime_seconds {}\n", uptime));
+        out.push_str("# HELP stamp_node_peers_identified_total Peers that completed the identify handshake.\n");
+        out.push_str("# TYPE stamp_node_peers_identified_total counter\n");
+        out.push_str(&format!("stamp_node_peers_identified_total {}\n", events.get("IdentifyRecv").unwrap_or(&0)));
+        out.push_str("# HELP stamp_node_events_total Network events seen by the node, by kind.\n");
+        out.push_str("# TYPE stamp_node_events_total counter\n");
+        for (kind, count) in events.iter() {
+            out.push_str(&format!("stamp_node_events_total{{kind=\"{}\"}} {}\n", kind, count));
+        }
+        out
+    }
+}
+
+async fn event_sink(
+    mut events: mpsc::Receiver<Event>,
+    tx_ident: mpsc::Sender<()>,
+    min_idents: usize,
+    metrics: Option<Arc<NodeMetrics>>,
+) -> stamp_net::error::Result<()> {
     let mut num_idents = 0;
     loop {
-        match events.recv().await {
+        let event = events.recv().await;
+        if let (Some(metrics), Some(ev)) = (metrics.as_ref(), event.as_ref()) {
+            metrics.record(ev).await;
+        }
+        match event {
             Some(Event::Quit) => break,
             Some(Event::IdentifyRecv) => {
                 num_idents += 1;
@@ -38,43 +100,100 @@ async fn event_sink(mut events: mpsc::Receiver<Event>, tx_ident: mpsc::Sender<()
     Ok(())
 }
 
+/// Serve node metrics in the Prometheus text format on `GET /metrics`.
+async fn metrics_server(listener: TcpListener, metrics: Arc<NodeMetrics>) -> stamp_net::error::Result<()> {
+    loop {
+        let (mut socket, _) = match listener.accept().await {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("metrics: accept failed: {}", e);
+                continue;
+            }
+        };
+        let metrics = metrics.clone();
+        task::spawn(async move {
+            let mut buf = [0u8; 1024];
+            let read = socket.read(&mut buf).await.unwrap_or(0);
+            let request = String::from_utf8_lossy(&buf[0..read]);
+            let response = if request.starts_with("GET /metrics ") {
+                let body = metrics.render().await;
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            } else {
+                String::from("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            };
+            let _ = socket.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+/// The public StampNet bootstrap servers.
+pub fn default_joinlist() -> Result<Vec<Multiaddr>> {
+    Ok(vec![
+        "/dns/join01.stampid.net/tcp/5757".parse()?,
+        "/dns/join02.stampid.net/tcp/5757".parse()?,
+    ])
+}
+
 pub fn get_stampnet_joinlist(join: Vec<Multiaddr>) -> Result<Vec<Multiaddr>> {
     if join.len() > 0 {
         return Ok(join);
     }
     let config = config::load()?;
-    let join_list = match config.net {
-        Some(net) => net.join_list.clone(),
-        None => {
-            vec![
-                "/dns/join01.stampid.net/tcp/5757".parse()?,
-                "/dns/join02.stampid.net/tcp/5757".parse()?,
-            ]
-        }
-    };
-    Ok(join_list)
+    let join_list = config.net.map(|net| net.join_list.clone()).unwrap_or_default();
+    if join_list.len() > 0 {
+        return Ok(join_list);
+    }
+    if config::load_cli()?.no_default_bootstrap {
+        Err(anyhow!(
+            "No StampNet servers to join. Pass --join, or add servers with `stamp config net add-join` (default bootstrap servers are disabled)"
+        ))?;
+    }
+    default_joinlist()
 }
 
-#[tokio::main(flavor = "current_thread")]
-pub async fn publish(id: &str, publish_transaction_file: Option<&str>, join: Vec<Multiaddr>) -> Result<()> {
-    let hash_with = config::hash_algo(Some(&id));
-    let transactions = try_load_single_identity(id)?;
-    let identity = util::build_identity(&transactions)?;
+/// Settings for automatically republishing an identity to StampNet before its
+/// DHT record expires.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoPublish {
+    pub identity_id: String,
+    pub interval_days: u64,
+    pub last_published: Timestamp,
+    /// The transaction IDs the last publish transaction was built from, used to
+    /// tell if the identity changed and the publish needs to be re-signed.
+    pub published_transactions: Vec<String>,
+    /// The last signed publish transaction (base64), re-sent as-is if the
+    /// identity hasn't changed.
+    pub publish_transaction: String,
+}
+
+fn transaction_ids(transactions: &Transactions) -> Result<Vec<String>> {
+    let mut ids = transactions
+        .transactions()
+        .iter()
+        .map(|t| id_str!(t.id()))
+        .collect::<Result<Vec<_>>>()?;
+    ids.sort();
+    Ok(ids)
+}
+
+fn create_publish_transaction(transactions: &Transactions) -> Result<Transaction> {
+    let identity = util::build_identity(transactions)?;
     let id_str = id_str!(identity.id())?;
-    let signed_publish_transaction = if let Some(publish_transaction_file) = publish_transaction_file {
-        let contents = util::load_file(publish_transaction_file)?;
-        Transaction::deserialize_binary(&contents).or_else(|_| Transaction::deserialize_binary(&base64_decode(&contents)?))?
-    } else {
-        let master_key =
-            util::passphrase_prompt(&format!("Your master passphrase for identity {}", IdentityID::short(&id_str)), identity.created())?;
-        let now = Timestamp::now();
-        let transaction = transactions
-            .publish(&hash_with, now)
-            .map_err(|e| anyhow!("Error creating publish transaction: {:?}", e))?;
-        sign_with_optimal_key(&identity, &master_key, transaction).map_err(|e| anyhow!("Error signing transaction: {:?}", e))?
-    };
-    let (_, identity) = signed_publish_transaction.clone().validate_publish_transaction()?;
+    let hash_with = config::hash_algo(Some(&id_str));
+    let master_key =
+        util::passphrase_prompt(&format!("Your master passphrase for identity {}", IdentityID::short(&id_str)), identity.created())?;
+    let now = Timestamp::now();
+    let transaction = transactions
+        .publish(&hash_with, now)
+        .map_err(|e| anyhow!("Error creating publish transaction: {:?}", e))?;
+    sign_with_optimal_key(&identity, &master_key, transaction).map_err(|e| anyhow!("Error signing transaction: {:?}", e))
+}
 
+async fn publish_signed(signed_publish_transaction: Transaction, join: Vec<Multiaddr>) -> Result<()> {
     let join = get_stampnet_joinlist(join)?;
     let join_len = join.len();
     let bind: Multiaddr = "/ip4/127.0.0.1/tcp/0".parse()?;
@@ -92,7 +211,7 @@ pub async fn publish(id: &str, publish_transaction_file: Option<&str>, join: Vec
     let agent = Arc::new(agent);
     let mut task_set = task::JoinSet::new();
     let (tx_ident, mut rx_ident) = mpsc::channel::<()>(1);
-    task_set.spawn(event_sink(events, tx_ident, join_len));
+    task_set.spawn(event_sink(events, tx_ident, join_len, None));
     let agent2 = agent.clone();
     task_set.spawn(async move { agent2.run(bind.clone(), join).await });
     match rx_ident.recv().await {
@@ -109,19 +228,144 @@ pub async fn publish(id: &str, publish_transaction_file: Option<&str>, join: Vec
     while let Some(res) = task_set.join_next().await {
         res??;
     }
+    Ok(())
+}
+
+#[tokio::main(flavor = "current_thread")]
+pub async fn publish(id: &str, publish_transaction_file: Option<&str>, join: Vec<Multiaddr>, auto_days: Option<u64>) -> Result<()> {
+    let transactions = try_load_single_identity(id)?;
+    let signed_publish_transaction = if let Some(publish_transaction_file) = publish_transaction_file {
+        let contents = util::load_file(publish_transaction_file)?;
+        Transaction::deserialize_binary(&contents).or_else(|_| Transaction::deserialize_binary(&base64_decode(&contents)?))?
+    } else {
+        create_publish_transaction(&transactions)?
+    };
+    let (published_transactions, identity) = signed_publish_transaction.clone().validate_publish_transaction()?;
+    let serialized = signed_publish_transaction
+        .serialize_binary()
+        .map_err(|e| anyhow!("Problem serializing publish transaction: {:?}", e))?;
+    publish_signed(signed_publish_transaction, join).await?;
+
     let green = dialoguer::console::Style::new().green();
     println!("{} stamp://{}", green.apply_to("Published identity"), identity.id());
-    println!(
-        "\n{}",
-        util::text_wrap("Your identity will expire in 365 days and must be republished before then to remain active in the network.")
-    );
-    println!(
-        "Set your calendar for {}!",
-        green.apply_to((Local::now().date_naive() + Days::new(365)).format("%b %e %Y"))
-    );
+    if let Some(interval_days) = auto_days {
+        let id_str = id_str!(identity.id())?;
+        let auto = AutoPublish {
+            identity_id: id_str.clone(),
+            interval_days,
+            last_published: Timestamp::now(),
+            published_transactions: transaction_ids(&published_transactions)?,
+            publish_transaction: base64_encode(serialized.as_slice()),
+        };
+        db::save_record("autopublish", &id_str, &auto)?;
+        println!(
+            "\n{}",
+            util::text_wrap(&format!(
+                "Automatic republishing enabled every {} days. Run `stamp net republish` regularly (for instance from cron) to keep your identity active in the network.",
+                interval_days
+            ))
+        );
+    } else {
+        println!(
+            "\n{}",
+            util::text_wrap("Your identity will expire in 365 days and must be republished before then to remain active in the network.")
+        );
+        println!(
+            "Set your calendar for {}!",
+            green.apply_to((Local::now().date_naive() + Days::new(365)).format("%b %e %Y"))
+        );
+    }
+    Ok(())
+}
+
+/// Republish any identities set up with `stamp net publish --auto` whose
+/// interval has passed. The saved publish transaction is reused unless the
+/// identity has changed since, in which case a new one is signed.
+///
+/// One identity failing doesn't stop the others from being republished, but
+/// the run returns an error at the end so schedulers notice. When stdin isn't
+/// a terminal (ex from cron) identities that need re-signing are skipped and
+/// reported instead of prompting for their passphrase.
+///
+/// TODO: once the agent runs with `--net`, it should call this on a timer.
+#[tokio::main(flavor = "current_thread")]
+pub async fn republish(join: Vec<Multiaddr>, force: bool) -> Result<()> {
+    let autos = db::list_records::<AutoPublish>("autopublish")?;
+    if autos.len() == 0 {
+        println!("No identities are set up for automatic republishing. Use `stamp net publish --auto`.");
+        return Ok(());
+    }
+    let green = dialoguer::console::Style::new().green();
+    let yellow = dialoguer::console::Style::new().yellow();
+    let red = dialoguer::console::Style::new().red();
+    let interactive = std::io::stdin().is_terminal();
+    let now = Timestamp::now();
+    let mut skipped = Vec::new();
+    let mut failed = Vec::new();
+    for (key, mut auto) in autos {
+        let id_short = IdentityID::short(&auto.identity_id);
+        let elapsed_days = (now.local().timestamp() - auto.last_published.local().timestamp()) / 86400;
+        if !force && elapsed_days < auto.interval_days as i64 {
+            println!("{}: not due ({} of {} days)", id_short, elapsed_days, auto.interval_days);
+            continue;
+        }
+        match republish_one(&key, &mut auto, join.clone(), interactive).await {
+            Ok(true) => println!("{} stamp://{}", green.apply_to("Republished identity"), auto.identity_id),
+            Ok(false) => {
+                eprintln!(
+                    "{} {}: it changed since the last publish and needs your master passphrase to re-sign",
+                    yellow.apply_to("Skipped identity"),
+                    id_short
+                );
+                skipped.push(id_short);
+            }
+            Err(e) => {
+                eprintln!("{} {}: {}", red.apply_to("Could not republish identity"), id_short, e);
+                failed.push(id_short);
+            }
+        }
+    }
+    if skipped.len() > 0 {
+        eprintln!(
+            "\n{}",
+            yellow.apply_to(util::text_wrap(&format!(
+                "Run `stamp net republish` from a terminal to re-sign and republish: {}",
+                skipped.join(", ")
+            )))
+        );
+    }
+    if failed.len() > 0 {
+        Err(anyhow!("Republishing failed for {} identity(s): {}", failed.len(), failed.join(", ")))?;
+    }
     Ok(())
 }
 
+/// Republish one identity for [republish], saving the new publish state.
+/// Returns `false` if the identity needs re-signing but we can't prompt for its
+/// passphrase.
+async fn republish_one(key: &str, auto: &mut AutoPublish, join: Vec<Multiaddr>, interactive: bool) -> Result<bool> {
+    let transactions = try_load_single_identity(&auto.identity_id)?;
+    let current_ids = transaction_ids(&transactions)?;
+    let signed = if current_ids == auto.published_transactions {
+        Transaction::deserialize_binary(&base64_decode(&auto.publish_transaction)?)
+            .map_err(|e| anyhow!("Problem reading saved publish transaction: {:?}", e))?
+    } else if !interactive {
+        return Ok(false);
+    } else {
+        println!("{}: identity changed since the last publish, re-signing", IdentityID::short(&auto.identity_id));
+        create_publish_transaction(&transactions)?
+    };
+    let serialized = signed
+        .serialize_binary()
+        .map_err(|e| anyhow!("Problem serializing publish transaction: {:?}", e))?;
+    publish_signed(signed, join).await?;
+    auto.last_published = Timestamp::now();
+    auto.published_transactions = current_ids;
+    auto.publish_transaction = base64_encode(serialized.as_slice());
+    db::save_record("autopublish", key, auto)?;
+    Ok(true)
+}
+
 pub async fn get_identity(id: &str, join: Vec<Multiaddr>) -> Result<(Transactions, Identity)> {
     let identity_id = IdentityID::try_from(id)?;
     let join = get_stampnet_joinlist(join)?;
@@ -141,7 +385,7 @@ pub async fn get_identity(id: &str, join: Vec<Multiaddr>) -> Result<(Transaction
     let agent = Arc::new(agent);
     let mut task_set = task::JoinSet::new();
     let (tx_ident, mut rx_ident) = mpsc::channel::<()>(1);
-    task_set.spawn(event_sink(events, tx_ident, join_len));
+    task_set.spawn(event_sink(events, tx_ident, join_len, None));
     let agent2 = agent.clone();
     task_set.spawn(async move { agent2.run(bind.clone(), join).await });
     match rx_ident.recv().await {
@@ -182,8 +426,15 @@ pub async fn get(id: &str, join: Vec<Multiaddr>) -> Result<()> {
     Ok(())
 }
 
+// TODO: nodes use an in-memory record store, so a restart drops every identity the
+// node was holding (they come back as peers republish). A disk-backed store with
+// quotas/GC needs to live in stamp-net since `Agent::new` only accepts its own store
+// types; once that exists, expose the path/quota here.
+//
+// TODO: connection limits, per-peer bandwidth limits, and peer ban/allow lists all
+// need hooks in the stamp-net swarm, which `Agent` doesn't expose yet.
 #[tokio::main(flavor = "current_thread")]
-pub async fn node(bind: Multiaddr, join: Vec<Multiaddr>) -> Result<()> {
+pub async fn node(bind: Multiaddr, join: Vec<Multiaddr>, metrics_bind: Option<SocketAddr>) -> Result<()> {
     let join = get_stampnet_joinlist(join)?;
     let peer_key = random_peer_key();
     let peer_id = stamp_net::PeerId::from(peer_key.public());
@@ -191,7 +442,14 @@ pub async fn node(bind: Multiaddr, join: Vec<Multiaddr>) -> Result<()> {
     let agent = Arc::new(agent);
     let mut task_set = task::JoinSet::new();
     let (tx_ident, mut rx_ident) = mpsc::channel::<()>(1);
-    task_set.spawn(event_sink(events, tx_ident, 1));
+    let metrics = metrics_bind.map(|_| Arc::new(NodeMetrics::new()));
+    if let (Some(metrics_bind), Some(metrics)) = (metrics_bind, metrics.as_ref()) {
+        let listener = TcpListener::bind(metrics_bind)
+            .await
+            .map_err(|e| anyhow!("Could not bind the metrics address {}: {}", metrics_bind, e))?;
+        task_set.spawn(metrics_server(listener, metrics.clone()));
+    }
+    task_set.spawn(event_sink(events, tx_ident, 1, metrics));
     let agent2 = agent.clone();
     let bind2 = bind.clone();
     task_set.spawn(async move { agent2.run(bind2.clone(), join).await });
@@ -205,3 +463,335 @@ pub async fn node(bind: Multiaddr, join: Vec<Multiaddr>) -> Result<()> {
     }
     Ok(())
 }
+
+/// Pull the host and TCP port out of a multiaddr like `/dns/example.com/tcp/5757`.
+fn multiaddr_host_port(addr: &Multiaddr) -> Option<(String, u16)> {
+    let parts = format!("{}", addr);
+    let parts = parts.split('/').filter(|x| x.len() > 0).collect::<Vec<_>>();
+    let mut host = None;
+    let mut port = None;
+    for pair in parts.chunks(2) {
+        match pair {
+            ["dns", val] | ["dns4", val] | ["dns6", val] | ["ip4", val] => host = Some(val.to_string()),
+            ["ip6", val] => host = Some(format!("[{}]", val)),
+            ["tcp", val] => port = val.parse::<u16>().ok(),
+            _ => {}
+        }
+    }
+    Some((host?, port?))
+}
+
+fn is_private_ip(ip: &std::net::IpAddr) -> bool {
+    match ip {
+        std::net::IpAddr::V4(ip) => {
+            ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.octets()[0] == 100 && (ip.octets()[1] & 0xc0) == 64
+        }
+        std::net::IpAddr::V6(ip) => ip.is_loopback() || (ip.segments()[0] & 0xfe00) == 0xfc00 || (ip.segments()[0] & 0xffc0) == 0xfe80,
+    }
+}
+
+/// Run a set of connectivity checks against StampNet and print advice for
+/// anything that looks wrong.
+#[tokio::main(flavor = "current_thread")]
+pub async fn doctor(bind: Option<Multiaddr>, join: Vec<Multiaddr>, lookup_id: Option<&str>) -> Result<()> {
+    let green = dialoguer::console::Style::new().green();
+    let yellow = dialoguer::console::Style::new().yellow();
+    let red = dialoguer::console::Style::new().red();
+    let mut advice: Vec<String> = Vec::new();
+
+    println!("Bootstrap nodes:");
+    let join = get_stampnet_joinlist(join)?;
+    let mut reachable = 0;
+    for addr in &join {
+        let (host, port) = match multiaddr_host_port(addr) {
+            Some(x) => x,
+            None => {
+                println!("  {} {} (only TCP addresses can be checked)", yellow.apply_to("skip"), addr);
+                continue;
+            }
+        };
+        let start = std::time::Instant::now();
+        let conn =
+            tokio::time::timeout(std::time::Duration::from_secs(5), tokio::net::TcpStream::connect(format!("{}:{}", host, port))).await;
+        match conn {
+            Ok(Ok(_)) => {
+                reachable += 1;
+                println!("  {} {} ({}ms)", green.apply_to("ok"), addr, start.elapsed().as_millis());
+            }
+            Ok(Err(e)) => println!("  {} {} ({})", red.apply_to("fail"), addr, e),
+            Err(_) => println!("  {} {} (timed out)", red.apply_to("fail"), addr),
+        }
+    }
+    if reachable == 0 {
+        advice.push(String::from("No bootstrap nodes are reachable. Check your internet connection and firewall, or add working nodes with `stamp config net add-join`."));
+    }
+
+    println!("\nNetwork:");
+    // connecting a UDP socket sends nothing, but tells us which local address
+    // outbound traffic uses
+    let local_ip = std::net::UdpSocket::bind("0.0.0.0:0")
+        .and_then(|sock| sock.connect("198.51.100.1:9").map(|_| sock))
+        .and_then(|sock| sock.local_addr())
+        .map(|addr| addr.ip());
+    match local_ip {
+        Ok(ip) if is_private_ip(&ip) => {
+            println!("  {} outbound address {} is private, you are likely behind NAT", yellow.apply_to("nat"), ip);
+            advice.push(String::from("You appear to be behind NAT. Publishing and lookups work as a client, but to run a node others can dial you'll need to forward your bind port or run it on a public server."));
+        }
+        Ok(ip) => println!("  {} outbound address {} is public", green.apply_to("ok"), ip),
+        Err(e) => println!("  {} could not determine outbound address ({})", yellow.apply_to("skip"), e),
+    }
+
+    if let Some(bind) = bind.as_ref() {
+        match multiaddr_host_port(bind) {
+            Some((host, port)) => match tokio::net::TcpListener::bind(format!("{}:{}", host, port)).await {
+                Ok(_) => {
+                    println!("  {} can listen on {}", green.apply_to("ok"), bind);
+                    println!(
+                        "  {} whether {} is dialable from the internet can't be tested from here; ask someone to run `nc -vz <your ip> {}`",
+                        yellow.apply_to("note"),
+                        bind,
+                        port
+                    );
+                }
+                Err(e) => {
+                    println!("  {} cannot listen on {} ({})", red.apply_to("fail"), bind, e);
+                    advice.push(format!("The bind address {} is unusable. Another process may be using the port, or the address isn't assigned to this machine.", bind));
+                }
+            },
+            None => println!("  {} only TCP bind addresses can be checked", yellow.apply_to("skip")),
+        }
+    }
+
+    if reachable > 0 {
+        println!("\nDHT:");
+        let join_len = join.len();
+        let client_bind: Multiaddr = "/ip4/127.0.0.1/tcp/0".parse()?;
+        let peer_key = random_peer_key();
+        let peer_id = stamp_net::PeerId::from(peer_key.public());
+        let (agent, events) = Agent::new(peer_key, agent::memory_store(&peer_id), RelayMode::Client, DHTMode::Client)?;
+        let agent = Arc::new(agent);
+        let mut task_set = task::JoinSet::new();
+        let (tx_ident, mut rx_ident) = mpsc::channel::<()>(1);
+        task_set.spawn(event_sink(events, tx_ident, join_len, None));
+        let agent2 = agent.clone();
+        task_set.spawn(async move { agent2.run(client_bind.clone(), join).await });
+        let start = std::time::Instant::now();
+        let joined = tokio::time::timeout(std::time::Duration::from_secs(30), rx_ident.recv()).await;
+        match joined {
+            Ok(Some(_)) => {
+                println!("  {} joined StampNet ({}ms)", green.apply_to("ok"), start.elapsed().as_millis());
+                let start = std::time::Instant::now();
+                agent.dht_bootstrap().await?;
+                println!("  {} DHT bootstrap ({}ms)", green.apply_to("ok"), start.elapsed().as_millis());
+                if let Some(lookup_id) = lookup_id {
+                    let identity_id = IdentityID::try_from(lookup_id)?;
+                    let start = std::time::Instant::now();
+                    let res = tokio::time::timeout(std::time::Duration::from_secs(60), agent.lookup_identity(identity_id)).await;
+                    let elapsed = start.elapsed().as_millis();
+                    match res {
+                        Ok(Ok(Some(_))) => {
+                            println!("  {} lookup found {} ({}ms)", green.apply_to("ok"), IdentityID::short(lookup_id), elapsed)
+                        }
+                        Ok(Ok(None)) => {
+                            println!(
+                                "  {} lookup finished but {} was not found ({}ms)",
+                                yellow.apply_to("warn"),
+                                IdentityID::short(lookup_id),
+                                elapsed
+                            );
+                            advice.push(String::from("The lookup worked but the identity wasn't found. It may not be published, or its record may have expired (see `stamp net publish --auto`)."));
+                        }
+                        Ok(Err(e)) => println!("  {} lookup failed ({})", red.apply_to("fail"), e),
+                        Err(_) => {
+                            println!("  {} lookup timed out", red.apply_to("fail"));
+                            advice.push(String::from(
+                                "DHT lookups are timing out. This usually means too few peers are reachable; try joining more nodes.",
+                            ));
+                        }
+                    }
+                }
+            }
+            _ => {
+                println!("  {} could not complete the handshake with any node", red.apply_to("fail"));
+                advice.push(String::from("The bootstrap nodes accept TCP connections but the StampNet handshake failed. They may be running an incompatible version, or something between you is interfering with the connection."));
+            }
+        }
+        agent.quit().await?;
+        while let Some(res) = task_set.join_next().await {
+            res??;
+        }
+    }
+
+    println!("");
+    if advice.len() == 0 {
+        println!("{}", green.apply_to("Everything looks good."));
+    } else {
+        for item in advice {
+            println!("{} {}", yellow.apply_to("Advice:"), util::text_wrap(&item));
+        }
+    }
+    Ok(())
+}
+
+/// A locally-imported identity we want to keep up to date via StampNet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Follow {
+    pub identity_id: String,
+    pub followed: Timestamp,
+    pub last_refresh: Option<Timestamp>,
+}
+
+/// Describe the differences in keys and claims between two versions of the same
+/// identity.
+pub(crate) fn identity_changes(old: &Identity, new: &Identity) -> Vec<String> {
+    let mut changes = Vec::new();
+    let keys = |identity: &Identity| -> Vec<(String, String, bool)> {
+        let admin = identity
+            .keychain()
+            .admin_keys()
+            .iter()
+            .map(|k| (k.key().key_id().as_string(), k.name().clone(), k.revocation().is_some()));
+        let sub = identity
+            .keychain()
+            .subkeys()
+            .iter()
+            .map(|k| (k.key_id().as_string(), k.name().clone(), k.revocation().is_some()));
+        admin.chain(sub).collect::<Vec<_>>()
+    };
+    let old_keys = keys(old);
+    let new_keys = keys(new);
+    for (key_id, name, revoked) in &new_keys {
+        match old_keys.iter().find(|(id, ..)| id == key_id) {
+            None => changes.push(format!("Key added: {} ({})", name, key_id)),
+            Some((_, _, old_revoked)) => {
+                if *revoked && !old_revoked {
+                    changes.push(format!("Key revoked: {} ({})", name, key_id));
+                }
+            }
+        }
+    }
+    for (key_id, name, _) in &old_keys {
+        if !new_keys.iter().any(|(id, ..)| id == key_id) {
+            changes.push(format!("Key removed: {} ({})", name, key_id));
+        }
+    }
+    let old_claims = old.claims().iter().filter_map(|c| id_str!(c.id()).ok()).collect::<Vec<_>>();
+    let new_claims = new.claims().iter().filter_map(|c| id_str!(c.id()).ok()).collect::<Vec<_>>();
+    for claim_id in &new_claims {
+        if !old_claims.contains(claim_id) {
+            changes.push(format!("Claim added: {}", claim_id));
+        }
+    }
+    for claim_id in &old_claims {
+        if !new_claims.contains(claim_id) {
+            changes.push(format!("Claim removed: {}", claim_id));
+        }
+    }
+    changes
+}
+
+pub fn follow(search: &str) -> Result<()> {
+    let transactions = try_load_single_identity(search)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    if identity.is_owned() {
+        Err(anyhow!("The identity {} is owned by you and cannot be followed", IdentityID::short(&id_str)))?;
+    }
+    if db::load_record::<Follow>("follow", &id_str)?.is_some() {
+        println!("Already following identity {}", IdentityID::short(&id_str));
+        return Ok(());
+    }
+    let follow = Follow {
+        identity_id: id_str.clone(),
+        followed: Timestamp::now(),
+        last_refresh: None,
+    };
+    db::save_record("follow", &id_str, &follow)?;
+    let green = dialoguer::console::Style::new().green();
+    println!("{} {}", green.apply_to("Following identity"), IdentityID::short(&id_str));
+    println!("Use `stamp net refresh` to update followed identities from StampNet.");
+    Ok(())
+}
+
+pub fn unfollow(search: &str) -> Result<()> {
+    let follows = db::list_records::<Follow>("follow")?;
+    let found = follows
+        .iter()
+        .filter(|(id, _)| id.starts_with(search))
+        .collect::<Vec<_>>();
+    if found.len() > 1 {
+        Err(anyhow!("Multiple followed identities matched {}", search))?;
+    }
+    let (id_str, _) = found.get(0).ok_or(anyhow!("You are not following any identity matching {}", search))?;
+    db::delete_record("follow", id_str)?;
+    println!("Unfollowed identity {}", IdentityID::short(id_str));
+    Ok(())
+}
+
+pub fn following(verbose: bool) -> Result<()> {
+    let follows = db::list_records::<Follow>("follow")?;
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    let id_field = if verbose { "ID" } else { "ID (short)" };
+    table.set_titles(row![id_field, "Name", "Followed", "Last refresh"]);
+    for (_, follow) in follows {
+        let name = IdentityID::try_from(follow.identity_id.as_str())
+            .ok()
+            .and_then(|id| db::load_identity(&id).ok().flatten())
+            .and_then(|t| util::build_identity(&t).ok())
+            .and_then(|i| i.names().get(0).cloned())
+            .unwrap_or_else(|| String::from(""));
+        let followed = follow.followed.local().format("%b %d, %Y").to_string();
+        let last_refresh = follow
+            .last_refresh
+            .as_ref()
+            .map(|x| x.local().format("%b %d, %Y  %H:%M:%S").to_string())
+            .unwrap_or_else(|| String::from("-"));
+        let id = if verbose {
+            follow.identity_id.clone()
+        } else {
+            IdentityID::short(&follow.identity_id)
+        };
+        table.add_row(row![id, name, followed, last_refresh]);
+    }
+    table.printstd();
+    Ok(())
+}
+
+#[tokio::main(flavor = "current_thread")]
+pub async fn refresh(join: Vec<Multiaddr>) -> Result<()> {
+    let follows = db::list_records::<Follow>("follow")?;
+    if follows.len() == 0 {
+        println!("You aren't following any identities. See `stamp net follow`.");
+        return Ok(());
+    }
+    let green = dialoguer::console::Style::new().green();
+    let yellow = dialoguer::console::Style::new().yellow();
+    let red = dialoguer::console::Style::new().red();
+    for (id_str, mut follow) in follows {
+        let (transactions, identity) = match get_identity(&id_str, join.clone()).await {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("{} {}: {}", red.apply_to("Problem refreshing identity"), IdentityID::short(&id_str), e);
+                continue;
+            }
+        };
+        let changes = match db::load_identity(identity.id())? {
+            Some(existing) => identity_changes(&util::build_identity(&existing)?, &identity),
+            None => Vec::new(),
+        };
+        db::save_identity(transactions)?;
+        follow.last_refresh = Some(Timestamp::now());
+        db::save_record("follow", &id_str, &follow)?;
+        if changes.len() == 0 {
+            println!("{} {} (no key or claim changes)", green.apply_to("Refreshed identity"), IdentityID::short(&id_str));
+        } else {
+            println!("{} {} has changed:", yellow.apply_to("Warning: identity"), IdentityID::short(&id_str));
+            for change in changes {
+                println!("  {}", change);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use crate::{
+    armor,
+    commands::{id, keychain, sign},
+    ssh, util,
+};
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+use stamp_core::{crypto::sign as core_sign, identity::IdentityID, util::SerdeBinary};
+use std::path::Path;
+
+const SUMS_FILE: &str = "SHA256SUMS";
+const SIG_FILE: &str = "SHA256SUMS.sig";
+const SSH_SIG_FILE: &str = "SHA256SUMS.sshsig";
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Write a `SHA256SUMS` file for a set of release artifacts, along with an
+/// armored Stamp signature of it (and optionally an SSH one) so the whole
+/// release can be checked with [verify]. Artifacts inside `output_dir` keep
+/// their path relative to it.
+pub fn sign(id_sign: &str, key_search_sign: Option<&str>, artifacts: &[&str], output_dir: &str, with_ssh: bool) -> Result<()> {
+    let transactions = id::try_load_single_identity(id_sign)?;
+    let identity = util::build_identity(&transactions)?;
+    let key_sign = keychain::find_keys_by_search_or_prompt(&identity, key_search_sign, "sign", |sub| sub.key().as_signkey())?;
+    let files = util::collect_files(artifacts)?;
+    if files.len() == 0 {
+        Err(anyhow!("No artifacts to sign"))?;
+    }
+    let dir = Path::new(output_dir);
+    let outputs = [SUMS_FILE, SIG_FILE, SSH_SIG_FILE];
+    for name in outputs.iter().take(if with_ssh { 3 } else { 2 }) {
+        if dir.join(name).exists() {
+            Err(anyhow!("Refusing to overwrite existing file: {}", dir.join(name).display()))?;
+        }
+    }
+
+    // entries are relative to the sums file. artifacts outside the output
+    // directory are listed by file name, since they get published next to it.
+    std::fs::create_dir_all(dir).map_err(|e| anyhow!("Error creating directory: {}: {:?}", dir.display(), e))?;
+    let dir_full = dir
+        .canonicalize()
+        .map_err(|e| anyhow!("Error reading directory: {}: {:?}", dir.display(), e))?;
+    let mut names: Vec<String> = Vec::with_capacity(files.len());
+    let mut lines = Vec::with_capacity(files.len());
+    for path in &files {
+        let full = path
+            .canonicalize()
+            .map_err(|e| anyhow!("Error reading file: {}: {:?}", path.display(), e))?;
+        let name = match full.strip_prefix(&dir_full) {
+            Ok(rel) => rel
+                .components()
+                .map(|x| x.as_os_str().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join("/"),
+            Err(_) => path
+                .file_name()
+                .map(|x| x.to_string_lossy().to_string())
+                .ok_or(anyhow!("Can't sign {}", path.display()))?,
+        };
+        if names.contains(&name) {
+            Err(anyhow!("More than one artifact is named {}", name))?;
+        }
+        let bytes = util::load_file(&path.to_string_lossy())?;
+        lines.push(format!("{}  {}", sha256_hex(bytes.as_slice()), name));
+        names.push(name);
+    }
+    let sums = format!("{}\n", lines.join("\n"));
+
+    let id_str = id_str!(identity.id())?;
+    let master_key = util::passphrase_prompt(
+        &format!("Your current master passphrase for identity {}", IdentityID::short(&id_str)),
+        identity.created(),
+    )?;
+    transactions
+        .test_master_key(&master_key)
+        .map_err(|e| anyhow!("Incorrect passphrase: {}", e))?;
+    let signature = core_sign::sign(&master_key, identity.id(), &key_sign, sums.as_bytes())
+        .map_err(|e| anyhow!("Problem creating signature: {}", e))?;
+    let serialized = signature
+        .serialize_binary()
+        .map_err(|e| anyhow!("Problem serializing the signature: {}", e))?;
+    let ssh_sig = if with_ssh {
+        let keypair = key_sign
+            .key()
+            .as_signkey()
+            .ok_or_else(|| anyhow!("Key {} is not a `sign` key", key_sign.name()))?;
+        let seed = keychain::ed25519_secret(&master_key, keypair)?;
+        Some(ssh::sshsig_sign(&seed, "file", sums.as_bytes())?)
+    } else {
+        None
+    };
+
+    util::write_file(&dir.join(SUMS_FILE).to_string_lossy(), sums.as_bytes())?;
+    let armored = armor::armor(armor::SIGNATURE, &[], serialized.as_slice());
+    util::write_file(&dir.join(SIG_FILE).to_string_lossy(), armored.as_bytes())?;
+    if let Some(ssh_sig) = ssh_sig {
+        util::write_file(&dir.join(SSH_SIG_FILE).to_string_lossy(), ssh_sig.as_bytes())?;
+    }
+    eprintln!("Wrote {} and {} for {} artifact(s) to {}", SUMS_FILE, SIG_FILE, files.len(), dir.display());
+    Ok(())
+}
+
+/// Check the signature on a `SHA256SUMS` file and then the hash of every
+/// artifact it lists. Artifact paths are relative to the sums file.
+pub fn verify(sums_file: &str, signature_file: Option<&str>, ignore_missing: bool) -> Result<()> {
+    let sums_path = Path::new(sums_file);
+    let dir = sums_path.parent().unwrap_or(Path::new("."));
+    let signature_file = signature_file
+        .map(|x| String::from(x))
+        .unwrap_or_else(|| format!("{}.sig", sums_file));
+    let sums = util::load_file(sums_file)?;
+    let sig_bytes = armor::strip(util::load_file(&signature_file)?)?;
+    let (id_str, key_name) = sign::verify_detached(sig_bytes.as_slice(), sums.as_slice())?;
+
+    let green = dialoguer::console::Style::new().green();
+    let red = dialoguer::console::Style::new().red();
+    let yellow = dialoguer::console::Style::new().yellow();
+    println!(
+        "The release signature is {}! It was made by the identity {} with the key {}.",
+        green.apply_to("valid"),
+        id_str,
+        key_name
+    );
+    let sums = String::from_utf8(sums).map_err(|_| anyhow!("{} is not a text file", sums_file))?;
+    let mut failed = 0;
+    let mut checked = 0;
+    for line in sums.lines().filter(|x| !x.trim().is_empty()) {
+        let (hash, name) = line
+            .split_once("  ")
+            .or_else(|| line.split_once(" *"))
+            .ok_or(anyhow!("Bad line in {}: {}", sums_file, line))?;
+        // don't let a sums file point us outside of its own directory
+        if Path::new(name).components().any(|x| !matches!(x, std::path::Component::Normal(..))) {
+            Err(anyhow!("Bad path in {}: {}", sums_file, name))?;
+        }
+        let path = dir.join(name);
+        if !path.exists() {
+            if ignore_missing {
+                continue;
+            }
+            println!("  {} {}", yellow.apply_to("MISSING"), name);
+            failed += 1;
+            continue;
+        }
+        let bytes = util::load_file(&path.to_string_lossy())?;
+        checked += 1;
+        if sha256_hex(bytes.as_slice()) == hash.to_lowercase() {
+            println!("  {}      {}", green.apply_to("OK"), name);
+        } else {
+            println!("  {}  {}", red.apply_to("FAILED"), name);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        Err(anyhow!("{} artifact(s) failed verification", failed))?;
+    }
+    if checked == 0 {
+        Err(anyhow!("None of the artifacts in {} were found", sums_file))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use crate::{commands::claim, db, util};
+use anyhow::Result;
+use prettytable::Table;
+use stamp_core::identity::IdentityID;
+use std::convert::TryFrom;
+
+/// Something in the local db that matched a search.
+struct SearchResult {
+    ty: &'static str,
+    identity: String,
+    field: String,
+    value: String,
+    command: String,
+}
+
+/// Search every local identity's names, emails, public claim values, claim
+/// names, key names and descriptions, and stamp IDs. Matching is
+/// case-insensitive.
+pub fn search(query: &str) -> Result<()> {
+    let query = query.trim().to_lowercase();
+    let matches = |val: &str| val.to_lowercase().contains(&query);
+    let mut results = Vec::new();
+    for transactions in db::list_local_identities(None)? {
+        let identity = util::build_identity(&transactions)?;
+        let id_full = id_str!(identity.id())?;
+        let id_short = IdentityID::short(&id_full);
+        let owned = identity.is_owned();
+
+        if matches(&id_full) {
+            results.push(SearchResult {
+                ty: "identity",
+                identity: id_short.clone(),
+                field: String::from("id"),
+                value: id_full.clone(),
+                command: format!("stamp id view {}", id_full),
+            });
+        }
+        for claim in identity.claims() {
+            let claim_id = id_str!(claim.id())?;
+            let command = if owned {
+                format!("stamp claim view --id {} {}", id_short, claim_id)
+            } else {
+                format!("stamp stamp new {}", claim_id)
+            };
+            let ty = claim::claim_type(claim.spec());
+            if let Some(value) = claim::public_claim_value(claim.spec()).filter(|x| matches(x.as_str())) {
+                results.push(SearchResult {
+                    ty: "claim",
+                    identity: id_short.clone(),
+                    field: format!("{} claim", ty),
+                    value,
+                    command: command.clone(),
+                });
+            }
+            if let Some(name) = claim.name().as_ref().filter(|x| matches(x.as_str())) {
+                results.push(SearchResult {
+                    ty: "claim",
+                    identity: id_short.clone(),
+                    field: format!("{} claim name", ty),
+                    value: name.clone(),
+                    command: command.clone(),
+                });
+            }
+            for stamp in claim.stamps() {
+                let stamp_id = format!("{}", stamp.id());
+                if matches(&stamp_id) {
+                    results.push(SearchResult {
+                        ty: "stamp",
+                        identity: id_short.clone(),
+                        field: format!("stamp on {} claim", ty),
+                        value: stamp_id.clone(),
+                        command: format!("stamp claim stamp view --id {} {}", id_short, stamp_id),
+                    });
+                }
+            }
+        }
+        let admin_keys = identity
+            .keychain()
+            .admin_keys()
+            .iter()
+            .map(|k| ("admin key", k.name().clone(), k.description().clone()));
+        let subkeys = identity
+            .keychain()
+            .subkeys()
+            .iter()
+            .map(|k| ("subkey", k.name().clone(), k.description().clone()));
+        for (field, name, description) in admin_keys.chain(subkeys) {
+            let command = format!("stamp keychain list --id {} {}", id_short, name);
+            if matches(&name) {
+                results.push(SearchResult {
+                    ty: "key",
+                    identity: id_short.clone(),
+                    field: format!("{} name", field),
+                    value: name.clone(),
+                    command: command.clone(),
+                });
+            }
+            if let Some(description) = description.filter(|x| matches(x.as_str())) {
+                results.push(SearchResult {
+                    ty: "key",
+                    identity: id_short.clone(),
+                    field: format!("{} description", field),
+                    value: description,
+                    command,
+                });
+            }
+        }
+        for stamp in identity.stamps() {
+            let stamp_id = format!("{}", stamp.id());
+            if matches(&stamp_id) {
+                results.push(SearchResult {
+                    ty: "stamp",
+                    identity: id_short.clone(),
+                    field: String::from("stamp made"),
+                    value: stamp_id,
+                    command: format!("stamp stamp list --id {}", id_short),
+                });
+            }
+        }
+    }
+    if results.len() == 0 {
+        println!("Nothing matches \"{}\".", query);
+        return Ok(());
+    }
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(row!["Type", "Identity", "Field", "Match", "Command"]);
+    for result in results.iter() {
+        table.add_row(row![result.ty, result.identity, result.field, result.value, result.command]);
+    }
+    table.printstd();
+    Ok(())
+}
@@ -1,26 +1,105 @@
 use crate::{
-    commands::{dag, id, keychain},
-    config, db, util,
+    armor,
+    commands::{dag, id, keychain, stage},
+    config, db, ssh, util,
 };
 use anyhow::{anyhow, Result};
-use stamp_aux::db::stage_transaction;
+use serde_derive::Serialize;
+use stamp_aux::db::{delete_staged_transaction, load_staged_transaction};
 use stamp_core::{
     crypto::{
         base::Hash,
         sign::{self, Signature},
     },
-    dag::{Transaction, TransactionBody},
+    dag::{Transaction, TransactionBody, TransactionID},
     identity::IdentityID,
     util::{base64_decode, base64_encode, SerdeBinary, Timestamp},
 };
 use std::convert::TryFrom;
 
-pub fn sign_id(id_sign: &str, input: &str, output: &str, base64: bool, stage: bool, sign_with: Option<&str>) -> Result<()> {
+const CLEARSIGN_BEGIN: &str = "-----BEGIN STAMP SIGNED MESSAGE-----";
+const CLEARSIGN_SIG_BEGIN: &str = "-----BEGIN STAMP SIGNATURE-----";
+
+/// Put text into the form that gets clearsigned: LF line endings and no
+/// trailing whitespace. Mail software and websites routinely mangle both, so
+/// signing the canonical form keeps the signature valid after a round trip.
+pub(crate) fn clearsign_canonical(message: &[u8]) -> Result<Vec<u8>> {
+    let text = std::str::from_utf8(message).map_err(|_| anyhow!("Only UTF-8 text can be clearsigned"))?;
+    let canonical = text
+        .replace("\r\n", "\n")
+        .split('\n')
+        .map(|line| line.trim_end_matches(|c| c == ' ' || c == '\t'))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok(canonical.into_bytes())
+}
+
+/// Wrap a message and its (detached) signature into one human-readable
+/// document. Lines in the message starting with a dash are escaped with "- " so
+/// they can't be confused with the armor lines. The message should already be
+/// in [clearsign_canonical] form.
+pub(crate) fn clearsign_wrap(message: &[u8], signature: &[u8]) -> Result<String> {
+    let text = std::str::from_utf8(message).map_err(|_| anyhow!("Only UTF-8 text can be clearsigned"))?;
+    let escaped = text
+        .split('\n')
+        .map(|line| if line.starts_with('-') { format!("- {}", line) } else { String::from(line) })
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok(format!("{}\n{}\n{}", CLEARSIGN_BEGIN, escaped, armor::armor(armor::SIGNATURE, &[], signature)))
+}
+
+/// Parse a clearsigned document into its (message, signature) parts, or return
+/// None if the input isn't clearsigned. The message comes back in
+/// [clearsign_canonical] form, same as it was signed.
+pub(crate) fn clearsign_parse(document: &[u8]) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+    // undo line ending and trailing whitespace damage before looking for
+    // anything, so it doesn't matter whether it hit the message or the armor
+    let document = match std::str::from_utf8(document) {
+        Ok(_) => String::from_utf8(clearsign_canonical(document)?)?,
+        Err(_) => return Ok(None),
+    };
+    let start = match document.find(&format!("{}\n", CLEARSIGN_BEGIN)) {
+        Some(x) => x + CLEARSIGN_BEGIN.len() + 1,
+        None => return Ok(None),
+    };
+    let sig_start = document[start..]
+        .find(&format!("\n{}\n", CLEARSIGN_SIG_BEGIN))
+        .map(|x| x + start)
+        .ok_or(anyhow!("Clearsigned document is missing its signature"))?;
+    let message = document[start..sig_start]
+        .split('\n')
+        .map(|line| line.strip_prefix("- ").unwrap_or(line))
+        .collect::<Vec<_>>()
+        .join("\n");
+    // older documents have no checksum or headers, which the armor reader accepts
+    let signature = armor::dearmor(document[sig_start..].as_bytes())
+        .map_err(|e| anyhow!("Clearsigned document has a bad signature: {}", e))?
+        .ok_or(anyhow!("Clearsigned document signature is not terminated"))?
+        .data;
+    Ok(Some((message.into_bytes(), signature)))
+}
+
+pub fn sign_id(
+    id_sign: &str,
+    input: &str,
+    output: &str,
+    base64: bool,
+    armored: bool,
+    clearsign: bool,
+    cbor: bool,
+    stage: bool,
+    sign_with: Option<&str>,
+) -> Result<()> {
     let hash_with = config::hash_algo(Some(&id_sign));
     let transactions = id::try_load_single_identity(id_sign)?;
     let identity_id = transactions.identity_id().ok_or(anyhow!("Unable to generate identity id"))?;
     let identity = util::build_identity(&transactions)?;
     let msg_bytes = util::read_file(input)?;
+    let msg_bytes = if clearsign {
+        clearsign_canonical(msg_bytes.as_slice())?
+    } else {
+        msg_bytes
+    };
     let id_str = id_str!(identity.id())?;
     let master_key = util::passphrase_prompt(
         &format!("Your current master passphrase for identity {}", IdentityID::short(&id_str)),
@@ -33,15 +112,24 @@ pub fn sign_id(id_sign: &str, input: &str, output: &str, base64: bool, stage: bo
     let signed = util::sign_helper(&identity, transaction, &master_key, stage, sign_with)?;
     if stage {
         let msg = dag::post_save(&transactions, &signed, stage)?;
-        stage_transaction(&identity_id, signed).map_err(|e| anyhow!("Error staging transaction: {:?}", e))?;
+        stage::stage(&identity_id, signed)?;
         if let Some(msg) = msg {
             println!("{}", msg);
         }
     } else {
-        let serialized = signed
-            .serialize_binary()
-            .map_err(|e| anyhow!("Problem serializing the signature: {}", e))?;
-        if base64 {
+        let serialized = if cbor {
+            util::serialize_cbor(&signed)?
+        } else {
+            signed
+                .serialize_binary()
+                .map_err(|e| anyhow!("Problem serializing the signature: {}", e))?
+        };
+        if clearsign {
+            let document = clearsign_wrap(msg_bytes.as_slice(), serialized.as_slice())?;
+            util::write_file(output, document.as_bytes())?;
+        } else if armored {
+            util::write_file(output, armor::armor(armor::SIGNATURE, &[], serialized.as_slice()).as_bytes())?;
+        } else if base64 {
             let base64 = base64_encode(serialized.as_slice());
             util::write_file(output, base64.as_bytes())?;
         } else {
@@ -51,12 +139,64 @@ pub fn sign_id(id_sign: &str, input: &str, output: &str, base64: bool, stage: bo
     Ok(())
 }
 
-pub fn sign_subkey(id_sign: &str, key_search_sign: Option<&str>, input: &str, output: &str, attached: bool, base64: bool) -> Result<()> {
+/// Write out a staged policy signature once enough admins have signed it with
+/// `stamp stage sign`, removing it from staging.
+pub fn finish(txid: &str, output: &str, base64: bool, armored: bool) -> Result<()> {
+    let transaction_id = TransactionID::try_from(txid).map_err(|e| anyhow!("Error loading transaction id: {:?}", e))?;
+    let (identity_id, transaction) = load_staged_transaction(&transaction_id)
+        .map_err(|e| anyhow!("Error loading staged transaction: {:?}", e))?
+        .ok_or_else(|| anyhow!("Transaction {} not found", txid))?;
+    match transaction.entry().body() {
+        TransactionBody::SignV1 { .. } => {}
+        _ => Err(anyhow!("Transaction {} is not a policy signature. Use `stamp stage apply` instead.", txid))?,
+    }
+    let id_str = id_str!(&identity_id)?;
+    let transactions = id::try_load_single_identity(&id_str)?;
+    let identity = util::build_identity(&transactions)?;
+    transaction.verify(Some(&identity)).map_err(|e| {
+        anyhow!(
+            "This signature isn't valid yet ({}). More admins may need to sign it with:\n  stamp stage sign {}",
+            e,
+            txid
+        )
+    })?;
+    let serialized = transaction
+        .serialize_binary()
+        .map_err(|e| anyhow!("Problem serializing the signature: {}", e))?;
+    if armored {
+        util::write_file(output, armor::armor(armor::SIGNATURE, &[], serialized.as_slice()).as_bytes())?;
+    } else if base64 {
+        util::write_file(output, base64_encode(serialized.as_slice()).as_bytes())?;
+    } else {
+        util::write_file(output, serialized.as_slice())?;
+    }
+    delete_staged_transaction(&transaction_id).map_err(|e| anyhow!("Error removing staged transaction: {:?}", e))?;
+    stage::forget_staged(&format!("{}", transaction_id))?;
+    eprintln!("Wrote policy signature with {} admin signature(s)", transaction.signatures().len());
+    Ok(())
+}
+
+pub fn sign_subkey(
+    id_sign: &str,
+    key_search_sign: Option<&str>,
+    input: &str,
+    output: &str,
+    attached: bool,
+    base64: bool,
+    armored: bool,
+    clearsign: bool,
+    cbor: bool,
+) -> Result<()> {
     let transactions = id::try_load_single_identity(id_sign)?;
     let identity = util::build_identity(&transactions)?;
     let key_sign = keychain::find_keys_by_search_or_prompt(&identity, key_search_sign, "sign", |sub| sub.key().as_signkey())?;
 
     let msg_bytes = util::read_file(input)?;
+    let msg_bytes = if clearsign {
+        clearsign_canonical(msg_bytes.as_slice())?
+    } else {
+        msg_bytes
+    };
     let id_str = id_str!(identity.id())?;
     let master_key = util::passphrase_prompt(
         &format!("Your current master passphrase for identity {}", IdentityID::short(&id_str)),
@@ -71,10 +211,19 @@ pub fn sign_subkey(id_sign: &str, key_search_sign: Option<&str>, input: &str, ou
     } else {
         sign::sign(&master_key, identity.id(), &key_sign, msg_bytes.as_slice()).map_err(|e| anyhow!("Problem creating signature: {}", e))?
     };
-    let serialized = signature
-        .serialize_binary()
-        .map_err(|e| anyhow!("Problem serializing the signature: {}", e))?;
-    if base64 {
+    let serialized = if cbor {
+        util::serialize_cbor(&signature)?
+    } else {
+        signature
+            .serialize_binary()
+            .map_err(|e| anyhow!("Problem serializing the signature: {}", e))?
+    };
+    if clearsign {
+        let document = clearsign_wrap(msg_bytes.as_slice(), serialized.as_slice())?;
+        util::write_file(output, document.as_bytes())?;
+    } else if armored {
+        util::write_file(output, armor::armor(armor::SIGNATURE, &[], serialized.as_slice()).as_bytes())?;
+    } else if base64 {
         let base64 = base64_encode(serialized.as_slice());
         util::write_file(output, base64.as_bytes())?;
     } else {
@@ -83,48 +232,448 @@ pub fn sign_subkey(id_sign: &str, key_search_sign: Option<&str>, input: &str, ou
     Ok(())
 }
 
-pub fn verify(input_signature: &str, input_message: Option<&str>) -> Result<()> {
-    let sig_bytes = util::read_file(input_signature)?;
+/// Sign a file with a `sign` subkey, producing an SSH signature that
+/// `ssh-keygen -Y verify` can check.
+pub fn sign_ssh(id_sign: &str, key_search_sign: Option<&str>, input: &str, output: &str, namespace: &str) -> Result<()> {
+    let transactions = id::try_load_single_identity(id_sign)?;
+    let identity = util::build_identity(&transactions)?;
+    let key_sign = keychain::find_keys_by_search_or_prompt(&identity, key_search_sign, "sign", |sub| sub.key().as_signkey())?;
+    let keypair = key_sign
+        .key()
+        .as_signkey()
+        .ok_or_else(|| anyhow!("Key {} is not a `sign` key", key_sign.name()))?;
+    let msg_bytes = util::read_file(input)?;
+    let id_str = id_str!(identity.id())?;
+    let master_key = util::passphrase_prompt(
+        &format!("Your current master passphrase for identity {}", IdentityID::short(&id_str)),
+        identity.created(),
+    )?;
+    transactions
+        .test_master_key(&master_key)
+        .map_err(|e| anyhow!("Incorrect passphrase: {}", e))?;
+    let seed = keychain::ed25519_secret(&master_key, keypair)?;
+    let signature = ssh::sshsig_sign(&seed, namespace, msg_bytes.as_slice())?;
+    util::write_file(output, signature.as_bytes())?;
+    Ok(())
+}
+
+/// List the blake3 hash of each file for a `sign batch --manifest`. Paths are
+/// relative to the manifest's directory (or the current directory when it goes
+/// to STDOUT) and always use `/`, so the manifest doesn't leak local paths and
+/// can be checked wherever the files are published next to it. Files outside
+/// that directory are refused.
+fn batch_manifest(files: &[std::path::PathBuf], output: &str) -> Result<String> {
+    let base = match std::path::Path::new(output).parent() {
+        Some(dir) if output != "-" && dir.as_os_str().len() > 0 => dir.to_path_buf(),
+        _ => std::path::PathBuf::from("."),
+    };
+    let base_full = base
+        .canonicalize()
+        .map_err(|e| anyhow!("Error reading directory: {}: {:?}", base.display(), e))?;
+    let mut lines = Vec::with_capacity(files.len());
+    for path in files {
+        let full = path
+            .canonicalize()
+            .map_err(|e| anyhow!("Error reading file: {}: {:?}", path.display(), e))?;
+        let relative = full
+            .strip_prefix(&base_full)
+            .map_err(|_| {
+                anyhow!(
+                    "{} is outside of {}. Manifest paths are relative to the manifest's directory (or the current directory when writing to STDOUT).",
+                    path.display(),
+                    base_full.display()
+                )
+            })?
+            .components()
+            .map(|x| x.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<_>>()
+            .join("/");
+        if relative.contains('\n') {
+            Err(anyhow!("Can't list file names with newlines in a manifest: {:?}", relative))?;
+        }
+        let bytes = util::load_file(&path.to_string_lossy())?;
+        let hash = Hash::new_blake3(bytes.as_slice()).map_err(|e| anyhow!("Problem hashing file: {}: {}", path.display(), e))?;
+        lines.push(format!("{}  {}", hash, relative));
+    }
+    Ok(format!("{}\n", lines.join("\n")))
+}
+
+/// Sign a batch of files with one `sign` subkey, asking for the passphrase only
+/// once. Either writes a detached signature per file into the `output`
+/// directory, or (with `manifest`) a single clearsigned manifest listing the
+/// blake3 hash of each file.
+pub fn sign_batch(
+    id_sign: &str,
+    key_search_sign: Option<&str>,
+    inputs: &[&str],
+    output: &str,
+    manifest: bool,
+    armored: bool,
+) -> Result<()> {
+    let transactions = id::try_load_single_identity(id_sign)?;
+    let identity = util::build_identity(&transactions)?;
+    let key_sign = keychain::find_keys_by_search_or_prompt(&identity, key_search_sign, "sign", |sub| sub.key().as_signkey())?;
+    let files = util::collect_files(inputs)?;
+    if files.len() == 0 {
+        Err(anyhow!("No files to sign"))?;
+    }
+    if !manifest && !util::is_output_dir(output) {
+        Err(anyhow!("Signing a batch of files needs an output directory (`-o <dir>/`), or use `--manifest`"))?;
+    }
+    let manifest_text = if manifest { Some(batch_manifest(&files, output)?) } else { None };
+    let id_str = id_str!(identity.id())?;
+    let master_key = util::passphrase_prompt(
+        &format!("Your current master passphrase for identity {}", IdentityID::short(&id_str)),
+        identity.created(),
+    )?;
+    transactions
+        .test_master_key(&master_key)
+        .map_err(|e| anyhow!("Incorrect passphrase: {}", e))?;
+
+    if let Some(manifest_text) = manifest_text {
+        let msg_bytes = clearsign_canonical(manifest_text.as_bytes())?;
+        let signature = sign::sign(&master_key, identity.id(), &key_sign, msg_bytes.as_slice())
+            .map_err(|e| anyhow!("Problem creating signature: {}", e))?;
+        let serialized = signature
+            .serialize_binary()
+            .map_err(|e| anyhow!("Problem serializing the signature: {}", e))?;
+        let document = clearsign_wrap(msg_bytes.as_slice(), serialized.as_slice())?;
+        util::write_file(output, document.as_bytes())?;
+        eprintln!("Signed a manifest of {} file(s)", files.len());
+        return Ok(());
+    }
+
+    let mut artifacts: Vec<util::Artifact> = Vec::with_capacity(files.len());
+    let mut signed = Vec::with_capacity(files.len());
+    for path in &files {
+        let name = path
+            .file_name()
+            .map(|x| format!("{}.sig", x.to_string_lossy()))
+            .ok_or(anyhow!("Can't sign {}", path.display()))?;
+        if artifacts.iter().any(|x| x.filename == name) {
+            Err(anyhow!("More than one file is named {}. Sign them in separate batches, or use `--manifest`.", name))?;
+        }
+        let bytes = util::load_file(&path.to_string_lossy())?;
+        let signature = sign::sign(&master_key, identity.id(), &key_sign, bytes.as_slice())
+            .map_err(|e| anyhow!("Problem creating signature: {}", e))?;
+        let serialized = signature
+            .serialize_binary()
+            .map_err(|e| anyhow!("Problem serializing the signature: {}", e))?;
+        let sig_bytes = if armored {
+            Vec::from(armor::armor(armor::SIGNATURE, &[], serialized.as_slice()).as_bytes())
+        } else {
+            serialized
+        };
+        signed.push(serde_json::json!({
+            "file": format!("{}", path.display()),
+            "signature": name,
+        }));
+        artifacts.push(util::Artifact::new(name, "signature", sig_bytes));
+    }
+    let meta = serde_json::json!({
+        "identity": id_str,
+        "key": key_sign.key_id().as_string(),
+        "signed": signed,
+    });
+    util::write_artifacts(output, artifacts.as_slice(), meta)?;
+    Ok(())
+}
+
+const TREE_HEADER: &str = "stamp-tree/1";
+
+/// The permission bits we record for a file in a tree manifest. Outside of unix
+/// there are no modes, so we fake the closest one.
+fn tree_mode(meta: &std::fs::Metadata) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        meta.permissions().mode() & 0o7777
+    }
+    #[cfg(not(unix))]
+    {
+        if meta.permissions().readonly() {
+            0o444
+        } else {
+            0o644
+        }
+    }
+}
+
+/// Walk a directory into (relative path, mode, blake3 hash) entries, sorted by
+/// path. Paths always use `/` so manifests made on different systems compare.
+fn tree_entries(dir: &str) -> Result<Vec<(String, u32, String)>> {
+    if !std::path::Path::new(dir).is_dir() {
+        Err(anyhow!("Not a directory: {}", dir))?;
+    }
+    let mut entries = Vec::new();
+    for path in util::collect_files(&[dir])? {
+        let relative = path
+            .strip_prefix(dir)
+            .map_err(|_| anyhow!("Problem reading path: {}", path.display()))?
+            .components()
+            .map(|x| x.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<_>>()
+            .join("/");
+        if relative.contains('\n') {
+            Err(anyhow!("Can't sign a tree with newlines in its file names: {:?}", relative))?;
+        }
+        let meta = std::fs::metadata(&path).map_err(|e| anyhow!("Problem reading file: {}: {:?}", path.display(), e))?;
+        let bytes = util::load_file(&path.to_string_lossy())?;
+        let hash = Hash::new_blake3(bytes.as_slice()).map_err(|e| anyhow!("Problem hashing file: {}: {}", path.display(), e))?;
+        entries.push((relative, tree_mode(&meta), format!("{}", hash)));
+    }
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(entries)
+}
+
+/// Check a detached subkey signature against a message, returning the signing
+/// identity ID and key name.
+pub(crate) fn verify_detached(sig_bytes: &[u8], message: &[u8]) -> Result<(String, String)> {
+    let signature = Signature::deserialize_binary(sig_bytes).map_err(|e| anyhow!("Error reading signature: {}", e))?;
+    let sig = match &signature {
+        Signature::Detached { sig } => sig,
+        Signature::Attached { .. } => Err(anyhow!("Expected a detached signature"))?,
+    };
+    let key_id = sig.signed_by_key();
+    let id_str = id_str!(sig.signed_by_identity())?;
+    let transactions =
+        db::load_identity(sig.signed_by_identity())?.ok_or(anyhow!("Identity {} not found. Have you imported it?", id_str))?;
+    let identity = util::build_identity(&transactions)?;
+    let subkey = identity.keychain().subkey_by_keyid(&key_id).ok_or(anyhow!(
+        "Signing key {} not found in identity {}",
+        key_id.as_string(),
+        IdentityID::short(&id_str)
+    ))?;
+    if subkey.revocation().is_some() {
+        Err(anyhow!("Signing key {} in identity {} has been revoked", subkey.name(), IdentityID::short(&id_str)))?;
+    }
+    sign::verify(&subkey, &signature, message).map_err(|e| anyhow!("Invalid signature: {}", e))?;
+    Ok((id_str, subkey.name().clone()))
+}
+
+/// Sign a directory tree: every file's path, mode, and hash go into a canonical
+/// manifest, which is clearsigned with a `sign` subkey.
+pub fn sign_tree(id_sign: &str, key_search_sign: Option<&str>, dir: &str, output: &str) -> Result<()> {
+    let transactions = id::try_load_single_identity(id_sign)?;
+    let identity = util::build_identity(&transactions)?;
+    let key_sign = keychain::find_keys_by_search_or_prompt(&identity, key_search_sign, "sign", |sub| sub.key().as_signkey())?;
+    let entries = tree_entries(dir)?;
+    let mut lines = vec![String::from(TREE_HEADER)];
+    for (path, mode, hash) in &entries {
+        lines.push(format!("{:04o} {} {}", mode, hash, path));
+    }
+    let msg_bytes = clearsign_canonical(format!("{}\n", lines.join("\n")).as_bytes())?;
+    let id_str = id_str!(identity.id())?;
+    let master_key = util::passphrase_prompt(
+        &format!("Your current master passphrase for identity {}", IdentityID::short(&id_str)),
+        identity.created(),
+    )?;
+    transactions
+        .test_master_key(&master_key)
+        .map_err(|e| anyhow!("Incorrect passphrase: {}", e))?;
+    let signature = sign::sign(&master_key, identity.id(), &key_sign, msg_bytes.as_slice())
+        .map_err(|e| anyhow!("Problem creating signature: {}", e))?;
+    let serialized = signature
+        .serialize_binary()
+        .map_err(|e| anyhow!("Problem serializing the signature: {}", e))?;
+    let document = clearsign_wrap(msg_bytes.as_slice(), serialized.as_slice())?;
+    util::write_file(output, document.as_bytes())?;
+    eprintln!("Signed {} file(s) in {}", entries.len(), dir);
+    Ok(())
+}
+
+/// Verify a tree signature from [sign_tree] and compare it against the
+/// directory as it is now, listing every added, removed, and changed file.
+pub fn verify_tree(input_signature: &str, dir: &str) -> Result<()> {
+    let document = util::read_file(input_signature)?;
+    let (message, sig_bytes) =
+        clearsign_parse(document.as_slice())?.ok_or(anyhow!("This doesn't look like a signature from `stamp sign tree`"))?;
+    let (id_str, key_name) = verify_detached(sig_bytes.as_slice(), message.as_slice())?;
+    let message = String::from_utf8(message).map_err(|_| anyhow!("Tree manifest is not UTF-8"))?;
+    let mut lines = message.lines();
+    if lines.next() != Some(TREE_HEADER) {
+        Err(anyhow!("This doesn't look like a signature from `stamp sign tree`"))?;
+    }
+    let mut signed = std::collections::BTreeMap::new();
+    for line in lines.filter(|x| !x.is_empty()) {
+        let mut parts = line.splitn(3, ' ');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(mode), Some(hash), Some(path)) => {
+                signed.insert(String::from(path), (String::from(mode), String::from(hash)));
+            }
+            _ => Err(anyhow!("Tree manifest has a bad line: {}", line))?,
+        }
+    }
+    let current = tree_entries(dir)?
+        .into_iter()
+        .map(|(path, mode, hash)| (path, (format!("{:04o}", mode), hash)))
+        .collect::<std::collections::BTreeMap<_, _>>();
+
+    let green = dialoguer::console::Style::new().green();
+    let red = dialoguer::console::Style::new().red();
+    let yellow = dialoguer::console::Style::new().yellow();
+    println!(
+        "The tree signature is {}! It was made by the identity {} with the key {}.",
+        green.apply_to("valid"),
+        id_str,
+        key_name
+    );
+    let mut changes = 0;
+    for (path, (mode, hash)) in &signed {
+        match current.get(path) {
+            None => {
+                println!("  {} {}", red.apply_to("removed "), path);
+                changes += 1;
+            }
+            Some((_, cur_hash)) if cur_hash != hash => {
+                println!("  {} {}", red.apply_to("modified"), path);
+                changes += 1;
+            }
+            Some((cur_mode, _)) if cur_mode != mode => {
+                println!("  {} {} ({} -> {})", yellow.apply_to("mode    "), path, mode, cur_mode);
+                changes += 1;
+            }
+            _ => {}
+        }
+    }
+    for path in current.keys().filter(|x| !signed.contains_key(*x)) {
+        println!("  {} {}", red.apply_to("added   "), path);
+        changes += 1;
+    }
+    if changes > 0 {
+        Err(anyhow!("{} does not match the signed tree ({} change(s))", dir, changes))?;
+    }
+    println!("All {} file(s) in {} match.", signed.len(), dir);
+    Ok(())
+}
+
+/// `stamp sign verify` exit code: the signature is invalid.
+pub const VERIFY_INVALID: i32 = 1;
+/// `stamp sign verify` exit code: the signing identity (or key) isn't imported.
+pub const VERIFY_UNKNOWN_SIGNER: i32 = 3;
+/// `stamp sign verify` exit code: the signature or message couldn't be read.
+pub const VERIFY_ERROR: i32 = 4;
+
+/// What verifying a signature found, printed as-is with `--json`.
+#[derive(Debug, Default, Serialize)]
+struct VerifyResult {
+    valid: bool,
+    #[serde(rename = "type")]
+    sig_type: Option<&'static str>,
+    identity: Option<String>,
+    key_id: Option<String>,
+    key_name: Option<String>,
+    key_revoked: Option<bool>,
+    signatures: Option<usize>,
+    created: Option<String>,
+    error: Option<String>,
+}
+
+/// Verify an SSH signature. The signer has to be one of the `sign` keys of a
+/// local identity, same as with Stamp's own signatures.
+fn verify_ssh(sig_bytes: &[u8], input_message: Option<&str>, namespace: &str) -> Result<VerifyResult> {
+    let input_message = input_message
+        .ok_or_else(|| util::ExitError::new(VERIFY_ERROR, "A MESSAGE argument must be given when verifying an SSH signature."))?;
+    let message_bytes = util::read_file(input_message).map_err(|e| util::ExitError::new(VERIFY_ERROR, e))?;
+    let public = ssh::sshsig_verify(sig_bytes, namespace, message_bytes.as_slice())
+        .map_err(|e| util::ExitError::new(VERIFY_INVALID, format!("Invalid signature: {}", e)))?;
+    for transactions in db::list_local_identities(None)? {
+        let identity = util::build_identity(&transactions)?;
+        let found = identity.keychain().subkeys().iter().find(|k| {
+            k.key()
+                .as_signkey()
+                .and_then(|keypair| keychain::ed25519_public(keypair).ok())
+                .map(|x| x == public)
+                .unwrap_or(false)
+        });
+        if let Some(subkey) = found {
+            return Ok(VerifyResult {
+                valid: true,
+                sig_type: Some("ssh"),
+                identity: Some(id_str!(identity.id())?),
+                key_id: Some(subkey.key_id().as_string()),
+                key_name: Some(subkey.name().clone()),
+                key_revoked: Some(subkey.revocation().is_some()),
+                ..Default::default()
+            });
+        }
+    }
+    Err(util::ExitError::new(
+        VERIFY_UNKNOWN_SIGNER,
+        format!(
+            "The key that made this SSH signature ({}) doesn't belong to any local identity. Have you imported it?",
+            ssh::ed25519_fingerprint(&public)
+        ),
+    ))
+}
+
+/// Read and check a signature, sorting failures into the exit codes above.
+fn verify_signature(input_signature: &str, input_message: Option<&str>, ssh_namespace: &str) -> Result<VerifyResult> {
+    let unreadable = |e: anyhow::Error| util::ExitError::new(VERIFY_ERROR, e);
+    let sig_bytes = util::read_file(input_signature).map_err(unreadable)?;
+    if ssh::is_sshsig(sig_bytes.as_slice()) {
+        return verify_ssh(sig_bytes.as_slice(), input_message, ssh_namespace);
+    }
+    let sig_bytes = armor::strip(sig_bytes).map_err(unreadable)?;
+    // a clearsigned document carries its own message
+    let (sig_bytes, clear_message) = match clearsign_parse(sig_bytes.as_slice()).map_err(unreadable)? {
+        Some((message, signature)) => (signature, Some(message)),
+        None => (sig_bytes, None),
+    };
+    let read_message = |err: &str| -> Result<Vec<u8>> {
+        match (clear_message.as_ref(), input_message) {
+            (Some(message), _) => Ok(message.clone()),
+            (None, Some(input_message)) => util::read_file(input_message).map_err(unreadable),
+            (None, None) => Err(util::ExitError::new(VERIFY_ERROR, err)),
+        }
+    };
     enum PolicyOrSub {
         Policy(Transaction),
         Subkey(Signature),
     }
-    let signature = Transaction::deserialize_binary(sig_bytes.as_slice())
-        .or_else(|_| Transaction::deserialize_binary(&base64_decode(sig_bytes.as_slice())?))
+    let signature = util::deserialize_transaction(sig_bytes.as_slice())
         .map(|x| PolicyOrSub::Policy(x))
         .or_else(|_| {
             Signature::deserialize_binary(sig_bytes.as_slice())
                 .or_else(|_| Signature::deserialize_binary(&base64_decode(sig_bytes.as_slice())?))
+                .or_else(|_| util::deserialize_cbor::<Signature>(sig_bytes.as_slice()))
                 .map(|x| PolicyOrSub::Subkey(x))
         })
-        .map_err(|e| anyhow!("Error reading signature: {}", e))?;
-    let res = match &signature {
+        .map_err(|e| util::ExitError::new(VERIFY_ERROR, format!("Error reading signature: {}", e)))?;
+    let invalid = |e: String| util::ExitError::new(VERIFY_INVALID, format!("Invalid signature: {}", e));
+    match &signature {
         PolicyOrSub::Policy(transaction) => {
-            let input_message = input_message.ok_or(anyhow!("A MESSAGE argument must be give when verifying an policy signature."))?;
-            let message_bytes = util::read_file(&input_message)?;
+            let message_bytes = read_message("A MESSAGE argument must be give when verifying an policy signature.")?;
             match transaction.entry().body() {
                 TransactionBody::SignV1 { creator, body_hash } => {
                     let id_str = format!("{}", creator);
-                    let creator_transactions =
-                        db::load_identity(&creator)?.ok_or(anyhow!("Identity {} not found. Have you imported it?", id_str))?;
+                    let creator_transactions = db::load_identity(&creator)?.ok_or_else(|| {
+                        util::ExitError::new(VERIFY_UNKNOWN_SIGNER, format!("Identity {} not found. Have you imported it?", id_str))
+                    })?;
                     let creator_identity = util::build_identity(&creator_transactions)?;
                     // TODO: verify against past version of creator_transactions if verification
                     // fails and we have a non-empty previous_transactions. see issue #41
                     transaction
                         .verify(Some(&creator_identity))
-                        .map_err(|e| anyhow!("Policy signature invalid: {}", e))?;
+                        .map_err(|e| invalid(format!("Policy signature invalid: {}", e)))?;
                     match body_hash {
                         Hash::Blake3(..) => {
                             let compare = Hash::new_blake3(message_bytes.as_slice())?;
-                            if &compare == body_hash {
-                                Ok(())
-                            } else {
-                                Err(anyhow!("Policy signature hash ({}) does not match message hash ({})", body_hash, compare))
+                            if &compare != body_hash {
+                                Err(invalid(format!("Policy signature hash ({}) does not match message hash ({})", body_hash, compare)))?;
                             }
                         }
                     }
+                    Ok(VerifyResult {
+                        valid: true,
+                        sig_type: Some("policy"),
+                        identity: Some(id_str),
+                        signatures: Some(transaction.signatures().len()),
+                        created: Some(transaction.entry().created().local().to_rfc3339()),
+                        ..Default::default()
+                    })
                 }
-                _ => Err(anyhow!("Invalid policy signature: invalid transaction type (expected `Sign` transaction)"))?,
+                _ => Err(invalid(String::from("invalid transaction type (expected `Sign` transaction)")))?,
             }
         }
         PolicyOrSub::Subkey(signature) => {
@@ -135,60 +684,101 @@ pub fn verify(input_signature: &str, input_message: Option<&str>) -> Result<()>
             let identity_id = sig.signed_by_identity();
             let key_id = sig.signed_by_key();
             let id_str = id_str!(identity_id)?;
-            let transactions = db::load_identity(identity_id)?.ok_or(anyhow!("Identity {} not found. Have you imported it?", id_str))?;
+            let transactions = db::load_identity(identity_id)?.ok_or_else(|| {
+                util::ExitError::new(VERIFY_UNKNOWN_SIGNER, format!("Identity {} not found. Have you imported it?", id_str))
+            })?;
             let identity = util::build_identity(&transactions)?;
-            let subkey = identity.keychain().subkey_by_keyid(&key_id).ok_or(anyhow!(
-                "Signing key {} not found in identity {}",
-                key_id.as_string(),
-                IdentityID::short(&id_str)
-            ))?;
+            let subkey = identity.keychain().subkey_by_keyid(&key_id).ok_or_else(|| {
+                util::ExitError::new(
+                    VERIFY_UNKNOWN_SIGNER,
+                    format!("Signing key {} not found in identity {}", key_id.as_string(), IdentityID::short(&id_str)),
+                )
+            })?;
             match signature {
                 Signature::Detached { .. } => {
-                    let input_message =
-                        input_message.ok_or(anyhow!("A MESSAGE argument must be give when verifying a detached signature."))?;
-                    let message_bytes = util::read_file(&input_message)?;
-                    sign::verify(&subkey, signature, message_bytes.as_slice()).map_err(|e| anyhow!("{}", e))
-                }
-                Signature::Attached { .. } => sign::verify_attached(&subkey, signature).map_err(|e| anyhow!("{}", e)),
-            }
-        }
-    };
-    match res {
-        Ok(..) => {
-            let green = dialoguer::console::Style::new().green();
-            match signature {
-                PolicyOrSub::Policy(trans) => {
-                    let identity_id = match trans.entry().body() {
-                        TransactionBody::SignV1 { creator, .. } => creator,
-                        _ => Err(anyhow!(
-                            "Problem pulling signature `creator` field from policy signature. Perhaps it is not a Sign transaction."
-                        ))?,
-                    };
-                    let id_str_creator = id_str!(identity_id)?;
-                    println!(
-                        "This signature is {}! It is a policy signature made by the identity {}.",
-                        green.apply_to("valid"),
-                        id_str_creator
-                    );
-                }
-                PolicyOrSub::Subkey(sig) => {
-                    let signed_obj = match sig {
-                        Signature::Detached { sig } => sig,
-                        Signature::Attached { sig, .. } => sig,
-                    };
-                    println!(
-                        "This signature is {}! It is a subkey signature made by the identity {} with the key {}.",
-                        green.apply_to("valid"),
-                        signed_obj.signed_by_identity(),
-                        signed_obj.signed_by_key()
-                    );
+                    let message_bytes = read_message("A MESSAGE argument must be give when verifying a detached signature.")?;
+                    sign::verify(&subkey, signature, message_bytes.as_slice()).map_err(|e| invalid(format!("{}", e)))?;
                 }
+                Signature::Attached { .. } => sign::verify_attached(&subkey, signature).map_err(|e| invalid(format!("{}", e)))?,
             }
+            Ok(VerifyResult {
+                valid: true,
+                sig_type: Some("subkey"),
+                identity: Some(id_str),
+                key_id: Some(key_id.as_string()),
+                key_name: Some(subkey.name().clone()),
+                key_revoked: Some(subkey.revocation().is_some()),
+                ..Default::default()
+            })
         }
+    }
+}
+
+/// Verify a policy, subkey, or SSH signature. Failures come back as
+/// [util::ExitError]s so scripts can tell an invalid signature
+/// ([VERIFY_INVALID]) from an unknown signer ([VERIFY_UNKNOWN_SIGNER]) or an
+/// unreadable input ([VERIFY_ERROR]).
+pub fn verify(input_signature: &str, input_message: Option<&str>, ssh_namespace: &str, json: bool) -> Result<()> {
+    let result = match verify_signature(input_signature, input_message, ssh_namespace) {
+        Ok(x) => x,
         Err(e) => {
-            let red = dialoguer::console::Style::new().red();
-            eprintln!("{}: {}", red.apply_to("Invalid signature"), e);
+            let code = e.downcast_ref::<util::ExitError>().map(|x| x.code).unwrap_or(VERIFY_ERROR);
+            if json {
+                let failed = VerifyResult {
+                    error: Some(format!("{}", e)),
+                    ..Default::default()
+                };
+                println!("{}", serde_json::to_string_pretty(&failed)?);
+                Err(util::ExitError::silent(code, e))?
+            }
+            Err(util::ExitError::new(code, e))?
         }
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&result)?);
+        return Ok(());
+    }
+    let green = dialoguer::console::Style::new().green();
+    let yellow = dialoguer::console::Style::new().yellow();
+    match (result.sig_type, result.identity.as_ref()) {
+        (Some("policy"), Some(identity)) => println!(
+            "This signature is {}! It is a policy signature made by the identity {} with {} admin signature(s).",
+            green.apply_to("valid"),
+            identity,
+            result.signatures.unwrap_or(1)
+        ),
+        (Some(sig_type), Some(identity)) => println!(
+            "This signature is {}! It is {} {} signature made by the identity {} with the key {}.",
+            green.apply_to("valid"),
+            if sig_type == "ssh" { "an" } else { "a" },
+            if sig_type == "ssh" { "SSH" } else { sig_type },
+            identity,
+            result.key_name.as_deref().unwrap_or("")
+        ),
+        _ => println!("This signature is {}!", green.apply_to("valid")),
+    }
+    if result.key_revoked == Some(true) {
+        println!("{}", yellow.apply_to("Note that this key has since been revoked."));
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clearsign_survives_whitespace_damage() {
+        let message = clearsign_canonical(b"hello there  \r\n- a list item\r\n\r\nbye\t\n").unwrap();
+        let signature = vec![1u8, 2, 3, 4, 5, 6, 7, 8];
+        let document = clearsign_wrap(message.as_slice(), signature.as_slice()).unwrap();
+        let damaged = document
+            .split('\n')
+            .map(|line| format!("{}  ", line))
+            .collect::<Vec<_>>()
+            .join("\r\n");
+        let (parsed_message, parsed_signature) = clearsign_parse(damaged.as_bytes()).unwrap().unwrap();
+        assert_eq!(parsed_message, message);
+        assert_eq!(parsed_signature, signature);
+    }
+}
@@ -1,24 +1,341 @@
 use crate::{
-    commands::{dag, id},
+    armor,
+    commands::{dag, id, message, net},
     db, util,
 };
 use anyhow::{anyhow, Result};
 use prettytable::Table;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use stamp_aux::db::{delete_staged_transaction, find_staged_transactions, load_staged_transaction, stage_transaction};
 use stamp_core::{
-    crypto::base::rng,
-    dag::{Transaction, TransactionID},
+    crypto::base::{rng, KeyID},
+    dag::{Transaction, TransactionBody, TransactionID, Transactions},
     identity::{Identity, IdentityID},
+    policy::MultisigPolicySignature,
     util::{base64_decode, base64_encode, Public, SerText, SerdeBinary, Timestamp},
 };
 use std::convert::TryFrom;
+use std::ops::Deref;
 use std::str::FromStr;
+use std::sync::Mutex;
+use tracing::warn;
 
-pub fn list(id: &str) -> Result<()> {
+/// When a staged transaction stops being signable or applicable, keyed by
+/// transaction ID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageExpiry {
+    pub identity: String,
+    pub expires: Timestamp,
+}
+
+/// The expiration `--stage-expires` gave this run, which [stage] puts on
+/// anything it stages. Every command that can stage takes the flag, so main
+/// sets this once after parsing arguments rather than passing it down.
+static STAGE_EXPIRES: Mutex<Option<Timestamp>> = Mutex::new(None);
+
+/// Set the expiration [stage] gives staged transactions for the rest of this
+/// run.
+pub fn set_stage_expires(expires: Option<Timestamp>) -> Result<()> {
+    *STAGE_EXPIRES
+        .lock()
+        .map_err(|_| anyhow!("Problem setting the staged transaction expiration"))? = expires;
+    Ok(())
+}
+
+fn staged_expiry(transaction_id: &TransactionID) -> Result<Option<Timestamp>> {
+    Ok(db::load_record::<StageExpiry>("stage-expiry", &format!("{}", transaction_id))?.map(|x| x.expires))
+}
+
+fn is_expired(transaction_id: &TransactionID) -> Result<bool> {
+    Ok(staged_expiry(transaction_id)?
+        .map(|expires| expires.local() <= Timestamp::now().local())
+        .unwrap_or(false))
+}
+
+/// Refuse to go on with a staged transaction that's past its expiration.
+fn check_expiry(transaction_id: &TransactionID) -> Result<()> {
+    if let Some(expires) = staged_expiry(transaction_id)? {
+        if expires.local() <= Timestamp::now().local() {
+            Err(anyhow!(
+                "Staged transaction {} expired {}. Remove it with `stamp stage prune` (or `stamp stage delete {}`).",
+                transaction_id,
+                expires.local().format("%b %e, %Y  %H:%M:%S"),
+                transaction_id
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+/// A note left on a staged transaction, ex why a co-signer did or didn't sign.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StageComment {
+    pub identity: String,
+    pub name: Option<String>,
+    pub comment: String,
+    pub created: Timestamp,
+    /// Local comments stay on this device, and aren't sent along with the
+    /// transaction by `stamp stage send`.
+    #[serde(default)]
+    pub local: bool,
+}
+
+/// How comments are stored, one record per staged transaction.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StageComments {
+    comments: Vec<StageComment>,
+}
+
+/// Load the comments on a staged transaction, oldest first.
+pub(crate) fn comments(txid: &str) -> Result<Vec<StageComment>> {
+    Ok(db::load_record::<StageComments>("stage-comment", txid)?
+        .unwrap_or_default()
+        .comments)
+}
+
+/// Add comments we don't have yet to a staged transaction, ex ones that came
+/// along with it in a `stamp stage send` message.
+pub(crate) fn merge_comments(txid: &str, incoming: &Vec<StageComment>) -> Result<()> {
+    let mut existing = comments(txid)?;
+    let before = existing.len();
+    for comment in incoming.iter() {
+        if !existing.contains(comment) {
+            existing.push(comment.clone());
+        }
+    }
+    if existing.len() > before {
+        existing.sort_by(|a, b| a.created.local().cmp(&b.created.local()));
+        db::save_record("stage-comment", txid, &StageComments { comments: existing })?;
+    }
+    Ok(())
+}
+
+// TODO: the CLI doesn't read an identity's multisig policies yet, so signing
+// progress is worked out from its admin keychain alone: which keys exist, which
+// have signed, and which local identities hold them. Once policies are readable
+// this is where to say which policy a transaction matches and how many more
+// signatures it needs, and `stage list`, `stage analyze`, and
+// `stage apply --dry-run` will pick that up.
+
+/// One of an identity's active admin keys, and whether a transaction carries its
+/// signature yet.
+pub(crate) struct AdminKeyProgress {
+    pub key_id: String,
+    pub name: String,
+    pub signed: bool,
+}
+
+/// Which of an identity's active admin keys have signed a transaction.
+pub(crate) fn admin_key_progress(identity: &Identity, transaction: &Transaction) -> Vec<AdminKeyProgress> {
+    let signers = transaction
+        .signatures()
+        .iter()
+        .map(|sig| match sig {
+            MultisigPolicySignature::Key { key, .. } => KeyID::from(key.key_id()).as_string(),
+        })
+        .collect::<Vec<_>>();
+    identity
+        .keychain()
+        .admin_keys()
+        .iter()
+        .filter(|k| k.revocation().is_none())
+        .map(|k| {
+            let key_id = k.key().key_id().as_string();
+            AdminKeyProgress {
+                signed: signers.contains(&key_id),
+                key_id,
+                name: k.name().clone(),
+            }
+        })
+        .collect()
+}
+
+/// Names of the identity's active admin keys that `holder` also has in its own
+/// keychain, which is how a co-signer of a group identity is recognized.
+pub(crate) fn shared_admin_keys(identity: &Identity, holder: &Identity) -> Vec<String> {
+    let held = holder
+        .keychain()
+        .admin_keys()
+        .iter()
+        .map(|k| k.key().key_id().as_string())
+        .collect::<Vec<_>>();
+    identity
+        .keychain()
+        .admin_keys()
+        .iter()
+        .filter(|k| k.revocation().is_none() && held.contains(&k.key().key_id().as_string()))
+        .map(|k| k.name().clone())
+        .collect()
+}
+
+/// The policy change a transaction makes, if any, in the style of
+/// [net::identity_changes].
+fn policy_change(transaction: &Transaction) -> Option<String> {
+    match transaction.entry().body() {
+        TransactionBody::AddPolicyV1 { .. } => Some(String::from("Policy added")),
+        TransactionBody::DeletePolicyV1 { .. } => Some(String::from("Policy removed")),
+        TransactionBody::ResetIdentityV1 { .. } => Some(String::from("Policies replaced")),
+        _ => None,
+    }
+}
+
+/// What a transaction would change, read from its body rather than by applying
+/// it, for transactions that don't verify yet. Lines match
+/// [net::identity_changes] so both previews read the same.
+fn body_changes(history: &[Transaction], transaction: &Transaction) -> Result<Vec<String>> {
+    let named = |key_id: &KeyID| format!("{} ({})", dag::key_name(history, key_id).unwrap_or_default(), key_id.as_string());
+    let change = match transaction.entry().body() {
+        TransactionBody::AddAdminKeyV1 { admin_key } => {
+            format!("Key added: {} ({})", admin_key.name(), admin_key.key().key_id().as_string())
+        }
+        TransactionBody::RevokeAdminKeyV1 { id, .. } => format!("Key revoked: {}", named(&KeyID::from(id.clone()))),
+        TransactionBody::AddSubkeyV1 { key, name, .. } => format!("Key added: {} ({})", name, key.key_id().as_string()),
+        TransactionBody::RevokeSubkeyV1 { id, .. } => format!("Key revoked: {}", named(id)),
+        TransactionBody::DeleteSubkeyV1 { id, .. } => format!("Key removed: {}", named(id)),
+        TransactionBody::MakeClaimV1 { .. } => format!("Claim added: {}", id_str!(transaction.id())?),
+        TransactionBody::DeleteClaimV1 { claim_id } => format!("Claim removed: {}", claim_id.deref()),
+        _ => match policy_change(transaction) {
+            Some(change) => change,
+            None => dag::transaction_description(transaction, history),
+        },
+    };
+    Ok(vec![change])
+}
+
+/// Other local identities that hold the given admin key in their own keychain,
+/// as "<short id> <name>". Identities that fail to build are skipped.
+pub(crate) fn admin_key_holders(identity: &Identity, key_id: &str) -> Result<Vec<String>> {
+    let mut holders = Vec::new();
+    for transactions in db::list_local_identities(None)? {
+        let holder = match util::build_identity(&transactions) {
+            Ok(holder) => holder,
+            Err(e) => {
+                warn!("skipping identity {:?} while looking for key holders: {}", transactions.identity_id(), e);
+                continue;
+            }
+        };
+        if holder.id() == identity.id() {
+            continue;
+        }
+        if holder
+            .keychain()
+            .admin_keys()
+            .iter()
+            .any(|k| k.key().key_id().as_string() == key_id)
+        {
+            let holder_id = id_str!(holder.id())?;
+            holders.push(format!("{} {}", IdentityID::short(&holder_id), holder.names().get(0).cloned().unwrap_or_default()));
+        }
+    }
+    Ok(holders)
+}
+
+/// Whether a transaction builds on the given identity, ie every transaction it
+/// points back to is already in it.
+pub(crate) fn builds_on(transactions: &Transactions, transaction: &Transaction) -> bool {
+    let previous = transaction.entry().previous_transactions();
+    previous.len() > 0
+        && previous
+            .iter()
+            .all(|prev| transactions.transactions().iter().any(|t| t.id() == prev))
+}
+
+/// Clear out the records we keep alongside a staged transaction once it's gone.
+pub(crate) fn forget_staged(txid: &str) -> Result<()> {
+    db::delete_record("stage-expiry", txid)?;
+    db::delete_record("stage-comment", txid)?;
+    Ok(())
+}
+
+/// Stage a transaction, giving it the `--stage-expires` expiration if this run
+/// has one (see [set_stage_expires]). Returns the staged transaction's ID.
+pub(crate) fn stage(identity_id: &IdentityID, transaction: Transaction) -> Result<String> {
+    let staged = stage_transaction(identity_id, transaction).map_err(|e| anyhow!("Error staging transaction: {:?}", e))?;
+    let txid = id_str!(staged.id())?;
+    let expires = STAGE_EXPIRES
+        .lock()
+        .map_err(|_| anyhow!("Problem reading the staged transaction expiration"))?
+        .clone();
+    if let Some(expires) = expires {
+        let expiry = StageExpiry {
+            identity: id_str!(identity_id)?,
+            expires: expires.clone(),
+        };
+        db::save_record("stage-expiry", &txid, &expiry)?;
+        println!("Staged transaction {} expires {}", txid, expires.local().format("%b %e, %Y  %H:%M:%S"));
+    }
+    Ok(txid)
+}
+
+/// Delete an identity's expired staged transactions.
+pub fn prune(id: &str, skip_confirm: bool) -> Result<()> {
     let transactions = id::try_load_single_identity(id)?;
     let identity = util::build_identity(&transactions)?;
-    let transactions = find_staged_transactions(identity.id()).map_err(|e| anyhow!("Error loading staged transactions: {:?}", e))?;
-    print_transactions_table(Some(&identity), &transactions);
+    let mut expired = Vec::new();
+    for staged in find_staged_transactions(identity.id()).map_err(|e| anyhow!("Error loading staged transactions: {:?}", e))? {
+        if is_expired(staged.id())? {
+            expired.push(staged);
+        }
+    }
+    if expired.len() == 0 {
+        println!("No expired staged transactions.");
+        return Ok(());
+    }
+    print_transactions_table(Some(&identity), &expired, false);
+    if !skip_confirm && !util::yesno_prompt(&format!("Delete these {} expired transaction(s)? [y/N]", expired.len()), "n")? {
+        return Ok(());
+    }
+    for staged in expired.iter() {
+        delete_staged_transaction(staged.id()).map_err(|e| anyhow!("Error deleting staged transaction: {:?}", e))?;
+        forget_staged(&format!("{}", staged.id()))?;
+    }
+    println!("Deleted {} expired staged transaction(s).", expired.len());
+    Ok(())
+}
+
+pub fn list(id: &str, ready_only: bool, verbose: bool) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let transactions = find_staged_transactions(identity.id())
+        .map_err(|e| anyhow!("Error loading staged transactions: {:?}", e))?
+        .into_iter()
+        .filter(|trans| !ready_only || (trans.verify(Some(&identity)).is_ok() && !is_expired(trans.id()).unwrap_or(false)))
+        .collect::<Vec<_>>();
+    print_transactions_table(Some(&identity), &transactions, verbose);
+    Ok(())
+}
+
+/// Let the user know a staged transaction we just received from a co-signer
+/// (in a message or through [import]) has all the signatures it needs, on the
+/// console and with a desktop notification.
+pub(crate) fn notify_if_ready(identity_id: &IdentityID, transaction: &Transaction) -> Result<()> {
+    let identity = match db::load_identity(identity_id)? {
+        Some(transactions) => util::build_identity(&transactions)?,
+        None => return Ok(()),
+    };
+    if transaction.verify(Some(&identity)).is_err() {
+        return Ok(());
+    }
+    let next = if is_policy_signature(transaction) {
+        format!("stamp sign finish {}", transaction.id())
+    } else {
+        format!("stamp stage apply {}", transaction.id())
+    };
+    let green = dialoguer::console::Style::new().green();
+    println!(
+        "Staged transaction {} {}. Finish it with:\n  {}",
+        transaction.id(),
+        green.apply_to("has all its signatures"),
+        next
+    );
+    let res = notify_rust::Notification::new()
+        .summary("Staged transaction ready")
+        .body(&format!("Transaction {} has all its signatures. Run `{}`.", transaction.id(), next))
+        .show();
+    if let Err(e) = res {
+        warn!("Problem showing desktop notification: {}", e);
+    }
     Ok(())
 }
 
@@ -31,10 +348,314 @@ pub fn view(txid: &str) -> Result<()> {
         .serialize_text()
         .map_err(|e| anyhow!("Error serializing staged transaction: {:?}", e))?;
     println!("{}", serialized);
+    let comments = comments(txid)?;
+    if comments.len() > 0 {
+        println!("Comments:");
+        for comment in comments.iter() {
+            println!(
+                "  {} {}{}: {}",
+                comment.created.local().format("%b %e, %Y  %H:%M"),
+                comment.name.as_ref().map(|x| format!("{} ", x)).unwrap_or_default(),
+                IdentityID::short(&comment.identity),
+                comment.comment
+            );
+        }
+    }
     Ok(())
 }
 
-pub fn export(txid: &str, output: &str, base64: bool) -> Result<()> {
+/// Leave a note on a staged transaction. Notes are sent along with the
+/// transaction by [send] unless `local` is set.
+pub fn comment(id: &str, txid: &str, note: &str, local: bool) -> Result<()> {
+    let transaction_id = TransactionID::try_from(txid).map_err(|e| anyhow!("Error loading transaction id: {:?}", e))?;
+    load_staged_transaction(&transaction_id)
+        .map_err(|e| anyhow!("Error loading staged transaction: {:?}", e))?
+        .ok_or_else(|| anyhow!("Transaction {} not found", txid))?;
+    if note.trim().is_empty() {
+        Err(anyhow!("Comment cannot be empty"))?;
+    }
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let comment = StageComment {
+        identity: id_str!(identity.id())?,
+        name: identity.names().get(0).cloned(),
+        comment: String::from(note.trim()),
+        created: Timestamp::now(),
+        local,
+    };
+    merge_comments(txid, &vec![comment])?;
+    println!("Comment added to staged transaction {}", txid);
+    Ok(())
+}
+
+/// Admin keys past this many aren't tried in combination by [analyze], since
+/// every subset gets test-signed.
+const ANALYZE_MAX_KEYS: usize = 8;
+
+/// Report what a staged transaction still needs: which of the identity's admin
+/// keys have signed, which haven't, and which local identities hold the missing
+/// ones. If the identity is owned here we also test-sign with combinations of
+/// its own keys and list the smallest ones that would make it valid.
+pub fn analyze(txid: &str) -> Result<()> {
+    let transaction_id = TransactionID::try_from(txid).map_err(|e| anyhow!("Error loading transaction id: {:?}", e))?;
+    let (identity_id, transaction) = load_staged_transaction(&transaction_id)
+        .map_err(|e| anyhow!("Error loading staged transaction: {:?}", e))?
+        .ok_or_else(|| anyhow!("Transaction {} not found", txid))?;
+    let id_str = id_str!(&identity_id)?;
+    let transactions = id::try_load_single_identity(&id_str)?;
+    let identity = util::build_identity(&transactions)?;
+    let green = dialoguer::console::Style::new().green();
+    let yellow = dialoguer::console::Style::new().yellow();
+
+    println!("Transaction: {}", transaction.id());
+    println!("Type:        {}", dag::transaction_to_string(&transaction));
+    println!(
+        "Identity:    {} {}",
+        IdentityID::short(&id_str),
+        identity.names().get(0).cloned().unwrap_or_default()
+    );
+    println!("Signatures:  {}", transaction.signatures().len());
+    let reason = match transaction.verify(Some(&identity)) {
+        Ok(_) => {
+            println!("Status:      {}", green.apply_to("ready, all required signatures are present"));
+            return Ok(());
+        }
+        Err(e) => format!("{}", e),
+    };
+    println!("Status:      {} ({})", yellow.apply_to("waiting"), reason);
+
+    let progress = admin_key_progress(&identity, &transaction);
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(row!["Admin key", "ID", "Signed", "Also held by"]);
+    for key in progress.iter() {
+        let signed = if key.signed { green.apply_to("yes") } else { yellow.apply_to("no") };
+        let holders = admin_key_holders(&identity, &key.key_id)?;
+        let holders = if holders.len() > 0 { holders.join(", ") } else { String::from("-") };
+        table.add_row(row![key.name, key.key_id, signed, holders]);
+    }
+    println!("");
+    table.printstd();
+
+    if !identity.is_owned() {
+        println!("\nThis identity isn't owned locally, so none of its admin keys can be tried here.");
+        return Ok(());
+    }
+    let active = identity
+        .keychain()
+        .admin_keys()
+        .iter()
+        .filter(|k| k.revocation().is_none())
+        .collect::<Vec<_>>();
+    let truncated = active.len() > ANALYZE_MAX_KEYS;
+    if truncated {
+        warn!("analyze: only trying {} of {} admin keys", ANALYZE_MAX_KEYS, active.len());
+        println!(
+            "\n{}",
+            yellow.apply_to(format!(
+                "Only the first {} of this identity's {} admin keys are tried in combination, so sets using the others won't be listed.",
+                ANALYZE_MAX_KEYS,
+                active.len()
+            ))
+        );
+    }
+    let keys = active.into_iter().take(ANALYZE_MAX_KEYS).collect::<Vec<_>>();
+    let master_key =
+        util::passphrase_prompt(&format!("Your master passphrase for identity {}", IdentityID::short(&id_str)), identity.created())?;
+    identity
+        .test_master_key(&master_key)
+        .map_err(|e| anyhow!("Incorrect master passphrase: {:?}", e))?;
+    let mut combos = (1..(1usize << keys.len()))
+        .map(|mask| (0..keys.len()).filter(|i| mask & (1 << i) != 0).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    combos.sort_by_key(|x| x.len());
+    let mut found: Vec<Vec<usize>> = Vec::new();
+    for combo in combos {
+        // only the smallest sets are interesting
+        if found.iter().any(|f| f.iter().all(|i| combo.contains(i))) {
+            continue;
+        }
+        let signed = combo
+            .iter()
+            .try_fold(transaction.clone(), |trans, i| trans.sign(&master_key, keys[*i]).ok());
+        if let Some(signed) = signed {
+            if signed.verify(Some(&identity)).is_ok() {
+                found.push(combo);
+            }
+        }
+    }
+    if found.len() == 0 && truncated {
+        println!(
+            "\nNo combination of the admin keys tried completes this transaction. A set including the keys that weren't tried might, or the remaining signatures have to come from the holders listed above (send it to them with `stamp stage send`)."
+        );
+        return Ok(());
+    } else if found.len() == 0 {
+        println!(
+            "\nNo combination of this identity's own admin keys completes this transaction. The remaining signatures have to come from the holders listed above (send it to them with `stamp stage send`)."
+        );
+        return Ok(());
+    }
+    println!("\nAny of these sets of admin keys would complete the transaction:");
+    for combo in found {
+        let names = combo.iter().map(|i| keys[*i].name().clone()).collect::<Vec<_>>();
+        println!("  - {}", names.join(" + "));
+    }
+    println!("\nSign with each key in a set using `stamp stage sign {} --sign-with <key>`.", txid);
+    Ok(())
+}
+
+/// Every QR frame starts with this, followed by `<index>/<total>:<check>:` and
+/// then a piece of the base64-encoded transaction.
+const QR_FRAME_PREFIX: &str = "STAMPTX1:";
+/// How much base64 goes in a single QR frame. Kept small so each frame stays
+/// readable on a terminal.
+const QR_FRAME_CHARS: usize = 400;
+/// How long each frame of an animated QR export stays on screen.
+const QR_FRAME_MILLIS: u64 = 1200;
+/// The most frames an export can have. Frames say how many there are in total,
+/// and we allocate that many slots, so a misread or made-up total can't be
+/// allowed to run away.
+const QR_FRAME_MAX: usize = 1024;
+
+/// A short hash of the whole payload, so frames from different exports can't
+/// get mixed together.
+fn qr_check(data: &[u8]) -> String {
+    let hash = Sha256::digest(data);
+    hash[0..4].iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+/// Split a serialized transaction into QR frame payloads.
+fn qr_frames(data: &[u8]) -> Result<Vec<String>> {
+    let check = qr_check(data);
+    let encoded = base64_encode(data);
+    let chunks = encoded.as_bytes().chunks(QR_FRAME_CHARS).collect::<Vec<_>>();
+    if chunks.len() > QR_FRAME_MAX {
+        Err(anyhow!(
+            "This transaction needs {} QR frames, more than the {} allowed. Export it to a file instead.",
+            chunks.len(),
+            QR_FRAME_MAX
+        ))?;
+    }
+    Ok(chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| format!("{}{}/{}:{}:{}", QR_FRAME_PREFIX, i + 1, chunks.len(), check, String::from_utf8_lossy(chunk)))
+        .collect())
+}
+
+/// Put QR frame payloads (in any order, duplicates allowed) back together into
+/// the serialized transaction.
+fn qr_reassemble(frames: &Vec<String>) -> Result<Vec<u8>> {
+    let mut total = None;
+    let mut check = None;
+    let mut pieces: Vec<Option<String>> = Vec::new();
+    for frame in frames.iter() {
+        let rest = frame
+            .trim()
+            .strip_prefix(QR_FRAME_PREFIX)
+            .ok_or(anyhow!("Not a stamp transaction QR frame: {}", frame))?;
+        let mut parts = rest.splitn(3, ':');
+        let (position, frame_check, chunk) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(position), Some(frame_check), Some(chunk)) => (position, frame_check, chunk),
+            _ => Err(anyhow!("Malformed QR frame: {}", frame))?,
+        };
+        let (index, frame_total) = position
+            .split_once('/')
+            .and_then(|(i, n)| Some((i.parse::<usize>().ok()?, n.parse::<usize>().ok()?)))
+            .filter(|(i, n)| *i >= 1 && i <= n)
+            .ok_or(anyhow!("Malformed QR frame position: {}", position))?;
+        if frame_total > QR_FRAME_MAX {
+            Err(anyhow!("QR frame claims {} frames in total, more than the {} allowed", frame_total, QR_FRAME_MAX))?;
+        }
+        match total {
+            // the first frame decides how many there are
+            None => {
+                total = Some(frame_total);
+                pieces.resize(frame_total, None);
+            }
+            Some(total) if total != frame_total => Err(anyhow!(
+                "QR frame {} says there are {} frames, but the first one said {}. They come from more than one export.",
+                position,
+                frame_total,
+                total
+            ))?,
+            Some(_) => {}
+        }
+        if check.get_or_insert(String::from(frame_check)) != frame_check {
+            Err(anyhow!("QR frames come from more than one export"))?;
+        }
+        pieces[index - 1] = Some(String::from(chunk));
+    }
+    let missing = pieces
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| x.is_none())
+        .map(|(i, _)| format!("{}", i + 1))
+        .collect::<Vec<_>>();
+    if pieces.len() == 0 {
+        Err(anyhow!("No QR frames found"))?;
+    }
+    if missing.len() > 0 {
+        Err(anyhow!("Missing QR frame(s) {} of {}", missing.join(", "), pieces.len()))?;
+    }
+    let encoded = pieces.into_iter().map(|x| x.unwrap_or_default()).collect::<String>();
+    let data = base64_decode(encoded.as_bytes()).map_err(|e| anyhow!("QR frames are damaged: {}", e))?;
+    if check.as_ref() != Some(&qr_check(data.as_slice())) {
+        Err(anyhow!("QR frames failed their checksum. One was probably misread."))?;
+    }
+    Ok(data)
+}
+
+/// Pull QR frame payloads out of a file: either an image (a screenshot or photo
+/// of the QR code) or text a scanner app saved, one frame per line.
+fn qr_read_frames(filename: &str) -> Result<Vec<String>> {
+    let bytes = util::read_file(filename)?;
+    if image::guess_format(bytes.as_slice()).is_ok() {
+        let img = image::load_from_memory(bytes.as_slice())
+            .map_err(|e| anyhow!("Problem reading image {}: {}", filename, e))?
+            .to_luma8();
+        let mut prepared = rqrr::PreparedImage::prepare(img);
+        let frames = prepared
+            .detect_grids()
+            .iter()
+            .filter_map(|grid| grid.decode().ok())
+            .map(|(_, content)| content)
+            .filter(|content| content.starts_with(QR_FRAME_PREFIX))
+            .collect::<Vec<_>>();
+        if frames.len() == 0 {
+            Err(anyhow!("No stamp transaction QR code found in {}", filename))?;
+        }
+        Ok(frames)
+    } else {
+        Ok(String::from_utf8_lossy(bytes.as_slice())
+            .lines()
+            .map(|x| x.trim())
+            .filter(|x| x.starts_with(QR_FRAME_PREFIX))
+            .map(String::from)
+            .collect())
+    }
+}
+
+/// Show QR frames on the terminal. A single frame is just printed, more than
+/// that are cycled through until ctrl+c.
+fn qr_display(frames: &Vec<String>) -> Result<()> {
+    if frames.len() == 1 {
+        println!("{}", util::qr_terminal(frames[0].as_bytes())?);
+        return Ok(());
+    }
+    let rendered = frames.iter().map(|x| util::qr_terminal(x.as_bytes())).collect::<Result<Vec<_>>>()?;
+    let term = dialoguer::console::Term::stdout();
+    loop {
+        for (i, frame) in rendered.iter().enumerate() {
+            term.clear_screen()?;
+            println!("{}", frame);
+            println!("Frame {} of {}. Scan until all frames are captured, then hit ctrl+c.", i + 1, rendered.len());
+            std::thread::sleep(std::time::Duration::from_millis(QR_FRAME_MILLIS));
+        }
+    }
+}
+
+pub fn export(txid: &str, output: &str, base64: bool, armored: bool, qr: bool) -> Result<()> {
     let transaction_id = TransactionID::try_from(txid).map_err(|e| anyhow!("Error loading transaction id: {:?}", e))?;
     let (identity_id, transaction) = load_staged_transaction(&transaction_id)
         .map_err(|e| anyhow!("Error loading staged transaction: {:?}", e))?
@@ -58,7 +679,13 @@ pub fn export(txid: &str, output: &str, base64: bool) -> Result<()> {
     let serialized = transaction
         .serialize_binary()
         .map_err(|e| anyhow!("Error serializing transaction: {}", e))?;
-    if base64 {
+    if qr {
+        qr_display(&qr_frames(serialized.as_slice())?)?;
+    } else if armored {
+        let short = IdentityID::short(&id_str!(&identity_id)?);
+        let armored = armor::armor(armor::TRANSACTION, &[("Identity", short)], serialized.as_slice());
+        util::write_file(output, armored.as_bytes())?;
+    } else if base64 {
         let base64 = base64_encode(serialized.as_slice());
         util::write_file(output, base64.as_bytes())?;
     } else {
@@ -67,14 +694,20 @@ pub fn export(txid: &str, output: &str, base64: bool) -> Result<()> {
     Ok(())
 }
 
-pub fn import(id: &str, input: &str) -> Result<()> {
+pub fn import(id: &str, input: &str, qr_files: &[&str]) -> Result<()> {
     let transactions = id::try_load_single_identity(id)?;
     let identity = util::build_identity(&transactions)?;
     let id_str = id_str!(identity.id())?;
-    let trans_bytes = util::read_file(input)?;
-    let transaction = Transaction::deserialize_binary(trans_bytes.as_slice())
-        .or_else(|_| Transaction::deserialize_binary(&base64_decode(trans_bytes.as_slice())?))
-        .map_err(|e| anyhow!("Error reading transaction: {}", e))?;
+    let trans_bytes = if qr_files.len() > 0 {
+        let mut frames = Vec::new();
+        for filename in qr_files {
+            frames.append(&mut qr_read_frames(filename)?);
+        }
+        qr_reassemble(&frames)?
+    } else {
+        armor::strip(util::read_file(input)?)?
+    };
+    let transaction = util::deserialize_transaction(trans_bytes.as_slice()).map_err(|e| anyhow!("Error reading transaction: {}", e))?;
     let transaction = if transaction.has_private() {
         let now = Timestamp::from_str("2020-12-29T07:04:27.000Z").unwrap();
         let mut rng = rng::chacha20();
@@ -93,29 +726,78 @@ pub fn import(id: &str, input: &str) -> Result<()> {
         transaction
     };
     let txid = transaction.id().clone();
-    stage_transaction(identity.id(), transaction).map_err(|e| anyhow!("Error staging transaction: {:?}", e))?;
+    stage_transaction(identity.id(), transaction.clone()).map_err(|e| anyhow!("Error staging transaction: {:?}", e))?;
     println!("Staged transaction {} import into identity {}", txid, IdentityID::short(&id_str));
+    notify_if_ready(identity.id(), &transaction)?;
     Ok(())
 }
 
+/// Send a staged transaction to a co-signer as a sealed message, which puts it
+/// in their staging area when they open it.
+pub fn send(
+    txid: &str,
+    id_from: &str,
+    key_search_from: Option<&str>,
+    key_search_to: Option<&str>,
+    search_to: &str,
+    note: Option<&str>,
+    output: &str,
+    armored: bool,
+) -> Result<()> {
+    let transaction_id = TransactionID::try_from(txid).map_err(|e| anyhow!("Error loading transaction id: {:?}", e))?;
+    let (identity_id, transaction) = load_staged_transaction(&transaction_id)
+        .map_err(|e| anyhow!("Error loading staged transaction: {:?}", e))?
+        .ok_or_else(|| anyhow!("Transaction {} not found", txid))?;
+    if transaction.has_private() {
+        Err(anyhow!(
+            "Transaction {} contains private data encrypted with your master key. Use `stamp stage export`, which re-encrypts it with a passphrase you can share.",
+            txid
+        ))?;
+    }
+    let serialized = transaction
+        .serialize_binary()
+        .map_err(|e| anyhow!("Error serializing transaction: {}", e))?;
+    let comments = comments(txid)?.into_iter().filter(|x| !x.local).collect::<Vec<_>>();
+    message::send_staged(
+        id_from,
+        key_search_from,
+        key_search_to,
+        search_to,
+        &id_str!(&identity_id)?,
+        serialized.as_slice(),
+        comments,
+        note,
+        output,
+        armored,
+    )
+}
+
 pub fn delete(txid: &str) -> Result<()> {
     let transaction_id = TransactionID::try_from(txid).map_err(|e| anyhow!("Error loading transaction id: {:?}", e))?;
     load_staged_transaction(&transaction_id)
         .map_err(|e| anyhow!("Error loading staged transaction: {:?}", e))?
         .ok_or_else(|| anyhow!("Transaction {} not found", txid))?;
-    if !util::yesno_prompt("Do you really want to delete this staged transaction?) [y/N]", "N")? {
+    if !util::yesno_prompt("Do you really want to delete this staged transaction? [y/N]", "N")? {
         return Ok(());
     }
     delete_staged_transaction(&transaction_id).map_err(|e| anyhow!("Error deleting staged transaction: {:?}", e))?;
+    forget_staged(txid)?;
     println!("Staged transaction {} deleted!", txid);
     Ok(())
 }
 
+/// Staged `Sign` transactions are documents signed under the identity's policy,
+/// which get written out instead of applied.
+fn is_policy_signature(transaction: &Transaction) -> bool {
+    matches!(transaction.entry().body(), TransactionBody::SignV1 { .. })
+}
+
 pub fn sign(txid: &str, sign_with: &str) -> Result<()> {
     let transaction_id = TransactionID::try_from(txid).map_err(|e| anyhow!("Error loading transaction id: {:?}", e))?;
     let (identity_id, transaction) = load_staged_transaction(&transaction_id)
         .map_err(|e| anyhow!("Error loading staged transaction: {:?}", e))?
         .ok_or_else(|| anyhow!("Transaction {} not found", txid))?;
+    check_expiry(&transaction_id)?;
     // dumb to keep converting this back and forth but oh well
     let id_str = id_str!(&identity_id)?;
     let transactions = id::try_load_single_identity(&id_str)?;
@@ -129,7 +811,14 @@ pub fn sign(txid: &str, sign_with: &str) -> Result<()> {
 
     // save it back into staging
     stage_transaction(identity.id(), signed).map_err(|e| anyhow!("Error saving staged transaction: {:?}", e))?;
-    if ready {
+    if ready && is_policy_signature(&signed) {
+        let green = dialoguer::console::Style::new().green();
+        println!(
+            "Transaction signed and saved! {} and the policy signature can be written out with:",
+            green.apply_to("All required signatures are present")
+        );
+        println!("  stamp sign finish {}", txid);
+    } else if ready {
         let green = dialoguer::console::Style::new().green();
         println!(
             "Transaction signed and saved! {} and the transaction can be applied with:",
@@ -147,12 +836,169 @@ pub fn sign(txid: &str, sign_with: &str) -> Result<()> {
 }
 
 pub fn apply(txid: &str) -> Result<()> {
+    let transaction_id = TransactionID::try_from(txid).map_err(|e| anyhow!("Error loading transaction id: {:?}", e))?;
+    apply_staged(&transaction_id)
+}
+
+/// Show what applying a staged transaction would do to its identity without
+/// saving anything.
+pub fn apply_dry_run(txid: &str) -> Result<()> {
     let transaction_id = TransactionID::try_from(txid).map_err(|e| anyhow!("Error loading transaction id: {:?}", e))?;
     let (identity_id, transaction) = load_staged_transaction(&transaction_id)
         .map_err(|e| anyhow!("Error loading staged transaction: {:?}", e))?
         .ok_or_else(|| anyhow!("Transaction {} not found", txid))?;
     let id_str = id_str!(&identity_id)?;
     let transactions = id::try_load_single_identity(&id_str)?;
+    let identity = util::build_identity(&transactions)?;
+    let green = dialoguer::console::Style::new().green();
+    let yellow = dialoguer::console::Style::new().yellow();
+    let red = dialoguer::console::Style::new().red();
+
+    println!("Transaction: {}", transaction.id());
+    println!("Type:        {}", dag::transaction_to_string(&transaction));
+    println!(
+        "Identity:    {} {}",
+        IdentityID::short(&id_str),
+        identity.names().get(0).cloned().unwrap_or_default()
+    );
+    println!("Signatures:  {}", transaction.signatures().len());
+    if is_policy_signature(&transaction) {
+        println!("\nThis is a policy signature, which isn't applied to the identity (see `stamp sign finish`).");
+        return Ok(());
+    }
+    if let Err(e) = check_expiry(&transaction_id) {
+        println!("Status:      {}", red.apply_to(format!("{}", e)));
+        return Ok(());
+    }
+    let changes = match transaction.verify(Some(&identity)) {
+        Ok(_) => {
+            println!("Status:      {}", green.apply_to("ready"));
+            let policy = policy_change(&transaction);
+            let transactions_mod = transactions
+                .push_transaction(transaction)
+                .map_err(|e| anyhow!("Problem applying staged transaction: {:?}", e))?;
+            let identity_mod = util::build_identity(&transactions_mod)?;
+            let mut changes = net::identity_changes(&identity, &identity_mod);
+            changes.extend(policy);
+            changes
+        }
+        Err(e) => {
+            // adding a transaction to an identity always verifies it, so until
+            // it's signed the preview comes from the transaction body instead
+            println!("Status:      {} ({})", yellow.apply_to("waiting"), e);
+            println!("\nIt isn't fully signed, so this preview is read from the transaction itself.");
+            body_changes(transactions.transactions(), &transaction)?
+        }
+    };
+    if changes.len() == 0 {
+        println!("\nApplying this transaction makes no changes to keys, claims, or policies.");
+    } else {
+        println!("\nApplying this transaction would make these changes:");
+        for change in changes {
+            println!("  {}", change);
+        }
+    }
+    println!("\nNothing was saved. Apply it with:\n  stamp stage apply {}", transaction_id);
+    Ok(())
+}
+
+/// Apply several staged transactions, either the given ones or every one that
+/// has its signatures (`all_ready`). They're applied in dependency order and
+/// each is verified against the identity as it stands after the ones before
+/// it. With explicit IDs, the first that doesn't verify stops the batch.
+pub fn apply_batch(id: &str, txids: &[&str], all_ready: bool) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let mut pending = if all_ready {
+        find_staged_transactions(identity.id())
+            .map_err(|e| anyhow!("Error loading staged transactions: {:?}", e))?
+            .into_iter()
+            .filter(|trans| !is_policy_signature(trans))
+            .collect::<Vec<_>>()
+    } else {
+        let mut pending = Vec::with_capacity(txids.len());
+        for txid in txids {
+            let transaction_id = TransactionID::try_from(*txid).map_err(|e| anyhow!("Error loading transaction id: {:?}", e))?;
+            let (identity_id, transaction) = load_staged_transaction(&transaction_id)
+                .map_err(|e| anyhow!("Error loading staged transaction: {:?}", e))?
+                .ok_or_else(|| anyhow!("Transaction {} not found", txid))?;
+            if &identity_id != identity.id() {
+                Err(anyhow!(
+                    "Transaction {} is staged for a different identity ({})",
+                    txid,
+                    IdentityID::short(&id_str!(&identity_id)?)
+                ))?;
+            }
+            if is_policy_signature(&transaction) {
+                Err(anyhow!(
+                    "Transaction {} is a policy signature. Write it out with `stamp sign finish {}` instead.",
+                    txid,
+                    txid
+                ))?;
+            }
+            pending.push(transaction);
+        }
+        pending
+    };
+    pending.sort_by(|a, b| a.entry().created().local().cmp(&b.entry().created().local()));
+
+    let mut applied = 0;
+    let mut skipped = 0;
+    while pending.len() > 0 {
+        // anything built on top of another pending transaction waits for it
+        let next = pending
+            .iter()
+            .position(|trans| {
+                !trans
+                    .entry()
+                    .previous_transactions()
+                    .iter()
+                    .any(|prev| pending.iter().any(|other| other.id() == prev))
+            })
+            .unwrap_or(0);
+        let transaction = pending.remove(next);
+        if all_ready && is_expired(transaction.id())? {
+            skipped += 1;
+            continue;
+        }
+        let current = util::build_identity(&id::try_load_single_identity(&id_str)?)?;
+        if let Err(e) = transaction.verify(Some(&current)) {
+            if all_ready {
+                skipped += 1;
+                continue;
+            }
+            Err(anyhow!(
+                "Transaction {} isn't ready to apply ({}). Stopping after {} applied.",
+                transaction.id(),
+                e,
+                applied
+            ))?;
+        }
+        apply_staged(transaction.id())?;
+        applied += 1;
+    }
+    if all_ready {
+        println!("Applied {} transaction(s), {} still waiting on signatures.", applied, skipped);
+    }
+    Ok(())
+}
+
+fn apply_staged(transaction_id: &TransactionID) -> Result<()> {
+    let txid = format!("{}", transaction_id);
+    let (identity_id, transaction) = load_staged_transaction(transaction_id)
+        .map_err(|e| anyhow!("Error loading staged transaction: {:?}", e))?
+        .ok_or_else(|| anyhow!("Transaction {} not found", txid))?;
+    if is_policy_signature(&transaction) {
+        Err(anyhow!(
+            "Transaction {} is a policy signature, which isn't applied to the identity. Write it out with:\n  stamp sign finish {}",
+            txid,
+            txid
+        ))?;
+    }
+    check_expiry(transaction_id)?;
+    let id_str = id_str!(&identity_id)?;
+    let transactions = id::try_load_single_identity(&id_str)?;
     let transactions_mod = transactions
         .push_transaction(transaction)
         .map_err(|e| anyhow!("Problem saving staged transaction to identity: {:?}", e))?;
@@ -161,32 +1007,80 @@ pub fn apply(txid: &str) -> Result<()> {
     let trans = transactions_mod
         .transactions()
         .iter()
-        .find(|t| t.id() == &transaction_id)
+        .find(|t| t.id() == transaction_id)
         .ok_or_else(|| anyhow!("Unable to find saved transaction {}", transaction_id))?;
     let post_save_msg = dag::post_save(&transactions_mod, trans, false)?;
     if let Some(msg) = post_save_msg {
         println!("{}", msg);
     }
-    delete_staged_transaction(&transaction_id).map_err(|_| {
+    delete_staged_transaction(transaction_id).map_err(|_| {
         anyhow!(
             "Problem removing staged transaction. The transaction was applied and can be safely removed with:\n  stamp stage delete {}",
             transaction_id
         )
     })?;
+    forget_staged(&txid)?;
     Ok(())
 }
 
-pub fn print_transactions_table(identity: Option<&Identity>, transactions: &Vec<Transaction>) {
+pub fn print_transactions_table(identity: Option<&Identity>, transactions: &Vec<Transaction>, verbose: bool) {
     let mut table = Table::new();
     table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
-    table.set_titles(row!["ID", "Type", "Signatures", "Ready", "Created"]);
+    if verbose {
+        table.set_titles(row![
+            "ID",
+            "Type",
+            "Description",
+            "Signatures",
+            "Signed by",
+            "Not signed by",
+            "Status",
+            "Why",
+            "Created"
+        ]);
+    } else {
+        table.set_titles(row!["ID", "Type", "Description", "Signatures", "Status", "Created"]);
+    }
     for trans in transactions {
         let ty = dag::transaction_to_string(trans);
+        let desc = dag::transaction_description(trans, transactions.as_slice());
         let id = id_str!(trans.id()).unwrap_or_else(|e| format!("<bad id {:?} -- {:?}>", trans.id(), e));
-        let ready = if trans.verify(identity).is_ok() { "x" } else { "" };
+        let expires = staged_expiry(trans.id()).ok().flatten();
+        let expired = expires.as_ref().map(|x| x.local() <= Timestamp::now().local()).unwrap_or(false);
+        let (status, why) = match (expired, trans.verify(identity)) {
+            (true, _) => (
+                "expired",
+                format!(
+                    "expired {}",
+                    expires
+                        .as_ref()
+                        .map(|x| x.local().format("%b %e, %Y").to_string())
+                        .unwrap_or_default()
+                ),
+            ),
+            (false, Ok(_)) => ("ready", String::from("")),
+            (false, Err(e)) => ("waiting", format!("{}", e)),
+        };
         let created = trans.entry().created().local().format("%b %e, %Y  %H:%M:%S");
         let num_sig = trans.signatures().len();
-        table.add_row(row![id, ty, num_sig, ready, created,]);
+        if verbose {
+            let progress = identity.map(|identity| admin_key_progress(identity, trans)).unwrap_or_default();
+            let names = |signed: bool| {
+                let names = progress
+                    .iter()
+                    .filter(|k| k.signed == signed)
+                    .map(|k| k.name.as_str())
+                    .collect::<Vec<_>>();
+                if names.len() > 0 {
+                    names.join(", ")
+                } else {
+                    String::from("-")
+                }
+            };
+            table.add_row(row![id, ty, desc, num_sig, names(true), names(false), status, why, created,]);
+        } else {
+            table.add_row(row![id, ty, desc, num_sig, status, created,]);
+        }
     }
     table.printstd();
 }
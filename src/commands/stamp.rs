@@ -1,25 +1,52 @@
 use crate::{
-    commands::{dag, id},
+    armor,
+    commands::{claim, dag, id, keychain, sign},
     config, db, util,
 };
 use anyhow::{anyhow, Result};
 use prettytable::Table;
 use stamp_core::{
     crypto::{
-        base::{rng, SecretKey},
-        message::Message,
+        base::{rng, KeyID, SecretKey},
+        message::{self, Message},
     },
-    dag::Transaction,
+    dag::{Transaction, TransactionBody, Transactions},
     identity::{
-        claim::ClaimID,
+        claim::{Claim, ClaimID},
+        keychain::{RevocationReason as KeyRevocationReason, Subkey},
         stamp::{Confidence, RevocationReason, Stamp, StampEntry, StampRequest},
-        IdentityID,
+        Identity, IdentityID,
     },
-    util::{base64_decode, SerText, SerdeBinary, Timestamp},
+    policy::MultisigPolicySignature,
+    util::{base64_decode, base64_encode, SerText, SerdeBinary, Timestamp},
 };
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::convert::TryFrom;
 
-pub fn new(our_identity_id: &str, claim_id: &str, stage: bool, sign_with: Option<&str>) -> Result<()> {
+/// Parse a stamp confidence level. "extreme" is accepted as an older name for
+/// "ultimate".
+pub(crate) fn parse_confidence(val: &str) -> Result<Confidence> {
+    match val.trim() {
+        "negative" => Ok(Confidence::Negative),
+        "low" => Ok(Confidence::Low),
+        "medium" => Ok(Confidence::Medium),
+        "high" => Ok(Confidence::High),
+        "ultimate" | "extreme" => Ok(Confidence::Ultimate),
+        _ => Err(anyhow!("Invalid confidence value: {} (expected negative, low, medium, high, or ultimate)", val)),
+    }
+}
+
+/// Stamp another identity's claim. Confidence and expiration are prompted for
+/// unless given.
+pub fn new(
+    our_identity_id: &str,
+    claim_id: &str,
+    confidence: Option<&str>,
+    expires: Option<Timestamp>,
+    stage: bool,
+    sign_with: Option<&str>,
+) -> Result<()> {
     let hash_with = config::hash_algo(Some(&our_identity_id));
     let our_transactions = id::try_load_single_identity(our_identity_id)?;
     let their_transactions =
@@ -38,48 +65,48 @@ pub fn new(our_identity_id: &str, claim_id: &str, stage: bool, sign_with: Option
         .ok_or(anyhow!("Claim {} not found in identity {}", claim_id, id_str!(their_identity.id())?))?;
     let their_id_str = id_str!(their_identity.id())?;
     let claim_id_str = id_str!(claim.id())?;
+    id::print_counterparty(&their_identity, "Stamping claim for")?;
     util::print_wrapped(&format!(
         "You are about to stamp the claim {} made by the identity {}.\n",
         ClaimID::short(&claim_id_str),
         IdentityID::short(&their_id_str)
     ));
-    util::print_wrapped(
-        "Effectively, you are vouching for them and that their claim is true. You can specify your confidence in the claim:\n",
-    );
-    util::print_wrapped("    negative\n");
-    util::print_wrapped_indent(
-        "you are certain this claim is false and you are creating this stamp as a warning to others\n",
-        "        ",
-    );
-    util::print_wrapped("    low\n");
-    util::print_wrapped_indent("you have done a quick and dirty verification of the claim\n", "        ");
-    util::print_wrapped("    medium\n");
-    util::print_wrapped_indent(
-        "you're doing a decent amount of verification, such as having them click a verification link in email\n",
-        "        ",
-    );
-    util::print_wrapped("    high\n");
-    util::print_wrapped_indent(
-        "you have verified the claim extensively (birth certificates, retinal scans, fingerprint matching, etc)\n",
-        "        ",
-    );
-    util::print_wrapped("    extreme\n");
-    util::print_wrapped_indent("you have known this person for the last 50 years and can be absolutely certain that the claim they are making is correct and they are not a hologram or an android imposter\n", "        ");
-    let confidence_val = util::value_prompt("\nHow confident are you in this claim?")?;
-    let confidence = match confidence_val.as_str() {
-        "negative" => Confidence::Negative,
-        "low" => Confidence::Low,
-        "medium" => Confidence::Medium,
-        "high" => Confidence::High,
-        "ultimate" => Confidence::Ultimate,
-        _ => Err(anyhow!("Invalid confidence value: {}", confidence_val))?,
+    // given on the command line, stay non-interactive
+    let interactive = confidence.is_none() && expires.is_none();
+    let confidence = match confidence {
+        Some(confidence) => parse_confidence(confidence)?,
+        None => {
+            util::print_wrapped(
+                "Effectively, you are vouching for them and that their claim is true. You can specify your confidence in the claim:\n",
+            );
+            util::print_wrapped("    negative\n");
+            util::print_wrapped_indent(
+                "you are certain this claim is false and you are creating this stamp as a warning to others\n",
+                "        ",
+            );
+            util::print_wrapped("    low\n");
+            util::print_wrapped_indent("you have done a quick and dirty verification of the claim\n", "        ");
+            util::print_wrapped("    medium\n");
+            util::print_wrapped_indent(
+                "you're doing a decent amount of verification, such as having them click a verification link in email\n",
+                "        ",
+            );
+            util::print_wrapped("    high\n");
+            util::print_wrapped_indent(
+                "you have verified the claim extensively (birth certificates, retinal scans, fingerprint matching, etc)\n",
+                "        ",
+            );
+            util::print_wrapped("    ultimate\n");
+            util::print_wrapped_indent("you have known this person for the last 50 years and can be absolutely certain that the claim they are making is correct and they are not a hologram or an android imposter\n", "        ");
+            let confidence_val = util::value_prompt("\nHow confident are you in this claim?")?;
+            parse_confidence(&confidence_val)?
+        }
     };
-    let expires: Option<Timestamp> = if util::yesno_prompt("Would you like your stamp to expire on a certain date? [y/N]", "n")? {
+    let expires: Option<Timestamp> = if !interactive {
+        expires
+    } else if util::yesno_prompt("Would you like your stamp to expire on a certain date? [y/N]", "n")? {
         let expire_val = util::value_prompt("What date would you like it to expire? [ex 2024-10-13T12:00:00Z]")?;
-        let ts: Timestamp = expire_val
-            .parse()
-            .map_err(|e| anyhow!("Error parsing time: {}: {}", expire_val, e))?;
-        Some(ts)
+        Some(util::parse_timestamp(&expire_val)?)
     } else {
         None
     };
@@ -107,7 +134,6 @@ pub fn request(
     stamper_identity_id: &str,
     stamper_crypto_subkey_search: &str,
 ) -> Result<Vec<u8>> {
-    let mut rng = rng::chacha20();
     let our_transactions = id::try_load_single_identity(our_identity_id)?;
     let stamper_transactions = id::try_load_single_identity(stamper_identity_id)?;
     let our_identity = util::build_identity(&our_transactions)?;
@@ -133,6 +159,7 @@ pub fn request(
             claim_id.starts_with(claim_search) || x.name().as_ref().map(|x| x == claim_search).unwrap_or(false)
         })
         .ok_or_else(|| anyhow!("Cannot find claim {}", claim_search))?;
+    id::print_counterparty(&stamper_identity, "Requesting a stamp from")?;
     let master_key = util::passphrase_prompt(
         &format!("Your master passphrase for identity {}", IdentityID::short(&our_id)),
         our_identity.created(),
@@ -140,8 +167,21 @@ pub fn request(
     our_transactions
         .test_master_key(&master_key)
         .map_err(|e| anyhow!("Incorrect passphrase: {:?}", e))?;
+    request_message(&master_key, &our_identity, key_from, key_to, claim)
+}
+
+/// Seal a request for a stamp on one of our claims to the stamper's `crypto`
+/// key.
+pub(crate) fn request_message(
+    master_key: &SecretKey,
+    our_identity: &Identity,
+    key_from: &Subkey,
+    key_to: &Subkey,
+    claim: &Claim,
+) -> Result<Vec<u8>> {
+    let mut rng = rng::chacha20();
     let sk_tmp = SecretKey::new_xchacha20poly1305(&mut rng)?;
-    let req_message = StampRequest::new_message(&mut rng, &master_key, our_identity.id(), &key_from, &key_to, claim, sk_tmp)
+    let req_message = StampRequest::new_message(&mut rng, master_key, our_identity.id(), key_from, key_to, claim, sk_tmp)
         .map_err(|e| anyhow!("Problem creating stamp request: {:?}", e))?;
     let bytes = req_message
         .serialize_binary()
@@ -149,20 +189,26 @@ pub fn request(
     Ok(bytes)
 }
 
-pub fn open_request(our_identity_id: &str, our_crypto_subkey_search: &str, req: &str) -> Result<()> {
+pub fn open_request(our_identity_id: &str, our_crypto_subkey_search: Option<&str>, req: &str) -> Result<()> {
+    let sealed_bytes = util::read_file(req)?;
+    open_request_bytes(our_identity_id, our_crypto_subkey_search, sealed_bytes.as_slice())
+}
+
+fn read_request_message(sealed_bytes: &[u8]) -> Result<Message> {
+    let sealed_bytes = armor::strip(sealed_bytes.to_vec())?;
+    let sealed_bytes = sealed_bytes.as_slice();
+    Message::deserialize_binary(sealed_bytes)
+        .or_else(|_| Message::deserialize_binary(&base64_decode(sealed_bytes)?))
+        .map_err(|e| anyhow!("Error reading sealed message: {}", e))
+}
+
+fn open_request_bytes(our_identity_id: &str, our_crypto_subkey_search: Option<&str>, sealed_bytes: &[u8]) -> Result<()> {
     let our_transactions = id::try_load_single_identity(our_identity_id)?;
     let our_identity = util::build_identity(&our_transactions)?;
     let our_id = id_str!(our_identity.id())?;
-    let key_to = our_identity
-        .keychain()
-        .subkeys()
-        .iter()
-        .find(|k| k.key_id().as_string().starts_with(our_crypto_subkey_search) || k.name() == our_crypto_subkey_search)
-        .ok_or_else(|| anyhow!("Cannot find `to` key {}", our_crypto_subkey_search))?;
-    let sealed_bytes = util::read_file(req)?;
-    let sealed_message = Message::deserialize_binary(sealed_bytes.as_slice())
-        .or_else(|_| Message::deserialize_binary(&base64_decode(sealed_bytes.as_slice())?))
-        .map_err(|e| anyhow!("Error reading sealed message: {}", e))?;
+    let key_to =
+        keychain::find_keys_by_search_or_prompt(&our_identity, our_crypto_subkey_search, "crypto", |sub| sub.key().as_cryptokey())?;
+    let sealed_message = read_request_message(sealed_bytes)?;
     let signed_message = sealed_message.signed().ok_or_else(|| anyhow!("Invalid stemp request message"))?;
     let stampee_identity_id = signed_message.signed_by_identity();
     let stampee_key_id = signed_message.signed_by_key();
@@ -173,6 +219,7 @@ pub fn open_request(our_identity_id: &str, our_crypto_subkey_search: &str, req:
         .keychain()
         .subkey_by_keyid(stampee_key_id)
         .ok_or_else(|| anyhow!("Cannot find `from` key {:?}", stampee_key_id))?;
+    id::print_counterparty(&stampee_identity, "Stamp request from")?;
     let master_key = util::passphrase_prompt(
         &format!("Your master passphrase for identity {}", IdentityID::short(&our_id)),
         our_identity.created(),
@@ -187,44 +234,703 @@ pub fn open_request(our_identity_id: &str, our_crypto_subkey_search: &str, req:
     Ok(())
 }
 
-pub fn list(id: &str, revoked: bool, verbose: bool) -> Result<()> {
+/// A stamp request waiting in an identity's inbox.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InboxRequest {
+    pub to_identity: String,
+    pub from_identity: String,
+    pub received: Timestamp,
+    /// The sealed request message, base64 encoded
+    pub message: String,
+    /// When we marked the request as dealt with.
+    #[serde(default)]
+    pub done: Option<Timestamp>,
+}
+
+fn save_inbox_request(to_identity: &str, from_identity: &str, req: &[u8]) -> Result<String> {
+    let now = Timestamp::now();
+    // the hash keeps two requests saved in the same millisecond apart
+    let hash = format!("{:x}", Sha256::digest(req));
+    let key = format!("{}-{}", now.format("%Y%m%d%H%M%S%3f"), &hash[0..16]);
+    if db::load_record::<InboxRequest>("stamp-inbox", &key)?.is_some() {
+        Err(anyhow!("This stamp request is already in the inbox"))?;
+    }
+    let request = InboxRequest {
+        to_identity: to_identity.into(),
+        from_identity: from_identity.into(),
+        received: now,
+        message: base64_encode(req),
+        done: None,
+    };
+    db::save_record("stamp-inbox", &key, &request)?;
+    Ok(key)
+}
+
+fn inbox_for(id: &str) -> Result<Vec<(String, InboxRequest)>> {
     let transactions = id::try_load_single_identity(id)?;
     let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let requests = db::list_records::<InboxRequest>("stamp-inbox")?
+        .into_iter()
+        .filter(|(_, req)| req.to_identity == id_str)
+        .collect::<Vec<_>>();
+    Ok(requests)
+}
+
+/// Save a stamp request someone sent us (as a file) into our inbox so it can be
+/// worked through later.
+pub fn inbox_import(id: &str, req: &str) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let sealed_bytes = util::read_file(req)?;
+    let sealed_message = read_request_message(sealed_bytes.as_slice())?;
+    let signed_message = sealed_message.signed().ok_or_else(|| anyhow!("Invalid stamp request message"))?;
+    let from_str = id_str!(signed_message.signed_by_identity())?;
+    if db::load_identity(signed_message.signed_by_identity())?.is_none() {
+        let yellow = dialoguer::console::Style::new().yellow();
+        util::print_wrapped(&format!(
+            "{}\n",
+            yellow.apply_to(format!(
+                "The identity that sent this request ({}) isn't imported. You'll need it to open the request.",
+                IdentityID::short(&from_str)
+            ))
+        ));
+    }
+    let serialized = sealed_message
+        .serialize_binary()
+        .map_err(|e| anyhow!("Problem serializing stamp request: {:?}", e))?;
+    let key = save_inbox_request(&id_str, &from_str, serialized.as_slice())?;
+    println!("Stamp request from {} saved to your inbox as {}", IdentityID::short(&from_str), key);
+    Ok(())
+}
+
+pub fn inbox_list(id: &str, all: bool, verbose: bool) -> Result<()> {
+    let requests = inbox_for(id)?
+        .into_iter()
+        .filter(|(_, req)| all || req.done.is_none())
+        .collect::<Vec<_>>();
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(row!["ID", "From", "Name", "Received", "Status"]);
+    for (key, req) in requests {
+        let name = IdentityID::try_from(req.from_identity.as_str())
+            .ok()
+            .and_then(|id| db::load_identity(&id).ok().flatten())
+            .and_then(|t| util::build_identity(&t).ok())
+            .and_then(|i| i.names().get(0).cloned())
+            .unwrap_or_else(|| String::from(""));
+        let from = if verbose {
+            req.from_identity.clone()
+        } else {
+            IdentityID::short(&req.from_identity)
+        };
+        let received = req.received.local().format("%b %d, %Y  %H:%M:%S").to_string();
+        let status = match req.done.as_ref() {
+            Some(done) => format!("done {}", done.local().format("%b %d, %Y")),
+            None => String::from("pending"),
+        };
+        table.add_row(row![key, from, name, received, status]);
+    }
+    table.printstd();
+    Ok(())
+}
+
+fn find_inbox_request(id: &str, inbox_id: &str) -> Result<(String, InboxRequest)> {
+    inbox_for(id)?
+        .into_iter()
+        .find(|(key, _)| key == inbox_id)
+        .ok_or_else(|| anyhow!("Stamp request {} not found in inbox", inbox_id))
+}
+
+pub fn inbox_open(id: &str, our_crypto_subkey_search: Option<&str>, inbox_id: &str) -> Result<()> {
+    let (_, req) = find_inbox_request(id, inbox_id)?;
+    let sealed_bytes = base64_decode(req.message.as_bytes()).map_err(|e| anyhow!("Problem decoding stamp request: {}", e))?;
+    open_request_bytes(id, our_crypto_subkey_search, sealed_bytes.as_slice())?;
+    if req.done.is_none() {
+        println!("\nWhen you're finished with this request, mark it with:\n  stamp stamp req-inbox done {}", inbox_id);
+    }
+    Ok(())
+}
+
+/// Mark an inbox request as dealt with, or remove it entirely.
+pub fn inbox_done(id: &str, inbox_id: &str, delete: bool) -> Result<()> {
+    let (key, mut req) = find_inbox_request(id, inbox_id)?;
+    if delete {
+        db::delete_record("stamp-inbox", &key)?;
+        println!("Stamp request {} removed from your inbox", key);
+    } else {
+        req.done = Some(Timestamp::now());
+        db::save_record("stamp-inbox", &key, &req)?;
+        println!("Stamp request {} marked as done", key);
+    }
+    Ok(())
+}
+
+/// List the stamps we've made. If `expiring_days` is given, only stamps that
+/// expire within that many days are shown.
+pub fn list(id: &str, revoked: bool, expiring_days: Option<u64>, verbose: bool) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let now = Timestamp::now().local();
+    let cutoff = match expiring_days {
+        Some(days) => Some(
+            i64::try_from(days)
+                .ok()
+                .and_then(|days| util::checked_duration(days, 24 * 60 * 60))
+                .and_then(|duration| now.checked_add_signed(duration))
+                .ok_or(anyhow!("--expiring {} is too many days", days))?,
+        ),
+        None => None,
+    };
     let stamps = identity
         .stamps()
         .iter()
         .filter(|x| if revoked { true } else { x.revocation().is_none() })
+        .filter(|x| match (cutoff, x.entry().expires().as_ref()) {
+            (Some(cutoff), Some(expires)) => expires.local() > now && expires.local() <= cutoff,
+            (Some(_), None) => false,
+            (None, _) => true,
+        })
         .collect::<Vec<_>>();
     print_stamps_table(&stamps, verbose, revoked)?;
     Ok(())
 }
 
-pub fn accept(id: &str, location: &str, stage: bool, sign_with: Option<&str>) -> Result<()> {
-    let hash_with = config::hash_algo(Some(&id));
+/// List every stamp others have made on our claims, grouped by stamper.
+pub fn received(id: &str, verbose: bool) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let mut by_stamper: Vec<(IdentityID, Vec<(&Claim, &Stamp)>)> = Vec::new();
+    for claim in identity.claims() {
+        for stamp in claim.stamps() {
+            let stamper = stamp.entry().stamper();
+            match by_stamper.iter_mut().find(|(id, _)| id == stamper) {
+                Some((_, stamps)) => stamps.push((claim, stamp)),
+                None => by_stamper.push((stamper.clone(), vec![(claim, stamp)])),
+            }
+        }
+    }
+    if by_stamper.len() == 0 {
+        println!("Nobody has stamped your claims yet.");
+        return Ok(());
+    }
+    let stamper_name = |stamper_id: &IdentityID| {
+        db::load_identity(stamper_id)
+            .ok()
+            .flatten()
+            .and_then(|t| util::build_identity(&t).ok())
+            .and_then(|i| i.names().get(0).cloned())
+            .unwrap_or_else(|| String::from("-"))
+    };
+    by_stamper.sort_by_cached_key(|(stamper_id, _)| stamper_name(stamper_id).to_lowercase());
+
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    let id_field = if verbose { "Stamper" } else { "Stamper (short)" };
+    table.set_titles(row![id_field, "Name", "Stamp", "Claim", "Confidence", "Created", "Expires", "Status"]);
+    let now = Timestamp::now();
+    let red = dialoguer::console::Style::new().red();
+    let yellow = dialoguer::console::Style::new().yellow();
+    let green = dialoguer::console::Style::new().green();
+    for (stamper_id, stamps) in by_stamper {
+        let (stamper_full, stamper_short) = id_str_split!(&stamper_id);
+        let name = stamper_name(&stamper_id);
+        for (i, (claim, stamp)) in stamps.into_iter().enumerate() {
+            let (stamp_full, stamp_short) = id_str_split!(stamp.id());
+            let (claim_full, claim_short) = id_str_split!(claim.id());
+            let claim_label = match claim.name() {
+                Some(name) => format!("{} ({})", if verbose { &claim_full } else { &claim_short }, name),
+                None => format!("{} ({})", if verbose { &claim_full } else { &claim_short }, claim::claim_type(claim.spec())),
+            };
+            let confidence = match stamp.entry().confidence() {
+                Confidence::Negative => format!("{}", red.apply_to("negative")),
+                Confidence::Low => String::from("low"),
+                Confidence::Medium => String::from("medium"),
+                Confidence::High => String::from("high"),
+                Confidence::Ultimate => String::from("ultimate"),
+            };
+            let expires = stamp.entry().expires().as_ref();
+            let status = if stamp.revocation().is_some() {
+                format!("{}", red.apply_to("revoked"))
+            } else if expires.map(|x| x.local() <= now.local()).unwrap_or(false) {
+                format!("{}", yellow.apply_to("expired"))
+            } else {
+                format!("{}", green.apply_to("active"))
+            };
+            // only label the first row of each stamper's group
+            let (stamper_col, name_col) = if i == 0 {
+                (if verbose { stamper_full.clone() } else { stamper_short.clone() }, name.clone())
+            } else {
+                (String::from(""), String::from(""))
+            };
+            table.add_row(row![
+                stamper_col,
+                name_col,
+                if verbose { &stamp_full } else { &stamp_short },
+                claim_label,
+                confidence,
+                stamp.created().local().format("%b %d, %Y").to_string(),
+                expires
+                    .map(|x| x.local().format("%b %d, %Y").to_string())
+                    .unwrap_or_else(|| String::from("-")),
+                status,
+            ]);
+        }
+    }
+    table.printstd();
+    Ok(())
+}
+
+/// Seal one of our stamps to the stampee's `crypto` key so only they can
+/// accept it, and write the sealed stamp to `output` for them to pick up.
+///
+/// StampNet can't carry messages between agents yet, so getting the sealed
+/// stamp to the stampee is up to the caller.
+pub fn send(
+    id: &str,
+    stamp_search: &str,
+    key_search_from: Option<&str>,
+    key_search_to: Option<&str>,
+    output: &str,
+    base64: bool,
+    armored: bool,
+) -> Result<()> {
     let transactions = id::try_load_single_identity(id)?;
     let identity = util::build_identity(&transactions)?;
     let id_str = id_str!(identity.id())?;
-    let stamp_bytes = util::read_file(location)?;
-    let stamp = Transaction::deserialize_binary(stamp_bytes.as_slice())
-        .or_else(|_| Transaction::deserialize_binary(&base64_decode(stamp_bytes.as_slice())?))
-        .map_err(|e| anyhow!("Error deserializing stamp transaction: {:?}", e))?;
+    let stamp_trans = transactions
+        .transactions()
+        .iter()
+        .find(|x| {
+            matches!(x.entry().body(), TransactionBody::MakeStampV1 { .. })
+                && id_str!(x.id()).map(|id| id.starts_with(stamp_search)).unwrap_or(false)
+        })
+        .ok_or_else(|| anyhow!("Stamp {} not found for identity {}", stamp_search, IdentityID::short(&id_str)))?;
+    let stampee_id = match stamp_trans.entry().body() {
+        TransactionBody::MakeStampV1 { stamp } => stamp.stampee().clone(),
+        _ => unreachable!(),
+    };
+    let stampee_transactions = db::load_identity(&stampee_id)?.ok_or_else(|| {
+        anyhow!(
+            "The stampee ({}) is not in your local identities. Import their identity first, see `stamp id import`.",
+            IdentityID::short(&id_str!(&stampee_id).unwrap_or_default())
+        )
+    })?;
+    let stampee = util::build_identity(&stampee_transactions)?;
+    let stampee_str = id_str!(stampee.id())?;
+    let key_from = keychain::find_keys_by_search_or_prompt(&identity, key_search_from, "crypto", |sub| sub.key().as_cryptokey())?;
+    let key_to = keychain::find_keys_by_search_or_prompt(&stampee, key_search_to, "crypto", |sub| sub.key().as_cryptokey())?;
+    id::print_counterparty(&stampee, "Sending a stamp to")?;
+    let master_key =
+        util::passphrase_prompt(&format!("Your master passphrase for identity {}", IdentityID::short(&id_str)), identity.created())?;
+    transactions
+        .test_master_key(&master_key)
+        .map_err(|e| anyhow!("Incorrect passphrase: {:?}", e))?;
+    let stamp_bytes = stamp_trans
+        .serialize_binary()
+        .map_err(|e| anyhow!("Problem serializing stamp transaction: {:?}", e))?;
+    let mut rng = rng::chacha20();
+    let sealed = message::send(&mut rng, &master_key, identity.id(), &key_from, &key_to, stamp_bytes.as_slice())
+        .map_err(|e| anyhow!("Problem sealing the stamp: {}", e))?;
+    let serialized = sealed
+        .serialize_binary()
+        .map_err(|e| anyhow!("Problem serializing the sealed stamp: {}", e))?;
+    if armored {
+        let armored = armor::armor(armor::MESSAGE, &[("Recipient", IdentityID::short(&stampee_str))], serialized.as_slice());
+        util::write_file(output, armored.as_bytes())?;
+    } else if base64 {
+        util::write_file(output, base64_encode(serialized.as_slice()).as_bytes())?;
+    } else {
+        util::write_file(output, serialized.as_slice())?;
+    }
+    Ok(())
+}
+
+/// Open a stamp that was sealed to one of our `crypto` keys (see [send]).
+fn open_sealed_stamp(
+    identity: &Identity,
+    master_key: &SecretKey,
+    key_search_open: Option<&str>,
+    sealed_message: &Message,
+) -> Result<Transaction> {
+    let signed_message = sealed_message
+        .signed()
+        .ok_or_else(|| anyhow!("Sealed stamps must be signed by the stamper"))?;
+    let stamper_transactions = db::load_identity(signed_message.signed_by_identity())?
+        .ok_or_else(|| anyhow!("The identity that sent this stamp has not been imported, see the `stamp id import` command"))?;
+    let stamper = util::build_identity(&stamper_transactions)?;
+    let key_from = stamper
+        .keychain()
+        .subkey_by_keyid(signed_message.signed_by_key())
+        .ok_or_else(|| anyhow!("The identity that sent this stamp is missing the key used to seal it"))?;
+    let key_to = keychain::find_keys_by_search_or_prompt(identity, key_search_open, "crypto", |sub| sub.key().as_cryptokey())?;
+    let opened =
+        message::open(master_key, &key_to, key_from, sealed_message).map_err(|e| anyhow!("Problem opening sealed stamp: {}", e))?;
+    let stamp = util::deserialize_transaction(opened.as_slice())?;
+    match stamp.entry().body() {
+        TransactionBody::MakeStampV1 { stamp: entry } if entry.stamper() == signed_message.signed_by_identity() => {}
+        TransactionBody::MakeStampV1 { .. } => Err(anyhow!("The sealed stamp was sent by someone other than the stamper"))?,
+        _ => Err(anyhow!("The sealed transaction is not a stamp"))?,
+    }
+    Ok(stamp)
+}
+
+fn master_key_prompt(transactions: &Transactions, identity: &Identity) -> Result<SecretKey> {
+    let id_str = id_str!(identity.id())?;
+    let master_key = util::passphrase_prompt(
+        &format!("Your current master passphrase for identity {}", IdentityID::short(&id_str)),
+        identity.created(),
+    )?;
+    transactions
+        .test_master_key(&master_key)
+        .map_err(|e| anyhow!("Incorrect passphrase: {:?}", e))?;
+    Ok(master_key)
+}
+
+/// Accept a stamp from a file, either as a plain stamp transaction or sealed to
+/// one of our `crypto` keys.
+pub fn accept(id: &str, location: &str, key_search_open: Option<&str>, stage: bool, sign_with: Option<&str>) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let stamp_bytes = armor::strip(util::read_file(location)?)?;
+    let (stamp, master_key) = match util::deserialize_transaction(stamp_bytes.as_slice()) {
+        Ok(stamp) => (stamp, None),
+        Err(e) => {
+            let sealed_message =
+                read_request_message(stamp_bytes.as_slice()).map_err(|_| anyhow!("Error deserializing stamp transaction: {}", e))?;
+            let master_key = master_key_prompt(&transactions, &identity)?;
+            let stamp = open_sealed_stamp(&identity, &master_key, key_search_open, &sealed_message)?;
+            (stamp, Some(master_key))
+        }
+    };
+    accept_transaction(transactions, &identity, stamp, master_key.as_ref(), stage, sign_with)?;
+    Ok(())
+}
+
+/// Show a stamp transaction, confirm, and accept it. Returns the updated
+/// transactions if the stamp was accepted.
+fn accept_transaction(
+    transactions: Transactions,
+    identity: &Identity,
+    stamp: Transaction,
+    master_key: Option<&SecretKey>,
+    stage: bool,
+    sign_with: Option<&str>,
+) -> Result<Option<Transactions>> {
+    let id_str = id_str!(identity.id())?;
+    let hash_with = config::hash_algo(Some(&id_str));
     let stamp_text = stamp
         .serialize_text()
         .map_err(|e| anyhow!("Problem serializing stamp transaction: {:?}", e))?;
     println!("{}", stamp_text);
     println!("----------");
-    if !util::yesno_prompt("Do you wish to accept the above stamp? [Y/n]", "Y")? {
-        println!("Aborted.");
-        return Ok(());
+    match stamp.entry().body() {
+        TransactionBody::MakeStampV1 { stamp: entry } => match db::load_identity(entry.stamper())? {
+            Some(stamper_transactions) => {
+                let stamper = util::build_identity(&stamper_transactions)?;
+                id::print_counterparty(&stamper, "Stamp made by")?;
+            }
+            None => {
+                let yellow = dialoguer::console::Style::new().yellow();
+                util::print_wrapped(&format!(
+                    "{}\n",
+                    yellow.apply_to(format!(
+                        "The stamper ({}) is not in your local identities, so they can't be shown here. Consider importing their identity first.",
+                        IdentityID::short(&id_str!(entry.stamper())?)
+                    ))
+                ));
+            }
+        },
+        _ => Err(anyhow!("This transaction is not a stamp"))?,
+    }
+    println!("Stamp checks:");
+    let default = if print_stamp_checks(&check_stamp(&stamp)?) {
+        "Y"
+    } else {
+        let red = dialoguer::console::Style::new().red();
+        println!("{}", red.apply_to("This stamp failed verification. Accepting it is not recommended."));
+        "n"
+    };
+    let prompt = if default == "Y" {
+        "Do you wish to accept the above stamp? [Y/n]"
+    } else {
+        "Do you wish to accept the above stamp? [y/N]"
+    };
+    if !util::yesno_prompt(prompt, default)? {
+        println!("Skipped.");
+        return Ok(None);
     }
     let trans = transactions
         .accept_stamp(&hash_with, Timestamp::now(), stamp)
         .map_err(|e| anyhow!("Problem creating acceptance transaction: {:?}", e))?;
-    let master_key = util::passphrase_prompt(
-        &format!("Your current master passphrase for identity {}", IdentityID::short(&id_str)),
-        identity.created(),
-    )?;
-    let signed = util::sign_helper(&identity, trans, &master_key, stage, sign_with)?;
+    let prompted;
+    let master_key = match master_key {
+        Some(master_key) => master_key,
+        None => {
+            prompted = master_key_prompt(&transactions, identity)?;
+            &prompted
+        }
+    };
+    let signed = util::sign_helper(identity, trans, master_key, stage, sign_with)?;
+    let (transactions, _) = dag::save_or_stage(transactions, signed, stage)?;
+    Ok(Some(transactions))
+}
+
+/// The outcome of one of the checks run against a stamp.
+pub enum StampCheck {
+    Pass(String),
+    Warn(String),
+    Fail(String),
+}
+
+/// Rebuild an identity as it was at a point in time, leaving out `skip` (the
+/// transaction being checked).
+fn identity_at(transactions: &Transactions, at: &Timestamp, skip: &Transaction) -> Result<Identity> {
+    let mut historical = Transactions::new();
+    for trans in transactions.transactions() {
+        if trans.id() == skip.id() || trans.entry().created().local() > at.local() {
+            continue;
+        }
+        historical = historical
+            .push_transaction(trans.clone())
+            .map_err(|e| anyhow!("Problem rebuilding identity history: {:?}", e))?;
+    }
+    util::build_identity(&historical)
+}
+
+/// Make sure falling back to the keys a stamper held when a stamp was made is
+/// safe: none of the admin keys that signed it were revoked as compromised, and
+/// the stamp claims to predate every revocation that retired them. Returns why
+/// not, otherwise.
+fn check_retired_keys(transactions: &Transactions, stamp: &Transaction) -> Option<String> {
+    let created = stamp.entry().created();
+    let signers = stamp
+        .signatures()
+        .iter()
+        .map(|sig| match sig {
+            MultisigPolicySignature::Key { key, .. } => KeyID::from(key.key_id()),
+        })
+        .collect::<Vec<_>>();
+    transactions.transactions().iter().find_map(|trans| match trans.entry().body() {
+        TransactionBody::RevokeAdminKeyV1 { id, reason, .. } if signers.contains(&KeyID::from(id.clone())) => {
+            let key_id = KeyID::from(id.clone());
+            match reason {
+                KeyRevocationReason::Compromised => {
+                    Some(format!("it was signed with admin key {}, which was revoked as compromised", key_id))
+                }
+                _ if created.local() >= trans.entry().created().local() => Some(format!(
+                    "it is dated after admin key {} was revoked on {}",
+                    key_id,
+                    trans.entry().created().local().format("%b %d, %Y")
+                )),
+                _ => None,
+            }
+        }
+        _ => None,
+    })
+}
+
+/// Check a stamp transaction against the stamper's identity: the signature
+/// (against the stamper's current keys, falling back to the keys they had when
+/// the stamp was made if those were retired rather than compromised),
+/// revocation, expiration, and whether the stamped claim exists.
+pub fn check_stamp(stamp: &Transaction) -> Result<Vec<StampCheck>> {
+    let entry = match stamp.entry().body() {
+        TransactionBody::MakeStampV1 { stamp } => stamp,
+        _ => Err(anyhow!("This transaction is not a stamp"))?,
+    };
+    let now = Timestamp::now();
+    let mut checks = Vec::new();
+    let stamper_str = id_str!(entry.stamper())?;
+    let stamper_transactions = match db::load_identity(entry.stamper())? {
+        Some(x) => x,
+        None => {
+            checks.push(StampCheck::Fail(format!(
+                "The stamper ({}) is not imported, so the signature can't be checked (see `stamp id import`)",
+                IdentityID::short(&stamper_str)
+            )));
+            return Ok(checks);
+        }
+    };
+    let stamper = util::build_identity(&stamper_transactions)?;
+    let created = stamp.entry().created();
+    if created.local() > now.local() {
+        checks.push(StampCheck::Warn(format!(
+            "Stamp is dated in the future ({})",
+            created.local().format("%b %d, %Y %H:%M:%S")
+        )));
+    }
+
+    // a stamp signed with keys that were later rotated is still valid, as long
+    // as the keys were good when it was made. the stamp's date is its own
+    // claim, so keys revoked as compromised don't get this benefit.
+    match stamp.verify(Some(&stamper)) {
+        Ok(_) => checks.push(StampCheck::Pass(String::from("Signed by the stamper's current keys"))),
+        Err(current_err) => match identity_at(&stamper_transactions, created, stamp)
+            .and_then(|historical| stamp.verify(Some(&historical)).map_err(|e| anyhow!("{}", e)))
+        {
+            Ok(_) => match check_retired_keys(&stamper_transactions, stamp) {
+                Some(why) => checks.push(StampCheck::Fail(format!("Signed by keys the stamper no longer trusts: {}", why))),
+                None => checks.push(StampCheck::Pass(format!(
+                    "Signed by keys the stamper held on {} (they have since been rotated)",
+                    created.local().format("%b %d, %Y")
+                ))),
+            },
+            Err(_) => checks.push(StampCheck::Fail(format!(
+                "Signature doesn't match the stamper's keys, now or when the stamp was made: {}",
+                current_err
+            ))),
+        },
+    }
+
+    match stamper.stamps().iter().find(|x| x.id() == stamp.id()) {
+        Some(made) => match made.revocation() {
+            Some(_) => checks.push(StampCheck::Fail(String::from("Revoked by the stamper"))),
+            None => checks.push(StampCheck::Pass(String::from("Not revoked"))),
+        },
+        None => checks.push(StampCheck::Warn(String::from(
+            "Not found in your copy of the stamper's identity, so revocation can't be checked (try `stamp net get`)",
+        ))),
+    }
+
+    match entry.expires().as_ref() {
+        Some(expires) if expires.local() <= now.local() => {
+            checks.push(StampCheck::Fail(format!("Expired on {}", expires.local().format("%b %d, %Y"))))
+        }
+        Some(expires) => checks.push(StampCheck::Pass(format!("Expires on {}", expires.local().format("%b %d, %Y")))),
+        None => checks.push(StampCheck::Pass(String::from("Does not expire"))),
+    }
+
+    let (_, stampee_short) = id_str_split!(entry.stampee());
+    match db::load_identity(entry.stampee())? {
+        Some(stampee_transactions) => {
+            let stampee = util::build_identity(&stampee_transactions)?;
+            if stampee.claims().iter().any(|c| c.id() == entry.claim_id()) {
+                checks.push(StampCheck::Pass(format!("Stamped claim exists on identity {}", stampee_short)));
+            } else {
+                checks.push(StampCheck::Warn(format!("Stamped claim was not found on identity {}", stampee_short)));
+            }
+        }
+        None => checks.push(StampCheck::Warn(format!("The stampee ({}) is not imported", stampee_short))),
+    }
+    Ok(checks)
+}
+
+/// Print the results of [check_stamp] and return whether the stamp passed.
+fn print_stamp_checks(checks: &[StampCheck]) -> bool {
+    let green = dialoguer::console::Style::new().green();
+    let yellow = dialoguer::console::Style::new().yellow();
+    let red = dialoguer::console::Style::new().red();
+    let mut valid = true;
+    for check in checks {
+        match check {
+            StampCheck::Pass(msg) => println!("  {} {}", green.apply_to("ok  "), msg),
+            StampCheck::Warn(msg) => println!("  {} {}", yellow.apply_to("warn"), msg),
+            StampCheck::Fail(msg) => {
+                valid = false;
+                println!("  {} {}", red.apply_to("FAIL"), msg);
+            }
+        }
+    }
+    valid
+}
+
+/// Verify a stamp, given either a file holding the stamp transaction or the ID
+/// of a stamp in one of our local identities.
+pub fn verify(stamp_search: &str) -> Result<()> {
+    let stamp = if stamp_search == "-" || std::path::Path::new(stamp_search).exists() {
+        let stamp_bytes = util::read_file(stamp_search)?;
+        util::deserialize_transaction(stamp_bytes.as_slice()).map_err(|e| anyhow!("Error deserializing stamp transaction: {}", e))?
+    } else {
+        db::list_local_identities(None)?
+            .iter()
+            .flat_map(|transactions| transactions.transactions().iter())
+            .find(|x| {
+                matches!(x.entry().body(), TransactionBody::MakeStampV1 { .. })
+                    && id_str!(x.id()).map(|id| id.starts_with(stamp_search)).unwrap_or(false)
+            })
+            .cloned()
+            .ok_or_else(|| anyhow!("Stamp {} not found as a file or in any local identity", stamp_search))?
+    };
+    let (stamp_full, _) = id_str_split!(stamp.id());
+    println!("Stamp {}", stamp_full);
+    let checks = check_stamp(&stamp)?;
+    if print_stamp_checks(&checks) {
+        let green = dialoguer::console::Style::new().green();
+        println!("\nThis stamp is {}.", green.apply_to("valid"));
+    } else {
+        Err(util::ExitError::new(sign::VERIFY_INVALID, "\nThis stamp is not valid."))?;
+    }
+    Ok(())
+}
+
+/// Make a fresh stamp on the same claim as one of our existing stamps, with a
+/// new expiration. Without an explicit expiration, the new stamp lasts as long
+/// as the old one did.
+pub fn renew(
+    id: &str,
+    stamp_search: &str,
+    expires: Option<Timestamp>,
+    confidence: Option<&str>,
+    stage: bool,
+    sign_with: Option<&str>,
+) -> Result<()> {
+    let hash_with = config::hash_algo(Some(&id));
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let stamp = identity
+        .stamps()
+        .iter()
+        .find(|x| id_str!(x.id()).map(|x| x.starts_with(stamp_search)).unwrap_or(false))
+        .ok_or_else(|| anyhow!("Stamp {} not found", stamp_search))?;
+    if stamp.revocation().is_some() {
+        Err(anyhow!("Stamp {} has been revoked", stamp_search))?;
+    }
+    let now = Timestamp::now();
+    let expires = match (expires, stamp.entry().expires().as_ref()) {
+        (Some(expires), _) => expires,
+        (None, Some(old_expires)) => {
+            let lifetime = old_expires.local().signed_duration_since(stamp.created().local());
+            util::parse_timestamp(&(now.local() + lifetime).to_rfc3339())?
+        }
+        (None, None) => Err(anyhow!(
+            "Stamp {} doesn't expire. Use --expires to set an expiration on the new stamp.",
+            stamp_search
+        ))?,
+    };
+    if expires.local() <= now.local() {
+        Err(anyhow!("The new expiration must be in the future"))?;
+    }
+    let confidence = match confidence {
+        Some(confidence) => parse_confidence(confidence)?,
+        None => stamp.entry().confidence().clone(),
+    };
+    let (_, stampee_short) = id_str_split!(stamp.entry().stampee());
+    let (_, claim_short) = id_str_split!(stamp.entry().claim_id());
+    println!(
+        "Renewing your stamp on claim {} of identity {} until {}",
+        claim_short,
+        stampee_short,
+        expires.local().format("%b %d, %Y")
+    );
+    let master_key =
+        util::passphrase_prompt(&format!("Your master passphrase for identity {}", IdentityID::short(&id_str)), identity.created())?;
+    transactions
+        .test_master_key(&master_key)
+        .map_err(|e| anyhow!("Incorrect passphrase: {}", e))?;
+    let stamp_entry = StampEntry::new(
+        identity.id().clone(),
+        stamp.entry().stampee().clone(),
+        stamp.entry().claim_id().clone(),
+        confidence,
+        Some(expires),
+    );
+    let transaction = transactions
+        .make_stamp(&hash_with, now, stamp_entry)
+        .map_err(|e| anyhow!("Error making stamp: {}", e))?;
+    let signed = util::sign_helper(&identity, transaction, &master_key, stage, sign_with)?;
     dag::save_or_stage(transactions, signed, stage)?;
     Ok(())
 }
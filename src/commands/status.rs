@@ -0,0 +1,153 @@
+use crate::{config, db, util};
+use anyhow::Result;
+use prettytable::Table;
+use serde_derive::{Deserialize, Serialize};
+use stamp_core::{identity::IdentityID, util::Timestamp};
+use std::convert::TryFrom;
+use tracing::warn;
+
+/// Tracks when an identity's secrets were last backed up, and when its master
+/// passphrase last changed (which invalidates older keyfiles).
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupStatus {
+    pub keyfile: Option<Timestamp>,
+    /// The last full backup of the identity, private data included
+    /// (`id export-private` or `dag export-all`).
+    #[serde(alias = "export")]
+    pub backup: Option<Timestamp>,
+    pub passwd: Option<Timestamp>,
+}
+
+pub enum BackupEvent {
+    Keyfile,
+    Backup,
+    Passwd,
+}
+
+pub fn load(id_str: &str) -> Result<BackupStatus> {
+    Ok(db::load_record("backup", id_str)?.unwrap_or_default())
+}
+
+/// Record that a backup (or passphrase change) happened just now.
+pub fn record(id_str: &str, event: BackupEvent) -> Result<()> {
+    let mut status = load(id_str)?;
+    let now = Some(Timestamp::now());
+    match event {
+        BackupEvent::Keyfile => status.keyfile = now,
+        BackupEvent::Backup => status.backup = now,
+        BackupEvent::Passwd => status.passwd = now,
+    }
+    db::save_record("backup", id_str, &status)
+}
+
+fn days_since(ts: &Timestamp) -> i64 {
+    (Timestamp::now().local().timestamp() - ts.local().timestamp()) / 86400
+}
+
+/// Returns a list of problems with an identity's backups, given the number of
+/// days after which a backup is considered stale.
+pub fn problems(status: &BackupStatus, stale_days: Option<u64>) -> Vec<String> {
+    let mut problems = Vec::new();
+    match (status.keyfile.as_ref(), status.passwd.as_ref()) {
+        (None, _) => problems.push(String::from("No keyfile backup has been made (see `stamp keychain keyfile`)")),
+        (Some(keyfile), Some(passwd)) if keyfile.local() < passwd.local() => problems.push(String::from(
+            "Your keyfile predates your last master passphrase change and will no longer work. Create a new one with `stamp keychain keyfile`",
+        )),
+        (Some(keyfile), _) => {
+            if let Some(stale_days) = stale_days {
+                let days = days_since(keyfile);
+                if days > stale_days as i64 {
+                    problems.push(format!("Your last keyfile backup was {} days ago", days));
+                }
+            }
+        }
+    }
+    match status.backup.as_ref() {
+        None => problems.push(String::from(
+            "No full backup of the identity has been made (see `stamp dag export-all` or `stamp id export-private`)",
+        )),
+        Some(backup) => {
+            if let Some(stale_days) = stale_days {
+                let days = days_since(backup);
+                if days > stale_days as i64 {
+                    problems.push(format!("Your last full backup was {} days ago", days));
+                }
+            }
+        }
+    }
+    problems
+}
+
+fn format_ts(ts: &Option<Timestamp>) -> String {
+    ts.as_ref()
+        .map(|x| format!("{} ({} days ago)", x.local().format("%b %d, %Y"), days_since(x)))
+        .unwrap_or_else(|| String::from("never"))
+}
+
+/// Print the backup status of all owned identities.
+pub fn status() -> Result<()> {
+    let conf = config::load_cli()?;
+    let yellow = dialoguer::console::Style::new().yellow();
+    let green = dialoguer::console::Style::new().green();
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(row!["ID (short)", "Name", "Last keyfile", "Last backup", "Passphrase changed"]);
+    let mut all_problems = Vec::new();
+    for transactions in db::list_local_identities(None)? {
+        let identity = util::build_identity(&transactions)?;
+        if !identity.is_owned() {
+            continue;
+        }
+        let id_str = id_str!(identity.id())?;
+        let status = load(&id_str)?;
+        let name = identity.names().get(0).cloned().unwrap_or_else(|| String::from(""));
+        table.add_row(row![
+            IdentityID::short(&id_str),
+            name,
+            format_ts(&status.keyfile),
+            format_ts(&status.backup),
+            format_ts(&status.passwd),
+        ]);
+        for problem in problems(&status, conf.backup_reminder_days) {
+            all_problems.push(format!("{}: {}", IdentityID::short(&id_str), problem));
+        }
+    }
+    table.printstd();
+    println!("");
+    if all_problems.len() == 0 {
+        println!("{}", green.apply_to("Backups look good."));
+    } else {
+        for problem in all_problems {
+            println!("{} {}", yellow.apply_to("Warning:"), util::text_wrap(&problem));
+        }
+    }
+    Ok(())
+}
+
+/// If backup reminders are enabled, warn about stale backups of the default
+/// identity. This runs after every command, so it's best-effort: a problem
+/// reading the backup record is logged instead of failing the command.
+pub fn nag(default_identity: Option<&str>) {
+    if let Err(e) = try_nag(default_identity) {
+        warn!("Problem checking backup reminders: {}", e);
+    }
+}
+
+fn try_nag(default_identity: Option<&str>) -> Result<()> {
+    let conf = config::load_cli()?;
+    let (stale_days, id_str) = match (conf.backup_reminder_days, default_identity) {
+        (Some(days), Some(id)) if days > 0 => (days, id),
+        _ => return Ok(()),
+    };
+    let status = load(id_str)?;
+    let problems = problems(&status, Some(stale_days));
+    if problems.len() > 0 {
+        let yellow = dialoguer::console::Style::new().yellow();
+        eprintln!("");
+        for problem in problems {
+            eprintln!("{} {}", yellow.apply_to("Backup reminder:"), util::text_wrap(&problem));
+        }
+    }
+    Ok(())
+}
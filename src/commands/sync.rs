@@ -0,0 +1,306 @@
+use crate::{
+    commands::{dag, id},
+    config, db, util,
+};
+use anyhow::{anyhow, Result};
+use prettytable::Table;
+use serde_derive::{Deserialize, Serialize};
+use stamp_core::{
+    dag::Transactions,
+    identity::{keychain::RevocationReason, IdentityID},
+    util::{Public, Timestamp},
+};
+use std::convert::TryFrom;
+
+/// The name of the `secret` subkey that private syncing derives its channel
+/// and shared key from.
+pub const SYNC_KEY_NAME: &str = "stamp/sync";
+
+// TODO: the agent is what actually moves transactions between devices (see
+// `stamp_aux::sync::listen`), and it's disabled for now. Once it's back, it should
+// register devices here when they join the channel and refuse ones that are
+// revoked.
+//
+// TODO: one-shot `stamp sync pull` / `stamp sync push` that connect to the
+// configured relay, exchange pending transactions (and `SyncRecords`), then exit,
+// for people who'd rather run a cron job than the agent. This needs a one-shot
+// entry point next to `stamp_aux::sync::listen`, and that module is disabled along
+// with the agent. Received transactions should go through `merge_transactions`.
+//
+// TODO: per-device filters (secret keys, private claims, all private data) that
+// withhold private data from a device. Whatever sends transactions to a device
+// would strip the matching data (`Transaction::strip_private`) before it goes
+// out. Don't store filters until that sender exists, or they'd promise
+// protection nobody enforces.
+
+/// A device we've handed a sync token to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Device {
+    pub identity_id: String,
+    pub name: String,
+    /// Blind devices only store and relay encrypted transactions.
+    pub blind: bool,
+    pub added: Timestamp,
+    pub revoked: Option<Timestamp>,
+}
+
+// TODO: the agent's sync listener should send local records besides identity
+// transactions (config, followed identities, staged transactions) to trusted
+// devices after syncing transactions (encrypted with the shared key, so blind
+// devices can't read them), and apply what it receives from them. Each device
+// should be able to opt out of record types, but only once something reads
+// that setting. Staged transactions should carry their non-local comments
+// (`stage::comments`) and go through `stage::notify_if_ready` when they arrive.
+
+fn device_key(identity_id: &str, name: &str) -> String {
+    format!("{}-{}", IdentityID::short(identity_id), name)
+}
+
+pub(crate) fn devices_for(identity_id: &str) -> Result<Vec<Device>> {
+    Ok(db::list_records::<Device>("sync-device")?
+        .into_iter()
+        .map(|(_, device)| device)
+        .filter(|device| device.identity_id == identity_id)
+        .collect())
+}
+
+fn load_identity_id(id: &str) -> Result<String> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    id_str!(identity.id())
+}
+
+pub fn devices_add(id: &str, name: &str, blind: bool) -> Result<()> {
+    let identity_id = load_identity_id(id)?;
+    if name.len() == 0 || name.contains(|c: char| c.is_control() || "/\\.<>:\"|?*".contains(c)) {
+        Err(anyhow!("Device names cannot be empty or contain any of: / \\ . < > : \" | ? *"))?;
+    }
+    let key = device_key(&identity_id, name);
+    if let Some(existing) = db::load_record::<Device>("sync-device", &key)? {
+        if existing.revoked.is_none() {
+            Err(anyhow!("A device named {} already exists", name))?;
+        }
+    }
+    let device = Device {
+        identity_id: identity_id.clone(),
+        name: name.into(),
+        blind,
+        added: Timestamp::now(),
+        revoked: None,
+    };
+    db::save_record("sync-device", &key, &device)?;
+    let green = dialoguer::console::Style::new().green();
+    println!("{} {}", green.apply_to("Added device"), name);
+    let yellow = dialoguer::console::Style::new().yellow();
+    println!(
+        "{} {}",
+        yellow.apply_to("Note:"),
+        util::text_wrap(
+            "Sync tokens can't be created yet (they're disabled along with the agent), so this device can't sync until they return."
+        )
+    );
+    Ok(())
+}
+
+pub fn devices_list(id: &str, show_revoked: bool) -> Result<()> {
+    let identity_id = load_identity_id(id)?;
+    let devices = devices_for(&identity_id)?
+        .into_iter()
+        .filter(|device| show_revoked || device.revoked.is_none())
+        .collect::<Vec<_>>();
+    if devices.len() == 0 {
+        println!(
+            "No devices registered for {}. Add one with `stamp sync devices add`.",
+            IdentityID::short(&identity_id)
+        );
+        return Ok(());
+    }
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(row!["Name", "Type", "Added", "Revoked"]);
+    for device in devices {
+        table.add_row(row![
+            device.name,
+            if device.blind { "blind" } else { "trusted" },
+            device.added.local().format("%b %d, %Y"),
+            device
+                .revoked
+                .as_ref()
+                .map(|x| x.local().format("%b %d, %Y").to_string())
+                .unwrap_or_else(|| String::from("-")),
+        ]);
+    }
+    table.printstd();
+    Ok(())
+}
+
+/// Show whether this device holds the sync channel key and which other devices
+/// are in the channel.
+pub fn status(id: &str) -> Result<()> {
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let green = dialoguer::console::Style::new().green();
+    let yellow = dialoguer::console::Style::new().yellow();
+
+    let channel = match identity.keychain().subkey_by_name(SYNC_KEY_NAME) {
+        Some(subkey) if subkey.revocation().is_some() => format!("{}", yellow.apply_to("revoked")),
+        Some(subkey) if subkey.key().has_private() => format!("{}", green.apply_to("trusted (shared key held locally)")),
+        Some(_) => format!("{}", yellow.apply_to("blind (shared key not held locally)")),
+        None => String::from("not set up"),
+    };
+    println!("Identity: {}", IdentityID::short(&id_str));
+    println!("Channel:  {}", channel);
+    println!("Transactions: {}", transactions.transactions().len());
+    println!("");
+
+    let devices = devices_for(&id_str)?
+        .into_iter()
+        .filter(|x| x.revoked.is_none())
+        .collect::<Vec<_>>();
+    if devices.len() == 0 {
+        println!("No devices registered. Add one with `stamp sync devices add`.");
+        return Ok(());
+    }
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(row!["Device", "Type", "Added"]);
+    for device in devices {
+        table.add_row(row![
+            device.name,
+            if device.blind { "blind" } else { "trusted" },
+            device.added.local().format("%b %d, %Y"),
+        ]);
+    }
+    table.printstd();
+    Ok(())
+}
+
+/// Kick a device out of the sync channel. Since anyone with a token can keep
+/// using the channel, this revokes the sync key. The key isn't replaced: sync
+/// tokens are disabled along with the agent, so there's no way to make a new
+/// channel for the remaining devices yet.
+pub fn devices_revoke(id: &str, name: &str, stage: bool, sign_with: Option<&str>) -> Result<()> {
+    let hash_with = config::hash_algo(Some(&id));
+    let transactions = id::try_load_single_identity(id)?;
+    let identity = util::build_identity(&transactions)?;
+    let id_str = id_str!(identity.id())?;
+    let key = device_key(&id_str, name);
+    let mut device = db::load_record::<Device>("sync-device", &key)?
+        .filter(|x| x.revoked.is_none())
+        .ok_or(anyhow!("Device {} not found", name))?;
+
+    let sync_key = identity
+        .keychain()
+        .subkey_by_name(SYNC_KEY_NAME)
+        .filter(|subkey| subkey.revocation().is_none());
+    if let Some(subkey) = sync_key {
+        let master_key = util::passphrase_prompt(
+            &format!("Your current master passphrase for identity {}", IdentityID::short(&id_str)),
+            identity.created(),
+        )?;
+        transactions
+            .test_master_key(&master_key)
+            .map_err(|e| anyhow!("Incorrect passphrase: {:?}", e))?;
+        let transaction = transactions
+            .revoke_subkey(&hash_with, Timestamp::now(), subkey.key_id(), RevocationReason::Superseded, None::<String>)
+            .map_err(|e| anyhow!("Error revoking sync key: {:?}", e))?;
+        let signed = util::sign_helper(&identity, transaction, &master_key, stage, sign_with)?;
+        dag::save_or_stage(transactions, signed, stage)?;
+    }
+    device.revoked = Some(Timestamp::now());
+    db::save_record("sync-device", &key, &device)?;
+
+    let yellow = dialoguer::console::Style::new().yellow();
+    match (sync_key.is_some(), stage) {
+        (true, false) => println!("Revoked device {}. The sync key has been revoked.", name),
+        (true, true) => println!("Revoked device {}. The sync key revocation is staged and takes effect once it's applied.", name),
+        (false, _) => println!("Revoked device {}. There was no active sync key to revoke.", name),
+    }
+    let remaining = devices_for(&id_str)?
+        .into_iter()
+        .filter(|x| x.revoked.is_none())
+        .map(|x| x.name)
+        .collect::<Vec<_>>();
+    if remaining.len() > 0 {
+        println!(
+            "{} {}",
+            yellow.apply_to("Note:"),
+            util::text_wrap(&format!(
+                "Your remaining devices ({}) used the revoked sync key too. Sync tokens can't be created yet, so there's no way to give them a new one until the agent returns.",
+                remaining.join(", ")
+            ))
+        );
+    }
+    Ok(())
+}
+
+/// Merge another copy of an identity into ours. Transactions we don't have are
+/// applied in a deterministic order (oldest first, ties broken by ID), retrying
+/// ones whose parents haven't been applied yet, so concurrent branches made on
+/// different devices end up as one DAG no matter which side does the merge.
+///
+/// Returns the merged transactions and the number of transactions added.
+pub(crate) fn merge_transactions(ours: Transactions, theirs: &Transactions) -> Result<(Transactions, usize)> {
+    if ours.identity_id() != theirs.identity_id() {
+        Err(anyhow!("Cannot merge two different identities"))?;
+    }
+    let existing = ours.transactions().iter().map(|t| id_str!(t.id())).collect::<Result<Vec<_>>>()?;
+    let mut pending = theirs
+        .transactions()
+        .iter()
+        .filter(|t| id_str!(t.id()).map(|id| !existing.contains(&id)).unwrap_or(false))
+        .map(|t| Ok((t.entry().created().local().timestamp_millis(), id_str!(t.id())?, t.clone())))
+        .collect::<Result<Vec<_>>>()?;
+    pending.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+
+    let mut merged = ours;
+    let mut added = 0;
+    loop {
+        let mut progress = false;
+        let mut retry = Vec::new();
+        for (created, id, transaction) in pending {
+            match merged.clone().push_transaction(transaction.clone()) {
+                Ok(next) => {
+                    merged = next;
+                    added += 1;
+                    progress = true;
+                }
+                Err(_) => retry.push((created, id, transaction)),
+            }
+        }
+        pending = retry;
+        if pending.len() == 0 || !progress {
+            break;
+        }
+    }
+    if pending.len() > 0 {
+        let ids = pending.iter().map(|(_, id, _)| id.clone()).collect::<Vec<_>>();
+        Err(anyhow!("Could not merge {} transaction(s), they may be invalid: {}", ids.len(), ids.join(", ")))?;
+    }
+    // make sure the result is actually a valid identity before anyone saves it
+    util::build_identity(&merged)?;
+    Ok((merged, added))
+}
+
+/// Manually merge a copy of an identity (from another device, a backup, etc)
+/// into the local one.
+pub fn merge(location: &str) -> Result<()> {
+    let contents = util::read_file(location)?;
+    let (theirs, _) = stamp_aux::id::import_pre(contents.as_slice()).map_err(|e| anyhow!("Error reading identity: {}", e))?;
+    let identity = util::build_identity(&theirs)?;
+    let id_str = id_str!(identity.id())?;
+    let ours = db::load_identity(identity.id())?.ok_or(anyhow!(
+        "Identity {} doesn't exist locally. Use `stamp id import` instead.",
+        IdentityID::short(&id_str)
+    ))?;
+    let (merged, added) = merge_transactions(ours, &theirs)?;
+    if added == 0 {
+        println!("Nothing to merge, identity {} is up to date.", IdentityID::short(&id_str));
+        return Ok(());
+    }
+    db::save_identity(merged)?;
+    let green = dialoguer::console::Style::new().green();
+    println!("{} {} transaction(s) into identity {}", green.apply_to("Merged"), added, IdentityID::short(&id_str));
+    Ok(())
+}
@@ -0,0 +1,625 @@
+use crate::{commands::claim, config::TrustConfig, db, util};
+use anyhow::{anyhow, Result};
+use stamp_core::{
+    identity::{
+        stamp::{Confidence, Stamp},
+        Identity, IdentityID,
+    },
+    util::Timestamp,
+};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
+
+/// A stamp one identity made on another's claim.
+#[derive(Debug, Clone)]
+pub struct StampEdge {
+    pub stamp_id: String,
+    pub stamper: String,
+    pub stampee: String,
+    pub claim_id: String,
+    pub confidence: Confidence,
+    pub expires: Option<Timestamp>,
+    pub revoked: bool,
+    /// The type of the stamped claim, if the stampee is stored locally.
+    pub claim_type: Option<&'static str>,
+}
+
+impl StampEdge {
+    fn from_stamp(stamp: &Stamp) -> Result<Self> {
+        Ok(Self {
+            stamp_id: id_str!(stamp.id())?,
+            stamper: id_str!(stamp.entry().stamper())?,
+            stampee: id_str!(stamp.entry().stampee())?,
+            claim_id: id_str!(stamp.entry().claim_id())?,
+            confidence: stamp.entry().confidence().clone(),
+            expires: stamp.entry().expires().clone(),
+            revoked: stamp.revocation().is_some(),
+            claim_type: None,
+        })
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires.as_ref().map(|x| x < &Timestamp::now()).unwrap_or(false)
+    }
+}
+
+/// Rank confidence values so they can be compared. Negative stamps rank lowest.
+pub fn confidence_rank(confidence: &Confidence) -> u8 {
+    match confidence {
+        Confidence::Negative => 0,
+        Confidence::Low => 1,
+        Confidence::Medium => 2,
+        Confidence::High => 3,
+        Confidence::Ultimate => 4,
+    }
+}
+
+pub fn confidence_to_str(confidence: &Confidence) -> &'static str {
+    match confidence {
+        Confidence::Negative => "negative",
+        Confidence::Low => "low",
+        Confidence::Medium => "medium",
+        Confidence::High => "high",
+        Confidence::Ultimate => "ultimate",
+    }
+}
+
+pub fn confidence_from_str(confidence: &str) -> Result<Confidence> {
+    match confidence {
+        "negative" => Ok(Confidence::Negative),
+        "low" => Ok(Confidence::Low),
+        "medium" => Ok(Confidence::Medium),
+        "high" => Ok(Confidence::High),
+        "ultimate" => Ok(Confidence::Ultimate),
+        _ => Err(anyhow!("Invalid confidence value: {}", confidence)),
+    }
+}
+
+/// The web of stamps between all locally-stored identities.
+pub struct StampGraph {
+    pub identities: HashMap<String, Identity>,
+    pub edges: Vec<StampEdge>,
+}
+
+impl StampGraph {
+    /// Build the stamp graph from every identity in the local db. Stamps are
+    /// pulled both from the stamper's identity and from the stampee's claims
+    /// (accepted stamps) so we see as much of the web as possible.
+    pub fn load() -> Result<Self> {
+        let mut identities = HashMap::new();
+        let mut edges = Vec::new();
+        let mut seen = HashSet::new();
+        for transactions in db::list_local_identities(None)? {
+            let identity = util::build_identity(&transactions)?;
+            let stamps = identity
+                .stamps()
+                .iter()
+                .chain(identity.claims().iter().flat_map(|c| c.stamps().iter()));
+            for stamp in stamps {
+                let edge = StampEdge::from_stamp(stamp)?;
+                if seen.insert(edge.stamp_id.clone()) {
+                    edges.push(edge);
+                }
+            }
+            identities.insert(id_str!(identity.id())?, identity);
+        }
+        for edge in edges.iter_mut() {
+            edge.claim_type = identities
+                .get(&edge.stampee)
+                .and_then(|i| i.claims().iter().find(|c| id_str!(c.id()).ok().as_ref() == Some(&edge.claim_id)))
+                .map(|c| claim::claim_type(c.spec()));
+        }
+        Ok(Self { identities, edges })
+    }
+
+    /// Find the full ID of a locally-stored identity by ID prefix.
+    pub fn resolve(&self, search: &str) -> Result<String> {
+        let found = self.identities.keys().filter(|id| id.starts_with(search)).collect::<Vec<_>>();
+        match found.len() {
+            0 => Err(anyhow!("No identities match the ID {}", search)),
+            1 => Ok(found[0].clone()),
+            _ => Err(anyhow!("Multiple identities matched ID {}", search)),
+        }
+    }
+
+    /// Edges that are currently usable for trust: not revoked, not expired, and
+    /// at least `min_confidence`.
+    pub fn active_edges<'a>(&'a self, min_confidence: &'a Confidence) -> impl Iterator<Item = &'a StampEdge> + 'a {
+        self.edges
+            .iter()
+            .filter(move |e| !e.revoked && !e.is_expired() && confidence_rank(&e.confidence) >= confidence_rank(min_confidence))
+    }
+
+    /// Returns the identities reachable from `root` by following stamps outward,
+    /// along with their distance from `root`.
+    pub fn reachable(&self, root: &str, max_depth: Option<usize>, min_confidence: &Confidence) -> HashMap<String, usize> {
+        let mut depths = HashMap::new();
+        let mut queue = VecDeque::new();
+        depths.insert(String::from(root), 0);
+        queue.push_back(String::from(root));
+        while let Some(current) = queue.pop_front() {
+            let depth = depths[&current];
+            if max_depth.map(|max| depth >= max).unwrap_or(false) {
+                continue;
+            }
+            for edge in self.active_edges(min_confidence).filter(|e| e.stamper == current) {
+                if !depths.contains_key(&edge.stampee) {
+                    depths.insert(edge.stampee.clone(), depth + 1);
+                    queue.push_back(edge.stampee.clone());
+                }
+            }
+        }
+        depths
+    }
+
+    /// The stamps usable for trust as a map of stamper -> stampee -> stamp. When
+    /// an identity stamped several of another's claims, only the most confident
+    /// of those stamps is kept.
+    fn adjacency(&self, min_confidence: &Confidence) -> HashMap<&str, HashMap<&str, &StampEdge>> {
+        let mut adjacency: HashMap<&str, HashMap<&str, &StampEdge>> = HashMap::new();
+        for edge in self.active_edges(min_confidence) {
+            let best = adjacency
+                .entry(edge.stamper.as_str())
+                .or_default()
+                .entry(edge.stampee.as_str())
+                .or_insert(edge);
+            if confidence_rank(&edge.confidence) > confidence_rank(&best.confidence) {
+                *best = edge;
+            }
+        }
+        adjacency
+    }
+
+    /// Find chains of active stamps leading from `from` to `to`, up to
+    /// `max_depth` stamps long. Paths are returned best first: fewest hops, then
+    /// the strongest weakest link. Lengths are searched shortest first and the
+    /// search stops once at least `limit` paths are found, so the last length
+    /// searched may add more than `limit`.
+    pub fn paths(&self, from: &str, to: &str, max_depth: usize, min_confidence: &Confidence, limit: usize) -> Vec<Vec<&StampEdge>> {
+        let adjacency = self.adjacency(min_confidence);
+        // how many stamps each identity is from `to`, found by walking the
+        // stamps backwards. this is what keeps the search below from wandering
+        // into parts of the graph that can't reach `to` in time.
+        let mut distances: HashMap<&str, usize> = HashMap::new();
+        let mut queue = VecDeque::new();
+        distances.insert(to, 0);
+        queue.push_back(to);
+        while let Some(current) = queue.pop_front() {
+            let depth = distances[current];
+            if depth >= max_depth {
+                continue;
+            }
+            for (stamper, stampees) in adjacency.iter() {
+                if stampees.contains_key(current) && !distances.contains_key(stamper) {
+                    distances.insert(*stamper, depth + 1);
+                    queue.push_back(*stamper);
+                }
+            }
+        }
+        fn walk<'a>(
+            adjacency: &HashMap<&str, HashMap<&str, &'a StampEdge>>,
+            distances: &HashMap<&str, usize>,
+            current: &str,
+            to: &str,
+            length: usize,
+            path: &mut Vec<&'a StampEdge>,
+            found: &mut Vec<Vec<&'a StampEdge>>,
+        ) {
+            if path.len() == length {
+                if current == to {
+                    found.push(path.clone());
+                }
+                return;
+            }
+            if current == to {
+                return;
+            }
+            let remaining = length - path.len() - 1;
+            for (next, edge) in adjacency.get(current).into_iter().flatten() {
+                if distances.get(next).map(|d| *d > remaining).unwrap_or(true) {
+                    continue;
+                }
+                // no cycles
+                if *next == path.get(0).map(|e| e.stamper.as_str()).unwrap_or(current) || path.iter().any(|e| e.stampee == *next) {
+                    continue;
+                }
+                path.push(*edge);
+                walk(adjacency, distances, next, to, length, path, found);
+                path.pop();
+            }
+        }
+        let mut found = Vec::new();
+        let shortest = match distances.get(from) {
+            Some(shortest) if from != to => *shortest,
+            _ => return found,
+        };
+        let weakest = |path: &Vec<&StampEdge>| path.iter().map(|e| confidence_rank(&e.confidence)).min().unwrap_or(0);
+        for length in shortest..=max_depth {
+            let mut level = Vec::new();
+            walk(&adjacency, &distances, from, to, length, &mut Vec::new(), &mut level);
+            level.sort_by(|a, b| weakest(b).cmp(&weakest(a)));
+            found.extend(level);
+            if found.len() >= limit {
+                break;
+            }
+        }
+        found
+    }
+
+    pub fn name_of(&self, id: &str) -> Option<String> {
+        self.identities.get(id).and_then(|i| i.names().get(0).cloned())
+    }
+}
+
+fn identity_label(graph: &StampGraph, id: &str) -> String {
+    match graph.name_of(id) {
+        Some(name) => format!("{} ({})", name, IdentityID::short(id)),
+        None => IdentityID::short(id),
+    }
+}
+
+/// Print the best chains of stamps connecting two identities.
+pub fn path(from: &str, to: &str, max_depth: usize, min_confidence: &str, limit: usize) -> Result<()> {
+    let graph = StampGraph::load()?;
+    let min_confidence = confidence_from_str(min_confidence)?;
+    let from = graph.resolve(from)?;
+    let to = graph.resolve(to)?;
+    let paths = graph.paths(&from, &to, max_depth, &min_confidence, limit + 1);
+    if paths.len() == 0 {
+        println!(
+            "No chain of stamps (at most {} long, {} confidence or better) connects {} to {}.",
+            max_depth,
+            confidence_to_str(&min_confidence),
+            identity_label(&graph, &from),
+            identity_label(&graph, &to)
+        );
+        return Ok(());
+    }
+    println!("Best path(s) from {} to {}", identity_label(&graph, &from), identity_label(&graph, &to));
+    for (i, path) in paths.iter().take(limit).enumerate() {
+        let weakest = path
+            .iter()
+            .min_by_key(|e| confidence_rank(&e.confidence))
+            .map(|e| confidence_to_str(&e.confidence))
+            .unwrap_or("-");
+        println!("\nPath {} ({} stamp(s), weakest link: {})", i + 1, path.len(), weakest);
+        println!("  {}", identity_label(&graph, &from));
+        for edge in path {
+            println!(
+                "    stamped ({}, stamp {}, claim {})",
+                confidence_to_str(&edge.confidence),
+                IdentityID::short(&edge.stamp_id),
+                IdentityID::short(&edge.claim_id)
+            );
+            println!("  {}", identity_label(&graph, &edge.stampee));
+        }
+    }
+    if paths.len() > limit {
+        println!("\nMore paths exist (see --limit)");
+    }
+    Ok(())
+}
+
+/// How the stamps along a path are combined into a score.
+pub enum ScoreAlgorithm {
+    /// Multiply the weights of each stamp on the path.
+    Decay,
+    /// Use the weight of the least confident stamp on the path.
+    WeakestLink,
+}
+
+impl std::str::FromStr for ScoreAlgorithm {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "decay" => Ok(Self::Decay),
+            "weakest-link" => Ok(Self::WeakestLink),
+            _ => Err(anyhow!("Unknown trust algorithm: {} (expected decay or weakest-link)", s)),
+        }
+    }
+}
+
+fn confidence_weight(config: &TrustConfig, confidence: &Confidence) -> f64 {
+    match confidence {
+        Confidence::Negative => 0.0,
+        Confidence::Low => config.weight_low,
+        Confidence::Medium => config.weight_medium,
+        Confidence::High => config.weight_high,
+        Confidence::Ultimate => config.weight_ultimate,
+    }
+}
+
+/// Score a single path of stamps.
+fn path_score(config: &TrustConfig, algorithm: &ScoreAlgorithm, path: &[&StampEdge]) -> f64 {
+    let weights = path.iter().map(|e| confidence_weight(config, &e.confidence));
+    let combined = match algorithm {
+        ScoreAlgorithm::Decay => weights.product(),
+        ScoreAlgorithm::WeakestLink => weights.fold(1.0, f64::min),
+    };
+    combined * config.depth_decay.powi(path.len().saturating_sub(1) as i32)
+}
+
+/// The trust score of one identity relative to another, and how it was reached.
+pub struct TrustScore<'a> {
+    pub score: f64,
+    pub best_path: Option<Vec<&'a StampEdge>>,
+    /// Active negative stamps on the target, with the stamper's own score.
+    pub negatives: Vec<(&'a StampEdge, f64)>,
+}
+
+impl StampGraph {
+    /// The best score any path from `root` gives each identity it reaches, along
+    /// with that path. This goes out one stamp at a time, keeping only the best
+    /// path into each identity at each length, so it's one pass over the stamps
+    /// per hop rather than a walk of every path.
+    fn best_paths(&self, config: &TrustConfig, algorithm: &ScoreAlgorithm, root: &str) -> HashMap<String, (f64, Vec<&StampEdge>)> {
+        let adjacency = self.adjacency(&Confidence::Low);
+        let combine = |acc: f64, edge: &StampEdge| {
+            let weight = confidence_weight(config, &edge.confidence);
+            match algorithm {
+                ScoreAlgorithm::Decay => acc * weight,
+                ScoreAlgorithm::WeakestLink => acc.min(weight),
+            }
+        };
+        let mut best: HashMap<String, (f64, Vec<&StampEdge>)> = HashMap::new();
+        // the best combined weight (before depth decay) into each identity using
+        // exactly as many stamps as we've gone out so far
+        let mut frontier: HashMap<String, (f64, Vec<&StampEdge>)> = HashMap::new();
+        frontier.insert(String::from(root), (1.0, Vec::new()));
+        for _ in 0..config.max_depth {
+            let mut next_frontier: HashMap<String, (f64, Vec<&StampEdge>)> = HashMap::new();
+            for (current, (acc, path)) in frontier.iter() {
+                for (next, edge) in adjacency.get(current.as_str()).into_iter().flatten() {
+                    // no cycles
+                    if *next == root || path.iter().any(|e| e.stampee == *next) {
+                        continue;
+                    }
+                    let value = combine(*acc, edge);
+                    if next_frontier.get(*next).map(|(v, _)| value > *v).unwrap_or(true) {
+                        let mut path = path.clone();
+                        path.push(*edge);
+                        next_frontier.insert(String::from(*next), (value, path));
+                    }
+                }
+            }
+            if next_frontier.len() == 0 {
+                break;
+            }
+            for (id, (_, path)) in next_frontier.iter() {
+                let score = path_score(config, algorithm, path);
+                if best.get(id).map(|(s, _)| score > *s).unwrap_or(true) {
+                    best.insert(id.clone(), (score, path.clone()));
+                }
+            }
+            frontier = next_frontier;
+        }
+        best
+    }
+
+    /// Compute how much `root` trusts `target`, following the settings in
+    /// `config`.
+    pub fn score(&self, config: &TrustConfig, root: &str, target: &str) -> Result<TrustScore<'_>> {
+        let algorithm: ScoreAlgorithm = config.algorithm.parse()?;
+        // the negative stampers are scored from `root` too, so work out every
+        // best path from `root` once and look them all up
+        let best = self.best_paths(config, &algorithm, root);
+        let best_path = |target: &str| {
+            if root == target {
+                return (1.0, Some(vec![]));
+            }
+            match best.get(target) {
+                Some((score, path)) if *score > 0.0 => (*score, Some(path.clone())),
+                _ => (0.0, None),
+            }
+        };
+        let (mut score, best_path_target) = best_path(target);
+        let mut negatives = Vec::new();
+        for edge in self
+            .active_edges(&Confidence::Negative)
+            .filter(|e| e.stampee == target && matches!(e.confidence, Confidence::Negative))
+        {
+            let (stamper_score, _) = best_path(&edge.stamper);
+            if stamper_score > 0.0 {
+                negatives.push((edge, stamper_score));
+            }
+        }
+        for (_, stamper_score) in &negatives {
+            if config.negative_fail {
+                score = 0.0;
+            } else {
+                score -= stamper_score * config.negative_penalty;
+            }
+        }
+        Ok(TrustScore {
+            score: score.max(0.0),
+            best_path: best_path_target,
+            negatives,
+        })
+    }
+}
+
+/// Print how much `root` trusts `target`.
+pub fn score(root: &str, target: &str, config: &TrustConfig) -> Result<()> {
+    let graph = StampGraph::load()?;
+    let root = graph.resolve(root)?;
+    let target = graph.resolve(target)?;
+    let result = graph.score(config, &root, &target)?;
+    println!("Trust in {}: {:.2}", identity_label(&graph, &target), result.score);
+    println!(
+        "  (algorithm: {}, depth decay: {}, max depth: {})",
+        config.algorithm, config.depth_decay, config.max_depth
+    );
+    match result.best_path.as_ref() {
+        Some(path) if path.len() == 0 => println!("\nThis is the identity being scored from."),
+        Some(path) => {
+            println!("\nBest path:");
+            println!("  {}", identity_label(&graph, &root));
+            for edge in path {
+                println!("    stamped ({})", confidence_to_str(&edge.confidence));
+                println!("  {}", identity_label(&graph, &edge.stampee));
+            }
+        }
+        None => println!("\nNo path of active stamps leads to this identity."),
+    }
+    if result.negatives.len() > 0 {
+        let red = dialoguer::console::Style::new().red();
+        println!("\n{}", red.apply_to("Negative stamps:"));
+        for (edge, stamper_score) in &result.negatives {
+            println!(
+                "  {} (trust {:.2}) stamped claim {} as false",
+                identity_label(&graph, &edge.stamper),
+                stamper_score,
+                IdentityID::short(&edge.claim_id)
+            );
+        }
+        if config.negative_fail {
+            println!("Negative stamps from trusted identities are a hard fail (see `negative_fail` in the [trust] config).");
+        }
+    }
+    Ok(())
+}
+
+pub enum GraphFormat {
+    Dot,
+    GraphML,
+    Json,
+}
+
+fn dot_escape(val: &str) -> String {
+    val.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn xml_escape(val: &str) -> String {
+    val.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Export the stamp graph, optionally limited to identities within `depth` stamps
+/// of `root`.
+pub fn graph(root: &str, depth: Option<usize>, min_confidence: &str, format: GraphFormat) -> Result<String> {
+    let graph = StampGraph::load()?;
+    let min_confidence = confidence_from_str(min_confidence)?;
+    let root = graph.resolve(root)?;
+    let included = graph.reachable(&root, depth, &min_confidence);
+    let edges = graph
+        .edges
+        .iter()
+        .filter(|e| confidence_rank(&e.confidence) >= confidence_rank(&min_confidence))
+        .filter(|e| included.contains_key(&e.stamper) && included.contains_key(&e.stampee))
+        .collect::<Vec<_>>();
+    let mut nodes = included.iter().collect::<Vec<_>>();
+    nodes.sort_by(|a, b| a.1.cmp(b.1).then(a.0.cmp(b.0)));
+    let out = match format {
+        GraphFormat::Dot => {
+            let mut out = vec![String::from("digraph stamp {")];
+            for (id, _) in &nodes {
+                let label = match graph.name_of(id) {
+                    Some(name) => format!("{}\\n{}", dot_escape(&name), IdentityID::short(id)),
+                    None => IdentityID::short(id),
+                };
+                let shape = if *id == &root { ", shape=doublecircle" } else { "" };
+                out.push(format!("    \"{}\" [label=\"{}\"{}];", id, label, shape));
+            }
+            for edge in &edges {
+                let mut label = String::from(confidence_to_str(&edge.confidence));
+                if let Some(claim_type) = edge.claim_type {
+                    label.push_str(&format!("\\n{}", claim_type));
+                }
+                if edge.revoked {
+                    label.push_str("\\nrevoked");
+                }
+                if let Some(expires) = edge.expires.as_ref() {
+                    label.push_str(&format!("\\nexpires {}", expires.local().format("%Y-%m-%d")));
+                }
+                let style = if edge.revoked || edge.is_expired() { ", style=dashed" } else { "" };
+                let color = if matches!(edge.confidence, Confidence::Negative) { ", color=red" } else { "" };
+                out.push(format!("    \"{}\" -> \"{}\" [label=\"{}\"{}{}];", edge.stamper, edge.stampee, label, style, color));
+            }
+            out.push(String::from("}"));
+            out.join("\n")
+        }
+        GraphFormat::GraphML => {
+            let mut out = vec![
+                String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#),
+                String::from(r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#),
+                String::from(r#"  <key id="name" for="node" attr.name="name" attr.type="string"/>"#),
+                String::from(r#"  <key id="depth" for="node" attr.name="depth" attr.type="int"/>"#),
+                String::from(r#"  <key id="root" for="node" attr.name="root" attr.type="boolean"/>"#),
+                String::from(r#"  <key id="stamp_id" for="edge" attr.name="stamp_id" attr.type="string"/>"#),
+                String::from(r#"  <key id="claim_id" for="edge" attr.name="claim_id" attr.type="string"/>"#),
+                String::from(r#"  <key id="claim_type" for="edge" attr.name="claim_type" attr.type="string"/>"#),
+                String::from(r#"  <key id="confidence" for="edge" attr.name="confidence" attr.type="string"/>"#),
+                String::from(r#"  <key id="expires" for="edge" attr.name="expires" attr.type="string"/>"#),
+                String::from(r#"  <key id="revoked" for="edge" attr.name="revoked" attr.type="boolean"/>"#),
+                String::from(r#"  <key id="expired" for="edge" attr.name="expired" attr.type="boolean"/>"#),
+                String::from(r#"  <graph id="stamp" edgedefault="directed">"#),
+            ];
+            for (id, depth) in &nodes {
+                out.push(format!(r#"    <node id="{}">"#, xml_escape(id)));
+                if let Some(name) = graph.name_of(id) {
+                    out.push(format!(r#"      <data key="name">{}</data>"#, xml_escape(&name)));
+                }
+                out.push(format!(r#"      <data key="depth">{}</data>"#, depth));
+                out.push(format!(r#"      <data key="root">{}</data>"#, *id == &root));
+                out.push(String::from("    </node>"));
+            }
+            for edge in &edges {
+                out.push(format!(
+                    r#"    <edge id="{}" source="{}" target="{}">"#,
+                    xml_escape(&edge.stamp_id),
+                    xml_escape(&edge.stamper),
+                    xml_escape(&edge.stampee)
+                ));
+                out.push(format!(r#"      <data key="stamp_id">{}</data>"#, xml_escape(&edge.stamp_id)));
+                out.push(format!(r#"      <data key="claim_id">{}</data>"#, xml_escape(&edge.claim_id)));
+                if let Some(claim_type) = edge.claim_type {
+                    out.push(format!(r#"      <data key="claim_type">{}</data>"#, claim_type));
+                }
+                out.push(format!(r#"      <data key="confidence">{}</data>"#, confidence_to_str(&edge.confidence)));
+                if let Some(expires) = edge.expires.as_ref() {
+                    out.push(format!(r#"      <data key="expires">{}</data>"#, expires.format("%+")));
+                }
+                out.push(format!(r#"      <data key="revoked">{}</data>"#, edge.revoked));
+                out.push(format!(r#"      <data key="expired">{}</data>"#, edge.is_expired()));
+                out.push(String::from("    </edge>"));
+            }
+            out.push(String::from("  </graph>"));
+            out.push(String::from("</graphml>"));
+            out.join("\n")
+        }
+        GraphFormat::Json => {
+            let nodes = nodes
+                .iter()
+                .map(|(id, depth)| {
+                    serde_json::json!({
+                        "id": id,
+                        "name": graph.name_of(id),
+                        "depth": depth,
+                    })
+                })
+                .collect::<Vec<_>>();
+            let edges = edges
+                .iter()
+                .map(|e| {
+                    serde_json::json!({
+                        "stamp_id": e.stamp_id,
+                        "from": e.stamper,
+                        "to": e.stampee,
+                        "claim_id": e.claim_id,
+                        "confidence": confidence_to_str(&e.confidence),
+                        "expires": e.expires.as_ref().map(|x| format!("{}", x.format("%+"))),
+                        "claim_type": e.claim_type,
+                        "revoked": e.revoked,
+                        "expired": e.is_expired(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            let json = serde_json::json!({ "nodes": nodes, "edges": edges });
+            serde_json::to_string_pretty(&json).map_err(|e| anyhow!("Problem serializing graph: {}", e))?
+        }
+    };
+    Ok(out)
+}
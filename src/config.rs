@@ -1,6 +1,8 @@
 use anyhow::{anyhow, Result};
+use serde_derive::{Deserialize, Serialize};
 pub use stamp_aux::config::Config;
 use stamp_core::crypto::base::HashAlgo;
+use std::path::PathBuf;
 
 pub fn load() -> Result<Config> {
     stamp_aux::config::load().map_err(|e| anyhow!("Problem loading config: {}", e))
@@ -13,3 +15,128 @@ pub fn save(config: &Config) -> Result<()> {
 pub fn hash_algo(_identity_id: Option<&str>) -> HashAlgo {
     HashAlgo::Blake3
 }
+
+// TODO: profiles (`--profile <name>` / `STAMP_PROFILE`) and a `STAMP_HOME` /
+// `--db-path` override. The identity database and the shared config are placed
+// by stamp-aux, so it needs a way to be pointed at another directory first.
+// Moving only the CLI's own data (below) would leave identities shared between
+// profiles, which is worse than no isolation at all.
+
+/// Returns the directory the CLI keeps its own local data in (things like
+/// followed identities, inboxes, etc), creating it if needed.
+pub fn data_dir() -> Result<PathBuf> {
+    let mut dir = dirs::data_dir().ok_or(anyhow!("Unable to determine local data directory"))?;
+    dir.push("stamp");
+    dir.push("cli");
+    std::fs::create_dir_all(&dir).map_err(|e| anyhow!("Problem creating data directory: {:?}: {}", dir, e))?;
+    Ok(dir)
+}
+
+/// Settings that only the CLI cares about. These are kept in their own file so
+/// the shared Stamp config stays untouched.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CliConfig {
+    /// The region (ex "US") used to normalize phone numbers that don't have a
+    /// country code.
+    pub phone_region: Option<String>,
+    /// Warn when an owned identity's keyfile backup is older than this many days.
+    pub backup_reminder_days: Option<u64>,
+    /// Never fall back to the public StampNet bootstrap servers when no join list
+    /// is configured.
+    pub no_default_bootstrap: bool,
+    /// Minimum number of minutes between exports of secret material for the same
+    /// identity.
+    pub secret_export_cooldown_mins: Option<u64>,
+    /// A DNS resolver (IP or IP:PORT) used for domain claim checks instead of the
+    /// system resolver.
+    pub dns_resolver: Option<String>,
+    /// A DNS-over-HTTPS provider used for domain claim checks. Takes precedence
+    /// over `dns_resolver`.
+    pub dns_doh: Option<String>,
+    /// Require DNSSEC validation when checking domain claims.
+    pub dns_dnssec: bool,
+    /// If set, master keys are cached in the OS keyring for this many minutes
+    /// after being unlocked.
+    pub keyring_ttl_mins: Option<u64>,
+    /// How `stamp trust score` computes trust. TOML can't have plain values
+    /// after a table, so this (and any other table) has to stay last.
+    pub trust: TrustConfig,
+}
+
+/// Settings for trust scoring, under `[trust]` in the CLI config.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrustConfig {
+    /// "decay" multiplies the confidence weights along a path, "weakest-link"
+    /// uses the lowest weight on the path. Either way the best path wins.
+    pub algorithm: String,
+    /// Each stamp past the first multiplies a path's score by this.
+    pub depth_decay: f64,
+    /// The longest path of stamps considered.
+    pub max_depth: usize,
+    pub weight_low: f64,
+    pub weight_medium: f64,
+    pub weight_high: f64,
+    pub weight_ultimate: f64,
+    /// A negative stamp from anyone we trust drops the score to zero.
+    pub negative_fail: bool,
+    /// If `negative_fail` is off, a negative stamp subtracts this much of the
+    /// stamper's own score instead.
+    pub negative_penalty: f64,
+}
+
+impl Default for TrustConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: String::from("decay"),
+            depth_decay: 0.5,
+            max_depth: 4,
+            weight_low: 0.25,
+            weight_medium: 0.5,
+            weight_high: 0.75,
+            weight_ultimate: 1.0,
+            negative_fail: true,
+            negative_penalty: 0.5,
+        }
+    }
+}
+
+fn cli_config_file() -> Result<PathBuf> {
+    let mut path = data_dir()?;
+    path.push("config.toml");
+    Ok(path)
+}
+
+pub fn load_cli() -> Result<CliConfig> {
+    let path = cli_config_file()?;
+    if !path.exists() {
+        return Ok(CliConfig::default());
+    }
+    let contents = std::fs::read_to_string(&path).map_err(|e| anyhow!("Problem loading CLI config: {:?}: {}", path, e))?;
+    toml::from_str(&contents).map_err(|e| anyhow!("Problem parsing CLI config: {:?}: {}", path, e))
+}
+
+pub fn save_cli(config: &CliConfig) -> Result<()> {
+    let path = cli_config_file()?;
+    let serialized = toml::to_string(config).map_err(|e| anyhow!("Problem serializing CLI config: {}", e))?;
+    std::fs::write(&path, serialized.as_bytes()).map_err(|e| anyhow!("Problem saving CLI config: {:?}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cli_config_roundtrip() {
+        let mut config = CliConfig::default();
+        config.keyring_ttl_mins = Some(15);
+        let serialized = toml::to_string(&config).unwrap();
+        let deserialized: CliConfig = toml::from_str(&serialized).unwrap();
+        assert_eq!(deserialized.keyring_ttl_mins, Some(15));
+        assert_eq!(deserialized.trust.max_depth, config.trust.max_depth);
+
+        let serialized = toml::to_string(&CliConfig::default()).unwrap();
+        toml::from_str::<CliConfig>(&serialized).unwrap();
+    }
+}
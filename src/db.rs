@@ -1,7 +1,16 @@
+use crate::config;
 use anyhow::{anyhow, Result};
+use serde::{de::DeserializeOwned, Serialize};
 use stamp_aux::db;
 use stamp_core::{dag::Transactions, identity::IdentityID};
+use std::path::PathBuf;
 
+// TODO: identities are already stored in SQLite, but the schema (and its
+// indexes) belong to `stamp_aux::db`. Lookups by ID prefix, name, and email
+// (`find_identity_by_prefix`, `list_local_identities`) should get indexed
+// columns there, along with claim and key ID tables, so `id list` and claim
+// lookups don't have to deserialize every identity. Once aux has them, these
+// wrappers don't need to change.
 pub fn ensure_schema() -> Result<()> {
     db::ensure_schema().map_err(|e| anyhow!("Error initializing database: {}", e))
 }
@@ -33,3 +42,158 @@ pub fn find_identity_by_prefix(ty: &str, id_prefix: &str) -> Result<Option<Trans
 pub fn delete_identity(id: &str) -> Result<()> {
     db::delete_identity(id).map_err(|e| anyhow!("Problem deleting identity: {}", e))
 }
+
+fn record_dir(kind: &str) -> Result<PathBuf> {
+    let mut dir = config::data_dir()?;
+    dir.push(kind);
+    std::fs::create_dir_all(&dir).map_err(|e| anyhow!("Problem creating record directory: {:?}: {}", dir, e))?;
+    Ok(dir)
+}
+
+/// Windows won't create files with these names, regardless of extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2", "LPT3", "LPT4",
+    "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+fn record_path(kind: &str, key: &str) -> Result<PathBuf> {
+    // keys become file names, so reject anything that isn't valid on every
+    // platform rather than just the one we happen to be running on
+    let invalid_char = |c: char| c.is_control() || "/\\.<>:\"|?*".contains(c);
+    if key.is_empty() || key.contains(invalid_char) || RESERVED_NAMES.contains(&key.to_uppercase().as_str()) {
+        Err(anyhow!("Invalid record key: {}", key))?;
+    }
+    let mut path = record_dir(kind)?;
+    path.push(format!("{}.toml", key));
+    Ok(path)
+}
+
+/// Save a local (CLI-only) record. Records are grouped by `kind` and keyed by
+/// `key`, which is generally some kind of ID.
+pub fn save_record<T: Serialize>(kind: &str, key: &str, record: &T) -> Result<()> {
+    let path = record_path(kind, key)?;
+    let serialized = toml::to_string(record).map_err(|e| anyhow!("Problem serializing {} record: {}", kind, e))?;
+    std::fs::write(&path, serialized.as_bytes()).map_err(|e| anyhow!("Problem saving {} record: {:?}: {}", kind, path, e))
+}
+
+/// Load a local record by kind/key.
+pub fn load_record<T: DeserializeOwned>(kind: &str, key: &str) -> Result<Option<T>> {
+    let path = record_path(kind, key)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = std::fs::read_to_string(&path).map_err(|e| anyhow!("Problem loading {} record: {:?}: {}", kind, path, e))?;
+    let record = toml::from_str(&contents).map_err(|e| anyhow!("Problem deserializing {} record: {:?}: {}", kind, path, e))?;
+    Ok(Some(record))
+}
+
+/// List all local records of a given kind, along with their keys.
+pub fn list_records<T: DeserializeOwned>(kind: &str) -> Result<Vec<(String, T)>> {
+    let mut records = Vec::new();
+    for key in list_record_keys(kind)? {
+        if let Some(record) = load_record(kind, &key)? {
+            records.push((key, record));
+        }
+    }
+    Ok(records)
+}
+
+/// List the keys of all local records of a given kind, without loading them.
+pub fn list_record_keys(kind: &str) -> Result<Vec<String>> {
+    let dir = record_dir(kind)?;
+    let mut keys = Vec::new();
+    let entries = std::fs::read_dir(&dir).map_err(|e| anyhow!("Problem listing {} records: {}", kind, e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| anyhow!("Problem listing {} records: {}", kind, e))?.path();
+        if path.extension().and_then(|x| x.to_str()) != Some("toml") {
+            continue;
+        }
+        match path.file_stem().and_then(|x| x.to_str()) {
+            Some(key) => keys.push(String::from(key)),
+            None => continue,
+        }
+    }
+    keys.sort();
+    Ok(keys)
+}
+
+/// Delete a local record. Deleting a record that doesn't exist is not an error.
+pub fn delete_record(kind: &str, key: &str) -> Result<()> {
+    let path = record_path(kind, key)?;
+    if path.exists() {
+        std::fs::remove_file(&path).map_err(|e| anyhow!("Problem deleting {} record: {:?}: {}", kind, path, e))?;
+    }
+    Ok(())
+}
+
+/// What a record points at, so `stamp db fsck` can tell when it's orphaned.
+pub enum RecordRef {
+    /// Keyed by the ID of a local identity.
+    IdentityKey,
+    /// Names a local identity in this field.
+    IdentityField(&'static str),
+    /// Keyed by the ID of a staged transaction.
+    StagedKey,
+    /// Keyed by the ID of a claim on a local identity (or a staged one).
+    ClaimKey,
+}
+
+/// Every kind of record the CLI saves, and what each points at. Add new kinds
+/// here so `stamp db fsck` checks them.
+pub const RECORD_KINDS: &[(&str, &[RecordRef])] = &[
+    ("autopublish", &[RecordRef::IdentityKey]),
+    ("backup", &[RecordRef::IdentityKey]),
+    ("claim-expiry", &[RecordRef::ClaimKey, RecordRef::IdentityField("identity")]),
+    ("follow", &[RecordRef::IdentityKey]),
+    ("message", &[RecordRef::IdentityField("our_identity")]),
+    ("phone", &[RecordRef::ClaimKey]),
+    ("secret-export", &[RecordRef::IdentityKey]),
+    ("stage-comment", &[RecordRef::StagedKey]),
+    ("stage-expiry", &[RecordRef::StagedKey, RecordRef::IdentityField("identity")]),
+    ("stamp-inbox", &[RecordRef::IdentityField("to_identity")]),
+    ("sync-device", &[RecordRef::IdentityField("identity_id")]),
+];
+
+/// Data directory folders that aren't record kinds.
+const NOT_RECORDS: &[&str] = &["quarantine", "reset-backups", "merge-backups"];
+
+/// List the kinds of local records that have been saved.
+pub fn list_record_kinds() -> Result<Vec<String>> {
+    let dir = config::data_dir()?;
+    let mut kinds = Vec::new();
+    let entries = std::fs::read_dir(&dir).map_err(|e| anyhow!("Problem listing record kinds: {}", e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| anyhow!("Problem listing record kinds: {}", e))?.path();
+        match path.file_name().and_then(|x| x.to_str()) {
+            Some(name) if path.is_dir() && !NOT_RECORDS.contains(&name) => kinds.push(String::from(name)),
+            _ => {}
+        }
+    }
+    kinds.sort();
+    Ok(kinds)
+}
+
+/// Try to read every record of a kind, without caring what type it is. Returns
+/// the keys of records that can't be read, along with why.
+pub fn check_records(kind: &str) -> Result<Vec<(String, String)>> {
+    let mut broken = Vec::new();
+    for key in list_record_keys(kind)? {
+        if let Err(e) = load_record::<toml::Value>(kind, &key) {
+            broken.push((key, format!("{}", e)));
+        }
+    }
+    Ok(broken)
+}
+
+/// Move a record out of the way (into the `quarantine` folder of the data
+/// directory) so it stops getting in the way of commands that read it.
+pub fn quarantine_record(kind: &str, key: &str) -> Result<PathBuf> {
+    let path = record_path(kind, key)?;
+    let mut dest = config::data_dir()?;
+    dest.push("quarantine");
+    dest.push(kind);
+    std::fs::create_dir_all(&dest).map_err(|e| anyhow!("Problem creating quarantine directory: {:?}: {}", dest, e))?;
+    dest.push(format!("{}.toml", key));
+    std::fs::rename(&path, &dest).map_err(|e| anyhow!("Problem quarantining {} record: {:?}: {}", kind, path, e))?;
+    Ok(dest)
+}
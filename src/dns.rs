@@ -0,0 +1,119 @@
+use crate::config::CliConfig;
+use anyhow::{anyhow, Result};
+use hickory_resolver::{
+    config::{NameServerConfigGroup, ResolverConfig, ResolverOpts},
+    system_conf, Resolver,
+};
+use std::net::{IpAddr, SocketAddr};
+
+/// DoH providers that can be picked by name.
+pub const DOH_PROVIDERS: &[&str] = &["cloudflare", "google", "quad9"];
+
+/// How TXT lookups are resolved.
+#[derive(Debug, Default, Clone)]
+pub struct DnsSettings {
+    /// A plain DNS resolver, `IP` or `IP:PORT`.
+    pub resolver: Option<String>,
+    /// A DoH provider (see [DOH_PROVIDERS]) or a custom `IP#HOSTNAME` endpoint.
+    pub doh: Option<String>,
+    /// Require DNSSEC validation of the answer.
+    pub dnssec: bool,
+}
+
+impl DnsSettings {
+    /// Settings from the CLI config, with any values given on the command line
+    /// taking precedence.
+    pub fn from_config(config: &CliConfig, resolver: Option<&str>, doh: Option<&str>, dnssec: bool) -> Self {
+        let (resolver, doh) = match (resolver, doh) {
+            (None, None) => (config.dns_resolver.clone(), config.dns_doh.clone()),
+            (resolver, doh) => (resolver.map(String::from), doh.map(String::from)),
+        };
+        Self {
+            resolver,
+            doh,
+            dnssec: dnssec || config.dns_dnssec,
+        }
+    }
+
+    /// Whether anything differs from just asking the system resolver.
+    pub fn is_custom(&self) -> bool {
+        self.resolver.is_some() || self.doh.is_some() || self.dnssec
+    }
+
+    /// A short description of where lookups go, for verification output.
+    pub fn describe(&self) -> String {
+        match (&self.doh, &self.resolver) {
+            (Some(doh), _) => format!("DNS-over-HTTPS ({})", doh),
+            (None, Some(resolver)) => format!("{}", resolver),
+            (None, None) => String::from("system resolver"),
+        }
+    }
+
+    fn resolver_config(&self) -> Result<(ResolverConfig, ResolverOpts)> {
+        let (config, mut opts) = match (&self.doh, &self.resolver) {
+            (Some(doh), _) => (doh_config(doh)?, ResolverOpts::default()),
+            (None, Some(resolver)) => {
+                let addr = parse_resolver(resolver)?;
+                let group = NameServerConfigGroup::from_ips_clear(&[addr.ip()], addr.port(), true);
+                (ResolverConfig::from_parts(None, vec![], group), ResolverOpts::default())
+            }
+            (None, None) => system_conf::read_system_conf().map_err(|e| anyhow!("Problem reading system DNS config: {}", e))?,
+        };
+        opts.validate = self.dnssec;
+        Ok((config, opts))
+    }
+}
+
+/// Parse a plain resolver address, defaulting to port 53.
+pub fn parse_resolver(resolver: &str) -> Result<SocketAddr> {
+    if let Ok(addr) = resolver.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    resolver
+        .parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, 53))
+        .map_err(|_| anyhow!("Invalid resolver {} (expected an IP or IP:PORT, ex 9.9.9.9 or [2620:fe::fe]:53)", resolver))
+}
+
+/// Build the config for a DoH provider, either by name or as `IP#HOSTNAME`
+/// where HOSTNAME is what the server's TLS certificate is issued for.
+pub fn doh_config(doh: &str) -> Result<ResolverConfig> {
+    match doh {
+        "cloudflare" => Ok(ResolverConfig::cloudflare_https()),
+        "google" => Ok(ResolverConfig::google_https()),
+        "quad9" => Ok(ResolverConfig::quad9_https()),
+        _ => {
+            let (ip, hostname) = doh
+                .split_once('#')
+                .ok_or_else(|| anyhow!("Invalid DoH endpoint {} (expected one of {} or IP#HOSTNAME)", doh, DOH_PROVIDERS.join(", ")))?;
+            let ip: IpAddr = ip.parse().map_err(|_| anyhow!("Invalid DoH endpoint IP: {}", ip))?;
+            let group = NameServerConfigGroup::from_ips_https(&[ip], 443, String::from(hostname), true);
+            Ok(ResolverConfig::from_parts(None, vec![], group))
+        }
+    }
+}
+
+/// The result of a TXT lookup.
+pub struct TxtAnswer {
+    pub records: Vec<String>,
+    /// True if the answer was DNSSEC-validated.
+    pub dnssec_validated: bool,
+}
+
+/// Look up the TXT records for a domain. If DNSSEC is required, answers that
+/// fail validation (or come from unsigned zones) are an error.
+pub fn lookup_txt(settings: &DnsSettings, domain: &str) -> Result<TxtAnswer> {
+    let (config, opts) = settings.resolver_config()?;
+    let resolver = Resolver::new(config, opts).map_err(|e| anyhow!("Problem creating DNS resolver: {}", e))?;
+    let lookup = resolver.txt_lookup(domain).map_err(|e| {
+        if settings.dnssec {
+            anyhow!("TXT lookup for {} failed (DNSSEC validation was required): {}", domain, e)
+        } else {
+            anyhow!("TXT lookup for {} failed: {}", domain, e)
+        }
+    })?;
+    Ok(TxtAnswer {
+        records: lookup.iter().map(|txt| txt.to_string()).collect(),
+        dnssec_validated: settings.dnssec,
+    })
+}
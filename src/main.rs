@@ -2,10 +2,15 @@
 extern crate prettytable;
 #[macro_use]
 mod util;
+mod armor;
 mod commands;
 mod config;
 mod db;
+mod dns;
 mod log;
+mod pdf;
+mod photo;
+mod ssh;
 
 use anyhow::{anyhow, Result};
 use clap::{
@@ -102,12 +107,26 @@ fn run() -> Result<()> {
             .long("stage")
             .help("Stage this transaction instead of immediately applying. This is mainly useful for group-managed identities or creating detached stamps.")
     };
+    let stage_expires_arg = || -> Arg {
+        Arg::new("stage-expires")
+            .long("stage-expires")
+            .value_name("when")
+            .requires("stage")
+            .help("Have the staged transaction expire after a duration (ex 12h, 7d, 2w) or on a date (2025-06-01). Expired transactions can't be signed or applied, and `stamp stage prune` removes them.")
+    };
     let signwith_arg = || -> Arg {
         Arg::new("admin-key")
             .short('k')
             .long("sign-with")
             .help("Sign this transaction with a specific admin key id/name (list admin keys with `stamp keychain list --admin`).")
     };
+    let format_arg = || -> Arg {
+        Arg::new("format")
+            .long("format")
+            .value_parser(clap::builder::PossibleValuesParser::new(["binary", "cbor"]))
+            .default_value("binary")
+            .help("The output format. \"binary\" is Stamp's native format, \"cbor\" is for interop with other CBOR tooling. Stamp reads both.")
+    };
     let claim_private_arg = || -> Arg {
         Arg::new("private")
             .action(ArgAction::SetTrue)
@@ -123,6 +142,18 @@ fn run() -> Result<()> {
             .help("Gives this claim a name. This is useful when you want a claim to be easily identifiable by other people or apps (ex \"primary-email\").")
     };
 
+    let claim_value_arg = || -> Arg {
+        Arg::new("value")
+            .long("value")
+            .help("The claim's value. Set this (or --value-file) to create the claim without being prompted, for instance from a script.")
+    };
+    let claim_value_file_arg = || -> Arg {
+        Arg::new("value-file")
+            .long("value-file")
+            .conflicts_with("value")
+            .help("Read the claim's value from a file. Use the value '-' to signify STDIN.")
+    };
+
     let id_val = |args: &ArgMatches| -> Result<String> {
         args.get_one::<String>("identity")
             .map(|x| x.clone())
@@ -170,6 +201,7 @@ fn run() -> Result<()> {
                             .long("prefix")
                             .help("Vanity prefix, ex: jeb-"))
                         .arg(stage_arg())
+                        .arg(stage_expires_arg())
                         .arg(signwith_arg())
                 )
                 .subcommand(
@@ -181,6 +213,28 @@ fn run() -> Result<()> {
                             .short('v')
                             .long("verbose")
                             .help("Verbose output, with long-form IDs."))
+                        .arg(Arg::new("sort")
+                            .long("sort")
+                            .value_parser(clap::builder::PossibleValuesParser::new(["created", "name", "updated", "stamps"]))
+                            .help("Sort by creation date (oldest first), name, last update (newest first), or number of stamps received (most first)."))
+                        .arg(Arg::new("owned")
+                            .action(ArgAction::SetTrue)
+                            .long("owned")
+                            .conflicts_with("imported")
+                            .help("Only show identities you own."))
+                        .arg(Arg::new("imported")
+                            .action(ArgAction::SetTrue)
+                            .long("imported")
+                            .help("Only show identities you've imported."))
+                        .arg(Arg::new("has-claim-type")
+                            .long("has-claim-type")
+                            .value_name("TYPE")
+                            .value_parser(clap::builder::PossibleValuesParser::new(["identity", "name", "birthday", "email", "photo", "pgp", "domain", "url", "address", "phone", "relation", "custom"]))
+                            .help("Only show identities with at least one claim of this type."))
+                        .arg(Arg::new("created-after")
+                            .long("created-after")
+                            .value_name("YYYY-MM-DD")
+                            .help("Only show identities created after this date."))
                         .arg(Arg::new("SEARCH")
                             .index(1)
                             .help("A search value to look for in an identity's ID, name, and email"))
@@ -210,6 +264,7 @@ fn run() -> Result<()> {
                             .long("output")
                             .help("The output file to write to. You can leave blank or use the value '-' to signify STDOUT."))
                         .arg(stage_arg())
+                        .arg(stage_expires_arg())
                         .arg(signwith_arg())
                         .group(ArgGroup::new("stage-out")
                             .args(["stage"])
@@ -219,6 +274,7 @@ fn run() -> Result<()> {
                     Command::new("export-private")
                         .about("Export one of your identities. This export includes private keys so even though it is encrypted, it's important you do not share it with *anybody*. EVER.")
                         .arg(id_arg("The ID of the identity we want to export. This overrides the configured default identity."))
+                        .arg(format_arg())
                         .arg(Arg::new("output")
                             .short('o')
                             .long("output")
@@ -280,23 +336,34 @@ fn run() -> Result<()> {
                         .alias("add")
                         .subcommand_required(true)
                         .arg_required_else_help(true)
+                        .arg(Arg::new("expires")
+                            .long("expires")
+                            .global(true)
+                            .value_name("DATE")
+                            .help("When the claim expires, as a date (2027-01-01) or RFC3339 timestamp. Expired claims are flagged in `stamp claim list` and can be re-issued with `stamp claim renew`."))
                         .subcommand(
                             Command::new("identity")
                                 .about("Create an identity ownership claim. This is always created automatically for any new identity you create, but can also be created for another identity (for instance if you move to a new identity).")
                                 .arg(id_arg("The ID of the identity we want to add a claim to. This overrides the configured default identity."))
                                 .arg(stage_arg())
+                                .arg(stage_expires_arg())
                                 .arg(signwith_arg())
                                 .arg(claim_private_arg())
                                 .arg(claim_name_arg())
+                                .arg(claim_value_arg())
+                                .arg(claim_value_file_arg())
                         )
                         .subcommand(
                             Command::new("name")
                                 .about("Claim your full name. Generally you only have one name claim, but you are free to add more if you wish.")
                                 .arg(id_arg("The ID of the identity we want to add a claim to. This overrides the configured default identity."))
                                 .arg(stage_arg())
+                                .arg(stage_expires_arg())
                                 .arg(signwith_arg())
                                 .arg(claim_private_arg())
                                 .arg(claim_name_arg())
+                                .arg(claim_value_arg())
+                                .arg(claim_value_file_arg())
                         )
                         .subcommand(
                             Command::new("birthday")
@@ -304,27 +371,38 @@ fn run() -> Result<()> {
                                 .about("Claim your birthday/date of birth. Generally you only have one birthday claim, but you are free to add more if you wish.")
                                 .arg(id_arg("The ID of the identity we want to add a claim to. This overrides the configured default identity."))
                                 .arg(stage_arg())
+                                .arg(stage_expires_arg())
                                 .arg(signwith_arg())
                                 .arg(claim_private_arg())
                                 .arg(claim_name_arg())
+                                .arg(claim_value_arg())
+                                .arg(claim_value_file_arg())
                         )
                         .subcommand(
                             Command::new("email")
                                 .about("Claim ownership of an email address.")
                                 .arg(id_arg("The ID of the identity we want to add a claim to. This overrides the configured default identity."))
                                 .arg(stage_arg())
+                                .arg(stage_expires_arg())
                                 .arg(signwith_arg())
                                 .arg(claim_private_arg())
                                 .arg(claim_name_arg())
+                                .arg(claim_value_arg())
+                                .arg(claim_value_file_arg())
                         )
                         .subcommand(
                             Command::new("photo")
                                 .about("Claim that a photo is you.")
                                 .arg(id_arg("The ID of the identity we want to add a claim to. This overrides the configured default identity."))
                                 .arg(stage_arg())
+                                .arg(stage_expires_arg())
                                 .arg(signwith_arg())
                                 .arg(claim_private_arg())
                                 .arg(claim_name_arg())
+                                .arg(Arg::new("resize")
+                                    .action(ArgAction::SetTrue)
+                                    .long("resize")
+                                    .help("If the photo is too big to fit in a claim, shrink and re-encode it (as JPEG) until it fits."))
                                 .arg(Arg::new("PHOTO-FILE")
                                     .index(1)
                                     .required(true)
@@ -335,51 +413,94 @@ fn run() -> Result<()> {
                                 .about("Claim ownership of a PGP identity. It's probably best to use the long-form ID for this.")
                                 .arg(id_arg("The ID of the identity we want to add a claim to. This overrides the configured default identity."))
                                 .arg(stage_arg())
+                                .arg(stage_expires_arg())
                                 .arg(signwith_arg())
                                 .arg(claim_private_arg())
                                 .arg(claim_name_arg())
+                                .arg(claim_value_arg())
+                                .arg(claim_value_file_arg())
                         )
                         .subcommand(
                             Command::new("domain")
                                 .about("Claim ownership of a domain. You must have access to create a TXT record on the domain. This claim can be checked by anybody using the `stamp claim check` command.")
                                 .arg(id_arg("The ID of the identity we want to add a claim to. This overrides the configured default identity."))
                                 .arg(stage_arg())
+                                .arg(stage_expires_arg())
                                 .arg(signwith_arg())
                                 .arg(claim_private_arg())
                                 .arg(claim_name_arg())
+                                .arg(claim_value_arg())
+                                .arg(claim_value_file_arg())
                         )
                         .subcommand(
                             Command::new("url")
                                 .about("Claim ownership of a URL. This can be used for claiming ownership of websites or social media profiles. You must have the ability to update the content this URL points to. This claim can be checked by anybody using the `stamp claim check` command.")
                                 .arg(id_arg("The ID of the identity we want to add a claim to. This overrides the configured default identity."))
                                 .arg(stage_arg())
+                                .arg(stage_expires_arg())
                                 .arg(signwith_arg())
                                 .arg(claim_private_arg())
                                 .arg(claim_name_arg())
+                                .arg(claim_value_arg())
+                                .arg(claim_value_file_arg())
+                                .arg(Arg::new("service")
+                                    .long("service")
+                                    .value_parser(clap::builder::PossibleValuesParser::new(["github", "mastodon", "reddit"]))
+                                    .requires("USERNAME")
+                                    .help("Build the profile URL for a well-known service from your username, name the claim after the service, and show how to publish the proof there."))
+                                .arg(Arg::new("USERNAME")
+                                    .index(1)
+                                    .requires("service")
+                                    .help("Your username on the service given by --service. For Mastodon, include the instance (ex: user@mastodon.social)."))
+                        )
+                        .subcommand(
+                            Command::new("social")
+                                .about("Claim a social media profile. This builds the profile URL from your username, creates a URL claim for it, and prints the exact content to publish (a gist, a post, or your bio) so `stamp claim check` can find the proof.")
+                                .arg(id_arg("The ID of the identity we want to add a claim to. This overrides the configured default identity."))
+                                .arg(stage_arg())
+                                .arg(stage_expires_arg())
+                                .arg(signwith_arg())
+                                .arg(claim_name_arg())
+                                .arg(Arg::new("platform")
+                                    .long("platform")
+                                    .required(true)
+                                    .value_parser(clap::builder::PossibleValuesParser::new(["github", "mastodon", "reddit"]))
+                                    .help("The platform the profile is on."))
+                                .arg(Arg::new("USERNAME")
+                                    .index(1)
+                                    .required(true)
+                                    .help("Your username on the platform. For Mastodon, include the instance (ex: user@mastodon.social)."))
                         )
                         .subcommand(
                             Command::new("address")
                                 .about("Claim a home address. (Hint: you might want the -p flag with this unless you like meeting internet strangers)")
                                 .arg(id_arg("The ID of the identity we want to add a claim to. This overrides the configured default identity."))
                                 .arg(stage_arg())
+                                .arg(stage_expires_arg())
                                 .arg(signwith_arg())
                                 .arg(claim_private_arg())
                                 .arg(claim_name_arg())
+                                .arg(claim_value_arg())
+                                .arg(claim_value_file_arg())
                         )
                         .subcommand(
                             Command::new("phone")
                                 .about("Claim a phone number. (Hint: you might want the -p flag with this unless you like phone calls about your car's extended warranty)")
                                 .arg(id_arg("The ID of the identity we want to add a claim to. This overrides the configured default identity."))
                                 .arg(stage_arg())
+                                .arg(stage_expires_arg())
                                 .arg(signwith_arg())
                                 .arg(claim_private_arg())
                                 .arg(claim_name_arg())
+                                .arg(claim_value_arg())
+                                .arg(claim_value_file_arg())
                         )
                         .subcommand(
                             Command::new("relation")
                                 .about("Claim that you are in a relationship with another identity.")
                                 .arg(id_arg("The ID of the identity we want to add a claim to. This overrides the configured default identity."))
                                 .arg(stage_arg())
+                                .arg(stage_expires_arg())
                                 .arg(signwith_arg())
                                 .arg(Arg::new("TYPE")
                                     .required(true)
@@ -388,8 +509,84 @@ fn run() -> Result<()> {
                                     .help("The relationship type."))
                                 .arg(claim_private_arg())
                                 .arg(claim_name_arg())
+                                .arg(claim_value_arg())
+                                .arg(claim_value_file_arg())
+                        )
+                        .subcommand(
+                            Command::new("file")
+                                .about("Claim a file or document by its hash, for publishing ownership or authorship of it. Only the file's hash, name, and size are stored. Anyone can check a copy of the file against the claim with `stamp claim check-file`.")
+                                .arg(id_arg("The ID of the identity we want to add a claim to. This overrides the configured default identity."))
+                                .arg(stage_arg())
+                                .arg(stage_expires_arg())
+                                .arg(signwith_arg())
+                                .arg(claim_private_arg())
+                                .arg(claim_name_arg())
+                                .arg(Arg::new("FILE")
+                                    .index(1)
+                                    .required(true)
+                                    .help("The file to claim."))
+                        )
+                        .subcommand(
+                            Command::new("custom")
+                                .about("Claim an application-defined piece of data that doesn't fit any of the other claim types, like a Matrix handle or a payment address.")
+                                .alias("extension")
+                                .arg(id_arg("The ID of the identity we want to add a claim to. This overrides the configured default identity."))
+                                .arg(stage_arg())
+                                .arg(stage_expires_arg())
+                                .arg(signwith_arg())
+                                .arg(Arg::new("kind")
+                                    .short('t')
+                                    .long("kind")
+                                    .required(true)
+                                    .help("What this claim is, ex \"matrix\" or \"btc-address\". Apps look for claims by this value."))
+                                .arg(Arg::new("value")
+                                    .long("value")
+                                    .help("The claim's value. If neither this nor --value-file is given, you will be prompted."))
+                                .arg(Arg::new("value-file")
+                                    .long("value-file")
+                                    .conflicts_with("value")
+                                    .help("Read the claim's value (text or binary) from a file. Use the value '-' to signify STDIN."))
+                                .arg(claim_private_arg())
+                                .arg(claim_name_arg())
                         )
                 )
+                .subcommand(
+                    Command::new("renew")
+                        .about("Re-issue a claim (same value and name) with a new expiration, then create stamp requests for everyone who stamped the old claim. The old claim is kept until you delete it.")
+                        .arg(id_arg("The ID of the identity that owns the claim. This overrides the configured default identity."))
+                        .arg(stage_arg())
+                        .arg(stage_expires_arg())
+                        .arg(signwith_arg())
+                        .arg(Arg::new("expires")
+                            .long("expires")
+                            .value_name("DATE")
+                            .help("When the new claim expires, as a date (2027-01-01) or RFC3339 timestamp."))
+                        .arg(Arg::new("key-from")
+                            .short('f')
+                            .long("key-from")
+                            .help("The ID or name of the `crypto` key in your keychain to send stamp requests from. If you don't specify this, you will be prompted."))
+                        .arg(Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .default_value("stamp-requests")
+                            .help("The directory to write stamp requests for stampers you don't own to (with a manifest.json)."))
+                        .arg(Arg::new("CLAIM")
+                            .required(true)
+                            .index(1)
+                            .help("The ID or name of the claim to renew."))
+                )
+                .subcommand(
+                    Command::new("check-file")
+                        .about("Check that a file matches a file claim (see `stamp claim new file`). The identity owning the claim must be imported locally.")
+                        .arg(Arg::new("CLAIM")
+                            .required(true)
+                            .index(1)
+                            .help("The ID of the file claim we're checking against."))
+                        .arg(Arg::new("FILE")
+                            .required(true)
+                            .index(2)
+                            .help("The file to check."))
+                )
                 .subcommand(
                     Command::new("check")
                         .about("This command verifies domain and URL claims immediately. This lets us prove ownership of domains, websites, and social media profiles in a distributed fashion without requiring third-party verification. Bye, Keybase.")
@@ -398,6 +595,19 @@ fn run() -> Result<()> {
                             .required(true)
                             .index(1)
                             .help("The ID of the claim we're checking. Must be a public `Domain` or `URL` claim. The identity owning the claim must be imported locally."))
+                        .arg(Arg::new("resolver")
+                            .long("resolver")
+                            .value_name("IP[:PORT]")
+                            .conflicts_with("doh")
+                            .help("Check domain claims using this DNS resolver instead of the configured one (see `stamp config dns`)."))
+                        .arg(Arg::new("doh")
+                            .long("doh")
+                            .value_name("cloudflare|google|quad9|IP#HOSTNAME")
+                            .help("Check domain claims using DNS-over-HTTPS with this provider."))
+                        .arg(Arg::new("dnssec")
+                            .action(ArgAction::SetTrue)
+                            .long("dnssec")
+                            .help("Require DNSSEC validation when checking domain claims."))
                 )
                 .subcommand(
                     Command::new("view")
@@ -407,6 +617,11 @@ fn run() -> Result<()> {
                             .short('o')
                             .long("output")
                             .help("The output file to write to. You can leave blank or use the value '-' to signify STDOUT."))
+                        .arg(Arg::new("preview")
+                            .long("preview")
+                            .value_parser(clap::builder::PossibleValuesParser::new(["auto", "kitty", "iterm", "sixel", "none"]))
+                            .default_value("auto")
+                            .help("How to show photo claims when writing to a terminal. \"auto\" detects kitty, iTerm2, and sixel terminals, and saves the photo to the temp dir if none are found (use --output to pick a file instead)."))
                         .arg(Arg::new("CLAIM")
                             .required(true)
                             .index(1)
@@ -435,6 +650,7 @@ fn run() -> Result<()> {
                         .alias("edit")
                         .arg(id_arg("The ID of the identity we are removing the claim from. This overrides the configured default identity."))
                         .arg(stage_arg())
+                        .arg(stage_expires_arg())
                         .arg(signwith_arg())
                         .arg(Arg::new("CLAIM")
                             .required(true)
@@ -480,6 +696,7 @@ fn run() -> Result<()> {
                                 .alias("rm")
                                 .arg(id_arg("The ID of the identity we are listing stamps for. This overrides the configured default identity."))
                                 .arg(stage_arg())
+                                .arg(stage_expires_arg())
                                 .arg(signwith_arg())
                                 .arg(Arg::new("STAMP")
                                     .required(true)
@@ -492,9 +709,29 @@ fn run() -> Result<()> {
                         .about("Remove a claim from your identity.")
                         .arg(id_arg("The ID of the identity we are removing the claim from. This overrides the configured default identity."))
                         .arg(stage_arg())
+                        .arg(stage_expires_arg())
                         .arg(signwith_arg())
+                        .arg(Arg::new("bulk")
+                            .action(ArgAction::SetTrue)
+                            .long("bulk")
+                            .conflicts_with("CLAIM")
+                            .help("Delete every claim matching the given filters (--type, --name, --unstamped-private) at once. You'll be shown the matching claims and asked to confirm."))
+                        .arg(Arg::new("type")
+                            .long("type")
+                            .requires("bulk")
+                            .value_parser(clap::builder::PossibleValuesParser::new(["identity", "name", "birthday", "email", "photo", "pgp", "domain", "url", "address", "phone", "relation", "custom"]))
+                            .help("With --bulk, only delete claims of this type."))
+                        .arg(Arg::new("name")
+                            .long("name")
+                            .requires("bulk")
+                            .help("With --bulk, only delete claims whose name matches this pattern (`*` and `?` are wildcards)."))
+                        .arg(Arg::new("unstamped-private")
+                            .action(ArgAction::SetTrue)
+                            .long("unstamped-private")
+                            .requires("bulk")
+                            .help("With --bulk, only delete private claims that have no stamps."))
                         .arg(Arg::new("CLAIM")
-                            .required(true)
+                            .required_unless_present("bulk")
                             .index(1)
                             .help("The ID of the claim we're deleting."))
                 )
@@ -514,7 +751,17 @@ fn run() -> Result<()> {
                             .index(1)
                             .required(true)
                             .help("The ID or name of the claim we wish to stamp."))
+                        .arg(Arg::new("confidence")
+                            .short('c')
+                            .long("confidence")
+                            .value_parser(clap::builder::PossibleValuesParser::new(["negative", "low", "medium", "high", "ultimate"]))
+                            .help("How confident you are in the claim. Skips the confidence prompt (and the expiration prompt unless --expires is also given)."))
+                        .arg(Arg::new("expires")
+                            .long("expires")
+                            .value_name("DATE")
+                            .help("When the stamp expires, as a date (2027-01-01) or RFC3339 timestamp. Skips the expiration prompt."))
                         .arg(stage_arg())
+                        .arg(stage_expires_arg())
                         .arg(signwith_arg())
                 )
                 .subcommand(
@@ -543,6 +790,11 @@ fn run() -> Result<()> {
                             .short('b')
                             .long("base64")
                             .help("If set, output the encrypted message as base64 (which is easier to put in email or a website)."))
+                        .arg(Arg::new("armor")
+                            .action(ArgAction::SetTrue)
+                            .long("armor")
+                            .conflicts_with("base64")
+                            .help("If set, output the stamp request as an ASCII-armored block (-----BEGIN STAMP MESSAGE-----) with a checksum, which survives being pasted into email or chat."))
                         .arg(id_arg("The ID of the identity we are creating the stamp request for. This overrides the configured default identity."))
                         .arg(Arg::new("CLAIM")
                             .index(1)
@@ -562,11 +814,84 @@ fn run() -> Result<()> {
                             .required(false)
                             .help("The input file to read the encrypted stamp request from. You can leave blank or use the value '-' to signify STDIN."))
                 )
+                .subcommand(
+                    Command::new("req-inbox")
+                        .alias("inbox")
+                        .about("Keep track of stamp requests sent to you, so you can collect them from several people and work through them later. Requests sent with `stamp stamp req --send` land here directly, and requests you receive as files can be imported.")
+                        .subcommand_required(true)
+                        .arg_required_else_help(true)
+                        .subcommand(
+                            Command::new("import")
+                                .about("Save a stamp request you received (binary or base64) into your inbox.")
+                                .arg(id_arg("The ID of the identity the request was sent to. This overrides the configured default identity."))
+                                .arg(Arg::new("ENCRYPTED")
+                                    .index(1)
+                                    .required(false)
+                                    .help("The input file to read the encrypted stamp request from. You can leave blank or use the value '-' to signify STDIN."))
+                        )
+                        .subcommand(
+                            Command::new("list")
+                                .alias("ls")
+                                .about("List the pending stamp requests in your inbox.")
+                                .arg(id_arg("The ID of the identity whose inbox we're listing. This overrides the configured default identity."))
+                                .arg(Arg::new("all")
+                                    .action(ArgAction::SetTrue)
+                                    .short('a')
+                                    .long("all")
+                                    .help("Include requests that have been marked done."))
+                                .arg(Arg::new("verbose")
+                                    .action(ArgAction::SetTrue)
+                                    .short('v')
+                                    .long("verbose")
+                                    .help("Verbose output, with long-form IDs."))
+                        )
+                        .subcommand(
+                            Command::new("open")
+                                .about("Open a stamp request from your inbox and display the claim inside of it.")
+                                .arg(id_arg("The ID of the identity whose inbox we're opening from. This overrides the configured default identity."))
+                                .arg(Arg::new("key-to")
+                                    .short('t')
+                                    .long("key-to")
+                                    .help("The ID or name of the `crypto` key in your keychain the request was encrypted with. If you don't specify this, you will be prompted."))
+                                .arg(Arg::new("REQUEST")
+                                    .index(1)
+                                    .required(true)
+                                    .help("The inbox ID of the request (see `stamp stamp req-inbox list`)."))
+                        )
+                        .subcommand(
+                            Command::new("done")
+                                .about("Mark a stamp request as dealt with. Done requests are hidden from `req-inbox list` unless --all is given.")
+                                .arg(id_arg("The ID of the identity whose inbox the request is in. This overrides the configured default identity."))
+                                .arg(Arg::new("delete")
+                                    .action(ArgAction::SetTrue)
+                                    .long("delete")
+                                    .help("Remove the request from the inbox entirely instead of marking it done."))
+                                .arg(Arg::new("REQUEST")
+                                    .index(1)
+                                    .required(true)
+                                    .help("The inbox ID of the request (see `stamp stamp req-inbox list`)."))
+                        )
+                )
+                .subcommand(
+                    Command::new("received")
+                        .about("List every stamp others have made on your claims, grouped by stamper, including revoked and expired stamps.")
+                        .arg(id_arg("The ID of the identity we are listing received stamps for. This overrides the configured default identity."))
+                        .arg(Arg::new("verbose")
+                            .action(ArgAction::SetTrue)
+                            .short('v')
+                            .long("verbose")
+                            .help("Verbose output, with long-form IDs."))
+                )
                 .subcommand(
                     Command::new("list")
-                        .about("List all public stamps we have made. To view stamps others have made, see the `stamp claim stamps` command.")
+                        .about("List all public stamps we have made. To view stamps others have made on your claims, see `stamp stamp received`.")
                         .alias("ls")
                         .arg(id_arg("The ID of the identity we are stamping from. This overrides the configured default identity."))
+                        .arg(Arg::new("expiring")
+                            .long("expiring")
+                            .value_name("DAYS")
+                            .value_parser(value_parser!(u64))
+                            .help("Only list stamps that expire within this many days (see `stamp stamp renew`)."))
                         .arg(Arg::new("revoked")
                             .short('r')
                             .long("revoked")
@@ -578,6 +903,27 @@ fn run() -> Result<()> {
                             .long("verbose")
                             .help("Verbose output, with long-form IDs."))
                 )
+                .subcommand(
+                    Command::new("renew")
+                        .about("Make a fresh stamp on the same claim as one of your existing stamps, with a new expiration. Use this to keep long-term stamps from lapsing (see `stamp stamp list --expiring`).")
+                        .arg(id_arg("The ID of the identity we are stamping from. This overrides the configured default identity."))
+                        .arg(Arg::new("STAMP")
+                            .required(true)
+                            .index(1)
+                            .help("The ID of the stamp we're renewing."))
+                        .arg(Arg::new("expires")
+                            .long("expires")
+                            .value_name("DATE")
+                            .help("When the new stamp expires, as a date (2027-01-01) or RFC3339 timestamp. Defaults to the old stamp's lifetime, starting now."))
+                        .arg(Arg::new("confidence")
+                            .short('c')
+                            .long("confidence")
+                            .value_parser(clap::builder::PossibleValuesParser::new(["negative", "low", "medium", "high", "ultimate"]))
+                            .help("Change the confidence on the new stamp. Defaults to the old stamp's confidence."))
+                        .arg(stage_arg())
+                        .arg(stage_expires_arg())
+                        .arg(signwith_arg())
+                )
                 .subcommand(
                     Command::new("export")
                         .about("Export a stamp in binary or text form that can be accepted by the identity in ownership of the stamped claim.")
@@ -595,18 +941,63 @@ fn run() -> Result<()> {
                             .short('b')
                             .long("base64")
                             .help("If set, output the stamp transaction as base64 (which is easier to put in email or a website)."))
+                        .arg(format_arg())
+                )
+                .subcommand(
+                    Command::new("send")
+                        .about("Seal a stamp you've made to the stampee's `crypto` key and write it out, so you can send it to them and they can accept it with `stamp stamp accept`.")
+                        .arg(id_arg("The ID of the identity that made the stamp. This overrides the configured default identity."))
+                        .arg(Arg::new("key-from")
+                            .short('f')
+                            .long("key-from")
+                            .help("The ID or name of the `crypto` key in your keychain you want to sign the message with. If you don't specify this, you will be prompted."))
+                        .arg(Arg::new("key-to")
+                            .short('t')
+                            .long("key-to")
+                            .help("The ID or name of the `crypto` key in the stampee's keychain that the stamp will be encrypted with. If you don't specify this, you will be prompted. The stampee's identity must be stored locally."))
+                        .arg(Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .help("Write the sealed stamp to this file. Use the value '-' to signify STDOUT (the default)."))
+                        .arg(Arg::new("base64")
+                            .action(ArgAction::SetTrue)
+                            .short('b')
+                            .long("base64")
+                            .help("If set, output the sealed stamp as base64."))
+                        .arg(Arg::new("armor")
+                            .action(ArgAction::SetTrue)
+                            .long("armor")
+                            .conflicts_with("base64")
+                            .help("If set, output the sealed stamp as an ASCII-armored block with a recipient hint and checksum, which survives being pasted into email or chat."))
+                        .arg(Arg::new("STAMP")
+                            .required(true)
+                            .index(1)
+                            .help("The ID of the stamp we're sending."))
                 )
                 .subcommand(
                     Command::new("accept")
                         .about("Accept a stamp someone else has made on one of our claims.")
                         .arg(id_arg("The ID of the identity we are accepting the stamp for. This overrides the configured default identity."))
+                        .arg(Arg::new("key-open")
+                            .short('k')
+                            .long("key-open")
+                            .help("The ID or name of the `crypto` key in your keychain that sealed stamps will be opened with. If you don't specify this and the stamp is sealed, you will be prompted."))
                         .arg(Arg::new("LOCATION")
                             .required(true)
                             .index(1)
-                            .help("The stamp we're accepting. This can be the path of a file holding the stamp transaction, or it can be a stamp URL (eg stamp://zef7Qo5S34k0yZMB/stamps/WUX2PKz20cwK7pgC). Set to - to read from STDIN."))
+                            .help("The stamp we're accepting. This can be the path of a file holding the stamp transaction (plain or sealed to you with `stamp stamp send`), or it can be a stamp URL (eg stamp://zef7Qo5S34k0yZMB/stamps/WUX2PKz20cwK7pgC). Set to - to read from STDIN."))
                         .arg(stage_arg())
+                        .arg(stage_expires_arg())
                         .arg(signwith_arg())
                 )
+                .subcommand(
+                    Command::new("verify")
+                        .about("Check that a stamp is valid: its signature matches the stamper's keys (now or when it was made), it hasn't been revoked or expired, and the stamped claim exists. The stamper's identity must be imported.")
+                        .arg(Arg::new("STAMP")
+                            .required(true)
+                            .index(1)
+                            .help("The ID of a stamp in one of your local identities, or the path of a file holding the stamp transaction. Set to - to read from STDIN."))
+                )
                 .subcommand(
                     Command::new("revoke")
                         .about("Revoke a stamp we've made on another identity. Note that the stamp must be present on an identity that's stored locally.")
@@ -621,6 +1012,7 @@ fn run() -> Result<()> {
                             .index(1)
                             .help("The ID of the stamp we're revoking."))
                         .arg(stage_arg())
+                        .arg(stage_expires_arg())
                         .arg(signwith_arg())
                 )
         )
@@ -648,6 +1040,7 @@ fn run() -> Result<()> {
                                     .long("desc")
                                     .help("They key's description, ex: Use this key to send me emails."))
                                 .arg(stage_arg())
+                                .arg(stage_expires_arg())
                                 .arg(signwith_arg())
                         )
                         .subcommand(
@@ -663,6 +1056,7 @@ fn run() -> Result<()> {
                                     .long("desc")
                                     .help("They key's description, ex: Use this key to send me emails."))
                                 .arg(stage_arg())
+                                .arg(stage_expires_arg())
                                 .arg(signwith_arg())
                         )
                         .subcommand(
@@ -678,6 +1072,7 @@ fn run() -> Result<()> {
                                     .long("desc")
                                     .help("They key's description, ex: Use this key to send me emails."))
                                 .arg(stage_arg())
+                                .arg(stage_expires_arg())
                                 .arg(signwith_arg())
                         )
                         .subcommand(
@@ -693,9 +1088,55 @@ fn run() -> Result<()> {
                                     .long("desc")
                                     .help("They key's description, ex: Use this key to send me emails."))
                                 .arg(stage_arg())
+                                .arg(stage_expires_arg())
                                 .arg(signwith_arg())
                         )
                 )
+                .subcommand(
+                    Command::new("import")
+                        .about("Import an existing ed25519 (sign) or x25519 (crypto) private key into your keychain, for instance a long-lived key from another system. OpenSSH (unencrypted), PKCS#8, and raw 32-byte keys are detected automatically. The key is encrypted with your master key like any other subkey.")
+                        .arg(id_arg("The ID of the identity we want to add a key to. This overrides the configured default identity."))
+                        .arg(Arg::new("type")
+                            .short('t')
+                            .long("type")
+                            .required(true)
+                            .value_parser(clap::builder::PossibleValuesParser::new(["sign", "crypto"]))
+                            .help("The type of key being imported."))
+                        .arg(Arg::new("name")
+                            .short('n')
+                            .long("name")
+                            .required(true)
+                            .help("This key's name. The name is public and allows for organization and referencing the key by a memorable value. Ex: turtl:master-key"))
+                        .arg(Arg::new("description")
+                            .short('d')
+                            .long("desc")
+                            .help("They key's description, ex: Use this key to send me emails."))
+                        .arg(Arg::new("KEYFILE")
+                            .required(true)
+                            .index(1)
+                            .help("The file holding the private key. Set to - to read from STDIN."))
+                        .arg(stage_arg())
+                        .arg(stage_expires_arg())
+                        .arg(signwith_arg())
+                )
+                .subcommand(
+                    Command::new("export-ssh")
+                        .about("Export a `sign` key as an OpenSSH ed25519 keypair, so the same key can be listed in `authorized_keys` or on GitHub. By default this writes the private key to OUTPUT and the public key to OUTPUT.pub.")
+                        .arg(id_arg("The ID of the identity the key belongs to. This overrides the configured default identity."))
+                        .arg(Arg::new("public")
+                            .action(ArgAction::SetTrue)
+                            .short('p')
+                            .long("public")
+                            .help("Only output the public key, in `authorized_keys` format."))
+                        .arg(Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .help("The file to write the private key to (ex: ~/.ssh/id_ed25519). With --public, the file to write the public key to, defaulting to STDOUT."))
+                        .arg(Arg::new("SEARCH")
+                            .required(true)
+                            .index(1)
+                            .help("The ID or name of the `sign` key to export."))
+                )
                 .subcommand(
                     Command::new("list")
                         .about("List the keys in a keychain.")
@@ -728,6 +1169,7 @@ fn run() -> Result<()> {
                             .long("desc")
                             .help("Set the new description of this key."))
                         .arg(stage_arg())
+                        .arg(stage_expires_arg())
                         .arg(signwith_arg())
                         .arg(Arg::new("SEARCH")
                             .required(true)
@@ -744,6 +1186,7 @@ fn run() -> Result<()> {
                             .help("The reason you're revoking this key (defaults to \"unspecified\")"))
                         .arg(id_arg("The ID of the identity we want to revoke a key of. This overrides the configured default identity."))
                         .arg(stage_arg())
+                        .arg(stage_expires_arg())
                         .arg(signwith_arg())
                         .arg(Arg::new("SEARCH")
                             .index(1)
@@ -754,6 +1197,7 @@ fn run() -> Result<()> {
                         .about("Delete a subkey from your keychain. This does not work on admin keys (they must be revoked before deletion). Generally, you'll want to only use `delete` for secret key types. If you're deleting a signing or crypto key, you really might want the `revoke` command instead.")
                         .arg(id_arg("The ID of the identity we want to delete keys from. This overrides the configured default identity."))
                         .arg(stage_arg())
+                        .arg(stage_expires_arg())
                         .arg(signwith_arg())
                         .arg(Arg::new("SEARCH")
                             .required(true)
@@ -775,11 +1219,29 @@ fn run() -> Result<()> {
                         // off in whose camper they were whacking
                         .arg(id_arg("The ID of the identity we want to change the master passphrase for. This overrides the configured default identity."))
                 )
+                .subcommand(
+                    Command::new("rekey-all")
+                        .about("Use this if you think your master passphrase was compromised. Replaces every active subkey with a new one (revoking the old ones as compromised), changes your master passphrase, and republishes your identity.")
+                        .arg(id_arg("The ID of the identity to re-key. This overrides the configured default identity."))
+                        .arg(Arg::new("publish")
+                            .action(ArgAction::SetTrue)
+                            .short('p')
+                            .long("publish")
+                            .help("Publish the updated identity to StampNet when done. This happens automatically if you've set up `stamp net publish --auto`."))
+                        .arg(Arg::new("join")
+                            .action(ArgAction::Append)
+                            .short('j')
+                            .long("join")
+                            .value_name("/dns/join01.stampid.net/tcp/5757")
+                            .value_parser(MultiaddrParser::new())
+                            .help("Join an existing StampNet node when publishing. Defaults to the servers set in the config or the public StampNet servers. Can be specified multiple times."))
+                )
                 .subcommand(
                     Command::new("sync-token")
                         .about("Create and display the token used for private syncing. Generally, you only create a syncing token on one device and then use that token for multiple devices. For devices you trust, you use the full token when running `stamp agent`. For devices on you don't trust (VPS for instance) you'll want to use a blind token, retreived using `stamp keychain sync-token -b`.") 
                         .arg(id_arg("The ID of the identity we want to set up syncing for. This overrides the configured default identity."))
                         .arg(stage_arg())
+                        .arg(stage_expires_arg())
                         .arg(signwith_arg())
                         .arg(Arg::new("blind")
                             .action(ArgAction::SetTrue)
@@ -790,7 +1252,7 @@ fn run() -> Result<()> {
                 )
                 .subcommand(
                     Command::new("keyfile")
-                        .about("Back up your master key such that it can be used with the `stamp keychain passwd` command to recover your identity in the event you lose your master passphrase. This command has the ability to use Shamir's algorithm so you can split your master key into multiple parts, each of which can be saved to different location (or given to different people). Later, you can recover your master key if you have some minimum number of these parts. If you elect to use Shamir's, each key part will be output on its own line.")
+                        .about("Back up your master key such that it can be used with the `stamp keychain passwd` command to recover your identity in the event you lose your master passphrase. This command has the ability to use Shamir's algorithm so you can split your master key into multiple parts, each of which can be saved to different location (or given to different people). Later, you can recover your master key if you have some minimum number of these parts. If you elect to use Shamir's, each key part will be output on its own line, or into its own file if the output is a directory.")
                         .arg(Arg::new("shamir")
                             .short('s')
                             .long("shamir")
@@ -798,7 +1260,13 @@ fn run() -> Result<()> {
                         .arg(Arg::new("output")
                             .short('o')
                             .long("output")
-                            .help("The output file to write to. You can leave blank or use the value '-' to signify STDOUT."))
+                            .help("The output file to write to. You can leave blank or use the value '-' to signify STDOUT. If this is a directory (or ends with '/'), each key part is written to its own file alongside a manifest.json."))
+                        .arg(Arg::new("format")
+                            .short('f')
+                            .long("format")
+                            .value_parser(clap::builder::PossibleValuesParser::new(["text", "pdf"]))
+                            .default_value("text")
+                            .help("\"pdf\" renders each key part as a printable page with a QR code, the identity's fingerprint, and recovery instructions, for offline paper backups."))
                         .arg(id_arg("The ID of the identity we want to backup the master key for. This overrides the configured default identity."))
                 )
         )
@@ -814,6 +1282,160 @@ fn run() -> Result<()> {
                         .about("Creates a new policy.")
                 )
         )
+        .subcommand(
+            Command::new("audit")
+                .about("Check the identities in your local store for problems.")
+                .alias("audits")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("conflicts")
+                        .about("List emails, domains, and URLs that are claimed by more than one identity in your store. This can help catch impersonation attempts.")
+                        .arg(Arg::new("verbose")
+                            .action(ArgAction::SetTrue)
+                            .short('v')
+                            .long("verbose")
+                            .help("Show full identity IDs"))
+                )
+        )
+        .subcommand(
+            Command::new("status")
+                .about("Show the backup status of your identities: when keyfiles and full backups (`stamp dag export-all` or `stamp id export-private`) were last made and when master passphrases last changed.")
+        )
+        .subcommand(
+            Command::new("sync")
+                .about("Manage private syncing of your identity between your devices.")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("merge")
+                        .about("Merge another copy of one of your identities (for instance exported from another device) into the local one. Changes made on both sides are kept, rather than one overwriting the other.")
+                        .arg(Arg::new("LOCATION")
+                            .index(1)
+                            .required(true)
+                            .help("The file containing the identity to merge, as output by `stamp id export-private` or `stamp id publish`. Use the value '-' to signify STDIN."))
+                )
+                .subcommand(
+                    Command::new("status")
+                        .about("Show whether this device holds the sync channel key, and which of your other devices are registered.")
+                        .arg(id_arg("The ID of the identity to show sync status for. This overrides the configured default identity."))
+                )
+                .subcommand(
+                    Command::new("devices")
+                        .about("Keep track of which devices have a sync token for your identity, and kick out ones you've lost.")
+                        .alias("device")
+                        .subcommand_required(true)
+                        .arg_required_else_help(true)
+                        .subcommand(
+                            Command::new("add")
+                                .about("Register a device you're giving a sync token to.")
+                                .arg(id_arg("The ID of the identity the device syncs. This overrides the configured default identity."))
+                                .arg(Arg::new("blind")
+                                    .action(ArgAction::SetTrue)
+                                    .short('b')
+                                    .long("blind")
+                                    .help("This device gets a blind token (it can store and relay your transactions but not read them)."))
+                                .arg(Arg::new("NAME")
+                                    .required(true)
+                                    .index(1)
+                                    .help("A name for the device, ex: laptop"))
+                        )
+                        .subcommand(
+                            Command::new("list")
+                                .alias("ls")
+                                .about("List the devices registered for syncing.")
+                                .arg(id_arg("The ID of the identity to list devices for. This overrides the configured default identity."))
+                                .arg(Arg::new("revoked")
+                                    .action(ArgAction::SetTrue)
+                                    .short('r')
+                                    .long("revoked")
+                                    .help("Include revoked devices."))
+                        )
+                        .subcommand(
+                            Command::new("revoke")
+                                .about("Remove a device from syncing. This revokes the sync channel key. Sync tokens can't be created yet, so a new key can't be handed to your other devices.")
+                                .arg(id_arg("The ID of the identity the device syncs. This overrides the configured default identity."))
+                                .arg(stage_arg())
+                                .arg(stage_expires_arg())
+                                .arg(signwith_arg())
+                                .arg(Arg::new("NAME")
+                                    .required(true)
+                                    .index(1)
+                                    .help("The name of the device to revoke."))
+                        )
+                )
+        )
+        .subcommand(
+            Command::new("trust")
+                .about("Explore the web of trust formed by the stamps between locally-stored identities.")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("path")
+                        .about("Find chains of stamps connecting one identity to another, using the stamps in all locally-stored identities. Revoked and expired stamps are ignored.")
+                        .arg(Arg::new("depth")
+                            .short('d')
+                            .long("depth")
+                            .value_parser(value_parser!(usize))
+                            .default_value("6")
+                            .help("The longest chain of stamps to search for."))
+                        .arg(Arg::new("min-confidence")
+                            .short('c')
+                            .long("min-confidence")
+                            .value_parser(clap::builder::PossibleValuesParser::new(["negative", "low", "medium", "high", "ultimate"]))
+                            .default_value("low")
+                            .help("Ignore stamps with a confidence lower than this."))
+                        .arg(Arg::new("limit")
+                            .short('n')
+                            .long("limit")
+                            .value_parser(value_parser!(usize))
+                            .default_value("3")
+                            .help("How many of the best paths to show."))
+                        .arg(Arg::new("FROM")
+                            .required(true)
+                            .index(1)
+                            .help("The ID of the identity the path starts from (usually your own)."))
+                        .arg(Arg::new("TO")
+                            .required(true)
+                            .index(2)
+                            .help("The ID of the identity the path leads to."))
+                )
+                .subcommand(
+                    Command::new("score")
+                        .about("Compute how much you trust an identity, based on the paths of stamps leading to it. The algorithm, depth decay, confidence weights, and handling of negative stamps are set under [trust] in the CLI config file.")
+                        .arg(id_arg("The ID of the identity trust is computed from. This overrides the configured default identity."))
+                        .arg(Arg::new("IDENTITY")
+                            .required(true)
+                            .index(1)
+                            .help("The ID of the identity being scored."))
+                )
+                .subcommand(
+                    Command::new("graph")
+                        .about("Export the stamp graph for visualization. Identities are nodes and stamps are edges annotated with confidence and expiration.")
+                        .arg(id_arg("The ID of the identity the graph starts from. This overrides the configured default identity."))
+                        .arg(Arg::new("format")
+                            .short('f')
+                            .long("format")
+                            .value_parser(clap::builder::PossibleValuesParser::new(["dot", "graphml", "json"]))
+                            .default_value("dot")
+                            .help("The output format. \"dot\" outputs Graphviz, \"graphml\" outputs GraphML (for Gephi, yEd, etc), \"json\" outputs a list of nodes and edges."))
+                        .arg(Arg::new("depth")
+                            .short('d')
+                            .long("depth")
+                            .value_parser(value_parser!(usize))
+                            .help("Only include identities within this many stamps of the starting identity (defaults to no limit)."))
+                        .arg(Arg::new("min-confidence")
+                            .short('c')
+                            .long("min-confidence")
+                            .value_parser(clap::builder::PossibleValuesParser::new(["negative", "low", "medium", "high", "ultimate"]))
+                            .default_value("negative")
+                            .help("Ignore stamps with a confidence lower than this."))
+                        .arg(Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .help("The output file to write to. You can leave blank or use the value '-' to signify STDOUT."))
+                )
+        )
         .subcommand(
             Command::new("message")
                 .about("Allows sending and receiving encrypted messages between identities.")
@@ -840,6 +1462,16 @@ fn run() -> Result<()> {
                             .short('b')
                             .long("base64")
                             .help("If set, output the encrypted message as base64 (which is easier to put in email or a website)."))
+                        .arg(Arg::new("attach")
+                            .short('a')
+                            .long("attach")
+                            .action(ArgAction::Append)
+                            .help("Attach a file, keeping its name and type so `stamp message open` can restore it. Can be given more than once. With attachments, MESSAGE is optional."))
+                        .arg(Arg::new("armor")
+                            .action(ArgAction::SetTrue)
+                            .long("armor")
+                            .conflicts_with("base64")
+                            .help("If set, output the encrypted message as an ASCII-armored block (-----BEGIN STAMP MESSAGE-----) with a checksum, which survives being pasted into email or chat. `stamp message open` detects it automatically."))
                         .arg(id_arg("The ID of the identity we want to send from. This overrides the configured default identity."))
                         .arg(Arg::new("SEARCH")
                             .index(1)
@@ -866,7 +1498,17 @@ fn run() -> Result<()> {
                             .short('b')
                             .long("base64")
                             .help("If set, output the encrypted message as base64 (which is easier to put in email or a website)."))
-                        .arg(Arg::new("SEARCH")
+                        .arg(Arg::new("attach")
+                            .short('a')
+                            .long("attach")
+                            .action(ArgAction::Append)
+                            .help("Attach a file, keeping its name and type so `stamp message open` can restore it. Can be given more than once. With attachments, MESSAGE is optional."))
+                        .arg(Arg::new("armor")
+                            .action(ArgAction::SetTrue)
+                            .long("armor")
+                            .conflicts_with("base64")
+                            .help("If set, output the encrypted message as an ASCII-armored block (-----BEGIN STAMP MESSAGE-----) with a checksum. No recipient hint is included, since that would tell anyone who sees the message who it's for."))
+                        .arg(Arg::new("SEARCH")
                             .index(1)
                             .required(true)
                             .help("Look for the recipient by identity ID, email, or name"))
@@ -875,6 +1517,77 @@ fn run() -> Result<()> {
                             .required(false)
                             .help("The input file to read the plaintext message from. You can leave blank or use the value '-' to signify STDIN."))
                 )
+                .subcommand(
+                    Command::new("reply")
+                        .about("Reply to a message in your message history (see `stamp message list`). The reply is sent to the other side of the conversation and carries the original's message ID, so both sides can thread the conversation.")
+                        .arg(Arg::new("key-from")
+                            .short('f')
+                            .long("key-from")
+                            .help("The ID or name of the `crypto` key in your keychain you want to sign the message with. If you don't specify this, you will be prompted."))
+                        .arg(Arg::new("key-to")
+                            .short('t')
+                            .long("key-to")
+                            .help("The ID or name of the `crypto` key in the recipient's keychain that the message will be encrypted with. If you don't specify this, you will be prompted."))
+                        .arg(Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .help("The output file to write the encrypted message to. You can leave blank or use the value '-' to signify STDOUT."))
+                        .arg(Arg::new("base64")
+                            .action(ArgAction::SetTrue)
+                            .short('b')
+                            .long("base64")
+                            .help("If set, output the encrypted message as base64 (which is easier to put in email or a website)."))
+                        .arg(Arg::new("armor")
+                            .action(ArgAction::SetTrue)
+                            .long("armor")
+                            .conflicts_with("base64")
+                            .help("If set, output the encrypted message as an ASCII-armored block (-----BEGIN STAMP MESSAGE-----) with a checksum, which survives being pasted into email or chat. `stamp message open` detects it automatically."))
+                        .arg(Arg::new("attach")
+                            .short('a')
+                            .long("attach")
+                            .action(ArgAction::Append)
+                            .help("Attach a file, keeping its name and type so `stamp message open` can restore it. Can be given more than once. With attachments, MESSAGE is optional."))
+                        .arg(id_arg("The ID of the identity we want to reply from. This overrides the configured default identity."))
+                        .arg(Arg::new("ID")
+                            .index(1)
+                            .required(true)
+                            .help("The ID of the message we're replying to, from `stamp message list`."))
+                        .arg(Arg::new("MESSAGE")
+                            .index(2)
+                            .required(false)
+                            .help("The input file to read the plaintext reply from. You can leave blank or use the value '-' to signify STDIN."))
+                )
+                .subcommand(
+                    Command::new("receipt")
+                        .about("Send a read receipt for a message you received. The receipt is a message signed by you, so the sender knows it really came from you. When they open it, their copy of the original is marked as read.")
+                        .arg(Arg::new("key-from")
+                            .short('f')
+                            .long("key-from")
+                            .help("The ID or name of the `crypto` key in your keychain you want to sign the message with. If you don't specify this, you will be prompted."))
+                        .arg(Arg::new("key-to")
+                            .short('t')
+                            .long("key-to")
+                            .help("The ID or name of the `crypto` key in the recipient's keychain that the message will be encrypted with. If you don't specify this, you will be prompted."))
+                        .arg(Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .help("The output file to write the encrypted message to. You can leave blank or use the value '-' to signify STDOUT."))
+                        .arg(Arg::new("base64")
+                            .action(ArgAction::SetTrue)
+                            .short('b')
+                            .long("base64")
+                            .help("If set, output the encrypted message as base64 (which is easier to put in email or a website)."))
+                        .arg(Arg::new("armor")
+                            .action(ArgAction::SetTrue)
+                            .long("armor")
+                            .conflicts_with("base64")
+                            .help("If set, output the encrypted message as an ASCII-armored block (-----BEGIN STAMP MESSAGE-----) with a checksum, which survives being pasted into email or chat. `stamp message open` detects it automatically."))
+                        .arg(id_arg("The ID of the identity that received the message. This overrides the configured default identity."))
+                        .arg(Arg::new("ID")
+                            .index(1)
+                            .required(true)
+                            .help("The ID of the message to acknowledge, from `stamp message list`."))
+                )
                 .subcommand(
                     Command::new("open")
                         .about("Open a message from another identity. This can be either a signed message or anonymous, although if the message is signed then the sender's identity must be imported.")
@@ -886,12 +1599,145 @@ fn run() -> Result<()> {
                             .short('o')
                             .long("output")
                             .help("The output file to write the plaintext message to. You can leave blank or use the value '-' to signify STDOUT."))
+                        .arg(Arg::new("save-attachments")
+                            .long("save-attachments")
+                            .value_name("DIR")
+                            .help("Save any files attached to the message into this directory, under their original names. Without this, attachments are only listed."))
+                        .arg(Arg::new("yes")
+                            .action(ArgAction::SetTrue)
+                            .short('y')
+                            .long("yes")
+                            .help("Stage a transaction carried by the message without asking first. The sender still has to hold one of the identity's admin keys."))
                         .arg(id_arg("The ID of the identity the message was sent to. This overrides the configured default identity."))
                         .arg(Arg::new("ENCRYPTED")
                             .index(1)
                             .required(false)
                             .help("The input file to read the encrypted message from. You can leave blank or use the value '-' to signify STDIN."))
                 )
+                .subcommand(
+                    Command::new("list")
+                        .alias("ls")
+                        .about("List the messages you've sent and opened. Opened messages are kept along with who sent them and whether their signature checked out, and sent messages are kept in an outbox.")
+                        .arg(id_arg("The ID of the identity whose messages we're listing. This overrides the configured default identity."))
+                        .arg(Arg::new("in")
+                            .action(ArgAction::SetTrue)
+                            .long("in")
+                            .conflicts_with("out")
+                            .help("Only list messages you received."))
+                        .arg(Arg::new("out")
+                            .action(ArgAction::SetTrue)
+                            .long("out")
+                            .help("Only list messages you sent."))
+                        .arg(Arg::new("verbose")
+                            .action(ArgAction::SetTrue)
+                            .short('v')
+                            .long("verbose")
+                            .help("Use long-form identity IDs."))
+                        .arg(Arg::new("SEARCH")
+                            .index(1)
+                            .required(false)
+                            .help("Only list messages with this identity (by identity ID, email, or name)."))
+                )
+                .subcommand(
+                    Command::new("read")
+                        .about("Read a message from your message history. Received messages are opened and verified again, sent messages are opened from the copy sealed to your own `crypto` key.")
+                        .arg(id_arg("The ID of the identity the message belongs to. This overrides the configured default identity."))
+                        .arg(Arg::new("key-open")
+                            .short('k')
+                            .long("key-open")
+                            .help("The ID or name of the `crypto` key in your keychain that received messages are opened with. If you don't specify this, you will be prompted."))
+                        .arg(Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .help("The output file to write the plaintext message to. You can leave blank or use the value '-' to signify STDOUT."))
+                        .arg(Arg::new("save-attachments")
+                            .long("save-attachments")
+                            .value_name("DIR")
+                            .help("Save any files attached to the message into this directory, under their original names. Without this, attachments are only listed."))
+                        .arg(Arg::new("MESSAGE")
+                            .index(1)
+                            .required(true)
+                            .help("The message ID, from `stamp message list`."))
+                )
+                .subcommand(
+                    Command::new("delete")
+                        .alias("rm")
+                        .about("Remove a message from your message history.")
+                        .arg(id_arg("The ID of the identity the message belongs to. This overrides the configured default identity."))
+                        .arg(Arg::new("yes")
+                            .action(ArgAction::SetTrue)
+                            .short('y')
+                            .long("yes")
+                            .help("Do not confirm deletion, just delete."))
+                        .arg(Arg::new("MESSAGE")
+                            .index(1)
+                            .required(true)
+                            .help("The message ID, from `stamp message list`."))
+                )
+                .subcommand(
+                    Command::new("export-session")
+                        .about("Export your message history with another identity as a signed archive, for keeping records. Messages you received are decrypted and verified. Messages you sent are sealed to the recipient, so they're included in sealed form.")
+                        .arg(id_arg("The ID of the identity whose messages we're exporting. This overrides the configured default identity."))
+                        .arg(Arg::new("key-open")
+                            .long("key-open")
+                            .help("The ID or name of the `crypto` key in your keychain used to open received messages. If you don't specify this, you will be prompted."))
+                        .arg(Arg::new("key-sign")
+                            .long("key-sign")
+                            .help("The ID or name of the `sign` key in your keychain used to sign the archive. If you don't specify this, you will be prompted."))
+                        .arg(Arg::new("encrypt")
+                            .action(ArgAction::SetTrue)
+                            .short('e')
+                            .long("encrypt")
+                            .help("Seal the archive to your own `crypto` key (output as base64). Open it later with `stamp message open`."))
+                        .arg(Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .help("The output file to write the archive to. You can leave blank or use the value '-' to signify STDOUT."))
+                        .arg(Arg::new("SEARCH")
+                            .index(1)
+                            .required(true)
+                            .help("The other side of the conversation, by identity ID, email, or name"))
+                )
+        )
+        .subcommand(
+            Command::new("crypt")
+                .about("Encrypt and decrypt your own files with `secret` keys from your keychain. No other identity is involved, this is for keeping things private to yourself. Create a key with `stamp keychain new secret <NAME>`.")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("encrypt")
+                        .about("Encrypt a file with one of your `secret` keys.")
+                        .arg(id_arg("The ID of the identity that owns the key. This overrides the configured default identity."))
+                        .arg(Arg::new("key")
+                            .short('k')
+                            .long("key")
+                            .help("The ID or name of the `secret` key in your keychain to encrypt with. If you don't specify this, you will be prompted."))
+                        .arg(Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .help("The output file to write the encrypted data to. You can leave blank or use the value '-' to signify STDOUT."))
+                        .arg(Arg::new("armor")
+                            .action(ArgAction::SetTrue)
+                            .long("armor")
+                            .help("If set, output an ASCII-armored block (-----BEGIN STAMP ENCRYPTED FILE-----) with a checksum instead of binary."))
+                        .arg(Arg::new("FILE")
+                            .index(1)
+                            .required(false)
+                            .help("The file to encrypt. You can leave blank or use the value '-' to signify STDIN."))
+                )
+                .subcommand(
+                    Command::new("decrypt")
+                        .about("Decrypt a file made with `stamp crypt encrypt`. The key is taken from the file, so this works even if the key has since been revoked.")
+                        .arg(id_arg("The ID of the identity that owns the key. This overrides the configured default identity."))
+                        .arg(Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .help("The output file to write the decrypted data to. You can leave blank or use the value '-' to signify STDOUT."))
+                        .arg(Arg::new("FILE")
+                            .index(1)
+                            .required(false)
+                            .help("The encrypted file (binary or armored). You can leave blank or use the value '-' to signify STDIN."))
+                )
         )
         .subcommand(
             Command::new("sign")
@@ -907,6 +1753,7 @@ fn run() -> Result<()> {
                         .alias("id")
                         .arg(id_arg("The ID of the identity we want to sign from. This overrides the configured default identity."))
                         .arg(stage_arg())
+                        .arg(stage_expires_arg())
                         .arg(signwith_arg())
                         .arg(Arg::new("output")
                             .short('o')
@@ -917,11 +1764,45 @@ fn run() -> Result<()> {
                             .short('b')
                             .long("base64")
                             .help("If set, output the signature as base64 (which is easier to put in email or a website)."))
+                        .arg(Arg::new("armor")
+                            .action(ArgAction::SetTrue)
+                            .long("armor")
+                            .conflicts_with("base64")
+                            .help("If set, output the signature as an ASCII-armored block (-----BEGIN STAMP SIGNATURE-----) with a checksum. `stamp sign verify` detects it automatically."))
+                        .arg(Arg::new("clearsign")
+                            .action(ArgAction::SetTrue)
+                            .short('c')
+                            .long("clearsign")
+                            .conflicts_with_all(["base64", "armor", "stage", "format"])
+                            .help("Output a human-readable document containing the original text followed by an armored signature (like PGP clearsign). `stamp sign verify` can check it directly. The message must be UTF-8 text."))
+                        .arg(format_arg())
                         .arg(Arg::new("MESSAGE")
                             .index(1)
                             .required(false)
                             .help("The input file to read the data from. You can leave blank or use the value '-' to signify STDIN."))
                 )
+                .subcommand(
+                    Command::new("finish")
+                        .about("Write out a policy signature that was created with `stamp sign policy --stage` once enough admins have signed it (with `stamp stage sign`). This is how a group identity signs a document that needs, for instance, 3 of 5 admin keys. The signature is removed from staging afterwards.")
+                        .arg(Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .help("The output file to write the signature to. You can leave blank or use the value '-' to signify STDOUT."))
+                        .arg(Arg::new("base64")
+                            .action(ArgAction::SetTrue)
+                            .short('b')
+                            .long("base64")
+                            .help("If set, output the signature as base64 (which is easier to put in email or a website)."))
+                        .arg(Arg::new("armor")
+                            .action(ArgAction::SetTrue)
+                            .long("armor")
+                            .conflicts_with("base64")
+                            .help("If set, output the signature as an ASCII-armored block (-----BEGIN STAMP SIGNATURE-----) with a checksum. `stamp sign verify` detects it automatically."))
+                        .arg(Arg::new("TXID")
+                            .index(1)
+                            .required(true)
+                            .help("The ID of the staged signature transaction."))
+                )
                 .subcommand(
                     Command::new("subkey")
                         .about("Sign a message or document with one of your `sign` subkeys. This type of signature carries less weight than a `policy` signature and only proves you have access to one of the identity's subkeys. Use this for less official signatures.")
@@ -945,23 +1826,177 @@ fn run() -> Result<()> {
                             .short('b')
                             .long("base64")
                             .help("If set, output the signature as base64 (which is easier to put in email or a website)."))
+                        .arg(Arg::new("armor")
+                            .action(ArgAction::SetTrue)
+                            .long("armor")
+                            .conflicts_with("base64")
+                            .help("If set, output the signature as an ASCII-armored block (-----BEGIN STAMP SIGNATURE-----) with a checksum. `stamp sign verify` detects it automatically."))
+                        .arg(Arg::new("clearsign")
+                            .action(ArgAction::SetTrue)
+                            .short('c')
+                            .long("clearsign")
+                            .conflicts_with_all(["base64", "armor", "attached", "format"])
+                            .help("Output a human-readable document containing the original text followed by an armored signature (like PGP clearsign). `stamp sign verify` can check it directly. The message must be UTF-8 text."))
+                        .arg(format_arg())
                         .arg(id_arg("The ID of the identity we want to sign from. This overrides the configured default identity."))
                         .arg(Arg::new("MESSAGE")
                             .index(1)
                             .required(false)
                             .help("The input file to read the data from. You can leave blank or use the value '-' to signify STDIN."))
                 )
+                .subcommand(
+                    Command::new("ssh")
+                        .about("Sign a file with one of your `sign` subkeys, producing an SSH signature (-----BEGIN SSH SIGNATURE-----) that can be checked with `ssh-keygen -Y verify` or `stamp sign verify`. Use `stamp keychain export-ssh --public` to get the key for an allowed_signers file.")
+                        .arg(Arg::new("key-sign")
+                            .short('k')
+                            .long("key-sign")
+                            .help("The ID or name of the `sign` key you wish to sign with. If you don't specify this, you will be prompted."))
+                        .arg(Arg::new("namespace")
+                            .short('n')
+                            .long("namespace")
+                            .default_value("file")
+                            .help("The signature namespace, which keeps signatures made for one purpose from being used for another (ex: file, git, email)."))
+                        .arg(Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .help("The output file to write the signature to. You can leave blank or use the value '-' to signify STDOUT."))
+                        .arg(id_arg("The ID of the identity we want to sign from. This overrides the configured default identity."))
+                        .arg(Arg::new("MESSAGE")
+                            .index(1)
+                            .required(false)
+                            .help("The input file to read the data from. You can leave blank or use the value '-' to signify STDIN."))
+                )
+                .subcommand(
+                    Command::new("batch")
+                        .about("Sign many files at once with one of your `sign` subkeys, entering your passphrase only once. By default each file gets its own detached signature (FILE.sig) in the output directory, along with a manifest.json. With --manifest, a single clearsigned list of file hashes is produced instead.")
+                        .arg(Arg::new("key-sign")
+                            .short('k')
+                            .long("key-sign")
+                            .help("The ID or name of the `sign` key you wish to sign with. If you don't specify this, you will be prompted."))
+                        .arg(Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .help("The directory to write signatures to, or with --manifest, the file to write the signed manifest to (defaults to STDOUT)."))
+                        .arg(Arg::new("manifest")
+                            .action(ArgAction::SetTrue)
+                            .short('m')
+                            .long("manifest")
+                            .help("Produce one clearsigned manifest with the blake3 hash of every file instead of a signature per file. Paths in the manifest are relative to its directory (or the current directory when writing to STDOUT), and every file must be inside it. `stamp sign verify` checks the manifest's signature."))
+                        .arg(Arg::new("armor")
+                            .action(ArgAction::SetTrue)
+                            .long("armor")
+                            .conflicts_with("manifest")
+                            .help("If set, write each signature as an ASCII-armored block."))
+                        .arg(id_arg("The ID of the identity we want to sign from. This overrides the configured default identity."))
+                        .arg(Arg::new("FILE")
+                            .index(1)
+                            .action(ArgAction::Append)
+                            .required(true)
+                            .help("The files to sign. Directories are signed recursively (hidden files are skipped)."))
+                )
+                .subcommand(
+                    Command::new("tree")
+                        .about("Sign a directory tree with one of your `sign` subkeys. The path, permissions, and hash of every file (hidden files are skipped) go into a manifest, which is clearsigned. Check it later with `stamp sign verify-tree`.")
+                        .arg(Arg::new("key-sign")
+                            .short('k')
+                            .long("key-sign")
+                            .help("The ID or name of the `sign` key you wish to sign with. If you don't specify this, you will be prompted."))
+                        .arg(Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .help("The output file to write the signed manifest to. You can leave blank or use the value '-' to signify STDOUT."))
+                        .arg(id_arg("The ID of the identity we want to sign from. This overrides the configured default identity."))
+                        .arg(Arg::new("DIR")
+                            .index(1)
+                            .required(true)
+                            .help("The directory to sign."))
+                )
+                .subcommand(
+                    Command::new("verify-tree")
+                        .about("Verify a signature from `stamp sign tree` and compare it to a directory, listing files that were added, removed, modified, or had their permissions changed. Exits with an error if anything changed.")
+                        .arg(Arg::new("SIGNATURE")
+                            .index(1)
+                            .required(true)
+                            .help("The signed manifest from `stamp sign tree`. Use the value '-' to signify STDIN."))
+                        .arg(Arg::new("DIR")
+                            .index(2)
+                            .required(true)
+                            .help("The directory to check."))
+                )
                 .subcommand(
                     Command::new("verify")
-                        .about("Verify a signature. This can verify both policy and subkey signatures. This requires having the signing identity imported.")
+                        .about("Verify a signature. This can verify policy, subkey, and SSH signatures. This requires having the signing identity imported.")
                         .arg(Arg::new("SIGNATURE")
                             .index(1)
                             .required(true)
-                            .help("The input file to read the signature from. If the signature is deattached, you will also need to specify the MESSAGE argument. You can leave blank or use the value '-' to signify STDIN."))
+                            .help("The input file to read the signature from. If the signature is deattached, you will also need to specify the MESSAGE argument (not needed for clearsigned documents). You can leave blank or use the value '-' to signify STDIN."))
                         .arg(Arg::new("MESSAGE")
                             .index(2)
                             .required(false)
                             .help("The input file to read the plaintext message from. You can leave blank or use the value '-' to signify STDIN."))
+                        .arg(Arg::new("namespace")
+                            .short('n')
+                            .long("namespace")
+                            .default_value("file")
+                            .help("The namespace an SSH signature must have been made for."))
+                        .arg(Arg::new("json")
+                            .action(ArgAction::SetTrue)
+                            .long("json")
+                            .help("Print the result as JSON: the signer's identity, key ID and name, signature type, creation time (for policy signatures), and whether it's valid. Failures are reported in the JSON's `error` field and the exit code, with nothing printed to STDERR."))
+                        .after_help("Exit codes: 0 if the signature is valid, 1 if it's invalid, 3 if the signing identity or key isn't imported, and 4 if the signature or message couldn't be read.")
+                )
+        )
+        .subcommand(
+            Command::new("search")
+                .about("Search every local identity: names, emails, public claim values, claim names, key names and descriptions, and stamp IDs. Each result comes with the command to act on it.")
+                .arg(Arg::new("QUERY")
+                    .index(1)
+                    .required(true)
+                    .help("The text to search for (case-insensitive)."))
+        )
+        .subcommand(
+            Command::new("release")
+                .about("Sign and verify software releases with SHA256SUMS files")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("sign")
+                        .about("Hash a set of release artifacts into a SHA256SUMS file and sign it with one of your `sign` subkeys, writing SHA256SUMS and SHA256SUMS.sig (and optionally SHA256SUMS.sshsig). Publish these next to the artifacts.")
+                        .arg(Arg::new("key-sign")
+                            .short('k')
+                            .long("key-sign")
+                            .help("The ID or name of the `sign` key you wish to sign with. If you don't specify this, you will be prompted."))
+                        .arg(Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .default_value(".")
+                            .help("The directory to write the SHA256SUMS files to."))
+                        .arg(Arg::new("ssh")
+                            .action(ArgAction::SetTrue)
+                            .long("ssh")
+                            .help("Also write an SSH signature (SHA256SUMS.sshsig, namespace \"file\") that can be checked with `ssh-keygen -Y verify`."))
+                        .arg(id_arg("The ID of the identity we want to sign from. This overrides the configured default identity."))
+                        .arg(Arg::new("ARTIFACT")
+                            .index(1)
+                            .action(ArgAction::Append)
+                            .required(true)
+                            .help("The release artifacts to sign. Directories are read recursively (hidden files are skipped). Artifacts inside the output directory are listed by their path relative to it, others by file name."))
+                )
+                .subcommand(
+                    Command::new("verify")
+                        .about("Verify the signature on a SHA256SUMS file, then check the hash of each artifact it lists. Artifact paths are relative to the directory SHA256SUMS is in. Exits with an error if anything fails.")
+                        .arg(Arg::new("signature")
+                            .short('s')
+                            .long("signature")
+                            .help("The Stamp signature of the sums file. Defaults to the sums file with .sig on the end."))
+                        .arg(Arg::new("ignore-missing")
+                            .action(ArgAction::SetTrue)
+                            .long("ignore-missing")
+                            .help("Don't fail for artifacts that aren't present (for when you only downloaded some of them)."))
+                        .arg(Arg::new("SUMS")
+                            .index(1)
+                            .default_value("SHA256SUMS")
+                            .help("The SHA256SUMS file to check."))
                 )
         )
         .subcommand(
@@ -969,6 +2004,70 @@ fn run() -> Result<()> {
                 .about("Allows manipulation of the local configuration.")
                 .subcommand_required(true)
                 .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("net")
+                        .about("Manage which StampNet nodes you connect to. If no join list is configured, the public StampNet bootstrap servers are used unless --no-default-bootstrap is set.")
+                        .arg(Arg::new("no-default-bootstrap")
+                            .action(ArgAction::SetTrue)
+                            .long("no-default-bootstrap")
+                            .conflicts_with("default-bootstrap")
+                            .help("Never fall back to the public StampNet bootstrap servers. Saved to the config."))
+                        .arg(Arg::new("default-bootstrap")
+                            .action(ArgAction::SetTrue)
+                            .long("default-bootstrap")
+                            .help("Fall back to the public StampNet bootstrap servers when no join list is configured (the default). Saved to the config."))
+                        .subcommand(
+                            Command::new("add-join")
+                                .about("Add a node to the StampNet join list.")
+                                .arg(Arg::new("SERVER")
+                                    .required(true)
+                                    .index(1)
+                                    .value_name("/dns/join01.stampid.net/tcp/5757")
+                                    .value_parser(MultiaddrParser::new())
+                                    .help("The StampNet Multiaddr to add."))
+                        )
+                        .subcommand(
+                            Command::new("remove-join")
+                                .about("Remove a node from the StampNet join list.")
+                                .arg(Arg::new("SERVER")
+                                    .required(true)
+                                    .index(1)
+                                    .value_name("/dns/join01.stampid.net/tcp/5757")
+                                    .value_parser(MultiaddrParser::new())
+                                    .help("The StampNet Multiaddr to remove."))
+                        )
+                        .subcommand(
+                            Command::new("list-join")
+                                .about("List the StampNet nodes that will be joined by default.")
+                        )
+                )
+                .subcommand(
+                    Command::new("dns")
+                        .about("Set how DNS lookups for domain claim checks are done. By default the system resolver is used. With no options, shows the current settings.")
+                        .arg(Arg::new("resolver")
+                            .long("resolver")
+                            .value_name("IP[:PORT]")
+                            .conflicts_with_all(["doh", "system"])
+                            .help("Use this DNS resolver for domain claim checks."))
+                        .arg(Arg::new("doh")
+                            .long("doh")
+                            .value_name("cloudflare|google|quad9|IP#HOSTNAME")
+                            .conflicts_with("system")
+                            .help("Use DNS-over-HTTPS for domain claim checks, either with a known provider or a custom endpoint (HOSTNAME is the name on its TLS certificate)."))
+                        .arg(Arg::new("system")
+                            .action(ArgAction::SetTrue)
+                            .long("system")
+                            .help("Go back to using the system resolver."))
+                        .arg(Arg::new("dnssec")
+                            .action(ArgAction::SetTrue)
+                            .long("dnssec")
+                            .conflicts_with("no-dnssec")
+                            .help("Require DNSSEC validation when checking domain claims."))
+                        .arg(Arg::new("no-dnssec")
+                            .action(ArgAction::SetTrue)
+                            .long("no-dnssec")
+                            .help("Don't require DNSSEC validation (the default)."))
+                )
                 .subcommand(
                     Command::new("set-default")
                         .about("Set the default identity ID used for many of the other commands")
@@ -977,6 +2076,45 @@ fn run() -> Result<()> {
                             .index(1)
                             .help("An identity ID, name, or email to search for when deleting."))
                 )
+                .subcommand(
+                    Command::new("set-backup-reminder")
+                        .about("Remind you (after running commands) when the default identity's keyfile backup is missing, stale, or invalidated by a passphrase change.")
+                        .arg(Arg::new("DAYS")
+                            .required(true)
+                            .index(1)
+                            .value_parser(value_parser!(u64))
+                            .help("Consider backups older than this many days stale. Use 0 to disable reminders."))
+                )
+                .subcommand(
+                    Command::new("set-export-cooldown")
+                        .about("Limit how often secret material (keyfiles, private exports) can be exported for an identity. Every such export also requires typing the identity's short ID.")
+                        .arg(Arg::new("MINUTES")
+                            .required(true)
+                            .index(1)
+                            .value_parser(value_parser!(u64))
+                            .help("Minimum minutes between secret exports for the same identity. Use 0 to disable."))
+                )
+                .subcommand(
+                    Command::new("keyring")
+                        .about("Cache master keys in the OS keyring (Secret Service, macOS Keychain, Windows Credential Manager) for a while after unlocking an identity, so you don't have to type your passphrase every time. Master keys are never written to disk in plaintext. Without options, shows the current setting.")
+                        .arg(Arg::new("ttl")
+                            .long("ttl")
+                            .value_name("MINUTES")
+                            .value_parser(value_parser!(u64))
+                            .help("How many minutes an unlocked master key stays cached. Use 0 to turn caching off (this also clears cached keys)."))
+                        .arg(Arg::new("forget")
+                            .action(ArgAction::SetTrue)
+                            .long("forget")
+                            .help("Remove all cached master keys from the OS keyring now."))
+                )
+                .subcommand(
+                    Command::new("set-phone-region")
+                        .about("Set the default region used to normalize phone numbers entered without a country code (ex US, GB, DE).")
+                        .arg(Arg::new("REGION")
+                            .required(true)
+                            .index(1)
+                            .help("A two-letter region code, ex: US"))
+                )
                 .subcommand(
                     Command::new("set-stampnet-servers")
                         .about("Set the default StampNet servers used for the `stamp net` or `stamp id import` commands.")
@@ -996,7 +2134,16 @@ fn run() -> Result<()> {
                 .subcommand(
                     Command::new("list")
                         .alias("ls")
-                        .about("List the staged transactions for an identity.")
+                        .about("List the staged transactions for an identity, with how many signatures each has and whether it's ready to apply.")
+                        .arg(Arg::new("ready")
+                            .action(ArgAction::SetTrue)
+                            .long("ready")
+                            .help("Only list transactions that have all their signatures and can be applied (or written out with `stamp sign finish`)."))
+                        .arg(Arg::new("verbose")
+                            .action(ArgAction::SetTrue)
+                            .short('v')
+                            .long("verbose")
+                            .help("Also show why transactions that aren't ready yet fail verification (usually which policy signatures are missing), and which admin keys have and haven't signed."))
                         .arg(id_arg("The ID of the identity we want to see staged transactions for. This overrides the configured default identity."))
                 )
                 .subcommand(
@@ -1007,6 +2154,31 @@ fn run() -> Result<()> {
                             .required(true)
                             .help("The transaction ID you wish to view."))
                 )
+                .subcommand(
+                    Command::new("comment")
+                        .about("Leave a note on a staged transaction, for instance why you did or didn't sign it. Notes show up in `stamp stage view` and are sent along with the transaction by `stamp stage send`.")
+                        .arg(id_arg("The ID of the identity leaving the comment. This overrides the configured default identity."))
+                        .arg(Arg::new("local")
+                            .action(ArgAction::SetTrue)
+                            .long("local")
+                            .help("Keep this comment on this device only, don't send it with the transaction."))
+                        .arg(Arg::new("TXID")
+                            .index(1)
+                            .required(true)
+                            .help("The transaction ID you're commenting on."))
+                        .arg(Arg::new("NOTE")
+                            .index(2)
+                            .required(true)
+                            .help("The comment text."))
+                )
+                .subcommand(
+                    Command::new("analyze")
+                        .about("Check a staged transaction against its identity: list which admin keys have signed it and which local identities hold the ones that haven't. If it isn't ready, also test-sign it with combinations of the identity's own admin keys to find which sets of signatures would complete it. Nothing is saved.")
+                        .arg(Arg::new("TXID")
+                            .index(1)
+                            .required(true)
+                            .help("The transaction ID you wish to analyze."))
+                )
                 .subcommand(
                     Command::new("export")
                         .about("Export a staged transaction (usually so it can be sent to someone else who needs to sign it). If this transaction contains private data, you'll be prompted to enter your master passphrase then enter a new passphrase to re-encrypt the transaction.")
@@ -1019,6 +2191,16 @@ fn run() -> Result<()> {
                             .short('b')
                             .long("base64")
                             .help("If set, output the staged transaction as base64 (which is easier to put in email or chat)."))
+                        .arg(Arg::new("armor")
+                            .action(ArgAction::SetTrue)
+                            .long("armor")
+                            .conflicts_with("base64")
+                            .help("If set, output the staged transaction as an ASCII-armored block (-----BEGIN STAMP TRANSACTION-----) with a checksum, so damage from copy/paste is caught on import."))
+                        .arg(Arg::new("qr")
+                            .action(ArgAction::SetTrue)
+                            .long("qr")
+                            .conflicts_with_all(["output", "base64", "armor"])
+                            .help("Show the transaction on the terminal as QR codes, for getting it to an offline machine. Larger transactions are split into frames that cycle until you hit ctrl+c. Read them back with `stamp stage import --qr-frames`."))
                         .arg(Arg::new("TXID")
                             .index(1)
                             .required(true)
@@ -1029,10 +2211,49 @@ fn run() -> Result<()> {
                         .about("Import a staged transaction that was previously exported. This is generally done so you can sign it. If the transaction contains private data, you will be prompted to input the passphrase that was used during export.")
                         .arg(id_arg("The ID of the identity we want to import the transaction into. This must be specified.")
                             .required(true))
+                        .arg(Arg::new("qr-frames")
+                            .long("qr-frames")
+                            .num_args(1..)
+                            .value_name("file")
+                            .help("Read the transaction from QR frames made by `stamp stage export --qr` instead. Each file is either an image of one or more frames or a text file of scanned frames (one per line), in any order."))
                         .arg(Arg::new("TRANSACTION")
                             .index(1)
+                            .required_unless_present("qr-frames")
+                            .conflicts_with("qr-frames")
+                            .help("The input file to read the exported transaction from (binary, base64, or armored). You can leave blank or use the value '-' to signify STDIN."))
+                )
+                .subcommand(
+                    Command::new("send")
+                        .about("Send a staged transaction to a co-signer as a sealed message (see `stamp message`). When they open the message, the transaction is put into their staging area so they can sign it. Transactions with private data must go through `stamp stage export` instead.")
+                        .arg(Arg::new("to")
+                            .long("to")
                             .required(true)
-                            .help("The input file to read the exported transaction from. You can leave blank or use the value '-' to signify STDIN."))
+                            .help("Look for the co-signer by identity ID, email, or name"))
+                        .arg(Arg::new("key-from")
+                            .short('f')
+                            .long("key-from")
+                            .help("The ID or name of the `crypto` key in your keychain you want to sign the message with. If you don't specify this, you will be prompted."))
+                        .arg(Arg::new("key-to")
+                            .short('t')
+                            .long("key-to")
+                            .help("The ID or name of the `crypto` key in the co-signer's keychain that the message will be encrypted with. If you don't specify this, you will be prompted."))
+                        .arg(Arg::new("note")
+                            .short('m')
+                            .long("note")
+                            .help("A note to the co-signer, sent as the message body."))
+                        .arg(Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .help("The output file to write the sealed message to. You can leave blank or use the value '-' to signify STDOUT."))
+                        .arg(Arg::new("armor")
+                            .action(ArgAction::SetTrue)
+                            .long("armor")
+                            .help("If set, output the sealed message as an ASCII-armored block."))
+                        .arg(id_arg("The ID of the identity we want to send from. This overrides the configured default identity."))
+                        .arg(Arg::new("TXID")
+                            .index(1)
+                            .required(true)
+                            .help("The transaction ID you are sending."))
                 )
                 .subcommand(
                     Command::new("delete")
@@ -1044,6 +2265,16 @@ fn run() -> Result<()> {
                             .required(true)
                             .help("The transaction ID you wish to view."))
                 )
+                .subcommand(
+                    Command::new("prune")
+                        .about("Delete an identity's staged transactions that are past their expiration (see --stage-expires).")
+                        .arg(Arg::new("yes")
+                            .action(ArgAction::SetTrue)
+                            .short('y')
+                            .long("yes")
+                            .help("Don't ask for confirmation."))
+                        .arg(id_arg("The ID of the identity we want to prune staged transactions for. This overrides the configured default identity."))
+                )
                 .subcommand(
                     Command::new("sign")
                         .about("Sign a staged transaction with one of our keys.")
@@ -1057,9 +2288,26 @@ fn run() -> Result<()> {
                     Command::new("apply")
                         .about("Apply a staged transaction that has a valid set of signatures to its identity. If successful, the transaction will be removed from staging.")
                         .alias("commit")
+                        .arg(Arg::new("all-ready")
+                            .action(ArgAction::SetTrue)
+                            .long("all-ready")
+                            .conflicts_with_all(["TXID", "ids"])
+                            .help("Apply every staged transaction for the identity that has all its signatures, in dependency order. Transactions still waiting on signatures are left alone."))
+                        .arg(Arg::new("ids")
+                            .long("ids")
+                            .value_delimiter(',')
+                            .action(ArgAction::Append)
+                            .conflicts_with("TXID")
+                            .help("A comma-separated list of transaction IDs to apply, in dependency order. Stops at the first one that isn't ready."))
+                        .arg(Arg::new("dry-run")
+                            .action(ArgAction::SetTrue)
+                            .long("dry-run")
+                            .conflicts_with_all(["all-ready", "ids"])
+                            .help("Show the changes (keys added or revoked, claims added or removed, policies added or removed) applying the transaction would make to the identity, without saving anything. Works before the transaction is fully signed."))
+                        .arg(id_arg("The ID of the identity to apply transactions to with --all-ready/--ids. This overrides the configured default identity."))
                         .arg(Arg::new("TXID")
                             .index(1)
-                            .required(true)
+                            .required_unless_present_any(["all-ready", "ids"])
                             .help("The transaction ID you wish to apply."))
                 )
         )
@@ -1076,6 +2324,39 @@ fn run() -> Result<()> {
                             .short('i')
                             .long("input")
                             .help("If your identity requires multiple signatures to create a publish transaction, then you would run `stamp id publish`, collect the needed signatures, and then use this argument to reference the final publish transaction file which is created via `stamp stage export <transactionid>`."))
+                        .arg(Arg::new("auto")
+                            .action(ArgAction::SetTrue)
+                            .long("auto")
+                            .help("Remember this publish so `stamp net republish` can send it again before it expires. The identity is only re-signed if it changed since the last publish."))
+                        .arg(Arg::new("interval")
+                            .long("interval")
+                            .value_name("DAYS")
+                            .default_value("30")
+                            .value_parser(value_parser!(u64))
+                            .requires("auto")
+                            .help("How often (in days) to republish when using --auto."))
+                        .arg(Arg::new("join")
+                            .action(ArgAction::Append)
+                            .short('j')
+                            .long("join")
+                            .value_name("/dns/join01.stampid.net/tcp/5757")
+                            .value_parser(MultiaddrParser::new())
+                            .help("Join an existing StampNet node. This will allow you to connect to the rest of the network. Defaults to the servers set in the config or the public StampNet servers. Can be specified multiple times."))
+                )
+                .subcommand(
+                    Command::new("doctor")
+                        .about("Diagnose StampNet connectivity problems: checks that bootstrap nodes are reachable, whether you're behind NAT, whether a bind address is usable, and how long DHT lookups take. Prints advice for anything that looks wrong.")
+                        .arg(Arg::new("bind")
+                            .short('b')
+                            .long("bind")
+                            .value_name("/ip4/0.0.0.0/tcp/5757")
+                            .value_parser(MultiaddrParser::new())
+                            .help("A bind address (as used by `stamp net node`) to check."))
+                        .arg(Arg::new("lookup")
+                            .short('l')
+                            .long("lookup")
+                            .value_name("IDENTITY ID")
+                            .help("A full identity ID to look up in the DHT to measure lookup latency."))
                         .arg(Arg::new("join")
                             .action(ArgAction::Append)
                             .short('j')
@@ -1085,9 +2366,65 @@ fn run() -> Result<()> {
                             .help("Join an existing StampNet node. This will allow you to connect to the rest of the network. Defaults to the servers set in the config or the public StampNet servers. Can be specified multiple times."))
                 )
                 .subcommand(
-                    Command::new("get")
-                        .alias("lookup")
-                        .about("Get an identity stored in the StampNet network.")
+                    Command::new("republish")
+                        .about("Republish identities set up with `stamp net publish --auto` whose interval has passed. Good to run from cron or a scheduled task.")
+                        .arg(Arg::new("force")
+                            .action(ArgAction::SetTrue)
+                            .short('f')
+                            .long("force")
+                            .help("Republish even if the interval hasn't passed yet."))
+                        .arg(Arg::new("join")
+                            .action(ArgAction::Append)
+                            .short('j')
+                            .long("join")
+                            .value_name("/dns/join01.stampid.net/tcp/5757")
+                            .value_parser(MultiaddrParser::new())
+                            .help("Join an existing StampNet node. This will allow you to connect to the rest of the network. Defaults to the servers set in the config or the public StampNet servers. Can be specified multiple times."))
+                )
+                .subcommand(
+                    Command::new("get")
+                        .alias("lookup")
+                        .about("Get an identity stored in the StampNet network.")
+                        .arg(Arg::new("join")
+                            .action(ArgAction::Append)
+                            .short('j')
+                            .long("join")
+                            .value_name("/dns/join01.stampid.net/tcp/5757")
+                            .value_parser(MultiaddrParser::new())
+                            .help("Join an existing StampNet node. This will allow you to connect to the rest of the network. Defaults to the servers set in the config or the public StampNet servers. Can be specified multiple times."))
+                        .arg(Arg::new("ID")
+                            .index(1)
+                            .required(true)
+                            .help("The identity ID we want to retrieve. This must be a full identity id, not an abbreviated one."))
+                )
+                .subcommand(
+                    Command::new("follow")
+                        .about("Follow an imported identity. Followed identities are re-fetched from StampNet when running `stamp net refresh`, and you will be warned if their keys or claims change.")
+                        .arg(Arg::new("SEARCH")
+                            .index(1)
+                            .required(true)
+                            .help("The ID (or ID prefix) of the locally-imported identity to follow."))
+                )
+                .subcommand(
+                    Command::new("unfollow")
+                        .about("Stop following an identity.")
+                        .arg(Arg::new("SEARCH")
+                            .index(1)
+                            .required(true)
+                            .help("The ID (or ID prefix) of the followed identity."))
+                )
+                .subcommand(
+                    Command::new("following")
+                        .about("List the identities you are following.")
+                        .arg(Arg::new("verbose")
+                            .action(ArgAction::SetTrue)
+                            .short('v')
+                            .long("verbose")
+                            .help("Verbose output, with long-form IDs."))
+                )
+                .subcommand(
+                    Command::new("refresh")
+                        .about("Re-fetch all followed identities from StampNet, saving them locally and reporting any added, removed, or revoked keys and claims.")
                         .arg(Arg::new("join")
                             .action(ArgAction::Append)
                             .short('j')
@@ -1095,10 +2432,6 @@ fn run() -> Result<()> {
                             .value_name("/dns/join01.stampid.net/tcp/5757")
                             .value_parser(MultiaddrParser::new())
                             .help("Join an existing StampNet node. This will allow you to connect to the rest of the network. Defaults to the servers set in the config or the public StampNet servers. Can be specified multiple times."))
-                        .arg(Arg::new("ID")
-                            .index(1)
-                            .required(true)
-                            .help("The identity ID we want to retrieve. This must be a full identity id, not an abbreviated one."))
                 )
                 .subcommand(
                     Command::new("node")
@@ -1117,6 +2450,12 @@ fn run() -> Result<()> {
                             .value_name("/dns/join01.stampid.net/tcp/5757")
                             .value_parser(MultiaddrParser::new())
                             .help("Join an existing StampNet node. This will allow you to connect to the rest of the network. Defaults to the servers set in the config or the public StampNet servers. Can be specified multiple times."))
+                        .arg(Arg::new("metrics")
+                            .short('m')
+                            .long("metrics")
+                            .value_name("127.0.0.1:9757")
+                            .value_parser(value_parser!(std::net::SocketAddr))
+                            .help("Serve Prometheus-style metrics (uptime, identified peers, and network events by kind) at http://<addr>/metrics. Disabled by default."))
                 )
         )
         /*
@@ -1178,17 +2517,160 @@ fn run() -> Result<()> {
                         .alias("ls")
                         .about("List the transactions in an identity.")
                         .arg(id_arg("The ID of the identity we want to see transactions for. This overrides the configured default identity."))
+                        .arg(Arg::new("type")
+                            .short('t')
+                            .long("type")
+                            .value_delimiter(',')
+                            .action(ArgAction::Append)
+                            .help("Only list transactions of this type, ex MakeClaimV1 (case-insensitive). Can be comma-separated or given more than once."))
+                        .arg(Arg::new("since")
+                            .long("since")
+                            .value_name("date")
+                            .help("Only list transactions created on or after this date (ex 2025-06-01 or 2025-06-01T12:00:00Z)."))
+                        .arg(Arg::new("until")
+                            .long("until")
+                            .value_name("date")
+                            .help("Only list transactions created on or before this date (ex 2025-06-01 or 2025-06-01T12:00:00Z). A plain date includes that whole day."))
+                        .arg(Arg::new("contains")
+                            .short('c')
+                            .long("contains")
+                            .value_name("text")
+                            .help("Only list transactions whose ID or description contains this text (case-insensitive)."))
+                        .arg(Arg::new("sort")
+                            .long("sort")
+                            .value_parser(clap::builder::PossibleValuesParser::new(["dag", "created", "type"]))
+                            .default_value("dag")
+                            .help("How to order the list. \"dag\" is the order transactions are stored in the identity."))
+                        .arg(Arg::new("reverse")
+                            .action(ArgAction::SetTrue)
+                            .short('r')
+                            .long("reverse")
+                            .help("Reverse the order of the list."))
+                )
+                .subcommand(
+                    Command::new("view")
+                        .about("Show a single transaction in detail: its full body, the transactions it builds on, who signed it, and whether it verifies against the current identity.")
+                        .arg(id_arg("The ID of the identity the transaction belongs to. This overrides the configured default identity."))
+                        .arg(Arg::new("TXID")
+                            .required(true)
+                            .index(1)
+                            .help("The transaction ID (or the start of it) to view."))
+                )
+                .subcommand(
+                    Command::new("stats")
+                        .about("Summarize an identity's DAG: transaction counts and sizes by type, the largest transactions, total size, number of signatures, and the dates of the first and last transactions.")
+                        .arg(id_arg("The ID of the identity we want stats for. This overrides the configured default identity."))
+                )
+                .subcommand(
+                    Command::new("merge")
+                        .about("Resolve an identity whose DAG has split into more than one head (for instance edits made on two devices, or conflicting imports). Each branch is shown, then you can keep all of them (the next transaction joins them back together) or keep one and drop the others' transactions. Every branch is saved to a recovery bundle before anything changes.")
+                        .arg(id_arg("The ID of the identity to merge. This overrides the configured default identity."))
+                        .arg(Arg::new("keep")
+                            .long("keep")
+                            .value_name("branch")
+                            .help("Don't prompt for which branch to keep: \"all\", a branch number, or the ID (or start of it) of a branch's head transaction."))
+                        .arg(Arg::new("yes")
+                            .action(ArgAction::SetTrue)
+                            .short('y')
+                            .long("yes")
+                            .help("Save the result without asking for confirmation."))
+                        .arg(Arg::new("OTHER")
+                            .index(1)
+                            .help("Another copy of the identity to merge in first: a file (an identity export or `stamp dag export-all` bundle) or the ID of a local identity."))
+                )
+                .subcommand(
+                    Command::new("diff")
+                        .about("Compare two copies of an identity, for instance your local copy and a freshly-fetched published one. Lists the transactions only one side has, then the keys, claims, and stamps that differ.")
+                        .arg(id_arg("The ID of the identity on the left side of the comparison. This overrides the configured default identity."))
+                        .arg(Arg::new("OTHER")
+                            .required(true)
+                            .index(1)
+                            .help("The right side of the comparison: a file (an identity export, published identity, or `stamp dag export-all` bundle) or the ID of another local identity."))
+                )
+                .subcommand(
+                    Command::new("verify")
+                        .about("Check an identity's DAG from the genesis transaction forward: every link between transactions, every transaction hash and signature, and that each transaction satisfies the identity's policies. Reports the first failure and its cause. Useful after database trouble, imports, or suspected tampering.")
+                        .arg(id_arg("The ID of the identity we want to verify. This overrides the configured default identity."))
+                )
+                .subcommand(
+                    Command::new("graph")
+                        .about("Output an identity's transaction DAG as a graph, with each transaction's type, date, and short ID. Forks, merges, and the current heads are highlighted, and parents missing from the DAG are drawn dashed.")
+                        .arg(id_arg("The ID of the identity we want to graph. This overrides the configured default identity."))
+                        .arg(Arg::new("format")
+                            .long("format")
+                            .value_parser(clap::builder::PossibleValuesParser::new(["dot", "mermaid"]))
+                            .default_value("dot")
+                            .help("The graph format. \"dot\" is for Graphviz (ex `stamp dag graph | dot -Tsvg > dag.svg`), \"mermaid\" can be pasted into markdown."))
+                        .arg(Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .help("The output file to write the graph to. You can leave blank or use the value '-' to signify STDOUT."))
+                )
+                .subcommand(
+                    Command::new("export-all")
+                        .about("Export an identity's entire transaction history as a bundle, for moving it between machines or keeping it in cold storage. Import it with `stamp dag import-bundle`. Unless --public is given, the bundle includes private data (encrypted with your master key), so keep it safe.")
+                        .arg(id_arg("The ID of the identity we want to export. This overrides the configured default identity."))
+                        .arg(Arg::new("public")
+                            .action(ArgAction::SetTrue)
+                            .long("public")
+                            .help("Strip private data from the transactions before exporting."))
+                        .arg(Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .help("The output file to write the bundle to. You can leave blank or use the value '-' to signify STDOUT."))
+                )
+                .subcommand(
+                    Command::new("import-bundle")
+                        .about("Import a bundle from `stamp dag export-all`. The bundle is verified transaction by transaction, then merged into the local identity (or imported as a new one). Nothing already stored locally is removed.")
+                        .arg(Arg::new("yes")
+                            .action(ArgAction::SetTrue)
+                            .short('y')
+                            .long("yes")
+                            .help("Don't ask for confirmation."))
+                        .arg(Arg::new("BUNDLE")
+                            .index(1)
+                            .required(true)
+                            .help("The bundle file to import. Use the value '-' to signify STDIN."))
                 )
                 .subcommand(
                     Command::new("reset")
-                        .about("Roll back an identity to a previous state.")
+                        .about("Roll back an identity to a previous state. The identity as it was is saved to a recovery bundle first, which `stamp dag import-bundle` can restore.")
                         .arg(id_arg("The ID of the identity we want to reset. This overrides the configured default identity."))
+                        .arg(Arg::new("dry-run")
+                            .action(ArgAction::SetTrue)
+                            .long("dry-run")
+                            .help("Show which transactions would be removed and how the identity would change, without changing anything."))
+                        .arg(Arg::new("backup")
+                            .long("backup")
+                            .value_name("file")
+                            .conflicts_with("dry-run")
+                            .help("Where to save the recovery bundle. Use the value '-' to write it to STDOUT (not a terminal). Defaults to a timestamped file in the reset-backups folder of Stamp's data directory."))
+                        .arg(Arg::new("force")
+                            .action(ArgAction::SetTrue)
+                            .short('f')
+                            .long("force")
+                            .requires("backup")
+                            .help("Overwrite the --backup file if it already exists."))
                         .arg(Arg::new("TXID")
                             .required(true)
                             .index(1)
                             .help("A transaction ID we wish to reset to. This transaction will be included in the final identity."))
                 )
         )
+        .subcommand(
+            Command::new("db")
+                .about("Maintain the local database.")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("fsck")
+                        .about("Check the local database: every identity builds, every stored record can be read, and no records point at identities or staged transactions that are gone.")
+                        .arg(Arg::new("repair")
+                            .action(ArgAction::SetTrue)
+                            .long("repair")
+                            .help("Move broken identities and records (and orphaned records) into the quarantine folder of Stamp's data directory, instead of just reporting them."))
+                )
+        )
         .subcommand(
             Command::new("debug")
                 .about("Tools for Stamp development. Will change rapidly and unexpectedly, so don't rely on these too heavily.")
@@ -1207,13 +2689,31 @@ fn run() -> Result<()> {
                 .subcommand(
                     Command::new("import")
                         .about("Import an identity exported via `stamp debug export`. This will *overwrite* the identity if it exists already.")
+                        .arg(Arg::new("verify-only")
+                            .action(ArgAction::SetTrue)
+                            .long("verify-only")
+                            .help("Check the export without importing it: shows which identity it contains, whether it's valid, and what importing it would overwrite. Also works on regular identity exports."))
                         .arg(Arg::new("EXPORT-PATH")
                             .index(1)
                             .required(true)
                             .help("The path to the file exported from `stamp debug export`. Use the value '-' to signify STDIN."))
                 )
+                .subcommand(
+                    Command::new("replay")
+                        .about("Rebuild an identity one transaction at a time, showing what each transaction changes and stopping at the first one that fails to verify or apply. Useful for tracking down which transaction breaks an identity.")
+                        .arg(id_arg("The ID of the identity we want to replay. This must be specified."))
+                )
         );
     let args = app.get_matches();
+    // `--stage-expires` works the same for every command that can stage, so
+    // it's read once here and put on whatever gets staged
+    let mut leaf = &args;
+    while let Some((_, sub)) = leaf.subcommand() {
+        leaf = sub;
+    }
+    if let Some(val) = leaf.try_get_one::<String>("stage-expires").ok().flatten() {
+        commands::stage::set_stage_expires(Some(util::parse_expires(val)?))?;
+    }
     match args.subcommand() {
         Some(("id", args)) => match args.subcommand() {
             Some(("new", _)) => {
@@ -1267,10 +2767,27 @@ fn run() -> Result<()> {
                 let search = args.get_one::<String>("SEARCH").map(|x| x.as_str());
                 let verbose = args.get_flag("verbose");
 
-                let identities = db::list_local_identities(search)?
-                    .iter()
-                    .map(|x| util::build_identity(x))
-                    .collect::<Result<Vec<_>>>()?;
+                let sort = match args.get_one::<String>("sort").map(|x| x.as_str()) {
+                    Some("created") => Some(commands::id::ListSort::Created),
+                    Some("name") => Some(commands::id::ListSort::Name),
+                    Some("updated") => Some(commands::id::ListSort::Updated),
+                    Some("stamps") => Some(commands::id::ListSort::Stamps),
+                    _ => None,
+                };
+                let created_after = args
+                    .get_one::<String>("created-after")
+                    .map(|x| chrono::NaiveDate::parse_from_str(x, "%Y-%m-%d"))
+                    .transpose()
+                    .map_err(|e| anyhow!("Invalid --created-after date (expected YYYY-MM-DD): {}", e))?;
+                let options = commands::id::ListOptions {
+                    sort,
+                    owned: args.get_flag("owned"),
+                    imported: args.get_flag("imported"),
+                    has_claim_type: args.get_one::<String>("has-claim-type").map(|x| x.clone()),
+                    created_after,
+                };
+                let identities = commands::id::search_identities(search)?;
+                let identities = commands::id::filter_and_sort(identities, &options)?;
                 commands::id::print_identities_table(&identities, verbose);
             }
             Some(("import", args)) => {
@@ -1301,7 +2818,8 @@ fn run() -> Result<()> {
             Some(("export-private", args)) => {
                 let id = id_val(args)?;
                 let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
-                let serialized = commands::id::export_private(&id)?;
+                let cbor = args.get_one::<String>("format").map(|x| x.as_str()) == Some("cbor");
+                let serialized = commands::id::export_private(&id, cbor)?;
                 util::write_file(output, serialized.as_slice())?;
             }
             Some(("delete", args)) => {
@@ -1346,6 +2864,19 @@ fn run() -> Result<()> {
                     (id, private, name, stage, sign_with)
                 }};
             }
+            macro_rules! claim_value {
+                ($args:ident) => {
+                    match ($args.get_one::<String>("value"), $args.get_one::<String>("value-file")) {
+                        (Some(value), _) => Some(value.clone()),
+                        (None, Some(file)) => {
+                            let contents = util::read_file(file)?;
+                            let value = String::from_utf8(contents).map_err(|_| anyhow!("The claim value must be UTF-8 text"))?;
+                            Some(String::from(value.trim_end()))
+                        }
+                        (None, None) => None,
+                    }
+                };
+            }
             macro_rules! aux_op {
                 ($op:expr) => {
                     $op.map_err(|e| anyhow!("Problem adding claim: {}", e))
@@ -1362,93 +2893,191 @@ fn run() -> Result<()> {
                 ($args:ident, $fn:ident, $prompt:expr) => {
                     let (id, private, name, stage, sign_with) = claim_args!($args);
                     let hash_with = config::hash_algo(Some(&id));
-                    let (master_key, transactions, value) = commands::claim::claim_pre(&id, $prompt)?;
+                    let (master_key, transactions, value) = commands::claim::claim_pre(&id, $prompt, claim_value!($args))?;
                     let trans = aux_op!(stamp_aux::claim::$fn(&master_key, &transactions, &hash_with, value, private, name))?;
                     save_trans!(transactions, master_key, trans, stage, sign_with);
                 };
             }
             match args.subcommand() {
-                Some(("new", args)) => match args.subcommand() {
-                    Some(("identity", args)) => {
-                        easy_claim! { args, new_id, "Enter the ID of your other identity" }
-                    }
-                    Some(("name", args)) => {
-                        easy_claim! { args, new_name, "Enter your name" }
-                    }
-                    Some(("birthday", args)) => {
-                        easy_claim! { args, new_birthday, "Enter your date of birth (eg 1987-11-23)" }
-                    }
-                    Some(("email", args)) => {
-                        easy_claim! { args, new_email, "Enter your email" }
-                    }
-                    Some(("photo", args)) => {
-                        let (id, private, name, stage, sign_with) = claim_args!(args);
-                        let photofile = args
-                            .get_one::<String>("PHOTO-FILE")
-                            .map(|x| x.as_str())
-                            .ok_or(anyhow!("Must specify a photo"))?;
-                        let hash_with = config::hash_algo(Some(&id));
+                Some(("new", args)) => {
+                    let expires = args.get_one::<String>("expires").map(|x| util::parse_timestamp(x)).transpose()?;
+                    let known = match (&expires, args.subcommand()) {
+                        (Some(_), Some((_, sub_args))) => {
+                            let id = id_val(sub_args)?;
+                            let known = commands::claim::known_claim_ids(&id)?;
+                            Some((id, known))
+                        }
+                        _ => None,
+                    };
+                    match args.subcommand() {
+                        Some(("identity", args)) => {
+                            easy_claim! { args, new_id, "Enter the ID of your other identity" }
+                        }
+                        Some(("name", args)) => {
+                            easy_claim! { args, new_name, "Enter your name" }
+                        }
+                        Some(("birthday", args)) => {
+                            easy_claim! { args, new_birthday, "Enter your date of birth (eg 1987-11-23)" }
+                        }
+                        Some(("email", args)) => {
+                            easy_claim! { args, new_email, "Enter your email" }
+                        }
+                        Some(("photo", args)) => {
+                            let (id, private, name, stage, sign_with) = claim_args!(args);
+                            let photofile = args
+                                .get_one::<String>("PHOTO-FILE")
+                                .map(|x| x.as_str())
+                                .ok_or(anyhow!("Must specify a photo"))?;
+                            let hash_with = config::hash_algo(Some(&id));
 
-                        let photo_bytes = util::read_file(photofile)?;
-                        if photo_bytes.len() > stamp_aux::claim::MAX_PHOTO_BYTES {
-                            Err(anyhow!(
-                                "Please choose a photo smaller than {} bytes (given photo is {} bytes)",
-                                stamp_aux::claim::MAX_PHOTO_BYTES,
-                                photo_bytes.len()
+                            let mut photo_bytes = util::read_file(photofile)?;
+                            if photo_bytes.len() > stamp_aux::claim::MAX_PHOTO_BYTES && args.get_flag("resize") {
+                                photo_bytes = photo::fit(photo_bytes.as_slice(), stamp_aux::claim::MAX_PHOTO_BYTES)?;
+                                println!("Photo resized to {} bytes", photo_bytes.len());
+                            }
+                            if photo_bytes.len() > stamp_aux::claim::MAX_PHOTO_BYTES {
+                                Err(anyhow!(
+                                    "Please choose a photo smaller than {} bytes (given photo is {} bytes), or use --resize",
+                                    stamp_aux::claim::MAX_PHOTO_BYTES,
+                                    photo_bytes.len()
+                                ))?;
+                            }
+                            let (master_key, transactions) = commands::claim::claim_pre_noval(&id)?;
+                            let trans =
+                                aux_op!(stamp_aux::claim::new_photo(&master_key, &transactions, &hash_with, photo_bytes, private, name))?;
+                            save_trans!(transactions, master_key, trans, stage, sign_with);
+                        }
+                        Some(("pgp", args)) => {
+                            easy_claim! { args, new_pgp, "Enter your PGP ID" }
+                        }
+                        Some(("domain", args)) => {
+                            easy_claim! { args, new_domain, "Enter your domain name" }
+                        }
+                        Some(("url", args)) => {
+                            if let Some(service) = args.get_one::<String>("service") {
+                                let (id, private, name, stage, sign_with) = claim_args!(args);
+                                let username = args
+                                    .get_one::<String>("USERNAME")
+                                    .map(|x| x.as_str())
+                                    .ok_or(anyhow!("Must specify a username"))?;
+                                commands::claim::new_social(&id, service, username, private, name, stage, sign_with)?;
+                            } else {
+                                easy_claim! { args, new_url, "Enter the URL you own" }
+                            }
+                        }
+                        Some(("social", args)) => {
+                            let id = id_val(args)?;
+                            let name = args.get_one::<String>("claim-name").map(|x| x.as_str());
+                            let stage = args.get_flag("stage");
+                            let sign_with = args.get_one::<String>("admin-key").map(|x| x.as_str());
+                            let platform = args
+                                .get_one::<String>("platform")
+                                .map(|x| x.as_str())
+                                .ok_or(anyhow!("Must specify a platform"))?;
+                            let username = args
+                                .get_one::<String>("USERNAME")
+                                .map(|x| x.as_str())
+                                .ok_or(anyhow!("Must specify a username"))?;
+                            commands::claim::new_social(&id, platform, username, false, name, stage, sign_with)?;
+                        }
+                        Some(("address", args)) => {
+                            let (id, private, name, stage, sign_with) = claim_args!(args);
+                            commands::claim::new_address(&id, claim_value!(args), private, name, stage, sign_with)?;
+                        }
+                        Some(("phone", args)) => {
+                            let (id, private, name, stage, sign_with) = claim_args!(args);
+                            commands::claim::new_phone(&id, claim_value!(args), private, name, stage, sign_with)?;
+                        }
+                        Some(("file", args)) => {
+                            let (id, private, name, stage, sign_with) = claim_args!(args);
+                            let file = args
+                                .get_one::<String>("FILE")
+                                .map(|x| x.as_str())
+                                .ok_or(anyhow!("Must specify a file"))?;
+                            commands::claim::new_file(&id, file, private, name, stage, sign_with)?;
+                        }
+                        Some(("custom", args)) => {
+                            let (id, private, name, stage, sign_with) = claim_args!(args);
+                            let kind = args
+                                .get_one::<String>("kind")
+                                .map(|x| x.as_str())
+                                .ok_or(anyhow!("Must specify a claim kind"))?;
+                            let value = match (args.get_one::<String>("value"), args.get_one::<String>("value-file")) {
+                                (Some(value), _) => Some(Vec::from(value.as_bytes())),
+                                (None, Some(file)) => Some(util::read_file(file)?),
+                                (None, None) => None,
+                            };
+                            commands::claim::new_custom(&id, kind, value, private, name, stage, sign_with)?;
+                        }
+                        Some(("relation", args)) => {
+                            let (id, private, name, stage, sign_with) = claim_args!(args);
+                            let ty = args
+                                .get_one::<String>("TYPE")
+                                .map(|x| x.as_str())
+                                .ok_or(anyhow!("Must specify a relationship type"))?;
+                            let hash_with = config::hash_algo(Some(&id));
+                            let reltype = match ty {
+                                "org" => RelationshipType::OrganizationMember,
+                                _ => Err(anyhow!("Invalid relationship type: {}", ty))?,
+                            };
+                            let (master_key, transactions, value) = commands::claim::claim_pre(
+                                &id,
+                                "Enter the full Stamp identity id for the entity you are related to",
+                                claim_value!(args),
+                            )?;
+                            let trans = aux_op!(stamp_aux::claim::new_relation(
+                                &master_key,
+                                &transactions,
+                                &hash_with,
+                                reltype,
+                                value,
+                                private,
+                                name
                             ))?;
+                            save_trans!(transactions, master_key, trans, stage, sign_with);
                         }
-                        let (master_key, transactions) = commands::claim::claim_pre_noval(&id)?;
-                        let trans =
-                            aux_op!(stamp_aux::claim::new_photo(&master_key, &transactions, &hash_with, photo_bytes, private, name))?;
-                        save_trans!(transactions, master_key, trans, stage, sign_with);
-                    }
-                    Some(("pgp", args)) => {
-                        easy_claim! { args, new_pgp, "Enter your PGP ID" }
-                    }
-                    Some(("domain", args)) => {
-                        easy_claim! { args, new_domain, "Enter your domain name" }
-                    }
-                    Some(("url", args)) => {
-                        easy_claim! { args, new_url, "Enter the URL you own" }
-                    }
-                    Some(("address", args)) => {
-                        easy_claim! { args, new_address, "Enter your address" }
+                        _ => unreachable!("Unknown command"),
                     }
-                    Some(("phone", args)) => {
-                        easy_claim! { args, new_phone, "Enter your phone number" }
+                    if let (Some(expires), Some((id, known))) = (expires.as_ref(), known.as_ref()) {
+                        commands::claim::expire_new_claims(id, known, expires)?;
                     }
-                    Some(("relation", args)) => {
-                        let (id, private, name, stage, sign_with) = claim_args!(args);
-                        let ty = args
-                            .get_one::<String>("TYPE")
-                            .map(|x| x.as_str())
-                            .ok_or(anyhow!("Must specify a relationship type"))?;
-                        let hash_with = config::hash_algo(Some(&id));
-                        let reltype = match ty {
-                            "org" => RelationshipType::OrganizationMember,
-                            _ => Err(anyhow!("Invalid relationship type: {}", ty))?,
-                        };
-                        let (master_key, transactions, value) =
-                            commands::claim::claim_pre(&id, "Enter the full Stamp identity id for the entity you are related to")?;
-                        let trans = aux_op!(stamp_aux::claim::new_relation(
-                            &master_key,
-                            &transactions,
-                            &hash_with,
-                            reltype,
-                            value,
-                            private,
-                            name
-                        ))?;
-                        save_trans!(transactions, master_key, trans, stage, sign_with);
-                    }
-                    _ => unreachable!("Unknown command"),
-                },
+                }
                 Some(("check", args)) => {
                     let claim_id = args
                         .get_one::<String>("CLAIM")
                         .map(|x| x.as_str())
                         .ok_or(anyhow!("Must specify a claim ID"))?;
-                    commands::claim::check(claim_id)?;
+                    let dns_settings = dns::DnsSettings::from_config(
+                        &config::load_cli()?,
+                        args.get_one::<String>("resolver").map(|x| x.as_str()),
+                        args.get_one::<String>("doh").map(|x| x.as_str()),
+                        args.get_flag("dnssec"),
+                    );
+                    commands::claim::check(claim_id, &dns_settings)?;
+                }
+                Some(("renew", args)) => {
+                    let id = id_val(args)?;
+                    let stage = args.get_flag("stage");
+                    let sign_with = args.get_one::<String>("admin-key").map(|x| x.as_str());
+                    let expires = args.get_one::<String>("expires").map(|x| util::parse_timestamp(x)).transpose()?;
+                    let key_from = args.get_one::<String>("key-from").map(|x| x.as_str());
+                    let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("stamp-requests");
+                    let claim = args
+                        .get_one::<String>("CLAIM")
+                        .map(|x| x.as_str())
+                        .ok_or(anyhow!("Must specify a claim"))?;
+                    commands::claim::renew(&id, claim, expires, key_from, output, stage, sign_with)?;
+                }
+                Some(("check-file", args)) => {
+                    let claim_id = args
+                        .get_one::<String>("CLAIM")
+                        .map(|x| x.as_str())
+                        .ok_or(anyhow!("Must specify a claim ID"))?;
+                    let file = args
+                        .get_one::<String>("FILE")
+                        .map(|x| x.as_str())
+                        .ok_or(anyhow!("Must specify a file"))?;
+                    commands::claim::check_file(claim_id, file)?;
                 }
                 Some(("view", args)) => {
                     let id = id_val(args)?;
@@ -1457,7 +3086,8 @@ fn run() -> Result<()> {
                         .get_one::<String>("CLAIM")
                         .map(|x| x.as_str())
                         .ok_or(anyhow!("Must specify a claim ID"))?;
-                    commands::claim::view(&id, claim_id, output)?;
+                    let preview = args.get_one::<String>("preview").map(|x| x.as_str()).unwrap_or("auto");
+                    commands::claim::view(&id, claim_id, output, preview)?;
                 }
                 Some(("list", args)) => {
                     let id = id_val(args)?;
@@ -1519,6 +3149,17 @@ fn run() -> Result<()> {
                     }
                     _ => unreachable!("Unknown command"),
                 },
+                Some(("delete", args)) if args.get_flag("bulk") => {
+                    let id = id_val(args)?;
+                    let stage = args.get_flag("stage");
+                    let sign_with = args.get_one::<String>("admin-key").map(|x| x.as_str());
+                    let filter = commands::claim::DeleteFilter {
+                        ty: args.get_one::<String>("type").map(|x| x.as_str()),
+                        name: args.get_one::<String>("name").map(|x| x.as_str()),
+                        unstamped_private: args.get_flag("unstamped-private"),
+                    };
+                    commands::claim::delete_bulk(&id, filter, stage, sign_with)?;
+                }
                 Some(("delete", args)) => {
                     let id = id_val(args)?;
                     let stage = args.get_flag("stage");
@@ -1553,7 +3194,9 @@ fn run() -> Result<()> {
                     .ok_or(anyhow!("Must specify a claim"))?;
                 let stage = args.get_flag("stage");
                 let sign_with = args.get_one::<String>("admin-key").map(|x| x.as_str());
-                commands::stamp::new(&our_identity_id, claim_id, stage, sign_with)?;
+                let confidence = args.get_one::<String>("confidence").map(|x| x.as_str());
+                let expires = args.get_one::<String>("expires").map(|x| util::parse_timestamp(x)).transpose()?;
+                commands::stamp::new(&our_identity_id, claim_id, confidence, expires, stage, sign_with)?;
             }
             Some(("req", args)) => {
                 let id = id_val(args)?;
@@ -1575,8 +3218,12 @@ fn run() -> Result<()> {
                     .get_one::<String>("CLAIM")
                     .map(|x| x.as_str())
                     .ok_or(anyhow!("Must specify a claim"))?;
+                let armored = args.get_flag("armor");
                 let req = commands::stamp::request(&id, claim, key_from, stamper_id, key_to)?;
-                if base64 {
+                if armored {
+                    let recipient = IdentityID::short(stamper_id);
+                    util::write_file(output, armor::armor(armor::MESSAGE, &[("Recipient", recipient)], req.as_slice()).as_bytes())?;
+                } else if base64 {
                     util::write_file(output, stamp_core::util::base64_encode(req.as_slice()).as_bytes())?;
                 } else {
                     util::write_file(output, req.as_slice())?;
@@ -1584,18 +3231,65 @@ fn run() -> Result<()> {
             }
             Some(("open-req", args)) => {
                 let id = id_val(args)?;
-                let key_to = args
-                    .get_one::<String>("key-to")
-                    .map(|x| x.as_str())
-                    .ok_or(anyhow!("Must specify the to key"))?;
+                let key_to = args.get_one::<String>("key-to").map(|x| x.as_str());
                 let req = args.get_one::<String>("ENCRYPTED").map(|x| x.as_str()).unwrap_or("-");
-                commands::stamp::open_request(&id, &key_to, req)?;
+                commands::stamp::open_request(&id, key_to, req)?;
+            }
+            Some(("req-inbox", args)) => match args.subcommand() {
+                Some(("import", args)) => {
+                    let id = id_val(args)?;
+                    let req = args.get_one::<String>("ENCRYPTED").map(|x| x.as_str()).unwrap_or("-");
+                    commands::stamp::inbox_import(&id, req)?;
+                }
+                Some(("list", args)) => {
+                    let id = id_val(args)?;
+                    let all = args.get_flag("all");
+                    let verbose = args.get_flag("verbose");
+                    commands::stamp::inbox_list(&id, all, verbose)?;
+                }
+                Some(("open", args)) => {
+                    let id = id_val(args)?;
+                    let key_to = args.get_one::<String>("key-to").map(|x| x.as_str());
+                    let request = args
+                        .get_one::<String>("REQUEST")
+                        .map(|x| x.as_str())
+                        .ok_or(anyhow!("Must specify a REQUEST id"))?;
+                    commands::stamp::inbox_open(&id, key_to, request)?;
+                }
+                Some(("done", args)) => {
+                    let id = id_val(args)?;
+                    let delete = args.get_flag("delete");
+                    let request = args
+                        .get_one::<String>("REQUEST")
+                        .map(|x| x.as_str())
+                        .ok_or(anyhow!("Must specify a REQUEST id"))?;
+                    commands::stamp::inbox_done(&id, request, delete)?;
+                }
+                _ => unreachable!("Unknown command"),
+            },
+            Some(("received", args)) => {
+                let id = id_val(args)?;
+                let verbose = args.get_flag("verbose");
+                commands::stamp::received(&id, verbose)?;
             }
             Some(("list", args)) => {
                 let id = id_val(args)?;
                 let revoked = args.get_flag("revoked");
+                let expiring = args.get_one::<u64>("expiring").map(|x| *x);
                 let verbose = args.get_flag("verbose");
-                commands::stamp::list(&id, revoked, verbose)?;
+                commands::stamp::list(&id, revoked, expiring, verbose)?;
+            }
+            Some(("renew", args)) => {
+                let id = id_val(args)?;
+                let stamp = args
+                    .get_one::<String>("STAMP")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a STAMP id"))?;
+                let expires = args.get_one::<String>("expires").map(|x| util::parse_timestamp(x)).transpose()?;
+                let confidence = args.get_one::<String>("confidence").map(|x| x.as_str());
+                let stage = args.get_flag("stage");
+                let sign_with = args.get_one::<String>("admin-key").map(|x| x.as_str());
+                commands::stamp::renew(&id, stamp, expires, confidence, stage, sign_with)?;
             }
             Some(("export", args)) => {
                 let id = id_val(args)?;
@@ -1605,17 +3299,39 @@ fn run() -> Result<()> {
                     .ok_or(anyhow!("Must specify a STAMP id"))?;
                 let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
                 let base64 = args.get_flag("base64");
-                commands::dag::export(&id, stamp, output, base64)?;
+                let cbor = args.get_one::<String>("format").map(|x| x.as_str()) == Some("cbor");
+                commands::dag::export(&id, stamp, output, base64, cbor)?;
+            }
+            Some(("send", args)) => {
+                let id = id_val(args)?;
+                let stamp = args
+                    .get_one::<String>("STAMP")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a STAMP id"))?;
+                let key_from = args.get_one::<String>("key-from").map(|x| x.as_str());
+                let key_to = args.get_one::<String>("key-to").map(|x| x.as_str());
+                let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
+                let base64 = args.get_flag("base64");
+                let armored = args.get_flag("armor");
+                commands::stamp::send(&id, stamp, key_from, key_to, output, base64, armored)?;
             }
             Some(("accept", args)) => {
                 let id = id_val(args)?;
+                let key_open = args.get_one::<String>("key-open").map(|x| x.as_str());
+                let stage = args.get_flag("stage");
+                let sign_with = args.get_one::<String>("admin-key").map(|x| x.as_str());
                 let location = args
                     .get_one::<String>("LOCATION")
                     .map(|x| x.as_str())
                     .ok_or_else(|| anyhow!("Must specify a LOCATION value"))?;
-                let stage = args.get_flag("stage");
-                let sign_with = args.get_one::<String>("admin-key").map(|x| x.as_str());
-                commands::stamp::accept(&id, location, stage, sign_with)?;
+                commands::stamp::accept(&id, location, key_open, stage, sign_with)?;
+            }
+            Some(("verify", args)) => {
+                let stamp = args
+                    .get_one::<String>("STAMP")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a STAMP"))?;
+                commands::stamp::verify(stamp)?;
             }
             Some(("revoke", args)) => {
                 let id = id_val(args)?;
@@ -1631,6 +3347,39 @@ fn run() -> Result<()> {
             _ => unreachable!("Unknown command"),
         },
         Some(("keychain", args)) => match args.subcommand() {
+            Some(("export-ssh", args)) => {
+                let id = id_val(args)?;
+                let search = args
+                    .get_one::<String>("SEARCH")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a key to export"))?;
+                let public = args.get_flag("public");
+                let output = match args.get_one::<String>("output").map(|x| x.as_str()) {
+                    Some(output) => output,
+                    None if public => "-",
+                    None => Err(anyhow!("Must specify an --output file for the private key (ex: -o ~/.ssh/id_ed25519)"))?,
+                };
+                commands::keychain::export_ssh(&id, search, output, public)?;
+            }
+            Some(("import", args)) => {
+                let id = id_val(args)?;
+                let ty = args
+                    .get_one::<String>("type")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a key type"))?;
+                let name = args
+                    .get_one::<String>("name")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a name"))?;
+                let desc = args.get_one::<String>("description").map(|x| x.as_str());
+                let keyfile = args
+                    .get_one::<String>("KEYFILE")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a KEYFILE"))?;
+                let stage = args.get_flag("stage");
+                let sign_with = args.get_one::<String>("admin-key").map(|x| x.as_str());
+                commands::keychain::import(&id, ty, keyfile, name, desc, stage, sign_with)?;
+            }
             Some(("new", args)) => {
                 macro_rules! parse_new_key_args {
                     ($args:ident) => {{
@@ -1700,36 +3449,134 @@ fn run() -> Result<()> {
             }
             Some(("delete-subkey", args)) => {
                 let id = id_val(args)?;
-                let stage = args.get_flag("stage");
-                let sign_with = args.get_one::<String>("admin-key").map(|x| x.as_str());
-                let search = args
-                    .get_one::<String>("SEARCH")
+                let stage = args.get_flag("stage");
+                let sign_with = args.get_one::<String>("admin-key").map(|x| x.as_str());
+                let search = args
+                    .get_one::<String>("SEARCH")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a key id or name"))?;
+                commands::keychain::delete_subkey(&id, search, stage, sign_with)?;
+            }
+            Some(("passwd", args)) => {
+                let id = id_val(args)?;
+                let keyfile = args.get_one::<String>("keyfile").map(|x| x.as_str());
+                let keyparts: Vec<&str> = args
+                    .get_many::<String>("KEYPARTS")
+                    .unwrap_or_default()
+                    .map(|v| v.as_str())
+                    .collect();
+                commands::keychain::passwd(&id, keyfile, keyparts)?;
+            }
+            Some(("rekey-all", args)) => {
+                let id = id_val(args)?;
+                let publish = args.get_flag("publish");
+                let join = args
+                    .get_many::<Multiaddr>("join")
+                    .into_iter()
+                    .flatten()
+                    .map(|x| x.clone())
+                    .collect::<Vec<_>>();
+                commands::keychain::rekey_all(&id, publish, join)?;
+            }
+            Some(("sync-token", args)) => {
+                let id = id_val(args)?;
+                let stage = args.get_flag("stage");
+                let sign_with = args.get_one::<String>("admin-key").map(|x| x.as_str());
+                let blind = args.get_flag("blind");
+                commands::keychain::sync_token(&id, blind, stage, sign_with)?;
+            }
+            Some(("keyfile", args)) => {
+                let id = id_val(args)?;
+                let shamir = args.get_one::<String>("shamir").map(|x| x.as_str()).unwrap_or("1/1");
+                let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
+                let pdf = args.get_one::<String>("format").map(|x| x.as_str()) == Some("pdf");
+                commands::keychain::keyfile(&id, shamir, output, pdf)?;
+            }
+            _ => unreachable!("Unknown command"),
+        },
+        Some(("audit", args)) => match args.subcommand() {
+            Some(("conflicts", args)) => {
+                let verbose = args.get_flag("verbose");
+                commands::audit::conflicts(verbose)?;
+            }
+            _ => unreachable!("Unknown command"),
+        },
+        Some(("status", _)) => {
+            commands::status::status()?;
+        }
+        Some(("sync", args)) => match args.subcommand() {
+            Some(("merge", args)) => {
+                let location = args.get_one::<String>("LOCATION").map(|x| x.as_str()).unwrap_or("-");
+                commands::sync::merge(location)?;
+            }
+            Some(("status", args)) => {
+                let id = id_val(args)?;
+                commands::sync::status(&id)?;
+            }
+            Some(("devices", args)) => match args.subcommand() {
+                Some(("add", args)) => {
+                    let id = id_val(args)?;
+                    let blind = args.get_flag("blind");
+                    let name = args
+                        .get_one::<String>("NAME")
+                        .map(|x| x.as_str())
+                        .ok_or(anyhow!("Must specify a device name"))?;
+                    commands::sync::devices_add(&id, name, blind)?;
+                }
+                Some(("list", args)) => {
+                    let id = id_val(args)?;
+                    let revoked = args.get_flag("revoked");
+                    commands::sync::devices_list(&id, revoked)?;
+                }
+                Some(("revoke", args)) => {
+                    let id = id_val(args)?;
+                    let stage = args.get_flag("stage");
+                    let sign_with = args.get_one::<String>("admin-key").map(|x| x.as_str());
+                    let name = args
+                        .get_one::<String>("NAME")
+                        .map(|x| x.as_str())
+                        .ok_or(anyhow!("Must specify a device name"))?;
+                    commands::sync::devices_revoke(&id, name, stage, sign_with)?;
+                }
+                _ => unreachable!("Unknown command"),
+            },
+            _ => unreachable!("Unknown command"),
+        },
+        Some(("trust", args)) => match args.subcommand() {
+            Some(("score", args)) => {
+                let id = id_val(args)?;
+                let target = args
+                    .get_one::<String>("IDENTITY")
                     .map(|x| x.as_str())
-                    .ok_or(anyhow!("Must specify a key id or name"))?;
-                commands::keychain::delete_subkey(&id, search, stage, sign_with)?;
-            }
-            Some(("passwd", args)) => {
-                let id = id_val(args)?;
-                let keyfile = args.get_one::<String>("keyfile").map(|x| x.as_str());
-                let keyparts: Vec<&str> = args
-                    .get_many::<String>("KEYPARTS")
-                    .unwrap_or_default()
-                    .map(|v| v.as_str())
-                    .collect();
-                commands::keychain::passwd(&id, keyfile, keyparts)?;
+                    .ok_or(anyhow!("Must specify an IDENTITY"))?;
+                commands::trust::score(&id, target, &config::load_cli()?.trust)?;
             }
-            Some(("sync-token", args)) => {
-                let id = id_val(args)?;
-                let stage = args.get_flag("stage");
-                let sign_with = args.get_one::<String>("admin-key").map(|x| x.as_str());
-                let blind = args.get_flag("blind");
-                commands::keychain::sync_token(&id, blind, stage, sign_with)?;
+            Some(("path", args)) => {
+                let from = args
+                    .get_one::<String>("FROM")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a FROM identity"))?;
+                let to = args
+                    .get_one::<String>("TO")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a TO identity"))?;
+                let depth = args.get_one::<usize>("depth").map(|x| *x).unwrap_or(6);
+                let min_confidence = args.get_one::<String>("min-confidence").map(|x| x.as_str()).unwrap_or("low");
+                let limit = args.get_one::<usize>("limit").map(|x| *x).unwrap_or(3);
+                commands::trust::path(from, to, depth, min_confidence, limit)?;
             }
-            Some(("keyfile", args)) => {
+            Some(("graph", args)) => {
                 let id = id_val(args)?;
-                let shamir = args.get_one::<String>("shamir").map(|x| x.as_str()).unwrap_or("1/1");
+                let format = match args.get_one::<String>("format").map(|x| x.as_str()).unwrap_or("dot") {
+                    "json" => commands::trust::GraphFormat::Json,
+                    "graphml" => commands::trust::GraphFormat::GraphML,
+                    _ => commands::trust::GraphFormat::Dot,
+                };
+                let depth = args.get_one::<usize>("depth").map(|x| *x);
+                let min_confidence = args.get_one::<String>("min-confidence").map(|x| x.as_str()).unwrap_or("negative");
                 let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
-                commands::keychain::keyfile(&id, shamir, output)?;
+                let graph = commands::trust::graph(&id, depth, min_confidence, format)?;
+                util::write_file(output, graph.as_bytes())?;
             }
             _ => unreachable!("Unknown command"),
         },
@@ -1743,9 +3590,11 @@ fn run() -> Result<()> {
                     .get_one::<String>("SEARCH")
                     .map(|x| x.as_str())
                     .ok_or(anyhow!("Must specify a search value"))?;
-                let input = args.get_one::<String>("MESSAGE").map(|x| x.as_str()).unwrap_or("-");
+                let input = args.get_one::<String>("MESSAGE").map(|x| x.as_str());
+                let attachments: Vec<&str> = args.get_many::<String>("attach").unwrap_or_default().map(|v| v.as_str()).collect();
                 let base64 = args.get_flag("base64");
-                commands::message::send(&from_id, key_from_search, key_to_search, input, output, search, base64)?;
+                let armored = args.get_flag("armor");
+                commands::message::send(&from_id, key_from_search, key_to_search, input, &attachments, output, search, base64, armored)?;
             }
             Some(("send-anonymous", args)) => {
                 let key_to_search = args.get_one::<String>("key-to").map(|x| x.as_str());
@@ -1754,28 +3603,145 @@ fn run() -> Result<()> {
                     .get_one::<String>("SEARCH")
                     .map(|x| x.as_str())
                     .ok_or(anyhow!("Must specify a search value"))?;
-                let input = args.get_one::<String>("MESSAGE").map(|x| x.as_str()).unwrap_or("-");
+                let input = args.get_one::<String>("MESSAGE").map(|x| x.as_str());
+                let attachments: Vec<&str> = args.get_many::<String>("attach").unwrap_or_default().map(|v| v.as_str()).collect();
+                let base64 = args.get_flag("base64");
+                let armored = args.get_flag("armor");
+                commands::message::send_anonymous(key_to_search, input, &attachments, output, search, base64, armored)?;
+            }
+            Some(("reply", args)) => {
+                let from_id = id_val(args)?;
+                let key_from_search = args.get_one::<String>("key-from").map(|x| x.as_str());
+                let key_to_search = args.get_one::<String>("key-to").map(|x| x.as_str());
+                let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
+                let message_id = args
+                    .get_one::<String>("ID")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a message ID"))?;
+                let input = args.get_one::<String>("MESSAGE").map(|x| x.as_str());
+                let attachments: Vec<&str> = args.get_many::<String>("attach").unwrap_or_default().map(|v| v.as_str()).collect();
+                let base64 = args.get_flag("base64");
+                let armored = args.get_flag("armor");
+                commands::message::reply(
+                    &from_id,
+                    key_from_search,
+                    key_to_search,
+                    message_id,
+                    input,
+                    &attachments,
+                    output,
+                    base64,
+                    armored,
+                )?;
+            }
+            Some(("receipt", args)) => {
+                let from_id = id_val(args)?;
+                let key_from_search = args.get_one::<String>("key-from").map(|x| x.as_str());
+                let key_to_search = args.get_one::<String>("key-to").map(|x| x.as_str());
+                let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
+                let message_id = args
+                    .get_one::<String>("ID")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a message ID"))?;
                 let base64 = args.get_flag("base64");
-                commands::message::send_anonymous(key_to_search, input, output, search, base64)?;
+                let armored = args.get_flag("armor");
+                commands::message::receipt(&from_id, key_from_search, key_to_search, message_id, output, base64, armored)?;
             }
             Some(("open", args)) => {
                 let to_id = id_val(args)?;
                 let key_open = args.get_one::<String>("key-open").map(|x| x.as_str());
                 let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
                 let input = args.get_one::<String>("ENCRYPTED").map(|x| x.as_str()).unwrap_or("-");
-                commands::message::open(&to_id, key_open, input, output)?;
+                let save_dir = args.get_one::<String>("save-attachments").map(|x| x.as_str());
+                let skip_confirm = args.get_flag("yes");
+                commands::message::open(&to_id, key_open, input, output, save_dir, skip_confirm)?;
+            }
+            Some(("list", args)) => {
+                let id = id_val(args)?;
+                let search = args.get_one::<String>("SEARCH").map(|x| x.as_str());
+                let direction = if args.get_flag("in") {
+                    Some("in")
+                } else if args.get_flag("out") {
+                    Some("out")
+                } else {
+                    None
+                };
+                let verbose = args.get_flag("verbose");
+                commands::message::list(&id, search, direction, verbose)?;
+            }
+            Some(("read", args)) => {
+                let id = id_val(args)?;
+                let key_open = args.get_one::<String>("key-open").map(|x| x.as_str());
+                let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
+                let message_id = args
+                    .get_one::<String>("MESSAGE")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a message ID"))?;
+                let save_dir = args.get_one::<String>("save-attachments").map(|x| x.as_str());
+                commands::message::read(&id, key_open, message_id, output, save_dir)?;
+            }
+            Some(("delete", args)) => {
+                let id = id_val(args)?;
+                let skip_confirm = args.get_flag("yes");
+                let message_id = args
+                    .get_one::<String>("MESSAGE")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a message ID"))?;
+                commands::message::delete(&id, message_id, skip_confirm)?;
+            }
+            Some(("export-session", args)) => {
+                let id = id_val(args)?;
+                let key_open = args.get_one::<String>("key-open").map(|x| x.as_str());
+                let key_sign = args.get_one::<String>("key-sign").map(|x| x.as_str());
+                let encrypt = args.get_flag("encrypt");
+                let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
+                let search = args
+                    .get_one::<String>("SEARCH")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a search value"))?;
+                commands::message::export_session(&id, search, key_open, key_sign, encrypt, output)?;
+            }
+            _ => unreachable!("Unknown command"),
+        },
+        Some(("crypt", args)) => match args.subcommand() {
+            Some(("encrypt", args)) => {
+                let id = id_val(args)?;
+                let key_search = args.get_one::<String>("key").map(|x| x.as_str());
+                let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
+                let input = args.get_one::<String>("FILE").map(|x| x.as_str()).unwrap_or("-");
+                let armored = args.get_flag("armor");
+                commands::crypt::encrypt(&id, key_search, input, output, armored)?;
+            }
+            Some(("decrypt", args)) => {
+                let id = id_val(args)?;
+                let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
+                let input = args.get_one::<String>("FILE").map(|x| x.as_str()).unwrap_or("-");
+                commands::crypt::decrypt(&id, input, output)?;
             }
             _ => unreachable!("Unknown command"),
         },
         Some(("sign", args)) => match args.subcommand() {
-            Some(("id", args)) => {
+            Some(("policy", args)) => {
                 let sign_id = id_val(args)?;
                 let stage = args.get_flag("stage");
                 let sign_with = args.get_one::<String>("admin-key").map(|x| x.as_str());
                 let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
                 let input = args.get_one::<String>("MESSAGE").map(|x| x.as_str()).unwrap_or("-");
                 let base64 = args.get_flag("base64");
-                commands::sign::sign_id(&sign_id, input, output, base64, stage, sign_with)?;
+                let clearsign = args.get_flag("clearsign");
+                let cbor = args.get_one::<String>("format").map(|x| x.as_str()) == Some("cbor");
+                let armored = args.get_flag("armor");
+                commands::sign::sign_id(&sign_id, input, output, base64, armored, clearsign, cbor, stage, sign_with)?;
+            }
+            Some(("finish", args)) => {
+                let txid = args
+                    .get_one::<String>("TXID")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a transaction ID"))?;
+                let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
+                let base64 = args.get_flag("base64");
+                let armored = args.get_flag("armor");
+                commands::sign::finish(txid, output, base64, armored)?;
             }
             Some(("subkey", args)) => {
                 let sign_id = id_val(args)?;
@@ -1784,12 +3750,80 @@ fn run() -> Result<()> {
                 let input = args.get_one::<String>("MESSAGE").map(|x| x.as_str()).unwrap_or("-");
                 let attached = args.get_flag("attached");
                 let base64 = args.get_flag("base64");
-                commands::sign::sign_subkey(&sign_id, key_sign_search, input, output, attached, base64)?;
+                let clearsign = args.get_flag("clearsign");
+                let cbor = args.get_one::<String>("format").map(|x| x.as_str()) == Some("cbor");
+                let armored = args.get_flag("armor");
+                commands::sign::sign_subkey(&sign_id, key_sign_search, input, output, attached, base64, armored, clearsign, cbor)?;
+            }
+            Some(("ssh", args)) => {
+                let sign_id = id_val(args)?;
+                let key_sign_search = args.get_one::<String>("key-sign").map(|x| x.as_str());
+                let namespace = args.get_one::<String>("namespace").map(|x| x.as_str()).unwrap_or("file");
+                let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
+                let input = args.get_one::<String>("MESSAGE").map(|x| x.as_str()).unwrap_or("-");
+                commands::sign::sign_ssh(&sign_id, key_sign_search, input, output, namespace)?;
+            }
+            Some(("batch", args)) => {
+                let sign_id = id_val(args)?;
+                let key_sign_search = args.get_one::<String>("key-sign").map(|x| x.as_str());
+                let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
+                let manifest = args.get_flag("manifest");
+                let armored = args.get_flag("armor");
+                let inputs: Vec<&str> = args.get_many::<String>("FILE").unwrap_or_default().map(|v| v.as_str()).collect();
+                commands::sign::sign_batch(&sign_id, key_sign_search, &inputs, output, manifest, armored)?;
+            }
+            Some(("tree", args)) => {
+                let sign_id = id_val(args)?;
+                let key_sign_search = args.get_one::<String>("key-sign").map(|x| x.as_str());
+                let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
+                let dir = args
+                    .get_one::<String>("DIR")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a directory"))?;
+                commands::sign::sign_tree(&sign_id, key_sign_search, dir, output)?;
+            }
+            Some(("verify-tree", args)) => {
+                let signature = args.get_one::<String>("SIGNATURE").map(|x| x.as_str()).unwrap_or("-");
+                let dir = args
+                    .get_one::<String>("DIR")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a directory"))?;
+                commands::sign::verify_tree(signature, dir)?;
             }
             Some(("verify", args)) => {
                 let signature = args.get_one::<String>("SIGNATURE").map(|x| x.as_str()).unwrap_or("-");
                 let input = args.get_one::<String>("MESSAGE").map(|x| x.as_str());
-                commands::sign::verify(signature, input)?;
+                let namespace = args.get_one::<String>("namespace").map(|x| x.as_str()).unwrap_or("file");
+                let json = args.get_flag("json");
+                commands::sign::verify(signature, input, namespace, json)?;
+            }
+            _ => unreachable!("Unknown command"),
+        },
+        Some(("search", args)) => {
+            let query = args
+                .get_one::<String>("QUERY")
+                .map(|x| x.as_str())
+                .ok_or(anyhow!("Must specify a search query"))?;
+            commands::search::search(query)?;
+        }
+        Some(("release", args)) => match args.subcommand() {
+            Some(("sign", args)) => {
+                let sign_id = id_val(args)?;
+                let key_sign_search = args.get_one::<String>("key-sign").map(|x| x.as_str());
+                let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or(".");
+                let with_ssh = args.get_flag("ssh");
+                let artifacts: Vec<&str> = args
+                    .get_many::<String>("ARTIFACT")
+                    .unwrap_or_default()
+                    .map(|v| v.as_str())
+                    .collect();
+                commands::release::sign(&sign_id, key_sign_search, &artifacts, output, with_ssh)?;
+            }
+            Some(("verify", args)) => {
+                let sums = args.get_one::<String>("SUMS").map(|x| x.as_str()).unwrap_or("SHA256SUMS");
+                let signature = args.get_one::<String>("signature").map(|x| x.as_str());
+                let ignore_missing = args.get_flag("ignore-missing");
+                commands::release::verify(sums, signature, ignore_missing)?;
             }
             _ => unreachable!("Unknown command"),
         },
@@ -1801,6 +3835,41 @@ fn run() -> Result<()> {
                     .ok_or(anyhow!("Must specify a search value"))?;
                 commands::config::set_default(search)?;
             }
+            Some(("set-backup-reminder", args)) => {
+                let days = args.get_one::<u64>("DAYS").map(|x| *x).ok_or(anyhow!("Must specify a number of days"))?;
+                commands::config::set_backup_reminder(days)?;
+            }
+            Some(("set-export-cooldown", args)) => {
+                let minutes = args
+                    .get_one::<u64>("MINUTES")
+                    .map(|x| *x)
+                    .ok_or(anyhow!("Must specify a number of minutes"))?;
+                commands::config::set_export_cooldown(minutes)?;
+            }
+            Some(("keyring", args)) => {
+                let ttl = args.get_one::<u64>("ttl").map(|x| *x);
+                let forget = args.get_flag("forget");
+                commands::config::keyring(ttl, forget)?;
+            }
+            Some(("set-phone-region", args)) => {
+                let region = args
+                    .get_one::<String>("REGION")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a region"))?;
+                commands::config::set_phone_region(region)?;
+            }
+            Some(("dns", args)) => {
+                let resolver = args.get_one::<String>("resolver").map(|x| x.as_str());
+                let doh = args.get_one::<String>("doh").map(|x| x.as_str());
+                let dnssec = if args.get_flag("dnssec") {
+                    Some(true)
+                } else if args.get_flag("no-dnssec") {
+                    Some(false)
+                } else {
+                    None
+                };
+                commands::config::dns(resolver, doh, dnssec, args.get_flag("system"))?;
+            }
             Some(("set-stampnet-servers", args)) => {
                 let servers = args
                     .get_many::<Multiaddr>("SERVERS")
@@ -1810,12 +3879,104 @@ fn run() -> Result<()> {
                     .collect::<Vec<_>>();
                 commands::config::set_stampnet_servers(servers)?;
             }
+            Some(("net", args)) => {
+                let set_bootstrap = if args.get_flag("no-default-bootstrap") {
+                    Some(false)
+                } else if args.get_flag("default-bootstrap") {
+                    Some(true)
+                } else {
+                    None
+                };
+                if let Some(enabled) = set_bootstrap {
+                    commands::config::net_set_default_bootstrap(enabled)?;
+                }
+                match args.subcommand() {
+                    Some(("add-join", args)) => {
+                        let server = args.get_one::<Multiaddr>("SERVER").ok_or(anyhow!("Must specify a server"))?.clone();
+                        commands::config::net_add_join(server)?;
+                    }
+                    Some(("remove-join", args)) => {
+                        let server = args.get_one::<Multiaddr>("SERVER").ok_or(anyhow!("Must specify a server"))?.clone();
+                        commands::config::net_remove_join(server)?;
+                    }
+                    Some(("list-join", _)) => {
+                        commands::config::net_list_join()?;
+                    }
+                    None if set_bootstrap.is_some() => {}
+                    None => Err(anyhow!("Must specify a subcommand or a bootstrap flag (see `stamp config net --help`)"))?,
+                    _ => unreachable!("Unknown command"),
+                }
+            }
             _ => unreachable!("Unknown command"),
         },
         Some(("dag", args)) => match args.subcommand() {
             Some(("list", args)) => {
                 let id = id_val(args)?;
-                commands::dag::list(&id)?;
+                let types: Vec<&str> = args.get_many::<String>("type").unwrap_or_default().map(|v| v.as_str()).collect();
+                let since = args.get_one::<String>("since").map(|x| util::parse_timestamp(x)).transpose()?;
+                let until = args
+                    .get_one::<String>("until")
+                    .map(|x| util::parse_timestamp_end_of_day(x))
+                    .transpose()?;
+                let contains = args.get_one::<String>("contains").map(|x| x.as_str());
+                let sort = match args.get_one::<String>("sort").map(|x| x.as_str()) {
+                    Some("created") => commands::dag::ListSort::Created,
+                    Some("type") => commands::dag::ListSort::Type,
+                    _ => commands::dag::ListSort::Dag,
+                };
+                let reverse = args.get_flag("reverse");
+                commands::dag::list(&id, &types, since, until, contains, sort, reverse)?;
+            }
+            Some(("view", args)) => {
+                let id = id_val(args)?;
+                let txid = args
+                    .get_one::<String>("TXID")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a TXID"))?;
+                commands::dag::view(&id, txid)?;
+            }
+            Some(("verify", args)) => {
+                let id = id_val(args)?;
+                commands::dag::verify(&id)?;
+            }
+            Some(("diff", args)) => {
+                let id = id_val(args)?;
+                let other = args
+                    .get_one::<String>("OTHER")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify an identity or file to compare against"))?;
+                commands::dag::diff(&id, other)?;
+            }
+            Some(("stats", args)) => {
+                let id = id_val(args)?;
+                commands::dag::stats(&id)?;
+            }
+            Some(("merge", args)) => {
+                let id = id_val(args)?;
+                let other = args.get_one::<String>("OTHER").map(|x| x.as_str());
+                let keep = args.get_one::<String>("keep").map(|x| x.as_str());
+                let skip_confirm = args.get_flag("yes");
+                commands::dag::merge(&id, other, keep, skip_confirm)?;
+            }
+            Some(("graph", args)) => {
+                let id = id_val(args)?;
+                let format = match args.get_one::<String>("format").map(|x| x.as_str()) {
+                    Some("mermaid") => commands::dag::GraphFormat::Mermaid,
+                    _ => commands::dag::GraphFormat::Dot,
+                };
+                let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
+                commands::dag::graph(&id, format, output)?;
+            }
+            Some(("export-all", args)) => {
+                let id = id_val(args)?;
+                let public = args.get_flag("public");
+                let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
+                commands::dag::export_all(&id, output, public)?;
+            }
+            Some(("import-bundle", args)) => {
+                let input = args.get_one::<String>("BUNDLE").map(|x| x.as_str()).unwrap_or("-");
+                let skip_confirm = args.get_flag("yes");
+                commands::dag::import_bundle(input, skip_confirm)?;
             }
             Some(("reset", args)) => {
                 let id = id_val(args)?;
@@ -1823,7 +3984,17 @@ fn run() -> Result<()> {
                     .get_one::<String>("TXID")
                     .map(|x| x.as_str())
                     .ok_or(anyhow!("Must specify a TXID"))?;
-                commands::dag::reset(&id, txid)?;
+                let dry_run = args.get_flag("dry-run");
+                let backup = args.get_one::<String>("backup").map(|x| x.as_str());
+                let force = args.get_flag("force");
+                commands::dag::reset(&id, txid, dry_run, backup, force)?;
+            }
+            _ => unreachable!("Unknown command"),
+        },
+        Some(("db", args)) => match args.subcommand() {
+            Some(("fsck", args)) => {
+                let repair = args.get_flag("repair");
+                commands::db::fsck(repair)?;
             }
             _ => unreachable!("Unknown command"),
         },
@@ -1848,7 +4019,16 @@ fn run() -> Result<()> {
                 Some(("import", args)) => {
                     // no default here, debug commands should be explicit
                     let input = args.get_one::<String>("EXPORT-PATH").map(|x| x.as_str()).unwrap_or("-");
-                    commands::debug::import(input)?;
+                    let verify_only = args.get_flag("verify-only");
+                    commands::debug::import(input, verify_only)?;
+                }
+                Some(("replay", args)) => {
+                    // no default here, debug commands should be explicit
+                    let id = args
+                        .get_one::<String>("identity")
+                        .map(|x| x.as_str())
+                        .ok_or(anyhow!("Must specify an ID"))?;
+                    commands::debug::replay(id)?;
                 }
                 _ => unreachable!("Unknown command"),
             }
@@ -1856,41 +4036,92 @@ fn run() -> Result<()> {
         Some(("stage", args)) => match args.subcommand() {
             Some(("list", args)) => {
                 let id = id_val(args)?;
-                commands::stage::list(&id)?;
+                let ready_only = args.get_flag("ready");
+                let verbose = args.get_flag("verbose");
+                commands::stage::list(&id, ready_only, verbose)?;
             }
             Some(("view", args)) => {
                 let txid = args
                     .get_one::<String>("TXID")
                     .map(|x| x.as_str())
-                    .ok_or(anyhow!("Must specify a join token"))?;
+                    .ok_or(anyhow!("Must specify a transaction ID"))?;
                 commands::stage::view(txid)?;
             }
+            Some(("comment", args)) => {
+                let id = id_val(args)?;
+                let txid = args
+                    .get_one::<String>("TXID")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a transaction ID"))?;
+                let note = args
+                    .get_one::<String>("NOTE")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a comment"))?;
+                let local = args.get_flag("local");
+                commands::stage::comment(&id, txid, note, local)?;
+            }
+            Some(("analyze", args)) => {
+                let txid = args
+                    .get_one::<String>("TXID")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a transaction ID"))?;
+                commands::stage::analyze(txid)?;
+            }
             Some(("export", args)) => {
                 let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
                 let base64 = args.get_flag("base64");
+                let armored = args.get_flag("armor");
                 let txid = args
                     .get_one::<String>("TXID")
                     .map(|x| x.as_str())
-                    .ok_or(anyhow!("Must specify a join token"))?;
-                commands::stage::export(txid, output, base64)?;
+                    .ok_or(anyhow!("Must specify a transaction ID"))?;
+                let qr = args.get_flag("qr");
+                commands::stage::export(txid, output, base64, armored, qr)?;
             }
             Some(("import", args)) => {
                 let id = args.get_one::<String>("identity").ok_or(anyhow!("Must specify an ID"))?;
                 let input = args.get_one::<String>("TRANSACTION").map(|x| x.as_str()).unwrap_or("-");
-                commands::stage::import(id, input)?;
+                let qr_files = args
+                    .get_many::<String>("qr-frames")
+                    .unwrap_or_default()
+                    .map(|v| v.as_str())
+                    .collect::<Vec<_>>();
+                commands::stage::import(id, input, &qr_files)?;
+            }
+            Some(("send", args)) => {
+                let txid = args
+                    .get_one::<String>("TXID")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a transaction ID"))?;
+                let id_from = id_val(args)?;
+                let search_to = args
+                    .get_one::<String>("to")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify a co-signer"))?;
+                let key_from_search = args.get_one::<String>("key-from").map(|x| x.as_str());
+                let key_to_search = args.get_one::<String>("key-to").map(|x| x.as_str());
+                let note = args.get_one::<String>("note").map(|x| x.as_str());
+                let output = args.get_one::<String>("output").map(|x| x.as_str()).unwrap_or("-");
+                let armored = args.get_flag("armor");
+                commands::stage::send(txid, &id_from, key_from_search, key_to_search, search_to, note, output, armored)?;
             }
             Some(("delete", args)) => {
                 let txid = args
                     .get_one::<String>("TXID")
                     .map(|x| x.as_str())
-                    .ok_or(anyhow!("Must specify a join token"))?;
+                    .ok_or(anyhow!("Must specify a transaction ID"))?;
                 commands::stage::delete(txid)?;
             }
+            Some(("prune", args)) => {
+                let id = id_val(args)?;
+                let skip_confirm = args.get_flag("yes");
+                commands::stage::prune(&id, skip_confirm)?;
+            }
             Some(("sign", args)) => {
                 let txid = args
                     .get_one::<String>("TXID")
                     .map(|x| x.as_str())
-                    .ok_or(anyhow!("Must specify a join token"))?;
+                    .ok_or(anyhow!("Must specify a transaction ID"))?;
                 let sign_with = args
                     .get_one::<String>("admin-key")
                     .map(|x| x.as_str())
@@ -1898,11 +4129,22 @@ fn run() -> Result<()> {
                 commands::stage::sign(txid, sign_with)?;
             }
             Some(("apply", args)) => {
-                let txid = args
-                    .get_one::<String>("TXID")
-                    .map(|x| x.as_str())
-                    .ok_or(anyhow!("Must specify a transaction ID"))?;
-                commands::stage::apply(txid)?;
+                let all_ready = args.get_flag("all-ready");
+                let ids: Vec<&str> = args.get_many::<String>("ids").unwrap_or_default().map(|v| v.as_str()).collect();
+                if all_ready || ids.len() > 0 {
+                    let id = id_val(args)?;
+                    commands::stage::apply_batch(&id, &ids, all_ready)?;
+                } else {
+                    let txid = args
+                        .get_one::<String>("TXID")
+                        .map(|x| x.as_str())
+                        .ok_or(anyhow!("Must specify a transaction ID"))?;
+                    if args.get_flag("dry-run") {
+                        commands::stage::apply_dry_run(txid)?;
+                    } else {
+                        commands::stage::apply(txid)?;
+                    }
+                }
             }
             _ => unreachable!("Unknown command"),
         },
@@ -1916,7 +4158,33 @@ fn run() -> Result<()> {
                     .flatten()
                     .map(|x| x.clone())
                     .collect::<Vec<_>>();
-                commands::net::publish(&id, input, join)?;
+                let auto = if args.get_flag("auto") {
+                    args.get_one::<u64>("interval").map(|x| *x)
+                } else {
+                    None
+                };
+                commands::net::publish(&id, input, join, auto)?;
+            }
+            Some(("doctor", args)) => {
+                let bind = args.get_one::<Multiaddr>("bind").map(|x| x.clone());
+                let lookup = args.get_one::<String>("lookup").map(|x| x.as_str());
+                let join = args
+                    .get_many::<Multiaddr>("join")
+                    .into_iter()
+                    .flatten()
+                    .map(|x| x.clone())
+                    .collect::<Vec<_>>();
+                commands::net::doctor(bind, join, lookup)?;
+            }
+            Some(("republish", args)) => {
+                let force = args.get_flag("force");
+                let join = args
+                    .get_many::<Multiaddr>("join")
+                    .into_iter()
+                    .flatten()
+                    .map(|x| x.clone())
+                    .collect::<Vec<_>>();
+                commands::net::republish(join, force)?;
             }
             Some(("get", args)) => {
                 let id = args
@@ -1931,6 +4199,33 @@ fn run() -> Result<()> {
                     .collect::<Vec<_>>();
                 commands::net::get(&id, join)?;
             }
+            Some(("follow", args)) => {
+                let search = args
+                    .get_one::<String>("SEARCH")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify an identity ID"))?;
+                commands::net::follow(search)?;
+            }
+            Some(("unfollow", args)) => {
+                let search = args
+                    .get_one::<String>("SEARCH")
+                    .map(|x| x.as_str())
+                    .ok_or(anyhow!("Must specify an identity ID"))?;
+                commands::net::unfollow(search)?;
+            }
+            Some(("following", args)) => {
+                let verbose = args.get_flag("verbose");
+                commands::net::following(verbose)?;
+            }
+            Some(("refresh", args)) => {
+                let join = args
+                    .get_many::<Multiaddr>("join")
+                    .into_iter()
+                    .flatten()
+                    .map(|x| x.clone())
+                    .collect::<Vec<_>>();
+                commands::net::refresh(join)?;
+            }
             Some(("node", args)) => {
                 let bind = args.get_one::<Multiaddr>("bind").expect("Missing `bind` argument.").clone();
                 let join = args
@@ -1939,7 +4234,8 @@ fn run() -> Result<()> {
                     .flatten()
                     .map(|x| x.clone())
                     .collect::<Vec<_>>();
-                commands::net::node(bind, join)?;
+                let metrics = args.get_one::<std::net::SocketAddr>("metrics").map(|x| x.clone());
+                commands::net::node(bind, join, metrics)?;
             }
             _ => unreachable!("Unknown command"),
         },
@@ -1974,6 +4270,7 @@ fn run() -> Result<()> {
         */
         _ => unreachable!("Unknown command"),
     }
+    commands::status::nag(conf.default_identity.as_ref().map(|x| x.as_str()));
     Ok(())
 }
 
@@ -1981,8 +4278,13 @@ fn main() {
     match run() {
         Ok(_) => {}
         Err(err) => {
-            let red = dialoguer::console::Style::new().red();
-            eprintln!("{}", red.apply_to(err));
+            let exit = err.downcast_ref::<util::ExitError>();
+            if !exit.map(|x| x.silent).unwrap_or(false) {
+                let red = dialoguer::console::Style::new().red();
+                eprintln!("{}", red.apply_to(&err));
+            }
+            let code = exit.map(|x| x.code).unwrap_or(1);
+            std::process::exit(code);
         }
     }
 }
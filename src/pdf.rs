@@ -0,0 +1,112 @@
+/// US Letter, in points.
+pub const PAGE_WIDTH: f32 = 612.0;
+pub const PAGE_HEIGHT: f32 = 792.0;
+
+/// The built-in fonts a page can use.
+#[derive(Debug, Clone, Copy)]
+pub enum Font {
+    Helvetica,
+    HelveticaBold,
+    Courier,
+}
+
+impl Font {
+    fn resource(&self) -> &'static str {
+        match self {
+            Self::Helvetica => "F1",
+            Self::HelveticaBold => "F2",
+            Self::Courier => "F3",
+        }
+    }
+}
+
+/// A single page, built up as PDF drawing operators. Coordinates start at the
+/// top left, unlike PDF's own.
+#[derive(Default)]
+pub struct Page {
+    ops: String,
+}
+
+impl Page {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fill a rectangle with an RGB color (0-255).
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, rgb: [u8; 3]) -> &mut Self {
+        let c = |v: u8| v as f32 / 255.0;
+        self.ops.push_str(&format!(
+            "{:.3} {:.3} {:.3} rg {:.2} {:.2} {:.2} {:.2} re f\n",
+            c(rgb[0]),
+            c(rgb[1]),
+            c(rgb[2]),
+            x,
+            PAGE_HEIGHT - y - height,
+            width,
+            height
+        ));
+        self
+    }
+
+    /// Write a line of text with its baseline at `y`. Non-ASCII characters are
+    /// replaced, since the built-in fonts can't show them.
+    pub fn text(&mut self, x: f32, y: f32, font: Font, size: f32, text: &str) -> &mut Self {
+        let escaped = text
+            .chars()
+            .map(|c| match c {
+                '(' | ')' | '\\' => format!("\\{}", c),
+                c if c.is_ascii() && !c.is_ascii_control() => c.to_string(),
+                _ => String::from("?"),
+            })
+            .collect::<String>();
+        self.ops.push_str(&format!(
+            "0 0 0 rg BT /{} {} Tf {:.2} {:.2} Td ({}) Tj ET\n",
+            font.resource(),
+            size,
+            x,
+            PAGE_HEIGHT - y,
+            escaped
+        ));
+        self
+    }
+}
+
+/// Assemble pages into a PDF document. This is just enough PDF to print paper
+/// backups: filled rectangles and text in the built-in fonts.
+pub fn render(pages: &[Page]) -> Vec<u8> {
+    // objects: 1 catalog, 2 pages, 3-5 fonts, then a page + content pair per page
+    let mut objects: Vec<String> = Vec::new();
+    let page_ids = (0..pages.len()).map(|i| 6 + i * 2).collect::<Vec<_>>();
+    objects.push(String::from("<< /Type /Catalog /Pages 2 0 R >>"));
+    objects.push(format!(
+        "<< /Type /Pages /Kids [{}] /Count {} >>",
+        page_ids.iter().map(|id| format!("{} 0 R", id)).collect::<Vec<_>>().join(" "),
+        pages.len()
+    ));
+    for font in &["Helvetica", "Helvetica-Bold", "Courier"] {
+        objects.push(format!("<< /Type /Font /Subtype /Type1 /BaseFont /{} >>", font));
+    }
+    for (page, id) in pages.iter().zip(page_ids.iter()) {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R /F2 4 0 R /F3 5 0 R >> >> /Contents {} 0 R >>",
+            PAGE_WIDTH,
+            PAGE_HEIGHT,
+            id + 1
+        ));
+        objects.push(format!("<< /Length {} >>\nstream\n{}endstream", page.ops.len(), page.ops));
+    }
+
+    let mut out = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.push_str(&format!("{} 0 obj\n{}\nendobj\n", i + 1, object));
+    }
+    let xref = out.len();
+    out.push_str(&format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1));
+    for offset in offsets {
+        out.push_str(&format!("{:010} 00000 n \n", offset));
+    }
+    out.push_str(&format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref));
+    out.into_bytes()
+}
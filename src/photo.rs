@@ -0,0 +1,185 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine};
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, GenericImageView, ImageFormat};
+use std::io::Cursor;
+
+/// Largest dimension we start from when shrinking a photo. Anything bigger than
+/// this won't fit in a claim anyway.
+const FIT_START_DIMENSION: u32 = 1024;
+
+/// Largest width (in pixels) of an inline sixel preview.
+const SIXEL_MAX_WIDTH: u32 = 320;
+
+/// Inline image protocols we can render photos with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    Kitty,
+    Iterm,
+    Sixel,
+}
+
+impl std::str::FromStr for Protocol {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "kitty" => Ok(Self::Kitty),
+            "iterm" => Ok(Self::Iterm),
+            "sixel" => Ok(Self::Sixel),
+            _ => Err(anyhow!("Unknown image protocol: {}", s)),
+        }
+    }
+}
+
+/// Guess which inline image protocol the current terminal speaks, if any.
+/// Terminals don't advertise sixel support in the environment, so only the
+/// ones known to support it by default are detected.
+pub fn detect_protocol() -> Option<Protocol> {
+    let var = |name: &str| std::env::var(name).unwrap_or_default();
+    let term = var("TERM");
+    let term_program = var("TERM_PROGRAM");
+    if term == "xterm-kitty" || std::env::var("KITTY_WINDOW_ID").is_ok() || term_program == "ghostty" {
+        Some(Protocol::Kitty)
+    } else if term_program == "iTerm.app" || term_program == "WezTerm" {
+        Some(Protocol::Iterm)
+    } else if term.starts_with("foot") || term.starts_with("mlterm") || term.contains("sixel") {
+        Some(Protocol::Sixel)
+    } else {
+        None
+    }
+}
+
+/// The file extension matching a photo's format (defaults to "bin").
+pub fn extension(bytes: &[u8]) -> &'static str {
+    image::guess_format(bytes)
+        .ok()
+        .and_then(|format| format.extensions_str().get(0).copied())
+        .unwrap_or("bin")
+}
+
+fn load(bytes: &[u8]) -> Result<DynamicImage> {
+    image::load_from_memory(bytes).map_err(|e| anyhow!("Problem reading photo: {}", e))
+}
+
+/// Downscale and re-encode a photo as JPEG until it fits in `max_bytes`.
+pub fn fit(bytes: &[u8], max_bytes: usize) -> Result<Vec<u8>> {
+    let mut img = load(bytes)?;
+    if img.width() > FIT_START_DIMENSION || img.height() > FIT_START_DIMENSION {
+        img = img.resize(FIT_START_DIMENSION, FIT_START_DIMENSION, FilterType::Lanczos3);
+    }
+    let mut quality = 85;
+    loop {
+        let mut out = Vec::new();
+        JpegEncoder::new_with_quality(&mut out, quality)
+            .encode_image(&img.to_rgb8())
+            .map_err(|e| anyhow!("Problem encoding photo: {}", e))?;
+        if out.len() <= max_bytes {
+            return Ok(out);
+        }
+        if quality > 55 {
+            quality -= 10;
+        } else {
+            let (width, height) = img.dimensions();
+            if width < 64 || height < 64 {
+                Err(anyhow!("Unable to shrink the photo below {} bytes", max_bytes))?;
+            }
+            img = img.resize(width * 3 / 4, height * 3 / 4, FilterType::Lanczos3);
+        }
+    }
+}
+
+/// Build the escape sequence that draws a photo inline with the given protocol.
+pub fn render(bytes: &[u8], protocol: Protocol) -> Result<Vec<u8>> {
+    match protocol {
+        Protocol::Kitty => render_kitty(bytes),
+        Protocol::Iterm => Ok(render_iterm(bytes)),
+        Protocol::Sixel => render_sixel(bytes),
+    }
+}
+
+fn render_kitty(bytes: &[u8]) -> Result<Vec<u8>> {
+    // kitty only takes PNG (or raw pixels) so re-encode if needed
+    let png = if image::guess_format(bytes).ok() == Some(ImageFormat::Png) {
+        Vec::from(bytes)
+    } else {
+        let mut out = Cursor::new(Vec::new());
+        load(bytes)?
+            .write_to(&mut out, ImageFormat::Png)
+            .map_err(|e| anyhow!("Problem encoding photo: {}", e))?;
+        out.into_inner()
+    };
+    let encoded = BASE64_STANDARD.encode(png);
+    let chunks = encoded.as_bytes().chunks(4096).collect::<Vec<_>>();
+    let mut out = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = if i + 1 < chunks.len() { 1 } else { 0 };
+        if i == 0 {
+            out.extend_from_slice(format!("\x1b_Gf=100,a=T,m={};", more).as_bytes());
+        } else {
+            out.extend_from_slice(format!("\x1b_Gm={};", more).as_bytes());
+        }
+        out.extend_from_slice(chunk);
+        out.extend_from_slice(b"\x1b\\");
+    }
+    Ok(out)
+}
+
+fn render_iterm(bytes: &[u8]) -> Vec<u8> {
+    format!(
+        "\x1b]1337;File=inline=1;size={};preserveAspectRatio=1:{}\x07",
+        bytes.len(),
+        BASE64_STANDARD.encode(bytes)
+    )
+    .into_bytes()
+}
+
+/// Sixel with a fixed 6x6x6 color cube, which is plenty for a preview.
+fn render_sixel(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut img = load(bytes)?;
+    if img.width() > SIXEL_MAX_WIDTH {
+        img = img.resize(SIXEL_MAX_WIDTH, u32::MAX, FilterType::Triangle);
+    }
+    let rgb = img.to_rgb8();
+    let (width, height) = rgb.dimensions();
+    let level = |c: u8| ((c as u16 * 5 + 127) / 255) as usize;
+    let index = |x: u32, y: u32| {
+        let px = rgb.get_pixel(x, y);
+        level(px[0]) * 36 + level(px[1]) * 6 + level(px[2])
+    };
+
+    let mut out = String::from("\x1bPq");
+    out.push_str(&format!("\"1;1;{};{}", width, height));
+    for i in 0..216 {
+        let pct = |l: usize| l * 100 / 5;
+        out.push_str(&format!("#{};2;{};{};{}", i, pct(i / 36), pct((i / 6) % 6), pct(i % 6)));
+    }
+    for band in (0..height).step_by(6) {
+        let rows = (height - band).min(6);
+        let mut colors = vec![vec![0u8; width as usize]; 216];
+        let mut used = vec![false; 216];
+        for x in 0..width {
+            for row in 0..rows {
+                let color = index(x, band + row);
+                colors[color][x as usize] |= 1 << row;
+                used[color] = true;
+            }
+        }
+        for (color, sixels) in colors.iter().enumerate().filter(|(i, _)| used[*i]) {
+            out.push_str(&format!("#{}", color));
+            let mut x = 0;
+            while x < sixels.len() {
+                let run = sixels[x..].iter().take_while(|s| **s == sixels[x]).count();
+                let ch = (63 + sixels[x]) as char;
+                if run > 3 {
+                    out.push_str(&format!("!{}{}", run, ch));
+                } else {
+                    (0..run).for_each(|_| out.push(ch));
+                }
+                x += run;
+            }
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    Ok(out.into_bytes())
+}
@@ -8,12 +8,47 @@ use stamp_core::{
     util::SerdeBinary,
 };
 use stamp_net::Multiaddr;
+use std::convert::TryInto;
 use std::fs::File;
-use std::io::{BufReader, Read, Write};
+use std::io::{BufReader, IsTerminal, Read, Write};
 use textwrap;
 use tracing::warn;
 use url::Url;
 
+/// Serialize a Stamp object as CBOR, for tooling that doesn't speak Stamp's own
+/// binary format.
+pub(crate) fn serialize_cbor<T: serde::Serialize>(val: &T) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    ciborium::ser::into_writer(val, &mut out).map_err(|e| anyhow!("Problem serializing CBOR: {}", e))?;
+    Ok(out)
+}
+
+pub(crate) fn deserialize_cbor<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    ciborium::de::from_reader(bytes).map_err(|e| anyhow!("Problem deserializing CBOR: {}", e))
+}
+
+/// Read a transaction in any of the formats we output them in: binary, base64,
+/// or CBOR.
+pub(crate) fn deserialize_transaction(bytes: &[u8]) -> Result<Transaction> {
+    Transaction::deserialize_binary(bytes)
+        .or_else(|_| Transaction::deserialize_binary(&stamp_core::util::base64_decode(bytes)?))
+        .or_else(|_| deserialize_cbor::<Transaction>(bytes))
+        .map_err(|e| anyhow!("Problem deserializing transaction: {:?}", e))
+}
+
+/// Render data as a QR code that can be printed to the terminal.
+pub(crate) fn qr_terminal(data: &[u8]) -> Result<String> {
+    let code = qrcode::QrCode::new(data).map_err(|e| anyhow!("Problem generating QR code: {}", e))?;
+    // inverted, since most terminals are light-on-dark and scanners expect dark-on-light
+    let rendered = code
+        .render::<qrcode::render::unicode::Dense1x2>()
+        .dark_color(qrcode::render::unicode::Dense1x2::Light)
+        .light_color(qrcode::render::unicode::Dense1x2::Dark)
+        .quiet_zone(true)
+        .build();
+    Ok(rendered)
+}
+
 pub(crate) fn term_maxwidth() -> usize {
     120
 }
@@ -41,6 +76,52 @@ pub(crate) fn value_prompt(prompt: &str) -> Result<String> {
     return Ok(val);
 }
 
+/// When secret material was last exported for an identity, used to enforce
+/// the export cooldown.
+#[derive(Debug, serde_derive::Serialize, serde_derive::Deserialize)]
+struct SecretExport {
+    last: stamp_core::util::Timestamp,
+}
+
+/// Gate operations that emit secret key material. The user has to type the
+/// identity's short ID to continue, and if a cooldown is configured, exports
+/// for the same identity are refused until it has passed.
+pub(crate) fn confirm_secret_export(id_str: &str) -> Result<()> {
+    let id_short = stamp_core::identity::IdentityID::short(id_str);
+    let cooldown = crate::config::load_cli()?.secret_export_cooldown_mins.unwrap_or(0);
+    if cooldown > 0 {
+        if let Some(export) = crate::db::load_record::<SecretExport>("secret-export", id_str)? {
+            let now = stamp_core::util::Timestamp::now().local().timestamp();
+            let elapsed_mins = (now - export.last.local().timestamp()) / 60;
+            if elapsed_mins < cooldown as i64 {
+                Err(anyhow!(
+                    "Secret material for identity {} was exported {} minute(s) ago. Try again in {} minute(s).",
+                    id_short,
+                    elapsed_mins,
+                    cooldown as i64 - elapsed_mins
+                ))?;
+            }
+        }
+    }
+    let red = dialoguer::console::Style::new().red();
+    eprintln!(
+        "{} {}",
+        red.apply_to("Warning:"),
+        text_wrap("This outputs secret key material. Anyone who gets a copy can take over your identity (keyfiles) or attack your passphrase (exports).")
+    );
+    let confirm = value_prompt(&format!("Type the identity's short ID ({}) to continue", id_short))?;
+    if confirm.trim() != id_short {
+        Err(anyhow!("Confirmation did not match, aborting"))?;
+    }
+    crate::db::save_record(
+        "secret-export",
+        id_str,
+        &SecretExport {
+            last: stamp_core::util::Timestamp::now(),
+        },
+    )
+}
+
 macro_rules! id_str {
     ($id:expr) => {
         String::try_from($id).map_err(|e| anyhow::anyhow!("There was a problem converting the id {:?} to a string: {:?}", $id, e))
@@ -59,6 +140,11 @@ macro_rules! id_str_split {
     };
 }
 
+// TODO: hardware-backed admin and sign keys (FIDO2/YubiKey). Keys whose private
+// half lives on a token would be generated on-device, marked as hardware-backed
+// in the keychain, and signed with here by asking for a touch instead of opening
+// the key with the master key. stamp-core's keypairs always carry their own
+// (sealed) private material, so this needs a hardware key variant there first.
 pub(crate) fn sign_helper(
     identity: &Identity,
     transaction: Transaction,
@@ -106,13 +192,88 @@ fn derive_master(passphrase: &str, now: &stamp_core::util::Timestamp) -> Result<
     Ok(master_key)
 }
 
+const KEYRING_SERVICE: &str = "stamp-cli";
+
+/// A master key cached in the OS keyring, see [passphrase_prompt].
+#[derive(Debug, serde_derive::Serialize, serde_derive::Deserialize)]
+struct CachedMasterKey {
+    /// base64 of the master key
+    key: String,
+    expires: stamp_core::util::Timestamp,
+}
+
+/// Master keys are salted with the identity's creation time, so that's what
+/// cached keys are looked up by.
+fn keyring_entry(now: &stamp_core::util::Timestamp) -> Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, &format!("master-key:{}", now.format("%+")))
+        .map_err(|e| anyhow!("Problem opening OS keyring: {}", e))
+}
+
+fn cached_master_key(now: &stamp_core::util::Timestamp) -> Option<SecretKey> {
+    let entry = keyring_entry(now).ok()?;
+    let cached: CachedMasterKey = serde_json::from_str(&entry.get_password().ok()?).ok()?;
+    if cached.expires.local() <= stamp_core::util::Timestamp::now().local() {
+        let _ = entry.delete_password();
+        return None;
+    }
+    let bytes: [u8; 32] = stamp_core::util::base64_decode(cached.key.as_bytes())
+        .ok()?
+        .as_slice()
+        .try_into()
+        .ok()?;
+    SecretKey::new_xchacha20poly1305_from_bytes(bytes).ok()
+}
+
+/// Cache a master key in the OS keyring, but only if it actually unlocks the
+/// local identity it belongs to, so a mistyped passphrase is never cached.
+fn cache_master_key(now: &stamp_core::util::Timestamp, master_key: &SecretKey, ttl_mins: u64) -> Result<()> {
+    let unlocks = crate::db::list_local_identities(None)?
+        .iter()
+        .filter_map(|t| build_identity(t).ok())
+        .any(|identity| identity.created() == now && identity.test_master_key(master_key).is_ok());
+    if !unlocks {
+        return Ok(());
+    }
+    let expires = stamp_core::util::Timestamp::now().local() + chrono::Duration::minutes(ttl_mins as i64);
+    let cached = CachedMasterKey {
+        key: stamp_core::util::base64_encode(master_key.as_ref()),
+        expires: parse_timestamp(&expires.to_rfc3339())?,
+    };
+    keyring_entry(now)?
+        .set_password(&serde_json::to_string(&cached)?)
+        .map_err(|e| anyhow!("Problem saving master key to OS keyring: {}", e))
+}
+
+/// Remove an identity's cached master key from the OS keyring, if there is one.
+pub(crate) fn forget_master_key(now: &stamp_core::util::Timestamp) -> Result<()> {
+    match keyring_entry(now)?.delete_password() {
+        Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(anyhow!("Problem removing master key from OS keyring: {}", e)),
+    }
+}
+
 /// Grab a password and use it along with a timestamp to generate a master key.
+///
+/// If `keyring_ttl_mins` is configured, the master key is taken from the OS
+/// keyring when it's there and stored in it after a successful unlock.
 pub(crate) fn passphrase_prompt<T: Into<String>>(prompt: T, now: &stamp_core::util::Timestamp) -> Result<SecretKey> {
+    let ttl_mins = crate::config::load_cli()?.keyring_ttl_mins.unwrap_or(0);
+    if ttl_mins > 0 {
+        if let Some(master_key) = cached_master_key(now) {
+            return Ok(master_key);
+        }
+    }
     let passphrase = dialoguer::Password::new()
         .with_prompt(prompt)
         .interact()
         .map_err(|err| anyhow!("There was an error grabbing your passphrase: {:?}", err))?;
-    derive_master(&passphrase, now)
+    let master_key = derive_master(&passphrase, now)?;
+    if ttl_mins > 0 {
+        if let Err(e) = cache_master_key(now, &master_key, ttl_mins) {
+            warn!("{}", e);
+        }
+    }
+    Ok(master_key)
 }
 
 pub(crate) fn with_new_passphrase<F, T>(prompt: &str, gen_fn: F, now: Option<stamp_core::util::Timestamp>) -> Result<(T, SecretKey)>
@@ -139,9 +300,48 @@ where
     Ok((res?, master_key))
 }
 
+/// An error that should end the process with a particular exit code, for
+/// commands that scripts check the exit status of.
+#[derive(Debug)]
+pub struct ExitError {
+    pub code: i32,
+    pub message: String,
+    /// The command already reported the failure (ex as JSON), so nothing more
+    /// is printed.
+    pub silent: bool,
+}
+
+impl ExitError {
+    pub fn new<T: std::fmt::Display>(code: i32, message: T) -> anyhow::Error {
+        anyhow::Error::new(Self {
+            code,
+            message: format!("{}", message),
+            silent: false,
+        })
+    }
+
+    pub fn silent<T: std::fmt::Display>(code: i32, message: T) -> anyhow::Error {
+        anyhow::Error::new(Self {
+            code,
+            message: format!("{}", message),
+            silent: true,
+        })
+    }
+}
+
+impl std::fmt::Display for ExitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ExitError {}
+
 pub fn read_file(filename: &str) -> Result<Vec<u8>> {
     if filename == "-" {
-        if atty::is(atty::Stream::Stdin) {
+        // std's check understands Windows consoles (including mintty/ConPTY),
+        // which atty got wrong and left us waiting on a pipe that never closes
+        if std::io::stdin().is_terminal() {
             let mut contents = String::new();
             let stdin = std::io::stdin();
             eprintln!("{}", text_wrap("Enter your message and hit enter/return:"));
@@ -181,6 +381,166 @@ pub fn write_file(filename: &str, bytes: &[u8]) -> Result<()> {
     Ok(())
 }
 
+/// One file produced by a command that can emit several outputs at once (keyfile
+/// shares, per-recipient messages, etc).
+pub struct Artifact {
+    /// The file name, relative to the output directory.
+    pub filename: String,
+    /// A short description of what this file is ("keyfile-share", "message", ...)
+    pub kind: String,
+    pub bytes: Vec<u8>,
+}
+
+impl Artifact {
+    pub fn new<T: Into<String>, K: Into<String>>(filename: T, kind: K, bytes: Vec<u8>) -> Self {
+        Self {
+            filename: filename.into(),
+            kind: kind.into(),
+            bytes,
+        }
+    }
+}
+
+/// Whether an `-o` value names a directory (it exists as one, or ends with a path
+/// separator) rather than a single output file.
+pub fn is_output_dir(output: &str) -> bool {
+    output != "-" && (output.ends_with('/') || output.ends_with(std::path::MAIN_SEPARATOR) || std::path::Path::new(output).is_dir())
+}
+
+/// Write a set of artifacts into a directory, along with a `manifest.json` that
+/// lists each file, what it is, and its blake3 hash. `meta` is merged into the
+/// top level of the manifest so commands can describe what was generated.
+///
+/// Refuses to overwrite existing files.
+pub fn write_artifacts(dir: &str, artifacts: &[Artifact], meta: serde_json::Value) -> Result<()> {
+    let dir = std::path::Path::new(dir);
+    std::fs::create_dir_all(dir).map_err(|e| anyhow!("Error creating directory: {}: {:?}", dir.display(), e))?;
+    let manifest_path = dir.join("manifest.json");
+    let paths = artifacts.iter().map(|x| dir.join(&x.filename)).collect::<Vec<_>>();
+    for path in paths.iter().chain(std::iter::once(&manifest_path)) {
+        if path.exists() {
+            Err(anyhow!("Refusing to overwrite existing file: {}", path.display()))?;
+        }
+    }
+    let mut files = Vec::with_capacity(artifacts.len());
+    for (artifact, path) in artifacts.iter().zip(paths.iter()) {
+        let hash = stamp_core::crypto::base::Hash::new_blake3(artifact.bytes.as_slice())
+            .map_err(|e| anyhow!("Error hashing {}: {:?}", artifact.filename, e))?;
+        write_file(&path.to_string_lossy(), artifact.bytes.as_slice())?;
+        files.push(serde_json::json!({
+            "file": artifact.filename,
+            "kind": artifact.kind,
+            "size": artifact.bytes.len(),
+            "blake3": format!("{}", hash),
+        }));
+    }
+    let mut manifest = serde_json::json!({
+        "created": stamp_core::util::Timestamp::now().local().to_rfc3339(),
+        "files": files,
+    });
+    if let (Some(manifest_obj), serde_json::Value::Object(meta_obj)) = (manifest.as_object_mut(), meta) {
+        for (key, val) in meta_obj {
+            manifest_obj.entry(key).or_insert(val);
+        }
+    }
+    let manifest_bytes = serde_json::to_vec_pretty(&manifest)?;
+    write_file(&manifest_path.to_string_lossy(), manifest_bytes.as_slice())?;
+    eprintln!("Wrote {} file(s) and manifest.json to {}", artifacts.len(), dir.display());
+    Ok(())
+}
+
+/// Expand a list of files and directories into the files they contain.
+/// Directories are walked recursively (in sorted order) and hidden entries
+/// inside them are skipped.
+pub fn collect_files(inputs: &[&str]) -> Result<Vec<std::path::PathBuf>> {
+    fn walk(dir: &std::path::Path, files: &mut Vec<std::path::PathBuf>) -> Result<()> {
+        let mut entries = std::fs::read_dir(dir)
+            .map_err(|e| anyhow!("Error reading directory: {}: {:?}", dir.display(), e))?
+            .filter_map(|x| x.ok())
+            .map(|x| x.path())
+            .filter(|x| !x.file_name().map(|n| n.to_string_lossy().starts_with('.')).unwrap_or(false))
+            .collect::<Vec<_>>();
+        entries.sort();
+        for entry in entries {
+            if entry.is_dir() {
+                walk(&entry, files)?;
+            } else {
+                files.push(entry);
+            }
+        }
+        Ok(())
+    }
+    let mut files = Vec::new();
+    for input in inputs {
+        let path = std::path::Path::new(input);
+        if path.is_dir() {
+            walk(path, &mut files)?;
+        } else if path.is_file() {
+            files.push(path.to_path_buf());
+        } else {
+            Err(anyhow!("File not found: {}", input))?;
+        }
+    }
+    Ok(files)
+}
+
+/// Parse a date/time given on the command line: either RFC3339 or a plain date
+/// (ex 2025-06-01), which is taken as midnight UTC.
+pub fn parse_timestamp(val: &str) -> Result<stamp_core::util::Timestamp> {
+    let val = val.trim();
+    let full = if val.len() == 10 {
+        format!("{}T00:00:00Z", val)
+    } else {
+        String::from(val)
+    };
+    full.parse()
+        .map_err(|e| anyhow!("Error parsing time: {} (expected a date like 2025-06-01 or 2025-06-01T12:00:00Z): {}", val, e))
+}
+
+/// Like [parse_timestamp], but a plain date is taken as the very end of that
+/// day (UTC), so "until 2025-06-01" includes all of June 1st.
+pub fn parse_timestamp_end_of_day(val: &str) -> Result<stamp_core::util::Timestamp> {
+    let val = val.trim();
+    if val.len() == 10 {
+        parse_timestamp(&format!("{}T23:59:59.999999999Z", val))
+    } else {
+        parse_timestamp(val)
+    }
+}
+
+/// `num` lots of `unit_secs` seconds as a duration, or None if that doesn't fit.
+/// chrono's own constructors panic on overflow.
+pub fn checked_duration(num: i64, unit_secs: i64) -> Option<chrono::Duration> {
+    num.checked_mul(unit_secs)
+        .and_then(|secs| secs.checked_mul(1000))
+        .map(chrono::Duration::milliseconds)
+}
+
+/// Parse an expiration given on the command line: either a positive duration
+/// from now (30m, 12h, 7d, 2w) or anything [parse_timestamp] takes.
+pub fn parse_expires(val: &str) -> Result<stamp_core::util::Timestamp> {
+    let val = val.trim();
+    let (num, unit) = val.split_at(val.len().saturating_sub(1));
+    let unit_secs = match unit {
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return parse_timestamp(val),
+    };
+    let num = match num.parse::<i64>() {
+        Ok(num) => num,
+        Err(_) => return parse_timestamp(val),
+    };
+    if num <= 0 {
+        Err(anyhow!("Expiration must be in the future: {}", val))?;
+    }
+    let expires = checked_duration(num, unit_secs)
+        .and_then(|duration| stamp_core::util::Timestamp::now().local().checked_add_signed(duration))
+        .ok_or(anyhow!("Expiration is too far in the future: {}", val))?;
+    parse_timestamp(&expires.to_rfc3339())
+}
+
 pub fn load_file(filename: &str) -> Result<Vec<u8>> {
     let file = File::open(filename).map_err(|e| anyhow!("Unable to open file: {}: {:?}", filename, e))?;
     let mut reader = BufReader::new(file);
@@ -227,3 +587,28 @@ pub fn print_wrapped_indent(text: &str, indent: &str) {
     let indented = textwrap::indent(lines.as_str(), indent);
     print!("{}", indented);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_expires_durations() {
+        let now = stamp_core::util::Timestamp::now().local().timestamp();
+        let in_a_week = parse_expires("1w").unwrap().local().timestamp();
+        assert!((in_a_week - now - 7 * 24 * 60 * 60).abs() < 60);
+        let in_a_bit = parse_expires(" 90m ").unwrap().local().timestamp();
+        assert!((in_a_bit - now - 90 * 60).abs() < 60);
+        assert!(parse_expires("0d").is_err());
+        assert!(parse_expires("-5d").is_err());
+        assert!(parse_expires(&format!("{}w", i64::MAX)).is_err());
+    }
+
+    #[test]
+    fn parse_expires_timestamps() {
+        assert_eq!(parse_expires("2030-01-01").unwrap().local().timestamp(), 1893456000);
+        assert_eq!(parse_expires("2030-01-01T12:00:00Z").unwrap().local().timestamp(), 1893499200);
+        assert!(parse_expires("soon").is_err());
+        assert!(parse_expires("5y").is_err());
+    }
+}